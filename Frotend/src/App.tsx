@@ -4,10 +4,18 @@ import logoImage from './logo.jpg';
 import './App.css';
 
 interface Document {
-  id: string;
+  id: number;
+  // Exact even for IDs above 2^53, which `id` can't represent.
+  id_str: string;
+  // Survives database rebuilds; used in document links.
+  uuid: string;
   title: string;
   text: string;
+  // HTML-escaped excerpt with the query terms in <em>; the only field rendered as HTML.
+  snippet_html?: string;
   score: number;
+  // Lead image of the article, when the scraper found one.
+  image_url?: string;
 }
 
 interface Stats {
@@ -25,8 +33,9 @@ function SearchPage() {
   const [error, setError] = useState<string | null>(null);
   const [resultCount, setResultCount] = useState<number>(10);
   const [searchTime, setSearchTime] = useState<number | null>(null);
-  const [searchMethod, setSearchMethod] = useState<number>(2); // 2=TF-IDF, 3=SVD, 4=Low-rank
+  const [searchMethod, setSearchMethod] = useState<number>(2); // 2=TF-IDF, 3=SVD, 4=Low-rank, 8=Hybrid
   const [svdK, setSvdK] = useState<number>(25); // Default SVD k value
+  const [hybridAlpha, setHybridAlpha] = useState<number>(0.5); // TF-IDF share of hybrid scores
   const navigate = useNavigate();
 
   const API_URL = 'http://127.0.0.1:8080';
@@ -39,7 +48,7 @@ function SearchPage() {
     try {
       setApiStatus('checking');
       
-      const response = await fetch(`${API_URL}/stats`, {
+      const response = await fetch(`${API_URL}/v1/stats`, {
         method: 'GET',
         headers: {
           'Content-Type': 'application/json',
@@ -78,15 +87,19 @@ function SearchPage() {
       const requestBody: any = { 
         query: query,
         limit: resultCount,
-        method: searchMethod
+        method: searchMethod,
+        snippet: true
       };
 
       // Add k parameter for SVD methods
-      if (searchMethod === 3 || searchMethod === 4) {
+      if (searchMethod === 3 || searchMethod === 4 || searchMethod === 8) {
         requestBody.k = svdK;
       }
+      if (searchMethod === 8) {
+        requestBody.params = { hybrid_alpha: hybridAlpha };
+      }
 
-      const response = await fetch(`${API_URL}/search`, {
+      const response = await fetch(`${API_URL}/v1/search`, {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
@@ -104,9 +117,9 @@ function SearchPage() {
       const endTime = performance.now();
       setSearchTime((endTime - startTime) / 1000); // Convert to seconds
       
-      setResults(data);
+      setResults(data.results);
       
-      if (data.length === 0) {
+      if (data.results.length === 0) {
         setError(`No results found for "${query}"`);
       }
     } catch (error) {
@@ -119,7 +132,7 @@ function SearchPage() {
   };
 
   const handleViewDocument = (docId: string) => {
-    navigate(`/document/${docId}`, { state: { document: results.find(doc => doc.id === docId) } });
+    navigate(`/document/${docId}`, { state: { document: results.find(doc => doc.uuid === docId) } });
   };
 
   const getMethodName = (method: number) => {
@@ -127,6 +140,7 @@ function SearchPage() {
       case 2: return 'TF-IDF';
       case 3: return 'SVD/LSI';
       case 4: return 'Low-rank';
+      case 8: return 'Hybrid';
       default: return 'Unknown';
     }
   };
@@ -198,11 +212,30 @@ function SearchPage() {
                 <option value={2}>TF-IDF</option>
                 <option value={3}>SVD/LSI</option>
                 <option value={4}>Low-rank</option>
+                <option value={8}>Hybrid</option>
               </select>
             </div>
 
+            {/* TF-IDF weight (only for the hybrid method) */}
+            {searchMethod === 8 && (
+              <div className="optionGroup">
+                <label htmlFor="hybridAlpha" className="optionLabel">TF-IDF weight:</label>
+                <select
+                  id="hybridAlpha"
+                  value={hybridAlpha}
+                  onChange={(e) => setHybridAlpha(Number(e.target.value))}
+                  className="optionSelect"
+                  disabled={apiStatus !== 'connected'}
+                >
+                  {[0, 0.25, 0.5, 0.75, 1].map(alpha => (
+                    <option key={alpha} value={alpha}>{alpha}</option>
+                  ))}
+                </select>
+              </div>
+            )}
+
             {/* SVD K Selection (only for SVD methods) */}
-            {(searchMethod === 3 || searchMethod === 4) && stats?.available_k_values && (
+            {(searchMethod === 3 || searchMethod === 4 || searchMethod === 8) && stats?.available_k_values && (
               <div className="optionGroup">
                 <label htmlFor="svdK" className="optionLabel">SVD k:</label>
                 <select
@@ -275,20 +308,28 @@ function SearchPage() {
           ) : (
             <div>
               {results.map((result) => (
-                <div key={result.id} className="resultItem">
+                <div key={result.id_str} className="resultItem">
                   <div 
-                    onClick={() => handleViewDocument(result.id)}
+                    onClick={() => handleViewDocument(result.uuid)}
                     className="resultClickable"
                   >
+                    {result.image_url && (
+                      <img className="resultThumbnail" src={result.image_url} alt="" loading="lazy" />
+                    )}
                     <h3 className="resultTitle">
                       {result.title || 'Untitled Document'}
                     </h3>
                     <div className="resultMeta">
-                      Document #{result.id} | Score: {result.score.toFixed(4)}
+                      Document #{result.id_str} | Score: {result.score.toFixed(4)}
                     </div>
-                    <p className="resultSnippet">
-                      {result.text ? result.text.substring(0, 150) + (result.text.length > 150 ? '...' : '') : 'No text available.'}
-                    </p>
+                    {/* Only the server's escaped snippet is rendered as HTML; the text is plain. */}
+                    {result.snippet_html ? (
+                      <p className="resultSnippet" dangerouslySetInnerHTML={{ __html: result.snippet_html }} />
+                    ) : result.text ? (
+                      <p className="resultSnippet">{result.text}</p>
+                    ) : (
+                      <p className="resultSnippet">No text available.</p>
+                    )}
                   </div>
                 </div>
               ))}
@@ -308,9 +349,18 @@ function DocumentPage() {
   const [document, setDocument] = useState<Document | null>(null);
   const [loading, setLoading] = useState<boolean>(true);
   const [error, setError] = useState<string | null>(null);
+  const [related, setRelated] = useState<Document[]>([]);
   
   const API_URL = 'http://127.0.0.1:8080';
 
+  useEffect(() => {
+    setRelated([]);
+    fetch(`${API_URL}/v1/document/${docId}/similar?limit=5`)
+      .then(response => response.ok ? response.json() : null)
+      .then(data => setRelated(data?.results || []))
+      .catch(error => console.error('Error fetching related articles:', error));
+  }, [docId]);
+
   useEffect(() => {
     const stateDocument = location.state?.document;
     
@@ -326,7 +376,7 @@ function DocumentPage() {
     try {
       setLoading(true);
       
-      const response = await fetch(`${API_URL}/document/${docId}`, {
+      const response = await fetch(`${API_URL}/v1/document/${docId}`, {
         method: 'GET',
         headers: {
           'Content-Type': 'application/json',
@@ -389,8 +439,21 @@ function DocumentPage() {
             </div>
           </div>
           
+          {related.length > 0 && (
+            <div className="documentSection">
+              <h2 className="documentSectionTitle">Related Articles</h2>
+              <ul>
+                {related.map(doc => (
+                  <li key={doc.uuid}>
+                    <Link to={`/document/${doc.uuid}`}>{doc.title || 'Untitled Document'}</Link>
+                  </li>
+                ))}
+              </ul>
+            </div>
+          )}
+          
           <div className="documentFooter">
-            <span className="documentId">Document #{document.id}</span>
+            <span className="documentId">Document #{document.id_str}</span>
             <span className="documentScore">
               Score: {document.score.toFixed(4)}
             </span>
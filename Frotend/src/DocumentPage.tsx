@@ -32,7 +32,7 @@ export default function DocumentPage() {
 
   const fetchDocument = async () => {
     try {
-      const response = await fetch(`${API_URL}/document/${docId}`);
+      const response = await fetch(`${API_URL}/v1/document/${docId}`);
       if (!response.ok) throw new Error(`API error ${response.status}`);
       const data = await response.json();
       setDocument(data);
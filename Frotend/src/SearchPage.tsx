@@ -4,11 +4,18 @@ import logoImage from '../logo.jpg';
 import styles from '../styles/App.css';
 
 interface Document {
-  id: string;
+  id: number;
+  // Exact even for IDs above 2^53, which `id` can't represent.
+  id_str: string;
+  // Survives database rebuilds; used in document links.
+  uuid: string;
   title: string;
   text: string;
+  // HTML-escaped excerpt with the query terms in <em>; the only field rendered as HTML.
+  snippet_html?: string;
   authors: string[];
   score: number;
+  image_url?: string;
 }
 
 interface Stats {
@@ -35,7 +42,7 @@ export default function SearchPage() {
 
   const checkApiConnection = async () => {
     try {
-      const response = await fetch(`${API_URL}/stats`);
+      const response = await fetch(`${API_URL}/v1/stats`);
       if (response.ok) {
         const statsData = await response.json();
         setStats(statsData);
@@ -58,10 +65,10 @@ export default function SearchPage() {
     const startTime = performance.now();
 
     try {
-      const response = await fetch(`${API_URL}/search`, {
+      const response = await fetch(`${API_URL}/v1/search`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ query, limit: resultCount }),
+        body: JSON.stringify({ query, limit: resultCount, snippet: true }),
       });
 
       if (!response.ok) throw new Error(`API responded with status: ${response.status}`);
@@ -69,8 +76,8 @@ export default function SearchPage() {
       const data = await response.json();
       const endTime = performance.now();
       setSearchTime((endTime - startTime) / 1000);
-      setResults(data);
-      if (data.length === 0) setError(`No results found for "${query}"`);
+      setResults(data.results);
+      if (data.results.length === 0) setError(`No results found for "${query}"`);
     } catch (err) {
       setError(`Search failed: ${(err as Error).message}`);
     } finally {
@@ -79,7 +86,7 @@ export default function SearchPage() {
   };
 
   const handleViewDocument = (docId: string) => {
-    navigate(`/document/${docId}`, { state: { document: results.find(doc => doc.id === docId) } });
+    navigate(`/document/${docId}`, { state: { document: results.find(doc => doc.uuid === docId) } });
   };
 
   return (
@@ -152,13 +159,18 @@ export default function SearchPage() {
             <div className={styles.errorMessage}>{error}</div>
           ) : (
             results.map((result) => (
-              <div key={result.id} className={styles.resultItem} onClick={() => handleViewDocument(result.id)}>
+              <div key={result.id_str} className={styles.resultItem} onClick={() => handleViewDocument(result.uuid)}>
+                {result.image_url && (
+                  <img className={styles.resultThumbnail} src={result.image_url} alt="" loading="lazy" />
+                )}
                 <h3 className={styles.resultTitle}>{result.title || 'Untitled'}</h3>
-                <div className={styles.resultMeta}>Document #{result.id} | Score: {result.score.toFixed(4)}</div>
+                <div className={styles.resultMeta}>Document #{result.id_str} | Score: {result.score.toFixed(4)}</div>
                 <div className={styles.resultAuthor}>{result.authors?.join(', ') || 'Unknown Author'}</div>
-                <p className={styles.resultSnippet}>
-                  {result.text?.substring(0, 150) + (result.text.length > 150 ? '...' : '')}
-                </p>
+                {result.snippet_html ? (
+                  <p className={styles.resultSnippet} dangerouslySetInnerHTML={{ __html: result.snippet_html }} />
+                ) : (
+                  <p className={styles.resultSnippet}>{result.text}</p>
+                )}
               </div>
             ))
           )}
@@ -3,7 +3,7 @@ use scraper::{Html, Selector};
 use tokio_rusqlite::Connection;
 use anyhow::Result;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tokio::sync::{Semaphore, Mutex};
 use futures::future::join_all;
 use log::{info, warn, error};
@@ -13,12 +13,22 @@ const WIKIPEDIA_RANDOM_URL: &str = "https://pl.wikipedia.org/wiki/Special:Random
 const CONCURRENT_REQUESTS: usize = 10;
 const DELAY_BETWEEN_BATCHES_MS: u64 = 100;
 const PROGRESS_UPDATE_INTERVAL: usize = 100;
+// Wersje artykułu w tych językach też są pobierane, żeby backend miał pary do wielojęzycznego LSI.
+const LINKED_LANGUAGES: &[&str] = &["en"];
 
 #[derive(Debug)]
 struct Article {
     url: String,
     title: String,
     text: String,
+    // Ostatnia modyfikacja z nagłówka Last-Modified, jako unix timestamp w sekundach
+    modified_at: Option<i64>,
+    // Współrzędne (szerokość, długość) z szablonu geo artykułu
+    coordinates: Option<(f64, f64)>,
+    // Główna ilustracja artykułu (zwykle z infoboksu)
+    image_url: Option<String>,
+    // (kod języka, url) wersji artykułu w innych językach
+    links: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,22 +97,53 @@ async fn create_table_if_not_exists(conn: &Connection) -> Result<()> {
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 url TEXT UNIQUE,
                 title TEXT,
-                text TEXT
+                text TEXT,
+                modified_at INTEGER,
+                lat REAL,
+                lon REAL,
+                image_url TEXT,
+                metadata TEXT
+            )",
+            [],
+        )?;
+        // Bazy z wcześniejszych wersji scrapera nie mają nowszych kolumn.
+        for (column, column_type) in [("modified_at", "INTEGER"), ("lat", "REAL"), ("lon", "REAL"), ("image_url", "TEXT"), ("metadata", "TEXT")] {
+            let has_column: bool = db_conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM pragma_table_info('articles') WHERE name = ?)",
+                [column],
+                |row| row.get(0),
+            )?;
+            if !has_column {
+                db_conn.execute(&format!("ALTER TABLE articles ADD COLUMN {} {}", column, column_type), [])?;
+            }
+        }
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS interlanguage_links (
+                article_id INTEGER NOT NULL,
+                language TEXT NOT NULL,
+                url TEXT NOT NULL,
+                PRIMARY KEY (article_id, language)
             )",
             [],
         )?;
         Ok(())
     }).await?;
-    info!("Tabela 'articles' sprawdzona/utworzona.");
+    info!("Tabele 'articles' i 'interlanguage_links' sprawdzone/utworzone.");
     Ok(())
 }
 
-async fn scrape_random_article(client: &Client) -> Result<Option<Article>> {
-    let response = client.get(WIKIPEDIA_RANDOM_URL)
+async fn scrape_article(client: &Client, url: &str) -> Result<Option<Article>> {
+    let response = client.get(url)
         .send()
         .await?;
 
     let final_url = response.url().to_string();
+    let modified_at = response.headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_secs() as i64);
     let html_content = response.text().await?;
     let document = Html::parse_document(&html_content);
 
@@ -123,6 +164,33 @@ async fn scrape_random_article(client: &Client) -> Result<Option<Article>> {
     }
     let text = paragraphs_text.join("\n");
 
+    let link_selector = Selector::parse("li.interlanguage-link > a").expect("Błędny selektor linków językowych");
+    let links = document
+        .select(&link_selector)
+        .filter_map(|element| {
+            let language = element.value().attr("lang")?;
+            let href = element.value().attr("href")?;
+            Some((language.to_string(), href.to_string()))
+        })
+        .collect();
+
+    // Szablony współrzędnych renderują się jako mikroformat geo: <span class="geo">52.23; 21.01</span>
+    let geo_selector = Selector::parse("span.geo").expect("Błędny selektor współrzędnych");
+    let coordinates = document
+        .select(&geo_selector)
+        .next()
+        .and_then(|element| parse_coordinates(&element.text().collect::<String>()));
+
+    // Wikipedia podaje główną ilustrację w og:image; bez niej bierzemy pierwszy obrazek infoboksu.
+    let og_image_selector = Selector::parse(r#"meta[property="og:image"]"#).expect("Błędny selektor og:image");
+    let infobox_image_selector = Selector::parse("table.infobox img").expect("Błędny selektor obrazka infoboksu");
+    let image_url = document
+        .select(&og_image_selector)
+        .next()
+        .and_then(|element| element.value().attr("content"))
+        .or_else(|| document.select(&infobox_image_selector).next().and_then(|element| element.value().attr("src")))
+        .map(|src| if src.starts_with("//") { format!("https:{}", src) } else { src.to_string() });
+
     if title == "Brak tytułu" || text.is_empty() {
         warn!("Nie udało się sparsować tytułu lub tekstu dla URL: {}", final_url);
         return Ok(None);
@@ -132,24 +200,67 @@ async fn scrape_random_article(client: &Client) -> Result<Option<Article>> {
         url: final_url,
         title,
         text,
+        modified_at,
+        coordinates,
+        image_url,
+        links,
     }))
 }
 
+fn parse_coordinates(text: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = text.split_once(';')?;
+    let lat: f64 = lat.trim().parse().ok()?;
+    let lon: f64 = lon.trim().parse().ok()?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+}
+
 async fn save_article(conn: &Connection, article: &Article) -> Result<bool> {
     let article_url = article.url.clone();
     let article_title = article.title.clone();
     let article_text = article.text.clone();
+    let article_modified_at = article.modified_at;
+    let article_lat = article.coordinates.map(|(lat, _)| lat);
+    let article_lon = article.coordinates.map(|(_, lon)| lon);
+    let article_image_url = article.image_url.clone();
+    let article_links = article.links.clone();
 
     let rows_affected = conn.call(move |db_conn| {
-        db_conn.execute(
-            "INSERT OR IGNORE INTO articles (url, title, text) VALUES (?, ?, ?)",
-            &[&article_url, &article_title, &article_text],
-        )
+        let rows_affected = db_conn.execute(
+            "INSERT OR IGNORE INTO articles (url, title, text, modified_at, lat, lon, image_url) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            tokio_rusqlite::rusqlite::params![article_url, article_title, article_text, article_modified_at, article_lat, article_lon, article_image_url],
+        )?;
+        if rows_affected > 0 {
+            let article_id = db_conn.last_insert_rowid();
+            for (language, url) in &article_links {
+                db_conn.execute(
+                    "INSERT OR IGNORE INTO interlanguage_links (article_id, language, url) VALUES (?, ?, ?)",
+                    tokio_rusqlite::rusqlite::params![article_id, language, url],
+                )?;
+            }
+        }
+        Ok(rows_affected)
     }).await?;
 
     Ok(rows_affected > 0)
 }
 
+async fn save_linked_articles(client: &Client, conn: &Connection, article: &Article) {
+    for (language, url) in &article.links {
+        if !LINKED_LANGUAGES.contains(&language.as_str()) {
+            continue;
+        }
+        match scrape_article(client, url).await {
+            Ok(Some(linked)) => match save_article(conn, &linked).await {
+                Ok(true) => info!("Zapisano wersję {} artykułu {}: {}", language, article.title, url),
+                Ok(false) => {}
+                Err(e) => error!("Błąd zapisu do bazy danych dla {}: {}", url, e),
+            },
+            Ok(None) => warn!("Pominięto wersję {} artykułu {} (brak danych)", language, article.title),
+            Err(e) => error!("Błąd podczas scrapowania {}: {}", url, e),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
@@ -182,7 +293,7 @@ async fn main() -> Result<()> {
             let permit = semaphore_clone.acquire_owned().await.expect("Nie udało się uzyskać pozwolenia z semafora");
 
             let result: Result<Option<Article>> = async {
-                let article_opt = scrape_random_article(&client_clone).await?;
+                let article_opt = scrape_article(&client_clone, WIKIPEDIA_RANDOM_URL).await?;
                 tokio::time::sleep(Duration::from_millis(DELAY_BETWEEN_BATCHES_MS)).await;
                 Ok(article_opt)
             }.await;
@@ -195,6 +306,7 @@ async fn main() -> Result<()> {
                             info!("Zapisano artykuł ({}): {} - {}", task_index, article.title, article.url);
                             saved_this_task = true;
                             progress_clone.increment_saved().await;
+                            save_linked_articles(&client_clone, &db_conn_clone, &article).await;
                         }
                         Ok(false) => {
                             info!("Artykuł już istnieje (zignorowano) ({}): {}", task_index, article.url);
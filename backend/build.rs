@@ -0,0 +1,19 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The C header is generated from src/ffi.rs into OUT_DIR when the C API is built. The copy
+    // in include/ is only rewritten when SEARCH_ENGINE_UPDATE_HEADER is set, so building never
+    // touches the source tree.
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-env-changed=SEARCH_ENGINE_UPDATE_HEADER");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let header = cbindgen::generate(&crate_dir).expect("Unable to generate the C header");
+        header.write_to_file(format!("{}/search_engine.h", out_dir));
+        if std::env::var_os("SEARCH_ENGINE_UPDATE_HEADER").is_some() {
+            header.write_to_file(format!("{}/include/search_engine.h", crate_dir));
+        }
+    }
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use search_engine::util::steming::porter_stem;
+
+fuzz_target!(|word: &str| {
+    let _ = porter_stem(word);
+});
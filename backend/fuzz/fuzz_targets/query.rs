@@ -0,0 +1,25 @@
+#![no_main]
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+use libfuzzer_sys::fuzz_target;
+use search_engine::util::analyzer::{Analyzer, AnalyzerConfig};
+use search_engine::util::search::query_term_counts;
+
+static ANALYZER: LazyLock<Analyzer> = LazyLock::new(|| {
+    Analyzer::from_config(&AnalyzerConfig::default(), Path::new(".")).unwrap()
+});
+
+// Runs a query through the same steps a search request does: analysis, then the lookup of its
+// terms in the vocabulary.
+fuzz_target!(|query: &str| {
+    let stages = ANALYZER.analyze_stages(query);
+    let mut term_dict: HashMap<String, usize> = HashMap::new();
+    for term in stages.normalized {
+        let next_idx = term_dict.len();
+        term_dict.entry(term).or_insert(next_idx);
+    }
+    let counts = query_term_counts(query, &term_dict, &ANALYZER);
+    assert!(counts.keys().all(|&idx| idx < term_dict.len()));
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use search_engine::util::tokenizer::tokenize;
+
+fuzz_target!(|text: &str| {
+    for token in tokenize(text) {
+        assert!(token.len() > 2, "tokenize kept a short token {:?}", token);
+    }
+});
@@ -0,0 +1,94 @@
+#ifndef SEARCH_ENGINE_H
+#define SEARCH_ENGINE_H
+
+/* Generated from src/ffi.rs by cbindgen; don't edit. Regenerate with SEARCH_ENGINE_UPDATE_HEADER=1 cargo build --features ffi. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Values of `search_engine_search`'s `method`, the numbers the HTTP API uses. Low-rank (4) and
+ * cross-language (7) search are only served over HTTP.
+ */
+typedef enum SearchEngineMethod {
+  SEARCH_ENGINE_METHOD_TF_IDF = 2,
+  /**
+   * Needs an engine built with LSI.
+   */
+  SEARCH_ENGINE_METHOD_LSI = 3,
+  SEARCH_ENGINE_METHOD_QUERY_LIKELIHOOD = 5,
+  SEARCH_ENGINE_METHOD_BM25 = 6,
+  /**
+   * TF-IDF and LSI blended; needs an engine built with LSI.
+   */
+  SEARCH_ENGINE_METHOD_HYBRID = 8,
+} SearchEngineMethod;
+
+/**
+ * The engine as a library, without the HTTP server: an index held in memory, searched with any
+ * single-index method and saved to or loaded from a directory.
+ */
+typedef struct SearchEngine SearchEngine;
+
+/**
+ * One result of `search_engine_search`.
+ */
+typedef struct SearchEngineHit {
+  /**
+   * The id the document was indexed with.
+   */
+  int64_t id;
+  double score;
+  /**
+   * The document's UUID, NUL-terminated.
+   */
+  char uuid[37];
+} SearchEngineHit;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Loads an engine saved with `SearchEngine::save`. Returns NULL on failure; see
+ * `search_engine_last_error`. Free the engine with `search_engine_free`.
+ *
+ * # Safety
+ * `path` must be a NUL-terminated string.
+ */
+struct SearchEngine *search_engine_load(const char *path);
+
+/**
+ * Searches `engine` for `query` with a `SearchEngineMethod`, writing the best `capacity`
+ * results, best first, to `hits`. Returns how many were written, or -1 on failure; see
+ * `search_engine_last_error`. The engine may be searched from several threads at once.
+ *
+ * # Safety
+ * `engine` must come from `search_engine_load` and not have been freed, `query` must be a
+ * NUL-terminated string and `hits` must have room for `capacity` results.
+ */
+ptrdiff_t search_engine_search(const struct SearchEngine *engine,
+                               const char *query,
+                               uint32_t method,
+                               struct SearchEngineHit *hits,
+                               size_t capacity);
+
+/**
+ * Frees an engine from `search_engine_load`; NULL is ignored.
+ *
+ * # Safety
+ * `engine` must come from `search_engine_load` and not have been freed already.
+ */
+void search_engine_free(struct SearchEngine *engine);
+
+/**
+ * The message of the last failure on this thread, or NULL if there was none. It stays valid
+ * until the next failure on this thread.
+ */
+const char *search_engine_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SEARCH_ENGINE_H */
@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+use serde::{Deserialize, Serialize};
+use crate::{util, deserialize_matrix, serialize_matrix, Document, PreprocessedData, SerializableCsrMatrix, SvdData};
+use crate::util::analyzer::{Analyzer, AnalyzerConfig};
+use crate::util::bm25::Bm25;
+use crate::util::config::SearchConfig;
+use crate::util::data::AtomicFile;
+use crate::util::qlm::QueryLikelihood;
+use crate::util::svd::SvdBuildConfig;
+use crate::util::weighting::Weighting;
+
+const INDEX_FILE: &str = "preprocessed.idx";
+const LSI_FILE: &str = "lsi.idx";
+const SETTINGS_FILE: &str = "engine.json";
+
+/// How `SearchEngine::search` ranks. The HTTP API's low-rank (4) and cross-language (7) methods
+/// aren't here: they search imported embeddings and a model trained on interlanguage links, which
+/// only the server has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// Cosine of the query and the weighted document columns.
+    TfIdf,
+    /// Cosine in the LSI space; needs an engine built `with_lsi`.
+    Lsi,
+    Bm25,
+    /// Query likelihood with Dirichlet smoothing.
+    QueryLikelihood,
+    /// TF-IDF and LSI cosines blended by `SearchConfig::hybrid_alpha`; needs an engine built `with_lsi`.
+    Hybrid,
+}
+
+// What an engine was built with, saved next to its index.
+#[derive(Serialize, Deserialize, Clone)]
+struct Settings {
+    analyzer: AnalyzerConfig,
+    resources: PathBuf,
+    weighting: Weighting,
+    lsi_rank: Option<usize>,
+    svd: SvdBuildConfig,
+    search: SearchConfig,
+}
+
+// An engine in one blob, for where there's no directory to load from (see `to_bundle`). The
+// settings are JSON as in engine.json: bincode can't read the weighting's tagged enum.
+#[derive(Serialize)]
+struct BundleRef<'a> {
+    settings: String,
+    analyzer: &'a Analyzer,
+    data: &'a PreprocessedData,
+    svd: Option<&'a SvdData>,
+}
+
+#[derive(Deserialize)]
+struct Bundle {
+    settings: String,
+    analyzer: Analyzer,
+    data: PreprocessedData,
+    svd: Option<SvdData>,
+}
+
+/// Configures a `SearchEngine`; start with `SearchEngine::builder()`.
+pub struct SearchEngineBuilder {
+    documents: Vec<Document>,
+    settings: Settings,
+}
+
+impl SearchEngineBuilder {
+    /// The documents to index, at least one. Empty `uuid`s are derived as for scraped articles.
+    pub fn corpus(mut self, documents: impl IntoIterator<Item = Document>) -> Self {
+        self.documents.extend(documents);
+        self
+    }
+
+    /// Tokenization, stop words and normalization; `AnalyzerConfig::default()` when not set.
+    pub fn analyzer(mut self, config: AnalyzerConfig) -> Self {
+        self.settings.analyzer = config;
+        self
+    }
+
+    /// Directory the analyzer's relative resource paths (stop words, lemma dictionary, synonyms)
+    /// are resolved against; the working directory when not set.
+    pub fn resources(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.settings.resources = dir.into();
+        self
+    }
+
+    /// How term counts are weighted for `Method::TfIdf` and LSI; TF-IDF when not set.
+    pub fn weighting(mut self, weighting: Weighting) -> Self {
+        self.settings.weighting = weighting;
+        self
+    }
+
+    /// Also computes a rank-`k` LSI model, for `Method::Lsi`.
+    pub fn with_lsi(mut self, k: usize) -> Self {
+        self.settings.lsi_rank = Some(k);
+        self
+    }
+
+    /// Lanczos settings for the LSI model.
+    pub fn svd_config(mut self, config: SvdBuildConfig) -> Self {
+        self.settings.svd = config;
+        self
+    }
+
+    /// Ranking parameters such as BM25's `k1` and `b` and the query-likelihood prior.
+    pub fn search_config(mut self, config: SearchConfig) -> Self {
+        self.settings.search = config;
+        self
+    }
+
+    pub fn build(self) -> Result<SearchEngine, Box<dyn Error>> {
+        let settings = self.settings;
+        settings.weighting.validate()?;
+        let mut documents = self.documents;
+        for doc in documents.iter_mut().filter(|doc| doc.uuid.is_empty()) {
+            doc.uuid = util::parser::document_uuid(&doc.url, &doc.title, &doc.text);
+        }
+
+        let mut analyzer = Analyzer::from_config(&settings.analyzer, &settings.resources)?;
+        analyzer.learn_collocations(&settings.analyzer, &documents);
+        analyzer.learn_subwords(&settings.analyzer, &documents);
+        let (term_dict, inverse_term_dict, coo) = util::tokenizer::build_term_document_matrix(&documents, &analyzer, settings.analyzer.title_weight)?;
+        let synonyms = match &settings.analyzer.synonyms_path {
+            Some(path) => util::synonyms::Synonyms::load(&settings.resources.join(path), settings.analyzer.synonym_weight, &analyzer, &term_dict)?,
+            None => util::synonyms::Synonyms::default(),
+        };
+        analyzer.set_synonyms(synonyms.clone());
+
+        let counts = CsrMatrix::from(&coo);
+        let mut data = PreprocessedData {
+            term_dict,
+            inverse_term_dict,
+            idf: Vec::new(),
+            collocations: analyzer.collocations().clone(),
+            synonyms,
+            subwords: analyzer.subwords().clone(),
+            documents: Arc::new(documents),
+            term_doc_csr: SerializableCsrMatrix::from_csr(&counts),
+            term_counts: counts.values().iter().map(|&count| count as u32).collect(),
+            texts: None,
+        };
+        apply_weighting(&mut data, settings.weighting);
+        let svd = match settings.lsi_rank {
+            Some(k) => {
+                let mut svd = util::svd::perform_svd(&data.term_doc_csr.to_csr(), k, &settings.svd, None)?;
+                svd.doc_ids = util::document_ids::column_ids(&data.documents);
+                Some(svd)
+            }
+            None => None,
+        };
+        Ok(SearchEngine::assemble(settings, analyzer, data, svd))
+    }
+}
+
+/// The engine as a library, without the HTTP server: an index held in memory, searched with any
+/// single-index method and saved to or loaded from a directory.
+pub struct SearchEngine {
+    settings: Settings,
+    analyzer: Analyzer,
+    data: PreprocessedData,
+    csr: CsrMatrix<f64>,
+    svd: Option<SvdData>,
+    bm25: Bm25,
+    qlm: QueryLikelihood,
+}
+
+impl SearchEngine {
+    pub fn builder() -> SearchEngineBuilder {
+        SearchEngineBuilder {
+            documents: Vec::new(),
+            settings: Settings {
+                analyzer: AnalyzerConfig::default(),
+                resources: PathBuf::from("."),
+                weighting: Weighting::default(),
+                lsi_rank: None,
+                svd: SvdBuildConfig::default(),
+                search: SearchConfig::default(),
+            },
+        }
+    }
+
+    fn assemble(settings: Settings, analyzer: Analyzer, data: PreprocessedData, svd: Option<SvdData>) -> Self {
+        SearchEngine {
+            csr: data.term_doc_csr.to_csr(),
+            bm25: Bm25::build(&data),
+            qlm: QueryLikelihood::build(&data),
+            settings,
+            analyzer,
+            data,
+            svd,
+        }
+    }
+
+    /// The best `top_k` documents for `query`, best first, with their scores.
+    pub fn search<'a>(&'a self, query: &'a str, method: Method, top_k: usize) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+        let (data, analyzer, config) = (&self.data, &self.analyzer, &self.settings.search);
+        match method {
+            Method::TfIdf => util::search::search(query, data, analyzer, &self.csr, None, top_k),
+            Method::Lsi => {
+                let svd = self.svd.as_ref().ok_or("The engine has no LSI model; build it with_lsi")?;
+                util::search::search_svd(query, data, analyzer, svd, config, None, top_k)
+            }
+            Method::Bm25 => util::search::search_bm25(query, data, analyzer, &self.bm25, config, None, top_k),
+            Method::QueryLikelihood => util::search::search_qlm(query, data, analyzer, &self.qlm, config, None, top_k),
+            Method::Hybrid => {
+                let svd = self.svd.as_ref().ok_or("The engine has no LSI model; build it with_lsi")?;
+                util::search::search_hybrid(query, data, analyzer, &self.csr, svd, config, None, top_k)
+            }
+        }
+    }
+
+    pub fn documents(&self) -> &[Document] {
+        &self.data.documents
+    }
+
+    /// Indexes one more document. Its words new to the vocabulary become terms and all weights
+    /// are recomputed, a pass over the index. The LSI model isn't recomputed: the document is
+    /// folded into it, projected onto the existing term space, so build again after many additions.
+    pub fn add_document(&mut self, mut document: Document) -> Result<(), Box<dyn Error>> {
+        if document.uuid.is_empty() {
+            document.uuid = util::parser::document_uuid(&document.url, &document.title, &document.text);
+        }
+        let doc_idx = self.data.documents.len();
+
+        // New terms get rows after the existing ones.
+        let mut counts: BTreeMap<usize, u32> = BTreeMap::new();
+        for (field, weight) in util::tokenizer::indexed_fields(&document, self.settings.analyzer.title_weight) {
+            for term in self.analyzer.analyze(field) {
+                let next = self.data.term_dict.len();
+                let term_idx = *self.data.term_dict.entry(term.clone()).or_insert(next);
+                if term_idx == next {
+                    self.data.inverse_term_dict.insert(next, term);
+                }
+                *counts.entry(term_idx).or_insert(0) += weight;
+            }
+        }
+
+        let old = &self.data.term_doc_csr;
+        let num_terms = self.data.term_dict.len();
+        let mut row_offsets = Vec::with_capacity(num_terms + 1);
+        let mut col_indices = Vec::with_capacity(old.col_indices.len() + counts.len());
+        let mut term_counts = Vec::with_capacity(old.col_indices.len() + counts.len());
+        row_offsets.push(0);
+        for term_idx in 0..num_terms {
+            if term_idx < old.nrows {
+                let entries = old.row_offsets[term_idx]..old.row_offsets[term_idx + 1];
+                col_indices.extend_from_slice(&old.col_indices[entries.clone()]);
+                term_counts.extend_from_slice(&self.data.term_counts[entries]);
+            }
+            // The new column is the last, so rows stay sorted.
+            if let Some(&count) = counts.get(&term_idx) {
+                col_indices.push(doc_idx);
+                term_counts.push(count);
+            }
+            row_offsets.push(col_indices.len());
+        }
+        self.data.term_doc_csr = SerializableCsrMatrix {
+            nrows: num_terms,
+            ncols: doc_idx + 1,
+            values: vec![0.0; col_indices.len()],
+            row_offsets,
+            col_indices,
+        };
+        self.data.term_counts = term_counts;
+        let uuid = document.uuid.clone();
+        Arc::make_mut(&mut self.data.documents).push(document);
+        apply_weighting(&mut self.data, self.settings.weighting);
+
+        if let Some(svd) = &mut self.svd {
+            fold_in(svd, &last_column(&self.data.term_doc_csr), uuid);
+        }
+        self.csr = self.data.term_doc_csr.to_csr();
+        self.bm25 = Bm25::build(&self.data);
+        self.qlm = QueryLikelihood::build(&self.data);
+        Ok(())
+    }
+
+    /// Writes the index, the LSI model and the engine's settings to `dir`, creating it if needed.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        util::data::save_preprocessed_data(&self.data, &dir.join(INDEX_FILE))?;
+        if let Some(svd) = &self.svd {
+            util::data::save_svd_data(svd, &dir.join(LSI_FILE))?;
+        }
+        // Last, so a directory with settings holds everything they refer to.
+        let mut settings = self.settings.clone();
+        settings.lsi_rank = self.svd.as_ref().map(|svd| svd.rank);
+        let mut file = AtomicFile::create(&dir.join(SETTINGS_FILE))?;
+        serde_json::to_writer_pretty(&mut file, &settings)?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Reads an engine written by `save`.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref();
+        let settings: Settings = serde_json::from_reader(BufReader::new(File::open(dir.join(SETTINGS_FILE))?))?;
+        let data = util::data::load_preprocessed_data(&dir.join(INDEX_FILE))?;
+        let svd = match settings.lsi_rank {
+            Some(_) => {
+                let path = dir.join(LSI_FILE);
+                let svd = util::data::load_svd_data(&path)?;
+                util::document_ids::check_model_columns(&svd, &data.documents, &path)?;
+                Some(svd)
+            }
+            None => None,
+        };
+        let mut analyzer = Analyzer::from_config(&settings.analyzer, &settings.resources)?;
+        analyzer.set_collocations(data.collocations.clone());
+        analyzer.set_synonyms(data.synonyms.clone());
+        analyzer.set_subwords(data.subwords.clone());
+        Ok(SearchEngine::assemble(settings, analyzer, data, svd))
+    }
+
+    /// The whole engine, its analyzer's resources included, as one blob for `from_bundle`. Meant
+    /// for small indexes shipped to a browser, where nothing can be read from disk.
+    pub fn to_bundle(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let bundle = BundleRef {
+            settings: serde_json::to_string(&self.settings)?,
+            analyzer: &self.analyzer,
+            data: &self.data,
+            svd: self.svd.as_ref(),
+        };
+        Ok(bincode::serialize(&bundle)?)
+    }
+
+    /// Reads an engine written by `to_bundle`.
+    pub fn from_bundle(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let Bundle { settings, analyzer, data, svd } = bincode::deserialize(bytes)?;
+        let settings: Settings = serde_json::from_str(&settings)?;
+        Ok(SearchEngine::assemble(settings, analyzer, data, svd))
+    }
+}
+
+// Weights the raw counts into `term_doc_csr` and `idf`.
+fn apply_weighting(data: &mut PreprocessedData, weighting: Weighting) {
+    let (term_doc_csr, idf) = util::weighting::reweight(data, weighting);
+    data.term_doc_csr = term_doc_csr;
+    data.idf = idf;
+}
+
+// The weights of the document in the matrix's last column.
+fn last_column(matrix: &SerializableCsrMatrix) -> DVector<f64> {
+    let doc_idx = matrix.ncols - 1;
+    let mut column = DVector::zeros(matrix.nrows);
+    for term_idx in 0..matrix.nrows {
+        let (start, end) = (matrix.row_offsets[term_idx], matrix.row_offsets[term_idx + 1]);
+        if end > start && matrix.col_indices[end - 1] == doc_idx {
+            column[term_idx] = matrix.values[end - 1];
+        }
+    }
+    column
+}
+
+// Adds a document to the model without recomputing it. Its vector is `U^T d`, which for the
+// documents the model was computed from equals their `Σ V^T` column; terms the model has never
+// seen get zero rows in `U`.
+fn fold_in(svd: &mut SvdData, weights: &DVector<f64>, uuid: String) {
+    let mut u = deserialize_matrix(&svd.u_ser);
+    if u.nrows() < weights.len() {
+        u = u.resize_vertically(weights.len(), 0.0);
+    }
+    let doc_vec = u.transpose() * weights;
+
+    let docs = deserialize_matrix(&svd.docs_ser);
+    let col = docs.ncols();
+    let mut docs = docs.insert_column(col, 0.0);
+    docs.set_column(col, &doc_vec);
+    let mut vt = deserialize_matrix(&svd.vt_ser).insert_column(col, 0.0);
+    for (dim, &sigma) in svd.sigma_k.iter().enumerate() {
+        vt[(dim, col)] = if sigma > 0.0 { doc_vec[dim] / sigma } else { 0.0 };
+    }
+
+    svd.u_ser = serialize_matrix(&u);
+    svd.docs_ser = serialize_matrix(&docs);
+    svd.vt_ser = serialize_matrix(&vt);
+    svd.doc_ids.push(uuid);
+}
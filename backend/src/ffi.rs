@@ -0,0 +1,135 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use crate::{Method, SearchEngine};
+
+/// Values of `search_engine_search`'s `method`, the numbers the HTTP API uses. Low-rank (4) and
+/// cross-language (7) search are only served over HTTP.
+#[repr(C)]
+pub enum SearchEngineMethod {
+    TfIdf = 2,
+    /// Needs an engine built with LSI.
+    Lsi = 3,
+    QueryLikelihood = 5,
+    Bm25 = 6,
+    /// TF-IDF and LSI blended; needs an engine built with LSI.
+    Hybrid = 8,
+}
+
+/// One result of `search_engine_search`.
+#[repr(C)]
+pub struct SearchEngineHit {
+    /// The id the document was indexed with.
+    pub id: i64,
+    pub score: f64,
+    /// The document's UUID, NUL-terminated.
+    pub uuid: [c_char; 37],
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    // An interior NUL would cut the message short rather than lose it.
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Runs `f`, recording its error or panic for `search_engine_last_error` and returning `failed`
+// instead, since neither may cross into the caller.
+fn guarded<T>(failed: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_error(message);
+            failed
+        }
+        Err(_) => {
+            set_error("The search engine panicked".to_string());
+            failed
+        }
+    }
+}
+
+// # Safety: `s` must be NULL or point to a NUL-terminated string.
+unsafe fn utf8<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", name));
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().map_err(|_| format!("{} is not UTF-8", name))
+}
+
+/// Loads an engine saved with `SearchEngine::save`. Returns NULL on failure; see
+/// `search_engine_last_error`. Free the engine with `search_engine_free`.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn search_engine_load(path: *const c_char) -> *mut SearchEngine {
+    guarded(ptr::null_mut(), || {
+        let path = unsafe { utf8(path, "path") }?;
+        let engine = SearchEngine::load(path).map_err(|e| e.to_string())?;
+        Ok(Box::into_raw(Box::new(engine)))
+    })
+}
+
+/// Searches `engine` for `query` with a `SearchEngineMethod`, writing the best `capacity`
+/// results, best first, to `hits`. Returns how many were written, or -1 on failure; see
+/// `search_engine_last_error`. The engine may be searched from several threads at once.
+///
+/// # Safety
+/// `engine` must come from `search_engine_load` and not have been freed, `query` must be a
+/// NUL-terminated string and `hits` must have room for `capacity` results.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn search_engine_search(
+    engine: *const SearchEngine,
+    query: *const c_char,
+    method: u32,
+    hits: *mut SearchEngineHit,
+    capacity: usize,
+) -> isize {
+    guarded(-1, || {
+        let engine = unsafe { engine.as_ref() }.ok_or("engine is NULL")?;
+        let query = unsafe { utf8(query, "query") }?;
+        if hits.is_null() && capacity > 0 {
+            return Err("hits is NULL".to_string());
+        }
+        let method = match method {
+            2 => Method::TfIdf,
+            3 => Method::Lsi,
+            5 => Method::QueryLikelihood,
+            6 => Method::Bm25,
+            8 => Method::Hybrid,
+            other => return Err(format!("Unknown method {}; use 2 (TF-IDF), 3 (LSI), 5 (query likelihood), 6 (BM25) or 8 (hybrid)", other)),
+        };
+        let ranked = engine.search(query, method, capacity).map_err(|e| e.to_string())?;
+        for (slot, (doc, score)) in ranked.iter().take(capacity).enumerate() {
+            let mut uuid = [0 as c_char; 37];
+            for (dst, &byte) in uuid.iter_mut().zip(doc.uuid.as_bytes().iter().take(36)) {
+                *dst = byte as c_char;
+            }
+            unsafe { hits.add(slot).write(SearchEngineHit { id: doc.id, score: *score, uuid }) };
+        }
+        Ok(ranked.len().min(capacity) as isize)
+    })
+}
+
+/// Frees an engine from `search_engine_load`; NULL is ignored.
+///
+/// # Safety
+/// `engine` must come from `search_engine_load` and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn search_engine_free(engine: *mut SearchEngine) {
+    if !engine.is_null() {
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// The message of the last failure on this thread, or NULL if there was none. It stays valid
+/// until the next failure on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn search_engine_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
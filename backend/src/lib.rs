@@ -0,0 +1,226 @@
+pub mod engine;
+pub mod util;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+pub use engine::{Method, SearchEngine, SearchEngineBuilder};
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use nalgebra::DMatrix;
+use nalgebra_sparse::CsrMatrix;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Document {
+    /// SQLite rowid; reassigned when the database is rebuilt, so only for internal use.
+    pub id: i64,
+    /// Derived from the URL (or the content, without one), so it survives rebuilds of the
+    /// database; the identifier clients should keep.
+    #[serde(default)]
+    pub uuid: String,
+    pub title: String,
+    pub url: String,
+    pub text: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// ISO 639-1 code detected at index time; empty when undetermined.
+    #[serde(default)]
+    pub language: String,
+    /// When the article was last modified, as a unix timestamp in seconds; `None` when the
+    /// scraper didn't record it.
+    #[serde(default)]
+    pub modified_at: Option<i64>,
+    /// `(latitude, longitude)` in degrees, from the article's coordinates template.
+    #[serde(default)]
+    pub coordinates: Option<(f64, f64)>,
+    /// The article's lead image, for result cards.
+    #[serde(default)]
+    pub image_url: Option<String>,
+    /// Corpus-specific fields as a JSON object's text (see `util::metadata`); empty when none.
+    #[serde(default)]
+    pub metadata: String,
+}
+
+impl Document {
+    /// A document with only a title, URL and text, e.g. for `SearchEngine`; the URL may be empty.
+    /// Its UUID is derived as for a scraped article.
+    pub fn new(id: i64, title: impl Into<String>, url: impl Into<String>, text: impl Into<String>) -> Self {
+        let (title, url, text) = (title.into(), url.into(), text.into());
+        Document {
+            id,
+            uuid: util::parser::document_uuid(&url, &title, &text),
+            title,
+            url,
+            text,
+            keywords: Vec::new(),
+            summary: String::new(),
+            entities: Vec::new(),
+            language: String::new(),
+            modified_at: None,
+            coordinates: None,
+            image_url: None,
+            metadata: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PreprocessedData {
+    pub term_dict: std::collections::HashMap<String, usize>,
+    pub inverse_term_dict: std::collections::HashMap<usize, String>,
+    pub idf: Vec<f64>,
+    pub collocations: util::collocations::Collocations,
+    /// Query expansions, analyzed against this index's vocabulary when it was built.
+    pub synonyms: util::synonyms::Synonyms,
+    /// Learned subword vocabulary; empty unless the index normalizes to subwords.
+    pub subwords: util::subwords::Subwords,
+    pub documents: Arc<Vec<Document>>,
+    pub term_doc_csr: SerializableCsrMatrix,
+    /// Raw term frequencies, one per stored entry of `term_doc_csr` (same sparsity pattern).
+    pub term_counts: Vec<u32>,
+    /// Where the document texts are when tiering keeps them out of `documents`; read them with
+    /// `text` rather than `Document::text`.
+    #[serde(skip)]
+    pub texts: Option<Arc<util::tiering::TextTier>>,
+}
+
+impl PreprocessedData {
+    /// The document's text, from the text tier when tiering is enabled.
+    pub fn text<'a>(&self, doc: &'a Document) -> Cow<'a, str> {
+        match &self.texts {
+            Some(texts) => match texts.text(doc) {
+                Ok(text) => Cow::Owned(text.to_string()),
+                Err(e) => {
+                    log::warn!("Can't read the text of document {}: {}", doc.id, e);
+                    Cow::Borrowed("")
+                }
+            },
+            None => Cow::Borrowed(&doc.text),
+        }
+    }
+
+    /// Length of the document's text in bytes, without reading a tiered one.
+    pub fn text_len(&self, doc: &Document) -> usize {
+        self.texts.as_ref().map_or(doc.text.len(), |texts| texts.len(doc))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub data: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SvdData {
+    pub rank: usize,
+    pub sigma_k: Vec<f64>,
+    pub u_ser: SerMatrix,
+    pub vt_ser: SerMatrix,
+    pub docs_ser: SerMatrix,
+    /// UUID of the document in each column of `docs_ser`, checked against the loaded documents
+    /// so a model built for another document order is never searched. Empty in older files.
+    pub doc_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableCsrMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub row_offsets: Vec<usize>,
+    pub col_indices: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl SerializableCsrMatrix {
+    pub fn from_csr(csr: &CsrMatrix<f64>) -> Self {
+        SerializableCsrMatrix {
+            nrows: csr.nrows(),
+            ncols: csr.ncols(),
+            row_offsets: csr.row_offsets().to_vec(),
+            col_indices: csr.col_indices().to_vec(),
+            values: csr.values().to_vec(),
+        }
+    }
+
+    pub fn to_csr(&self) -> CsrMatrix<f64> {
+        CsrMatrix::try_from_csr_data(
+            self.nrows,
+            self.ncols,
+            self.row_offsets.clone(),
+            self.col_indices.clone(),
+            self.values.clone(),
+        ).unwrap()
+    }
+}
+
+impl SvdData {
+    pub fn u_k(&self) -> DMatrix<f64> {
+        deserialize_matrix(&self.u_ser)
+    }
+
+    pub fn doc_vectors(&self) -> DMatrix<f64> {
+        deserialize_matrix(&self.docs_ser)
+    }
+
+    pub fn effective_rank(&self, requested_k: Option<usize>) -> usize {
+        requested_k.map(|k| k.min(self.rank)).unwrap_or(self.rank)
+    }
+
+    pub fn get_u_k(&self, requested_k: Option<usize>) -> DMatrix<f64> {
+        let k = self.effective_rank(requested_k);
+        self.u_k().columns(0, k).into_owned()
+    }
+
+    pub fn get_doc_vectors(&self, requested_k: Option<usize>) -> DMatrix<f64> {
+        let k = self.effective_rank(requested_k);
+        self.doc_vectors().rows(0, k).into_owned()
+    }
+
+    /// A model of the first `rank` dimensions (all of them if there are fewer). The factors of
+    /// a lower-rank SVD are exactly these, so one run at the largest rank serves every smaller one.
+    pub fn truncated(&self, rank: usize) -> SvdData {
+        let rank = rank.min(self.rank);
+        let u = &self.u_ser;
+        SvdData {
+            rank,
+            sigma_k: self.sigma_k[..rank].to_vec(),
+            u_ser: SerMatrix {
+                nrows: u.nrows,
+                ncols: rank,
+                data: u.data.chunks(u.ncols.max(1)).flat_map(|row| &row[..rank]).copied().collect(),
+            },
+            vt_ser: first_rows(&self.vt_ser, rank),
+            docs_ser: first_rows(&self.docs_ser, rank),
+            doc_ids: self.doc_ids.clone(),
+        }
+    }
+}
+
+fn first_rows(m: &SerMatrix, rows: usize) -> SerMatrix {
+    SerMatrix {
+        nrows: rows,
+        ncols: m.ncols,
+        data: m.data[..rows * m.ncols].to_vec(),
+    }
+}
+
+pub fn serialize_matrix(m: &DMatrix<f64>) -> SerMatrix {
+    SerMatrix {
+        nrows: m.nrows(),
+        ncols: m.ncols(),
+        data: m.transpose().iter().cloned().collect(), // row-major, matching deserialize_matrix
+    }
+}
+
+pub fn deserialize_matrix(s: &SerMatrix) -> DMatrix<f64> {
+    DMatrix::from_row_slice(s.nrows, s.ncols, &s.data)
+}
\ No newline at end of file
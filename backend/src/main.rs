@@ -1,576 +1,2842 @@
-<<<<<<< Updated upstream
-mod util;
 use actix_cors::Cors;
-use actix_web::{web, App, HttpServer, HttpResponse, Responder};
-use std::sync::Arc;
-use std::path::Path;
+use search_engine::{util, Document, PreprocessedData, SerializableCsrMatrix, SvdData};
+use actix_web::{web, App, HttpRequest, HttpServer, HttpResponse, Responder};
+use actix_web::http::header;
+use actix_web::cookie::{Cookie, SameSite};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use std::path::{Path, PathBuf};
 use std::error::Error;
 use serde::{Serialize, Deserialize};
+use nalgebra::DVector;
 use nalgebra_sparse::CsrMatrix;
-use nalgebra::DMatrix;
-use actix_web::get;
-=======
-use std::sync::Arc;
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
-use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
-use crate::document::parser::parse_sqlite_documents;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use crate::matrix::SingularValueDecomposition;
-use std::path::Path;
-use std::mem;
-mod document;
-mod preprocessing;
-mod stemer;
-mod matrix;
-mod engine;
->>>>>>> Stashed changes
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct Document {
-    pub id: i64,
-    pub title: String,
-    pub url: String,
-    pub text: String,
-}
+use actix_web::{delete, get, post, put};
+use util::doc_set::DocSet;
+use util::query_cache::PreparedQuery;
+use util::query_classifier::QueryKind;
+use util::timing::Phase;
+use util::weighting::Weighting;
+use util::search::MatchedTerm;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 
-#[derive(Serialize, Deserialize)]
-struct PreprocessedData {
-    term_dict: std::collections::HashMap<String, usize>,
-    inverse_term_dict: std::collections::HashMap<usize, String>,
-    idf: Vec<f64>,
-    documents: Vec<Document>,
-<<<<<<< Updated upstream
-    term_doc_csr: SerializableCsrMatrix,
-=======
-    terms: Vec<String>,
-    tfidf_matrix: matrix::TfIdfMatrix,
-}
-
-#[derive(Serialize)]
-struct SearchResult {
-    id: i32,
-    score: f64,
-    title: String,
-    text: String,
->>>>>>> Stashed changes
-}
+const RESULT_SET_HEADER: &str = "X-Result-Set";
+const RESULT_SET_SIZE_HEADER: &str = "X-Result-Set-Size";
+const API_KEY_HEADER: &str = "X-API-Key";
+const SESSION_COOKIE: &str = "search_session";
 
-#[derive(Serialize, Deserialize)]
-struct SerMatrix {
-    nrows: usize,
-    ncols: usize,
-    data: Vec<f64>,
-}
+#[cfg(all(feature = "mimalloc", feature = "jemalloc"))]
+compile_error!("the `mimalloc` and `jemalloc` features are mutually exclusive");
 
-#[derive(Serialize, Deserialize)]
-struct SvdData {
-    rank: usize,
-    sigma_k: Vec<f64>,
-    u_ser: SerMatrix,
-    vt_ser: SerMatrix,
-    docs_ser: SerMatrix,
-}
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-#[derive(Serialize, Deserialize)]
-struct SerializableCsrMatrix {
-    nrows: usize,
-    ncols: usize,
-    row_offsets: Vec<usize>,
-    col_indices: Vec<usize>,
-    values: Vec<f64>,
-}
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+/// Index data and config are swapped atomically on reweight and reload, so requests never wait on
+/// a lock to read them and keep the snapshot they started with.
 struct AppState {
-    preprocessed_data: Arc<PreprocessedData>,
-    svd_data: Arc<SvdData>,
-    k: usize,
+    preprocessed_data: ArcSwap<PreprocessedData>,
+    svd_models: util::svd_catalog::SvdCatalog,
+    title_index: Arc<util::title_index::TitleIndex>,
+    document_ids: util::document_ids::DocumentIds,
+    entity_index: Arc<util::entities::EntityIndex>,
+    metadata_index: util::metadata::MetadataIndex,
+    exact_title_index: Arc<util::exact_index::ExactTitleIndex>,
+    title_terms: util::title_terms::TitleTermIndex,
+    config: ArcSwap<util::config::Config>,
+    result_sets: util::doc_set::ResultSetStore,
+    query_cache: util::query_cache::QueryCache,
+    scrolls: util::scroll::ScrollStore<SearchResult>,
+    admission: util::admission::AdmissionControl,
+    /// Rebuilt when the index is reweighted.
+    languages: ArcSwap<util::language::LanguagePartitions>,
+    /// Cross-language LSI model, when `languages.cross_language` is on and enough pairs exist.
+    cross_language: Option<Arc<SvdData>>,
+    query_likelihood: util::qlm::QueryLikelihood,
+    bm25: util::bm25::Bm25,
+    boost_rules: ArcSwap<util::boost_rules::BoostRules>,
+    blocklist: ArcSwap<util::blocklist::Blocklist>,
+    /// Held while the blocklist is edited, so concurrent edits don't drop each other's entries.
+    blocklist_edits: Mutex<()>,
+    views: ArcSwap<util::views::Views>,
+    /// Held while views are edited, so concurrent edits don't drop each other's changes.
+    view_edits: Mutex<()>,
+    view_stats: util::views::ViewStats,
+    /// Scoring speed of each method, for the latency estimates of dry runs.
+    cost_model: util::explain::CostModel,
+    /// Content policies every result passes before it is returned.
+    result_filters: util::result_filter::ResultFilters,
+    history: Option<util::history::SearchHistory>,
+    slow_queries: Option<util::slow_queries::SlowQueryLog>,
+    /// Trips when SVD models fail to load or score, taking the LSI methods out of service.
+    svd_breaker: util::circuit_breaker::CircuitBreaker,
+    /// SVD rebuilds and snapshots, run one at a time by a background worker.
+    jobs: Arc<util::jobs::JobQueue>,
+    term_suggester: util::suggest::TermSuggester,
+    autocomplete: util::autocomplete::Autocomplete,
+    query_suggester: util::suggest::QuerySuggester,
+    spell_corrector: util::spell::SpellCorrector,
+    analyzer: util::analyzer::Analyzer,
+    /// Replaced when the index is reweighted.
+    manifest: ArcSwapOption<util::manifest::IndexManifest>,
+    corpus_stats: Option<Arc<util::corpus_stats::CorpusStats>>,
+    /// SVD rank used when a request doesn't ask for one.
     noise_filter_k: usize,
 }
 
-<<<<<<< Updated upstream
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct SearchResult {
     score: f64,
     title: String,
     url: String,
+    /// Stable across database rebuilds; prefer it over `id` for links and bookmarks.
+    uuid: String,
     id: i64,
+    /// `id` as a string, for clients such as JavaScript that can't hold integers above 2^53 exactly.
+    id_str: String,
+    /// The whole text, as stored; not HTML-escaped.
     text: String,
+    /// For searches asking for `snippet`, an excerpt around the query terms, always HTML-escaped
+    /// and with matches in `<em>`. The only field meant to be rendered as HTML.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet_html: Option<String>,
+    /// A few of the text's key sentences, for previews; empty when the index has no summaries.
+    summary: String,
+    /// Listed ahead of the ranking because its title matches a navigational query.
+    title_match: bool,
+    keywords: Vec<String>,
+    entities: Vec<String>,
+    /// Analyzed query terms found in the document, for clients to highlight.
+    matched_terms: Vec<MatchedTerm>,
+    /// Where query terms occur in `text`, when the request asked for `highlight`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    highlights: Option<Vec<util::highlight::Span>>,
+    /// The document's lead image, when the scraper found one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+    /// The document's corpus-specific fields, when it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// The same shape for every ranking method, so clients don't branch on which one ran.
+#[derive(Serialize)]
+struct SearchResponse {
+    query: String,
+    method: &'static str,
+    /// The language partition the query was routed to, if any.
+    language: Option<String>,
+    /// The number of results asked for after clamping to `max_limit`.
+    k: usize,
+    /// Documents matching the query in scope, before truncating to `k`.
+    total_candidates: usize,
+    timings: SearchTimings,
+    /// `None` when the index has no manifest.
+    index_version: Option<String>,
+    results: Vec<SearchResult>,
+    /// Present when the request asked for `scroll: true`; pass it to `/search/scroll` for the next page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scroll_token: Option<String>,
+    /// Terms pseudo-relevance feedback added to the query, strongest first, when it asked for `expand`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expansion_terms: Option<Vec<String>>,
+    /// Where `results` sit in the ranking, for ranked searches that don't scroll.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<PageInfo>,
+}
+
+#[derive(Serialize)]
+struct PageInfo {
+    offset: usize,
+    /// 1-based, in pages of `k` results.
+    page: usize,
+    /// Offset of the next page, while more matches are within `search.max_result_window`.
+    next_offset: Option<usize>,
+}
+
+/// Milliseconds spent per phase of a search.
+#[derive(Serialize)]
+struct SearchTimings {
+    analyze_ms: f64,
+    score_ms: f64,
+    fetch_ms: f64,
+    /// Present when the request asked for `timings: true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phases: Option<util::timing::PhaseTimings>,
 }
 
 #[derive(Serialize)]
 struct StatsResponse {
     document_count: usize,
     vocabulary_size: usize,
+    /// Whether the LSI methods are in service.
+    svd: util::circuit_breaker::BreakerStatus,
+    /// Document texts in memory and read from disk, when tiering is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_tier: Option<util::tiering::TierStats>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize)]
+struct ReadinessResponse {
+    /// "ready", or "degraded" while some search methods fall back to TF-IDF.
+    status: &'static str,
+    svd: util::circuit_breaker::BreakerStatus,
+}
+
+#[derive(Serialize, Deserialize)]
 struct SearchRequest {
     query: String,
     limit: Option<usize>,
-    method: Option<u8>, // 2 = TF-IDF, 3 = SVD/LSI, 4 = Low-rank
-}
-
-impl SerializableCsrMatrix {
-    fn from_csr(csr: &CsrMatrix<f64>) -> Self {
-        SerializableCsrMatrix {
-            nrows: csr.nrows(),
-            ncols: csr.ncols(),
-            row_offsets: csr.row_offsets().to_vec(),
-            col_indices: csr.col_indices().to_vec(),
-            values: csr.values().to_vec(),
-=======
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    println!("Loading search index from cache...");
-
-    let (documents_arc, terms_arc, tfidf_matrix_arc, base_tfidf_matrix) = match load_cached_data(CACHED_DATA_PATH) {
-        Ok(cached_data) => {
-            println!("Successfully loaded search index from cache!");
-            let loaded_tfidf_matrix = cached_data.tfidf_matrix;
-            (
-                Arc::new(cached_data.documents),
-                Arc::new(cached_data.terms),
-                Arc::new(loaded_tfidf_matrix.clone()),
-                loaded_tfidf_matrix,
+    method: Option<MethodParam>,
+    exact: Option<bool>,
+    within: Option<String>, // result set token from a previous search's X-Result-Set header
+    filter: Option<String>,
+    params: Option<util::config::RankingOverrides>,
+    /// SVD rank for methods 3, 4 and 8; the closest available model is used.
+    k: Option<usize>,
+    /// ISO 639-1 code of the partition to search, or "auto" (the default) to detect it from the query.
+    language: Option<String>,
+    /// Whether other languages may fill up results; `languages.federate` when unset.
+    federate: Option<bool>,
+    /// Keep up to `max_scroll_window` results server-side and page through them with a scroll token.
+    scroll: Option<bool>,
+    /// Return the offsets of query-term matches in each result's text, for clients that
+    /// render highlighting themselves. The offsets refer to the whole text.
+    highlight: Option<bool>,
+    /// Also return an excerpt of about `search.snippet_chars` characters around the query terms
+    /// as each result's `snippet_html`.
+    snippet: Option<bool>,
+    /// Instead of ranking, return this many random documents from the scope (`within`, `filter`,
+    /// `language` and the query's terms, when it has any). Capped at `max_limit`.
+    sample: Option<usize>,
+    /// Seeds `sample`, so the same request over the same index draws the same documents.
+    seed: Option<u64>,
+    /// Only documents located in this area, ranked by text relevance as usual.
+    geo: Option<util::geo::GeoFilter>,
+    /// Only documents with these values of facet fields declared in `metadata.facets`.
+    metadata: Option<BTreeMap<String, String>>,
+    /// A named view defined with `PUT /admin/views/{name}`, narrowing the search further.
+    view: Option<String>,
+    /// Plan the search without running it: terms, postings to scan, indexes read and an
+    /// estimated scoring time.
+    dry_run: Option<bool>,
+    /// Pseudo-relevance feedback for TF-IDF: rank again with the query expanded by the strongest
+    /// terms of its top hits (`search.feedback_docs`, Rocchio weighting).
+    expand: Option<bool>,
+    /// Ranked results to skip; the same ranking gives the same pages.
+    offset: Option<usize>,
+    /// 1-based page of `limit` results, instead of `offset`.
+    page: Option<usize>,
+    /// Leave out results the method scores below this, before `score_normalization`. Scores
+    /// fall with rank, so a page it cuts short is the last.
+    min_score: Option<f64>,
+    /// "raw" (the default), "minmax" or "softmax", over the returned results.
+    score_normalization: Option<util::search::ScoreNormalization>,
+    /// Break `timings` down further, into analysis, candidate generation, scoring, sorting and
+    /// fetching.
+    timings: Option<bool>,
+    /// Rerank the top `search.mmr_candidates` results by maximal marginal relevance, so
+    /// near-duplicates give way to other relevant results.
+    diversify: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct SimilarRequest {
+    /// Example texts, e.g. abstracts of papers already found relevant.
+    #[serde(default)]
+    examples: Vec<String>,
+    /// Example documents in the index, by UUID or rowid; they are left out of the results.
+    #[serde(default)]
+    documents: Vec<String>,
+    /// Texts and documents on a subtopic to steer away from.
+    #[serde(default)]
+    negative_examples: Vec<String>,
+    #[serde(default)]
+    negative_documents: Vec<String>,
+    /// How strongly the negative examples are subtracted from the centroid, 0.5 when unset.
+    negative_weight: Option<f64>,
+    limit: Option<usize>,
+    /// 2 / "tfidf" (the default) or 3 / "svd" to compare in LSI space.
+    method: Option<MethodParam>,
+    /// SVD rank for method 3; the closest available model is used.
+    k: Option<usize>,
+    within: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SimilarityRequest {
+    /// Document UUIDs or rowids, at most `similarity::MAX_DOCUMENTS`.
+    ids: Vec<String>,
+    /// 2 / "tfidf" (the default) or 3 / "svd" for LSI vectors.
+    method: Option<MethodParam>,
+    /// SVD rank for method 3; the closest available model is used.
+    k: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SimilarityResponse {
+    method: &'static str,
+    /// UUIDs of the requested documents, the order of the matrix's rows and columns.
+    ids: Vec<String>,
+    /// Symmetric matrix of cosine similarities; `similarities[i][j]` compares `ids[i]` and `ids[j]`.
+    similarities: Vec<Vec<f64>>,
+}
+
+#[derive(Deserialize)]
+struct RescoreRequest {
+    query: String,
+    /// Candidates from another system, by UUID or rowid, at most `search.max_limit`.
+    ids: Vec<String>,
+    method: Option<MethodParam>,
+    /// SVD rank for methods 3, 4 and 8; the closest available model is used.
+    k: Option<usize>,
+    params: Option<util::config::RankingOverrides>,
+}
+
+#[derive(Serialize)]
+struct RescoreResponse {
+    query: String,
+    /// The method that scored the candidates; TF-IDF while the LSI methods are out of service.
+    method: &'static str,
+    index_version: Option<String>,
+    /// Every distinct candidate, best first.
+    results: Vec<RescoredDocument>,
+}
+
+#[derive(Serialize)]
+struct RescoredDocument {
+    uuid: String,
+    id: i64,
+    id_str: String,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct ScrollRequest {
+    scroll_token: String,
+}
+
+/// Search method as its number (2 = TF-IDF, 3 = SVD/LSI, 4 = Low-rank, 5 = query likelihood,
+/// 6 = BM25, 7 = cross-language LSI, 8 = TF-IDF and LSI blended) or name ("tfidf", "svd",
+/// "lowrank", "qlm", "bm25", "cllsi", "hybrid").
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MethodParam {
+    Code(u8),
+    Name(String),
+}
+
+impl MethodParam {
+    fn code(&self) -> Option<u8> {
+        match self {
+            MethodParam::Code(code) => Some(*code),
+            MethodParam::Name(name) => match name.as_str() {
+                "tfidf" => Some(2),
+                "svd" => Some(3),
+                "lowrank" => Some(4),
+                "qlm" => Some(5),
+                "bm25" => Some(6),
+                "cllsi" => Some(7),
+                "hybrid" => Some(8),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn method_name(code: u8) -> &'static str {
+    match code {
+        2 => "tfidf",
+        3 => "svd",
+        4 => "lowrank",
+        5 => "qlm",
+        6 => "bm25",
+        7 => "cllsi",
+        8 => "hybrid",
+        _ => "unknown",
+    }
+}
+
+fn elapsed_ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
+
+#[get("/stats")]
+async fn get_stats(data: web::Data<AppState>) -> impl Responder {
+    let pre = data.preprocessed_data.load_full();
+    HttpResponse::Ok().json(StatsResponse {
+        document_count: pre.documents.len(),
+        vocabulary_size: pre.term_dict.len(),
+        svd: data.svd_breaker.status(),
+        text_tier: pre.texts.as_ref().map(|texts| texts.stats()),
+    })
+}
+
+/// The server only listens once the index is loaded, so it is ready whenever it answers; a
+/// degraded search path is reported but still served by the fallback.
+#[get("/readyz")]
+async fn readyz(data: web::Data<AppState>) -> impl Responder {
+    let svd = data.svd_breaker.status();
+    let status = if svd.state == util::circuit_breaker::BreakerState::Closed { "ready" } else { "degraded" };
+    HttpResponse::Ok().json(ReadinessResponse { status, svd })
+}
+
+async fn search_handler(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<SearchRequest>,
+) -> impl Responder {
+    let Some(_slot) = data.admission.admit().await else {
+        let retry_after = data.config.load().server.retry_after_secs;
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after))
+            .body("Too many searches in progress; try again shortly");
+    };
+
+    // The rest of the search runs without yielding, so its phases are recorded on this thread.
+    let recording = req.timings.unwrap_or(false).then(util::timing::record);
+    let analyze_start = Instant::now();
+    let analyze_span = util::timing::span(Phase::Analyze);
+    let query = &req.query;
+    let (mut search_config, mut entity_boost, language_config) = {
+        let config = data.config.load();
+        (config.search.clone(), config.entities.boost, config.languages.clone())
+    };
+    if let Some(util::weighting::Weighting::Bm25 { k1, b }) = data.manifest.load().as_ref().and_then(|manifest| manifest.weighting) {
+        search_config.bm25_k1 = k1;
+        search_config.bm25_b = b;
+    }
+    if let Some(params) = &req.params
+        && let Err(e) = params.apply(&mut search_config, &mut entity_boost)
+    {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let top_k = req.limit.unwrap_or(search_config.default_limit).min(search_config.max_limit);
+    let scroll = req.scroll.unwrap_or(false);
+    let offset = match (req.offset, req.page) {
+        (Some(_), Some(_)) => return HttpResponse::BadRequest().body("Give offset or page, not both"),
+        (Some(offset), None) => offset,
+        (None, Some(0)) => return HttpResponse::BadRequest().body("page counts from 1"),
+        (None, Some(page)) => (page - 1).saturating_mul(top_k),
+        (None, None) => 0,
+    };
+    if offset > 0 && scroll {
+        return HttpResponse::BadRequest().body("A scroll pages with its token; offset and page don't apply");
+    }
+    if req.min_score.is_some_and(|min_score| !min_score.is_finite()) {
+        return HttpResponse::BadRequest().body("min_score must be a finite number");
+    }
+    if offset.saturating_add(top_k) > search_config.max_result_window {
+        return HttpResponse::BadRequest().body(format!("offset plus limit may be at most {}; use scroll to go deeper", search_config.max_result_window));
+    }
+    // A scroll ranks its whole window up front so later pages come from the same ranking. A page
+    // ranks everything before it, so it continues the previous one exactly.
+    let window = if scroll { top_k.max(search_config.max_scroll_window) } else { offset + top_k };
+    // A diversified page is picked from a deeper ranking, every pair of which is compared.
+    let diversify = req.diversify.unwrap_or(false);
+    if diversify && window > util::similarity::MAX_DOCUMENTS {
+        return HttpResponse::BadRequest().body(format!("diversify covers the first {} results", util::similarity::MAX_DOCUMENTS));
+    }
+    let depth = if diversify { window.max(search_config.mmr_candidates.min(util::similarity::MAX_DOCUMENTS)) } else { window };
+    let mut method = req.method.as_ref().map_or(Some(2), MethodParam::code); // Domyślnie TF-IDF
+    let pre = data.preprocessed_data.load_full();
+
+    let csr = pre.term_doc_csr.to_csr();
+
+    // Switching methods on the same query reuses its analysis and scope.
+    let blocklist = data.blocklist.load_full();
+    let views = data.views.load_full();
+    let cache_key = query_cache_key(&req, &language_config);
+    let prepared = match data.query_cache.get(&cache_key, &pre, &blocklist, &views) {
+        Some(prepared) => prepared,
+        None => match prepare_query(&data, &pre, &csr, &blocklist, &views, &req, &language_config) {
+            Ok(prepared) => data.query_cache.insert(cache_key, &pre, &blocklist, &views, prepared),
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        },
+    };
+    if let Some(name) = &req.view {
+        data.view_stats.record(name);
+    }
+    let query = prepared.ranking_query.as_ref().unwrap_or(query);
+    let within = &prepared.within;
+    let language_scope = &prepared.language_scope;
+    let query_vec = &prepared.query_vec();
+    let in_scope = |doc_idx: usize| within.as_ref().is_none_or(|set| set.contains(doc_idx));
+    let analyze_ms = elapsed_ms(analyze_start);
+    drop(analyze_span);
+
+    if let Some(n) = req.sample {
+        let mut scope = language_scope.clone().or_else(|| within.clone());
+        if !query_vec.iter().all(|&weight| weight == 0.0) {
+            let matching = DocSet::matching(query_vec, &csr);
+            match scope.as_mut() {
+                Some(set) => set.intersect_with(&matching),
+                None => scope = Some(matching),
+            }
+        }
+        let candidates = pre.documents.iter()
+            .enumerate()
+            .filter(|&(doc_idx, doc)| {
+                scope.as_ref().is_none_or(|set| set.contains(doc_idx)) && data.result_filters.allows(query, doc, &pre)
+            })
+            .map(|(_, doc)| doc);
+        let sample_start = Instant::now();
+        let sample_span = util::timing::span(Phase::Score);
+        let n = n.min(search_config.max_limit);
+        let (sample, total_candidates) = match req.seed {
+            Some(seed) => util::sampling::reservoir(candidates, n, &mut StdRng::seed_from_u64(seed)),
+            None => util::sampling::reservoir(candidates, n, &mut rand::rng()),
+        };
+        let score_ms = elapsed_ms(sample_start);
+        drop(sample_span);
+        let results = sample.into_iter()
+            .map(|doc| SearchResult {
+                score: 0.0,
+                title: doc.title.clone(),
+                url: doc.url.clone(),
+                image_url: doc.image_url.clone(),
+                metadata: util::metadata::parse(doc),
+                uuid: doc.uuid.clone(),
+                id: doc.id,
+                id_str: doc.id.to_string(),
+                text: pre.text(doc).into_owned(),
+                snippet_html: None,
+                summary: doc.summary.clone(),
+                title_match: false,
+                keywords: doc.keywords.clone(),
+                entities: doc.entities.clone(),
+                matched_terms: Vec::new(),
+                highlights: None,
+            })
+            .collect::<Vec<_>>();
+        let timings = SearchTimings {
+            analyze_ms,
+            score_ms,
+            fetch_ms: 0.0,
+            phases: recording.map(util::timing::Recording::finish),
+        };
+        log_if_slow(&data, &req, "sample", results.len(), &timings, elapsed_ms(analyze_start));
+        return HttpResponse::Ok().json(SearchResponse {
+            query: req.query.clone(),
+            method: "sample",
+            language: prepared.routed_language.clone(),
+            k: n,
+            total_candidates,
+            timings,
+            index_version: data.manifest.load().as_deref().map(util::manifest::IndexManifest::version),
+            results,
+            scroll_token: None,
+            expansion_terms: None,
+            page: None,
+        });
+    }
+
+    let score_start = Instant::now();
+    let score_span = util::timing::span(Phase::Score);
+    if !matches!(method, Some(2..=8)) {
+        return HttpResponse::BadRequest().body("Invalid search method. Use 2 (TF-IDF), 3 (SVD/LSI), 4 (Low-rank), 5 (\"qlm\", query likelihood), 6 (\"bm25\"), 7 (\"cllsi\", cross-language LSI), or 8 (\"hybrid\", TF-IDF and LSI blended)");
+    }
+    let expand = req.expand.unwrap_or(false);
+    if expand && method != Some(2) {
+        return HttpResponse::BadRequest().body("expand is only supported by TF-IDF (method 2)");
+    }
+    let svd_rank = req.k.unwrap_or(data.noise_filter_k);
+    // LSI methods are skipped for TF-IDF while their breaker is open.
+    if matches!(method, Some(3 | 4 | 7 | 8)) && !data.svd_breaker.allows() {
+        method = Some(2);
+    }
+    let svd = if matches!(method, Some(3 | 4 | 8)) {
+        match data.svd_models.closest_rank(svd_rank).map(|available| data.svd_models.get(available)) {
+            Some(Ok(svd)) => Some(svd),
+            Some(Err(e)) => {
+                data.svd_breaker.record_failure(&e.to_string());
+                method = Some(2);
+                None
+            }
+            None => return HttpResponse::NotFound().body("No SVD models are available"),
+        }
+    } else if method == Some(7) {
+        match &data.cross_language {
+            Some(model) => Some(model.clone()),
+            None => return HttpResponse::NotFound().body("No cross-language model; enable languages.cross_language and scrape interlanguage-linked articles"),
+        }
+    } else {
+        None
+    };
+    // A query routed to a language partition is ranked in its sub-index. Until the partition's
+    // own LSI model is built (queued here on first use), the LSI methods rank with the shared
+    // model within the partition's documents.
+    let languages = data.languages.load();
+    let partition = prepared.routed_language.as_deref().and_then(|language| Some((language, languages.get(language)?)));
+    let partition_svd = match partition {
+        Some((language, partition)) if matches!(method, Some(3 | 4 | 8)) => {
+            let model = partition.svd();
+            if model.is_none() && partition.max_rank() > 0 {
+                request_language_svd(&data, language, data.noise_filter_k.min(partition.max_rank()));
+            }
+            model
+        }
+        _ => None,
+    };
+    let partition = partition.map(|(_, partition)| partition);
+    let mut method_name = method.map_or("unknown", method_name);
+    let lsi_rank = svd.as_ref().map(|svd| match method {
+        Some(4) => svd_rank.min(svd.rank),
+        _ => svd.rank,
+    });
+    let mut work = util::explain::Work::estimate(method_name, query_vec, &csr, lsi_rank);
+    if req.dry_run.unwrap_or(false) {
+        let mut indexes = vec!["inverted".to_string()];
+        match (method, lsi_rank) {
+            (Some(7), Some(k)) => indexes.push(format!("cross-language lsi k={}", k)),
+            (_, Some(k)) => indexes.push(format!("lsi k={}", k)),
+            _ => {}
+        }
+        if req.exact.unwrap_or(false) {
+            indexes.push("exact title".to_string());
+        }
+        if search_config.title_boost > 0.0 {
+            indexes.push("title terms".to_string());
+        }
+        if !data.entity_index.matching_documents(query).is_empty() {
+            indexes.push("entity".to_string());
+        }
+        if matches!(util::query_classifier::classify(query, &data.title_index), QueryKind::Navigational) {
+            indexes.push("title".to_string());
+        }
+        let (estimated_ms, calibrated) = data.cost_model.estimate_ms(method_name, work);
+        return HttpResponse::Ok().json(util::explain::QueryPlan {
+            method: method_name,
+            terms: util::explain::term_costs(query_vec, &csr, &pre.inverse_term_dict),
+            unknown_terms: prepared.terms.iter()
+                .filter(|term| !pre.term_dict.contains_key(*term))
+                .cloned()
+                .collect(),
+            documents_in_scope: within.as_ref().map_or(pre.documents.len(), DocSet::count),
+            indexes,
+            work,
+            estimated_ms,
+            calibrated,
+        });
+    }
+    // Feedback documents come from the same scope the first ranking pass searches.
+    let expansion = expand.then(|| {
+        util::search::rocchio_expand(query_vec, &pre, &csr, language_scope.as_ref().or(within.as_ref()), &search_config)
+    });
+    let shared = RankIndex {
+        pre: &pre,
+        csr: &csr,
+        query_likelihood: &data.query_likelihood,
+        bm25: &data.bm25,
+        query_vec,
+        expansion: expansion.as_ref(),
+    };
+    // The query again, against the partition's vocabulary and IDF.
+    let partition_scope = partition.and_then(|partition| partition.local_scope(language_scope.as_ref().or(within.as_ref())));
+    let partition_query = partition.map(|partition| {
+        let query_vec = util::search::create_query_vector(query, &partition.index.term_dict, &partition.index.idf, &data.analyzer);
+        let expansion = expand.then(|| {
+            util::search::rocchio_expand(&query_vec, &partition.index, &partition.csr, partition_scope.as_ref(), &search_config)
+        });
+        (query_vec, expansion)
+    });
+    let query_terms: Vec<usize> = util::explain::query_terms(query_vec).collect();
+
+    let rank_all = |method: Option<u8>, svd: Option<&Arc<SvdData>>| -> Result<Vec<(&Document, f64)>, Box<dyn Error>> {
+        let in_partition = partition.zip(partition_query.as_ref())
+            .filter(|_| svd.is_none() || partition_svd.is_some());
+        let mut ranked = match in_partition {
+            Some((partition, (partition_vec, partition_expansion))) => {
+                let index = RankIndex {
+                    pre: &partition.index,
+                    csr: &partition.csr,
+                    query_likelihood: &partition.query_likelihood,
+                    bm25: &partition.bm25,
+                    query_vec: partition_vec,
+                    expansion: partition_expansion.as_ref(),
+                };
+                let ranked = rank_in(index, method, svd.and(partition_svd.as_ref()), partition_scope.as_ref(), query, &data.analyzer, &search_config, svd_rank, depth)?;
+                partition.to_shared(ranked, &pre, &query_terms, depth)
+            }
+            None => rank_in(shared, method, svd, language_scope.as_ref().or(within.as_ref()), query, &data.analyzer, &search_config, svd_rank, depth)?,
+        };
+        // Federated: other languages fill up what the partition can't.
+        if language_scope.is_some() && ranked.len() < depth {
+            for (doc, score) in rank_in(shared, method, svd, within.as_ref(), query, &data.analyzer, &search_config, svd_rank, depth)? {
+                if ranked.len() >= depth {
+                    break;
+                }
+                if !ranked.iter().any(|(hit, _)| hit.id == doc.id) {
+                    ranked.push((doc, score));
+                }
+            }
+        }
+        Ok(ranked)
+    };
+    let mut results = rank_all(method, svd.as_ref());
+
+    // A broken LSI model shows up as errors or NaN scores; such searches are answered by TF-IDF.
+    if svd.is_some() {
+        let failure = match &results {
+            Err(e) => Some(e.to_string()),
+            Ok(ranked) => ranked.iter().any(|(_, score)| !score.is_finite()).then(|| "non-finite scores".to_string()),
+        };
+        match failure {
+            Some(e) => {
+                data.svd_breaker.record_failure(&e);
+                method = Some(2);
+                method_name = "tfidf";
+                work = util::explain::Work::estimate(method_name, query_vec, &csr, None);
+                results = rank_all(method, None);
+            }
+            None => data.svd_breaker.record_success(),
+        }
+    }
+
+    // Case-folded ranking is combined with case-sensitive title matches when asked for.
+    let results = results.map(|ranked| {
+        if !req.exact.unwrap_or(false) {
+            return ranked;
+        }
+        let mut boosts = data.exact_title_index.boosts(query, search_config.exact_title_boost);
+        boosts.retain(|&doc_idx, _| in_scope(doc_idx));
+        util::search::apply_document_boosts(ranked, &boosts, query_vec, &pre, depth)
+    });
+
+    let results = results.map(|ranked| {
+        let mut boosts = data.title_terms.boosts(&prepared.terms, search_config.title_boost);
+        boosts.retain(|&doc_idx, _| in_scope(doc_idx));
+        util::search::apply_document_boosts(ranked, &boosts, query_vec, &pre, depth)
+    });
+
+    let boost_rules = data.boost_rules.load();
+    let results = results.map(|ranked| boost_rules.apply(ranked, &pre));
+
+    let results = results.map(|ranked| {
+        let boosts: HashMap<usize, f64> = data.entity_index.matching_documents(query)
+            .into_iter()
+            .filter(|&doc_idx| in_scope(doc_idx))
+            .map(|doc_idx| (doc_idx, entity_boost))
+            .collect();
+        util::search::apply_document_boosts(ranked, &boosts, query_vec, &pre, depth)
+    });
+
+    // Navigational queries get matching titles first, then the regular ranking fills the rest.
+    // Title hits keep the score the ranking gave them (0 if it didn't reach them), so
+    // `min_score` and normalization treat them like any other result; the response flags them.
+    let mut title_hit_ids: HashSet<i64> = HashSet::new();
+    let results = results.map(|ranked| {
+        let title_hits = match util::query_classifier::classify(query, &data.title_index) {
+            QueryKind::Navigational => data.title_index.lookup(query, depth)
+                .into_iter()
+                .filter(|&doc_idx| in_scope(doc_idx))
+                .collect(),
+            QueryKind::Informational => Vec::new(),
+        };
+
+        let documents = &pre.documents;
+        let ranked_scores: HashMap<i64, f64> = ranked.iter().map(|(doc, score)| (doc.id, *score)).collect();
+        let mut merged: Vec<(&Document, f64)> = title_hits.into_iter()
+            .map(|doc_idx| {
+                let doc = &documents[doc_idx];
+                (doc, ranked_scores.get(&doc.id).copied().unwrap_or(0.0))
+            })
+            .collect();
+        title_hit_ids.extend(merged.iter().map(|(doc, _)| doc.id));
+        for (doc, score) in ranked {
+            if merged.len() >= depth {
+                break;
+            }
+            if !merged.iter().any(|(hit, _)| hit.id == doc.id) {
+                merged.push((doc, score));
+            }
+        }
+        merged
+    });
+
+    let results = results.map(|ranked| data.result_filters.apply(query, ranked, &pre));
+
+    // Results are compared by their LSI vectors: the method's model, or the default one for the
+    // other methods. TF-IDF vectors stand in while no model is usable.
+    let results = results.map(|ranked| {
+        if !diversify || ranked.len() < 2 {
+            return ranked;
+        }
+        let Some(doc_indices) = ranked.iter()
+            .map(|(doc, _)| data.document_ids.resolve(&doc.id.to_string()))
+            .collect::<Option<Vec<usize>>>()
+        else {
+            return ranked;
+        };
+        let model = match method {
+            Some(3 | 4 | 7 | 8) => svd.clone(),
+            // Diversifying with LSI isn't a trial of the path, so it waits until the breaker closes.
+            _ if data.svd_breaker.status().state == util::circuit_breaker::BreakerState::Closed => data.svd_models.closest_rank(svd_rank)
+                .and_then(|available| data.svd_models.get(available).ok()),
+            _ => None,
+        };
+        let similarities = match &model {
+            Some(model) => util::similarity::lsi_matrix(model, &doc_indices),
+            None => util::similarity::tfidf_matrix(&pre, &doc_indices),
+        };
+        util::mmr::rerank(ranked, &similarities, search_config.mmr_lambda, window)
+    });
+
+    // Everything this query matched (within the current scope) becomes the next result set.
+    let mut result_set = match prepared.ranking_query {
+        // `within` already holds the boolean matches.
+        Some(_) => DocSet::full(pre.documents.len()),
+        None => DocSet::matching(expansion.as_ref().map_or(query_vec, |(expanded, _)| expanded), &csr),
+    };
+    if let Some(set) = &within {
+        result_set.intersect_with(set);
+    }
+    let result_set_size = result_set.count();
+    let result_set_token = data.result_sets.insert(result_set);
+
+    let mut results = match results {
+        Ok(results) => results,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    if let Some(min_score) = req.min_score {
+        results.retain(|&(_, score)| score >= min_score);
+    }
+    let page_full = results.len() >= window;
+    results.drain(..offset.min(results.len()));
+    util::search::normalize_scores(&mut results, req.score_normalization.unwrap_or_default());
+    let score_ms = elapsed_ms(score_start);
+    drop(score_span);
+    data.cost_model.observe(method_name, work, score_ms);
+
+    let fetch_start = Instant::now();
+    let fetch_span = util::timing::span(Phase::Fetch);
+    let hit_ids: HashSet<i64> = results.iter().map(|(doc, _)| doc.id).collect();
+    let mut matched_terms = util::search::matched_terms(query_vec, &csr, &pre, &hit_ids);
+    let highlight_terms: Option<HashSet<String>> = req.highlight.unwrap_or(false)
+        .then(|| prepared.terms.iter().cloned().collect());
+    let snippet_chars = search_config.snippet_chars;
+    let snippet_terms: Option<Vec<String>> = (req.snippet.unwrap_or(false) && snippet_chars > 0)
+        .then(|| prepared.terms.clone());
+    let results: Vec<SearchResult> = results.into_iter()
+        .map(|(doc, score)| {
+            let text = pre.text(doc).into_owned();
+            let snippet_html = snippet_terms.as_ref().map(|terms| {
+                util::snippet::snippet(&text, terms, &data.analyzer, snippet_chars).unwrap_or_else(|| {
+                    util::snippet::lead(if doc.summary.is_empty() { &text } else { &doc.summary }, snippet_chars)
+                })
+            });
+            SearchResult {
+                score,
+                title: doc.title.clone(),
+                url: doc.url.clone(),
+                image_url: doc.image_url.clone(),
+                metadata: util::metadata::parse(doc),
+                uuid: doc.uuid.clone(),
+                id: doc.id,
+                id_str: doc.id.to_string(),
+                highlights: highlight_terms.as_ref().map(|terms| util::highlight::spans(&text, terms, &data.analyzer)),
+                text,
+                snippet_html,
+                summary: doc.summary.clone(),
+                title_match: title_hit_ids.contains(&doc.id),
+                keywords: doc.keywords.clone(),
+                entities: doc.entities.clone(),
+                matched_terms: matched_terms.remove(&doc.id).unwrap_or_default(),
+            }
+        })
+        .collect();
+    let index_version = data.manifest.load().as_deref().map(util::manifest::IndexManifest::version);
+    let (results, scroll_token) = if scroll {
+        let (token, page) = data.scrolls.open(util::scroll::Scroll::new(
+            req.query.clone(),
+            method_name,
+            prepared.routed_language.clone(),
+            index_version.clone(),
+            result_set_size,
+            top_k,
+            results,
+        ));
+        (page, Some(token))
+    } else {
+        (results, None)
+    };
+    let expansion_terms = expansion.map(|(_, terms)| {
+        terms.iter().map(|term_idx| pre.inverse_term_dict[term_idx].clone()).collect()
+    });
+    let page = (!scroll).then(|| {
+        let next = offset + top_k;
+        PageInfo {
+            offset,
+            page: offset / top_k.max(1) + 1,
+            next_offset: (page_full && next < result_set_size && next + top_k <= search_config.max_result_window).then_some(next),
+        }
+    });
+    let fetch_ms = elapsed_ms(fetch_start);
+    drop(fetch_span);
+    let timings = SearchTimings { analyze_ms, score_ms, fetch_ms, phases: recording.map(util::timing::Recording::finish) };
+    log_if_slow(&data, &req, method_name, results.len(), &timings, elapsed_ms(analyze_start));
+
+    let mut response = HttpResponse::Ok();
+    if let Some(history) = &data.history {
+        let (session, new_cookie) = match session_id(&http_req) {
+            Some(session) => (session, None),
+            None => {
+                let id = format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>());
+                (format!("cookie:{}", id), Some(id))
+            }
+        };
+        if let Err(e) = history.record(&session, &req.query, method, results.len()) {
+            log::error!("Failed to record search history: {}", e);
+        }
+        data.query_suggester.record(&req.query, util::history::unix_now());
+        if let Some(id) = new_cookie {
+            response.cookie(
+                Cookie::build(SESSION_COOKIE, id)
+                    .path("/")
+                    .http_only(true)
+                    .same_site(SameSite::Lax)
+                    .finish(),
+            );
+        }
+    }
+
+    response
+        .insert_header((RESULT_SET_HEADER, result_set_token))
+        .insert_header((RESULT_SET_SIZE_HEADER, result_set_size))
+        .json(SearchResponse {
+            query: req.query.clone(),
+            method: method_name,
+            language: prepared.routed_language.clone(),
+            k: top_k,
+            total_candidates: result_set_size,
+            timings,
+            index_version,
+            results,
+            scroll_token,
+            expansion_terms,
+            page,
+        })
+}
+
+/// The request fields a prepared query depends on, besides the index, blocklist and views.
+fn query_cache_key(req: &SearchRequest, language_config: &util::language::LanguageConfig) -> String {
+    serde_json::json!({
+        "query": req.query,
+        "within": req.within,
+        "filter": req.filter,
+        "geo": req.geo,
+        "metadata": req.metadata,
+        "view": req.view,
+        "language": req.language,
+        "federate": req.federate,
+        "languages": [language_config.enabled, language_config.federate],
+    }).to_string()
+}
+
+/// An index a search ranks in: the shared one or a language partition's, with the query vector
+/// (and its expansion, if asked for) against that index's vocabulary.
+#[derive(Clone, Copy)]
+struct RankIndex<'a> {
+    pre: &'a PreprocessedData,
+    csr: &'a CsrMatrix<f64>,
+    query_likelihood: &'a util::qlm::QueryLikelihood,
+    bm25: &'a util::bm25::Bm25,
+    query_vec: &'a DVector<f64>,
+    expansion: Option<&'a (DVector<f64>, Vec<usize>)>,
+}
+
+/// Ranks the best `depth` documents of `scope` in `index` by `method`.
+#[allow(clippy::too_many_arguments)]
+fn rank_in<'a>(
+    index: RankIndex<'a>,
+    method: Option<u8>,
+    svd: Option<&Arc<SvdData>>,
+    scope: Option<&DocSet>,
+    query: &'a str,
+    analyzer: &util::analyzer::Analyzer,
+    config: &util::config::SearchConfig,
+    svd_rank: usize,
+    depth: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    match (method, svd) {
+        (Some(3 | 7), Some(svd)) => {
+            // SVD/LSI search, in the joint space of both languages for method 7
+            util::search::search_svd(
+                query,
+                index.pre,
+                analyzer,
+                svd,
+                config,
+                scope,
+                depth,
             )
         }
+        (Some(4), Some(svd)) => {
+            // Low-rank approximation with noise filtering
+            util::search::search_with_low_rank(
+                query,
+                index.pre,
+                analyzer,
+                svd,
+                Some(svd_rank),
+                config,
+                scope,
+                depth,
+            )
+        }
+        (Some(8), Some(svd)) => {
+            // TF-IDF and LSI cosines blended by `search.hybrid_alpha`
+            util::search::search_hybrid(
+                query,
+                index.pre,
+                analyzer,
+                index.csr,
+                svd,
+                config,
+                scope,
+                depth,
+            )
+        }
+        (Some(5), _) => {
+            // Query likelihood with Dirichlet smoothing
+            util::search::search_qlm(
+                query,
+                index.pre,
+                analyzer,
+                index.query_likelihood,
+                config,
+                scope,
+                depth,
+            )
+        }
+        (Some(6), _) => {
+            // BM25 over raw counts
+            util::search::search_bm25(
+                query,
+                index.pre,
+                analyzer,
+                index.bm25,
+                config,
+                scope,
+                depth,
+            )
+        }
+        _ => match index.expansion {
+            // TF-IDF with the query expanded by pseudo-relevance feedback
+            Some((expanded, _)) => util::search::search_expanded(index.query_vec, expanded, index.pre, index.csr, scope, depth),
+            // Standard TF-IDF search
+            None => util::search::search(
+                query,
+                index.pre,
+                analyzer,
+                index.csr,
+                scope,
+                depth,
+            ),
+        },
+    }
+}
+
+/// Works out the scope a search runs in and analyzes its query, or why it is rejected.
+fn prepare_query(
+    data: &AppState,
+    pre: &PreprocessedData,
+    csr: &CsrMatrix<f64>,
+    blocklist: &util::blocklist::Blocklist,
+    views: &util::views::Views,
+    req: &SearchRequest,
+    language_config: &util::language::LanguageConfig,
+) -> Result<PreparedQuery, String> {
+    let _candidates = util::timing::span(Phase::Candidates);
+    let query = &req.query;
+    // Narrow the search to a previous result set and/or the documents matching a filter query.
+    let mut within = match &req.within {
+        Some(token) => match data.result_sets.get(token) {
+            Some(set) => Some(DocSet::clone(&set)),
+            None => return Err("Unknown or expired result set token".to_string()),
+        },
+        None => None,
+    };
+    narrow_scope(data, pre, csr, &mut within, req.filter.as_deref(), req.geo.as_ref(), req.metadata.as_ref())?;
+    // A boolean query selects the candidates; its words outside a NOT rank them.
+    let ranking_query = if util::boolean_query::is_boolean(query) {
+        let expr = util::boolean_query::parse(query)?;
+        let set = expr.evaluate(&data.analyzer, &pre.term_dict, csr)?;
+        match within.as_mut() {
+            Some(within) => within.intersect_with(&set),
+            None => within = Some(set),
+        }
+        Some(expr.positive_words().join(" "))
+    } else {
+        None
+    };
+    let query = ranking_query.as_ref().unwrap_or(query);
+    let view = match &req.view {
+        Some(name) => match views.get(name) {
+            Some(view) => Some(view.clone()),
+            None => return Err(format!("Unknown view {:?}", name)),
+        },
+        None => None,
+    };
+    if let Some(view) = &view {
+        narrow_scope(data, pre, csr, &mut within, view.filter.as_deref(), view.geo.as_ref(), Some(&view.metadata))?;
+    }
+
+    within = blocklist.exclude_from(within, pre.documents.len());
+
+    // Route the query to its language's partition. Without federation the partition is the whole
+    // scope; with it, other languages only fill up what the partition can't. A view's language
+    // is the whole scope.
+    let view_language = view.as_ref().and_then(|view| view.language.as_deref());
+    let language = match req.language.as_deref() {
+        _ if !language_config.enabled => None,
+        Some(language) if view_language.is_some_and(|view_language| view_language != language && language != "auto") => {
+            return Err(format!("The view only covers language {:?}", view_language.unwrap_or_default()));
+        }
+        None | Some("auto") if view_language.is_some() => view_language,
+        None | Some("auto") => util::language::detect(query),
+        Some(language) => Some(language),
+    };
+    let federate = view_language.is_none() && req.federate.unwrap_or(language_config.federate);
+    let mut language_scope = None;
+    let routed_language = language.and_then(|language| {
+        let mut scope = data.languages.load().documents(language)?.clone();
+        if let Some(set) = &within {
+            scope.intersect_with(set);
+        }
+        if federate {
+            language_scope = Some(scope);
+        } else {
+            within = Some(scope);
+        }
+        Some(language.to_string())
+    });
+
+    let _analyze = util::timing::span(Phase::Analyze);
+    let query_vec = util::search::create_query_vector(
+        query,
+        &pre.term_dict,
+        &pre.idf,
+        &data.analyzer,
+    );
+    Ok(PreparedQuery {
+        terms: data.analyzer.analyze(query),
+        query_weights: PreparedQuery::sparse_weights(&query_vec),
+        num_terms: query_vec.len(),
+        within,
+        language_scope,
+        routed_language,
+        ranking_query,
+    })
+}
+
+/// Records the search in the slow-query log when it took longer than `slow_queries.threshold_ms`.
+fn log_if_slow(data: &AppState, req: &SearchRequest, method: &str, result_count: usize, timings: &SearchTimings, total_ms: f64) {
+    let Some(log) = &data.slow_queries else {
+        return;
+    };
+    let threshold_ms = data.config.load().slow_queries.threshold_ms;
+    if total_ms <= threshold_ms {
+        return;
+    }
+    log::warn!("Slow query ({:.0} ms, {}): {:?}", total_ms, method, req.query);
+    let entry = util::slow_queries::SlowQuery {
+        query: req.query.clone(),
+        method: method.to_string(),
+        request: serde_json::to_value(req).unwrap_or_default(),
+        result_count,
+        analyze_ms: timings.analyze_ms,
+        score_ms: timings.score_ms,
+        fetch_ms: timings.fetch_ms,
+        total_ms,
+        logged_at: util::history::unix_now(),
+    };
+    if let Err(e) = log.record(&entry) {
+        log::error!("Failed to record slow query: {}", e);
+    }
+}
+
+/// "Find documents like these": ranks documents by similarity to the centroid of several example
+/// texts and/or indexed documents, optionally steered away from negative examples.
+#[post("/search/similar")]
+async fn search_similar(data: web::Data<AppState>, req: web::Json<SimilarRequest>) -> impl Responder {
+    let Some(_slot) = data.admission.admit().await else {
+        let retry_after = data.config.load().server.retry_after_secs;
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after))
+            .body("Too many searches in progress; try again shortly");
+    };
+    if req.examples.is_empty() && req.documents.is_empty() {
+        return HttpResponse::BadRequest().body("Give at least one example text or document");
+    }
+
+    let analyze_start = Instant::now();
+    let search_config = data.config.load().search.clone();
+    let top_k = req.limit.unwrap_or(search_config.default_limit).min(search_config.max_limit);
+    let method = req.method.as_ref().map_or(Some(2), MethodParam::code);
+    let pre = data.preprocessed_data.load_full();
+    let csr = pre.term_doc_csr.to_csr();
+
+    let negative_weight = req.negative_weight.unwrap_or(util::search::DEFAULT_NEGATIVE_WEIGHT);
+    if !negative_weight.is_finite() || negative_weight < 0.0 {
+        return HttpResponse::BadRequest().body("negative_weight must be a non-negative number");
+    }
+
+    let mut example_docs = HashSet::new();
+    let mut vectors_of = |texts: &[String], ids: &[String]| {
+        let mut vecs = Vec::new();
+        for id in ids {
+            let doc_idx = data.document_ids.resolve(id).ok_or_else(|| format!("Document {} not found", id))?;
+            let doc = &pre.documents[doc_idx];
+            example_docs.insert(doc_idx);
+            vecs.push(util::search::create_query_vector(&format!("{} {}", doc.title, pre.text(doc)), &pre.term_dict, &pre.idf, &data.analyzer));
+        }
+        for text in texts {
+            vecs.push(util::search::create_query_vector(text, &pre.term_dict, &pre.idf, &data.analyzer));
+        }
+        Ok::<_, String>(vecs)
+    };
+    let (positive_vecs, negative_vecs) = match (
+        vectors_of(&req.examples, &req.documents),
+        vectors_of(&req.negative_examples, &req.negative_documents),
+    ) {
+        (Ok(positive), Ok(negative)) => (positive, negative),
+        (Err(e), _) | (_, Err(e)) => return HttpResponse::NotFound().body(e),
+    };
+
+    let within = match &req.within {
+        Some(token) => match data.result_sets.get(token) {
+            Some(set) => Some(DocSet::clone(&set)),
+            None => return HttpResponse::BadRequest().body("Unknown or expired result set token"),
+        },
+        None => None,
+    };
+    let within = data.blocklist.load().exclude_from(within, pre.documents.len());
+    let analyze_ms = elapsed_ms(analyze_start);
+
+    let score_start = Instant::now();
+    let svd = match method {
+        Some(2) => None,
+        Some(3) => match data.svd_models.closest_rank(req.k.unwrap_or(data.noise_filter_k)).map(|available| data.svd_models.get(available)) {
+            Some(Ok(svd)) => Some(svd),
+            Some(Err(e)) => return HttpResponse::InternalServerError().body(e.to_string()),
+            None => return HttpResponse::NotFound().body("No SVD models are available"),
+        },
+        _ => return HttpResponse::BadRequest().body("Invalid method. Use 2 (TF-IDF) or 3 (SVD/LSI)"),
+    };
+    let (ranked, total_candidates) = util::search::search_by_examples(&positive_vecs, &negative_vecs, negative_weight, &pre, &csr, svd.as_deref(), within.as_ref(), &example_docs, top_k);
+    let ranked = data.result_filters.apply("", ranked, &pre);
+    let score_ms = elapsed_ms(score_start);
+
+    let fetch_start = Instant::now();
+    let results: Vec<SearchResult> = ranked.into_iter()
+        .map(|(doc, score)| SearchResult {
+            score,
+            title: doc.title.clone(),
+            url: doc.url.clone(),
+            image_url: doc.image_url.clone(),
+            metadata: util::metadata::parse(doc),
+            uuid: doc.uuid.clone(),
+            id: doc.id,
+            id_str: doc.id.to_string(),
+            text: pre.text(doc).into_owned(),
+            snippet_html: None,
+            summary: doc.summary.clone(),
+            title_match: false,
+            keywords: doc.keywords.clone(),
+            entities: doc.entities.clone(),
+            matched_terms: Vec::new(),
+            highlights: None,
+        })
+        .collect();
+    let fetch_ms = elapsed_ms(fetch_start);
+
+    HttpResponse::Ok().json(SearchResponse {
+        query: String::new(),
+        method: method.map_or("unknown", method_name),
+        language: None,
+        k: top_k,
+        total_candidates,
+        timings: SearchTimings { analyze_ms, score_ms, fetch_ms, phases: None },
+        index_version: data.manifest.load().as_deref().map(util::manifest::IndexManifest::version),
+        results,
+        scroll_token: None,
+        expansion_terms: None,
+        page: None,
+    })
+}
+
+/// Pairwise cosine similarities of a set of documents, e.g. a page of results to cluster or plot.
+#[post("/documents/similarity")]
+async fn document_similarity(data: web::Data<AppState>, req: web::Json<SimilarityRequest>) -> impl Responder {
+    if req.ids.len() > util::similarity::MAX_DOCUMENTS {
+        return HttpResponse::BadRequest().body(format!("At most {} documents at a time", util::similarity::MAX_DOCUMENTS));
+    }
+    let pre = data.preprocessed_data.load_full();
+    let mut doc_indices = Vec::with_capacity(req.ids.len());
+    for id in &req.ids {
+        match data.document_ids.resolve(id) {
+            Some(doc_idx) => doc_indices.push(doc_idx),
+            None => return HttpResponse::NotFound().body(format!("Document {} not found", id)),
+        }
+    }
+
+    let method = req.method.as_ref().map_or(Some(2), MethodParam::code);
+    let similarities = match method {
+        Some(2) => util::similarity::tfidf_matrix(&pre, &doc_indices),
+        Some(3) => match data.svd_models.closest_rank(req.k.unwrap_or(data.noise_filter_k)).map(|available| data.svd_models.get(available)) {
+            Some(Ok(svd)) => util::similarity::lsi_matrix(&svd, &doc_indices),
+            Some(Err(e)) => return HttpResponse::InternalServerError().body(e.to_string()),
+            None => return HttpResponse::NotFound().body("No SVD models are available"),
+        },
+        _ => return HttpResponse::BadRequest().body("Invalid method. Use 2 (TF-IDF) or 3 (SVD/LSI)"),
+    };
+
+    HttpResponse::Ok().json(SimilarityResponse {
+        method: method.map_or("unknown", method_name),
+        ids: doc_indices.iter().map(|&doc_idx| pre.documents[doc_idx].uuid.clone()).collect(),
+        similarities,
+    })
+}
+
+/// Scores exactly the given documents for a query, for when another system generates the
+/// candidates and this index ranks them. Candidates the method doesn't match still get its score
+/// for a document without the query's terms. No boosts, filters or blocklist apply.
+#[post("/search/rescore")]
+async fn rescore(data: web::Data<AppState>, req: web::Json<RescoreRequest>) -> impl Responder {
+    let Some(_slot) = data.admission.admit().await else {
+        let retry_after = data.config.load().server.retry_after_secs;
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after))
+            .body("Too many searches in progress; try again shortly");
+    };
+    let (mut search_config, mut entity_boost) = {
+        let config = data.config.load();
+        (config.search.clone(), config.entities.boost)
+    };
+    if let Some(params) = &req.params
+        && let Err(e) = params.apply(&mut search_config, &mut entity_boost)
+    {
+        return HttpResponse::BadRequest().body(e);
+    }
+    if req.ids.len() > search_config.max_limit {
+        return HttpResponse::BadRequest().body(format!("At most {} documents at a time", search_config.max_limit));
+    }
+
+    let pre = data.preprocessed_data.load_full();
+    let mut candidates = DocSet::empty(pre.documents.len());
+    let mut doc_indices = Vec::with_capacity(req.ids.len());
+    for id in &req.ids {
+        match data.document_ids.resolve(id) {
+            Some(doc_idx) if !candidates.contains(doc_idx) => {
+                candidates.insert(doc_idx);
+                doc_indices.push(doc_idx);
+            }
+            Some(_) => {}
+            None => return HttpResponse::NotFound().body(format!("Document {} not found", id)),
+        }
+    }
+
+    let mut method = req.method.as_ref().map_or(Some(2), MethodParam::code);
+    if !matches!(method, Some(2..=8)) {
+        return HttpResponse::BadRequest().body("Invalid search method. Use 2 (TF-IDF), 3 (SVD/LSI), 4 (Low-rank), 5 (\"qlm\", query likelihood), 6 (\"bm25\"), 7 (\"cllsi\", cross-language LSI), or 8 (\"hybrid\", TF-IDF and LSI blended)");
+    }
+    let svd_rank = req.k.unwrap_or(data.noise_filter_k);
+    if matches!(method, Some(3 | 4 | 7 | 8)) && !data.svd_breaker.allows() {
+        method = Some(2);
+    }
+    let svd = match method {
+        Some(3 | 4 | 8) => match data.svd_models.closest_rank(svd_rank).map(|available| data.svd_models.get(available)) {
+            Some(Ok(svd)) => Some(svd),
+            Some(Err(e)) => {
+                data.svd_breaker.record_failure(&e.to_string());
+                method = Some(2);
+                None
+            }
+            None => return HttpResponse::NotFound().body("No SVD models are available"),
+        },
+        Some(7) => match &data.cross_language {
+            Some(model) => Some(model.clone()),
+            None => return HttpResponse::NotFound().body("No cross-language model; enable languages.cross_language and scrape interlanguage-linked articles"),
+        },
+        _ => None,
+    };
+
+    let query = &req.query;
+    let top_k = doc_indices.len();
+    let csr = pre.term_doc_csr.to_csr();
+    let ranked = match (method, &svd) {
+        (Some(3 | 7), Some(svd)) => util::search::search_svd(query, &pre, &data.analyzer, svd, &search_config, Some(&candidates), top_k),
+        (Some(4), Some(svd)) => util::search::search_with_low_rank(query, &pre, &data.analyzer, svd, Some(svd_rank), &search_config, Some(&candidates), top_k),
+        (Some(8), Some(svd)) => util::search::search_hybrid(query, &pre, &data.analyzer, &csr, svd, &search_config, Some(&candidates), top_k),
+        (Some(5), _) => util::search::search_qlm(query, &pre, &data.analyzer, &data.query_likelihood, &search_config, Some(&candidates), top_k),
+        (Some(6), _) => util::search::search_bm25(query, &pre, &data.analyzer, &data.bm25, &search_config, Some(&candidates), top_k),
+        _ => util::search::search(query, &pre, &data.analyzer, &csr, Some(&candidates), top_k),
+    };
+    let ranked = match ranked {
+        Ok(ranked) => {
+            if svd.is_some() {
+                data.svd_breaker.record_success();
+            }
+            ranked
+        }
         Err(e) => {
-            println!("Failed to load from cache (Reason: {}). Rebuilding...", e);
-            println!("Loading documents and building search index...");
-
-            let documents = parse_sqlite_documents("data/articles.db")
-                .expect("Failed to read from SQLite");
-
-            let stop_words_path = "stop_words/english.txt";
-            let stop_words = preprocessing::tokenizer::load_stop_words(stop_words_path)
-                .unwrap_or_else(|err| {
-                    eprintln!(
-                        "Warning: Failed to load stop-words from {}: {}. Continuing without stop-words.",
-                        stop_words_path, err
-                    );
-                    std::collections::HashSet::new()
-                });
-
-            let terms_map = preprocessing::tokenizer::build_vocabulary(&documents, &stop_words);
-            let mut terms_vec: Vec<String> = terms_map.keys().cloned().collect();
-            terms_vec.sort_unstable();
-
-            let new_tfidf_matrix = matrix::TfIdfMatrix::build(&documents, &terms_map);
-
-            println!("Search index built successfully!");
-            println!("Documents: {}", documents.len());
-            println!("Vocabulary size: {}", terms_vec.len());
-
-            let data_to_cache = CachedData {
-                documents: documents.clone(),
-                terms: terms_vec.clone(),
-                tfidf_matrix: new_tfidf_matrix.clone(),
-            };
-
-            if let Err(save_err) = save_cached_data(&data_to_cache, CACHED_DATA_PATH) {
-                eprintln!("Error saving index to cache: {}", save_err);
-            } else {
-                println!("Search index saved to cache: {}", CACHED_DATA_PATH);
+            if svd.is_some() {
+                data.svd_breaker.record_failure(&e.to_string());
             }
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    let mut results: Vec<RescoredDocument> = ranked.iter()
+        .map(|&(doc, score)| RescoredDocument { uuid: doc.uuid.clone(), id: doc.id, id_str: doc.id.to_string(), score })
+        .collect();
+    let scored: HashSet<i64> = results.iter().map(|result| result.id).collect();
+    let query_terms = (method == Some(5)).then(|| util::search::query_term_counts(query, &pre.term_dict, &data.analyzer));
+    let unmatched = doc_indices.iter()
+        .filter(|&&doc_idx| !scored.contains(&pre.documents[doc_idx].id))
+        .map(|&doc_idx| {
+            let doc = &pre.documents[doc_idx];
+            let score = query_terms.as_ref()
+                .map_or(0.0, |terms| data.query_likelihood.unmatched_score(terms, search_config.qlm_mu, doc_idx));
+            RescoredDocument { uuid: doc.uuid.clone(), id: doc.id, id_str: doc.id.to_string(), score }
+        });
+    results.extend(unmatched);
+    // Stable, so the ranking's tie-breaking holds; unmatched query-likelihood scores can be
+    // higher than some matched ones.
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    HttpResponse::Ok().json(RescoreResponse {
+        query: req.query.clone(),
+        method: method.map_or("unknown", method_name),
+        index_version: data.manifest.load().as_deref().map(util::manifest::IndexManifest::version),
+        results,
+    })
+}
+
+/// The next page of a scroll opened by a `scroll: true` search, ranked against the index as it was
+/// then. Past the end the page is empty.
+#[post("/search/scroll")]
+async fn scroll_search(data: web::Data<AppState>, req: web::Json<ScrollRequest>) -> impl Responder {
+    let fetch_start = Instant::now();
+    let response = data.scrolls.with_scroll(&req.scroll_token, |scroll| {
+        let results = scroll.next_page();
+        SearchResponse {
+            query: scroll.query.clone(),
+            method: scroll.method,
+            language: scroll.language.clone(),
+            k: scroll.page_size,
+            total_candidates: scroll.total_candidates,
+            timings: SearchTimings { analyze_ms: 0.0, score_ms: 0.0, fetch_ms: elapsed_ms(fetch_start), phases: None },
+            index_version: scroll.index_version.clone(),
+            results,
+            scroll_token: Some(req.scroll_token.clone()),
+            expansion_terms: None,
+            page: None,
+        }
+    });
+    match response {
+        Some(response) => HttpResponse::Ok().json(response),
+        None => HttpResponse::NotFound().body("Unknown or expired scroll token"),
+    }
+}
+
+#[delete("/search/scroll/{token}")]
+async fn close_scroll(data: web::Data<AppState>, token: web::Path<String>) -> impl Responder {
+    if data.scrolls.close(&token) {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body("Unknown or expired scroll token")
+    }
+}
+
+/// History is scoped to the API key when the client sends one, otherwise to the session cookie.
+fn session_id(req: &HttpRequest) -> Option<String> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+        return Some(format!("key:{}", key));
+    }
+    req.cookie(SESSION_COOKIE).map(|cookie| format!("cookie:{}", cookie.value()))
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    limit: Option<usize>,
+}
+
+#[get("/history")]
+async fn get_history(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    let Some(history) = &data.history else {
+        return HttpResponse::NotFound().body("Search history is disabled");
+    };
+    let Some(session) = session_id(&http_req) else {
+        return HttpResponse::Ok().json(Vec::<util::history::HistoryEntry>::new());
+    };
+
+    match history.recent(&session, query.limit.unwrap_or(20)) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct SuggestQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SuggestResponse {
+    queries: Vec<util::suggest::QuerySuggestion>,
+    terms: Vec<util::suggest::TermSuggestion>,
+    corrections: Vec<util::spell::Correction>,
+}
+
+/// Completions for a partially typed query: popular past queries starting with it,
+/// vocabulary terms completing its last word, and spelling corrections for unknown words.
+#[get("/suggest")]
+async fn suggest(data: web::Data<AppState>, query: web::Query<SuggestQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(10);
+    HttpResponse::Ok().json(SuggestResponse {
+        queries: data.query_suggester.suggest(&query.q, util::history::unix_now(), limit),
+        terms: data.term_suggester.suggest(&query.q, limit),
+        corrections: data.spell_corrector.correct_query(&query.q),
+    })
+}
+
+#[derive(Deserialize)]
+struct AutocompleteQuery {
+    prefix: String,
+    kind: Option<util::autocomplete::CompletionKind>,
+    limit: Option<usize>,
+}
+
+/// The most frequent vocabulary terms and document titles starting with `prefix`.
+#[get("/autocomplete")]
+async fn autocomplete(data: web::Data<AppState>, query: web::Query<AutocompleteQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(10);
+    HttpResponse::Ok().json(data.autocomplete.complete(&query.prefix, query.kind, limit))
+}
+
+#[derive(Serialize)]
+struct HistoryDeleteResponse {
+    deleted: usize,
+}
+
+#[delete("/history")]
+async fn delete_history(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    let Some(history) = &data.history else {
+        return HttpResponse::NotFound().body("Search history is disabled");
+    };
+    let Some(session) = session_id(&http_req) else {
+        return HttpResponse::Ok().json(HistoryDeleteResponse { deleted: 0 });
+    };
+
+    match history.clear(&session) {
+        Ok(deleted) => HttpResponse::Ok().json(HistoryDeleteResponse { deleted }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/document/{id}")]
+async fn get_document(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let pre = data.preprocessed_data.load_full();
+
+    if let Some(doc_idx) = data.document_ids.resolve(&id) {
+        let doc = &pre.documents[doc_idx];
+        HttpResponse::Ok().json(SearchResult {
+            score: 0.0,
+            title: doc.title.clone(),
+            url: doc.url.clone(),
+            image_url: doc.image_url.clone(),
+            metadata: util::metadata::parse(doc),
+            uuid: doc.uuid.clone(),
+            id: doc.id,
+            id_str: doc.id.to_string(),
+            text: pre.text(doc).into_owned(),
+            snippet_html: None,
+            summary: doc.summary.clone(),
+            title_match: false,
+            keywords: doc.keywords.clone(),
+            entities: doc.entities.clone(),
+            matched_terms: Vec::new(),
+            highlights: None,
+        })
+    } else {
+        HttpResponse::NotFound().body("Document not found")
+    }
+}
+
+#[derive(Deserialize)]
+struct MoreLikeThisQuery {
+    limit: Option<usize>,
+    /// "svd" (or 3, the default) to compare LSI vectors, "tfidf" (or 2) for TF-IDF columns. LSI
+    /// falls back to TF-IDF while no model is usable.
+    method: Option<String>,
+    /// SVD rank; the closest available model is used.
+    k: Option<usize>,
+    within: Option<String>,
+}
+
+/// Documents like this one, e.g. for "related articles".
+#[get("/document/{id}/similar")]
+async fn more_like_this(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<MoreLikeThisQuery>,
+) -> impl Responder {
+    let Some(_slot) = data.admission.admit().await else {
+        let retry_after = data.config.load().server.retry_after_secs;
+        return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after))
+            .body("Too many searches in progress; try again shortly");
+    };
+
+    let analyze_start = Instant::now();
+    let search_config = data.config.load().search.clone();
+    let top_k = query.limit.unwrap_or(search_config.default_limit).min(search_config.max_limit);
+    let method = match query.method.as_deref() {
+        None => Some(3),
+        Some(method) => method.parse().ok().or_else(|| MethodParam::Name(method.to_string()).code()),
+    };
+    if !matches!(method, Some(2 | 3)) {
+        return HttpResponse::BadRequest().body("Invalid method. Use 2 (TF-IDF) or 3 (SVD/LSI)");
+    }
+    let pre = data.preprocessed_data.load_full();
+    let Some(doc_idx) = data.document_ids.resolve(&id) else {
+        return HttpResponse::NotFound().body("Document not found");
+    };
+    let within = match &query.within {
+        Some(token) => match data.result_sets.get(token) {
+            Some(set) => Some(DocSet::clone(&set)),
+            None => return HttpResponse::BadRequest().body("Unknown or expired result set token"),
+        },
+        None => None,
+    };
+    let within = data.blocklist.load().exclude_from(within, pre.documents.len());
+    let csr = pre.term_doc_csr.to_csr();
+    let analyze_ms = elapsed_ms(analyze_start);
+
+    let score_start = Instant::now();
+    let svd = match method {
+        Some(3) if data.svd_breaker.allows() => data.svd_models
+            .closest_rank(query.k.unwrap_or(data.noise_filter_k))
+            .and_then(|available| match data.svd_models.get(available) {
+                Ok(svd) => Some(svd),
+                Err(e) => {
+                    data.svd_breaker.record_failure(&e.to_string());
+                    None
+                }
+            }),
+        _ => None,
+    };
+    let ranked = match util::search::more_like_this(doc_idx, &pre, &csr, svd.as_deref(), within.as_ref(), top_k) {
+        Err(e) if svd.is_some() => {
+            data.svd_breaker.record_failure(&e.to_string());
+            util::search::more_like_this(doc_idx, &pre, &csr, None, within.as_ref(), top_k).map(|ranked| (ranked, 2))
+        }
+        Ok(ranked) if svd.is_some() => {
+            data.svd_breaker.record_success();
+            Ok((ranked, 3))
+        }
+        ranked => ranked.map(|ranked| (ranked, 2)),
+    };
+    let ((ranked, total_candidates), method) = match ranked {
+        Ok(ranked) => ranked,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    let ranked = data.result_filters.apply("", ranked, &pre);
+    let score_ms = elapsed_ms(score_start);
+
+    let fetch_start = Instant::now();
+    let results: Vec<SearchResult> = ranked.into_iter()
+        .map(|(doc, score)| SearchResult {
+            score,
+            title: doc.title.clone(),
+            url: doc.url.clone(),
+            image_url: doc.image_url.clone(),
+            metadata: util::metadata::parse(doc),
+            uuid: doc.uuid.clone(),
+            id: doc.id,
+            id_str: doc.id.to_string(),
+            text: pre.text(doc).into_owned(),
+            snippet_html: None,
+            summary: doc.summary.clone(),
+            title_match: false,
+            keywords: doc.keywords.clone(),
+            entities: doc.entities.clone(),
+            matched_terms: Vec::new(),
+            highlights: None,
+        })
+        .collect();
+    let fetch_ms = elapsed_ms(fetch_start);
+
+    HttpResponse::Ok().json(SearchResponse {
+        query: String::new(),
+        method: method_name(method),
+        language: None,
+        k: top_k,
+        total_candidates,
+        timings: SearchTimings { analyze_ms, score_ms, fetch_ms, phases: None },
+        index_version: data.manifest.load().as_deref().map(util::manifest::IndexManifest::version),
+        results,
+        scroll_token: None,
+        expansion_terms: None,
+        page: None,
+    })
+}
+
+#[derive(Deserialize)]
+struct FacetQuery {
+    limit: Option<usize>,
+}
+
+#[get("/facets/entities")]
+async fn get_entity_facets(
+    data: web::Data<AppState>,
+    query: web::Query<FacetQuery>,
+) -> impl Responder {
+    HttpResponse::Ok().json(data.entity_index.facets(query.limit.unwrap_or(50)))
+}
+
+/// The most common values of a metadata field declared in `metadata.facets`.
+#[get("/facets/metadata/{field}")]
+async fn get_metadata_facets(
+    data: web::Data<AppState>,
+    field: web::Path<String>,
+    query: web::Query<FacetQuery>,
+) -> impl Responder {
+    match data.metadata_index.facets(&field, query.limit.unwrap_or(50)) {
+        Some(facets) => HttpResponse::Ok().json(facets),
+        None => HttpResponse::NotFound().body(format!("Metadata field {:?} isn't declared in metadata.facets", field.as_str())),
+    }
+}
+
+#[derive(Deserialize)]
+struct TrendQuery {
+    term: String,
+}
+
+#[derive(Serialize)]
+struct TrendResponse {
+    term: String,
+    /// The indexed terms `term` analyzed to; documents must contain all of them.
+    terms: Vec<String>,
+    #[serde(flatten)]
+    trend: util::trends::Trend,
+}
+
+/// Per-month document frequency of a term (or all words of a phrase), for plotting a topic over time.
+#[get("/trends")]
+async fn get_trends(data: web::Data<AppState>, query: web::Query<TrendQuery>) -> impl Responder {
+    let pre = data.preprocessed_data.load_full();
+    let terms = data.analyzer.analyze(&query.term);
+    if terms.is_empty() {
+        return HttpResponse::BadRequest().body("The term has no indexable words");
+    }
+    let trend = match terms.iter().map(|term| pre.term_dict.get(term).copied()).collect::<Option<Vec<_>>>() {
+        Some(term_indices) => util::trends::monthly_frequency(&pre, &term_indices),
+        // A word outside the vocabulary matches nothing; the months still frame the chart.
+        None => util::trends::monthly_frequency(&pre, &[]),
+    };
+    HttpResponse::Ok().json(TrendResponse { term: query.term.clone(), terms, trend })
+}
+
+#[get("/admin/corpus-stats")]
+async fn get_corpus_stats(data: web::Data<AppState>) -> impl Responder {
+    match &data.corpus_stats {
+        Some(stats) => HttpResponse::Ok().json(stats.as_ref()),
+        None => HttpResponse::NotFound().body("Corpus statistics not available; rebuild the index to compute them"),
+    }
+}
+
+#[derive(Deserialize)]
+struct AnalyzeRequest {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct AnalyzeResponse {
+    #[serde(flatten)]
+    stages: util::analyzer::AnalysisStages,
+    /// Normalized terms that aren't in the index vocabulary.
+    unknown_terms: Vec<String>,
+}
+
+/// Shows how text is turned into index terms, one token stream per analyzer stage.
+#[post("/admin/analyze")]
+async fn analyze(data: web::Data<AppState>, req: web::Json<AnalyzeRequest>) -> impl Responder {
+    let pre = data.preprocessed_data.load_full();
+    let stages = data.analyzer.analyze_stages(&req.text);
+    let unknown_terms = stages.normalized.iter()
+        .filter(|term| !pre.term_dict.contains_key(*term))
+        .cloned()
+        .collect();
+
+    HttpResponse::Ok().json(AnalyzeResponse { stages, unknown_terms })
+}
+
+#[get("/admin/manifest")]
+async fn get_manifest(data: web::Data<AppState>) -> impl Responder {
+    match data.manifest.load().as_deref() {
+        Some(manifest) => HttpResponse::Ok().json(manifest),
+        None => HttpResponse::NotFound().body("This index has no manifest; rebuild it to create one"),
+    }
+}
+
+#[get("/admin/languages")]
+async fn get_languages(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.languages.load().stats())
+}
+
+#[get("/admin/boost-rules")]
+async fn get_boost_rules(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.boost_rules.load().rules())
+}
 
-            (
-                Arc::new(documents),
-                Arc::new(terms_vec),
-                Arc::new(new_tfidf_matrix.clone()),
-                new_tfidf_matrix,
-            )
+#[post("/admin/boost-rules/reload")]
+async fn reload_boost_rules(data: web::Data<AppState>) -> impl Responder {
+    let config = data.config.load();
+    match load_boost_rules(&config) {
+        Ok(rules) => {
+            let loaded = rules.rules();
+            data.boost_rules.store(Arc::new(rules));
+            HttpResponse::Ok().json(loaded)
         }
-    };
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
 
-    // Sample queries for testing
-    let sample_queries = vec![
-        "science",
-        "technology",
-        "health",
-        "artificial intelligence",
-        "climate change",
-    ];
-
-    // Process each SVD configuration one at a time to manage memory
-    let svd_configs = [
-        (10, SVD_CACHE_10),
-        (25, SVD_CACHE_25),
-        (50, SVD_CACHE_50),
-    ];
-
-    for (k, path) in &svd_configs {
-        println!("\n==================================================================");
-        println!("PROCESSING SVD CONFIGURATION WITH k={}", k);
-        println!("==================================================================");
-
-        // Load or compute SVD
-        let svd = if Path::new(path).exists() {
-            println!("Loading SVD from cache: {}", path);
-            match matrix::TfIdfMatrix::load_svd(path) {
-                Ok(svd) => {
-                    println!("Successfully loaded SVD with k={}", k);
-                    svd
-                }
-                Err(e) => {
-                    println!("Failed to load SVD from cache, computing fresh: {}", e);
-                    let svd = base_tfidf_matrix.compute_svd(*k);
-                    if let Err(e) = svd.save(path) {
-                        eprintln!("Warning: Failed to save computed SVD: {}", e);
-                    }
-                    svd
-                }
-            }
-        } else {
-            println!("No cache found, computing fresh SVD with k={}", k);
-            let svd = base_tfidf_matrix.compute_svd(*k);
-            if let Err(e) = svd.save(path) {
-                eprintln!("Warning: Failed to save computed SVD: {}", e);
-            }
-            svd
-        };
+#[derive(Serialize)]
+struct BlocklistResponse<'a> {
+    #[serde(flatten)]
+    entries: &'a util::blocklist::BlocklistEntries,
+    blocked_documents: usize,
+}
 
-        // Test each query with the current SVD configuration
-        for query in &sample_queries {
-            println!("\n--------------------------------------------------");
-            println!("Testing query: '{}' with k={}", query, k);
+impl<'a> BlocklistResponse<'a> {
+    fn of(blocklist: &'a util::blocklist::Blocklist) -> Self {
+        BlocklistResponse { entries: blocklist.entries(), blocked_documents: blocklist.blocked_count() }
+    }
+}
 
-            // SVD search
-            let start = std::time::Instant::now();
-            let svd_results = engine::search::search_with_svd(query, &base_tfidf_matrix, &svd, 5);
-            let svd_time = start.elapsed();
+#[get("/admin/blocklist")]
+async fn get_blocklist(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(BlocklistResponse::of(&data.blocklist.load()))
+}
 
-            println!("SVD search results (k={}, took {:?}):", k, svd_time);
-            for (i, (doc_idx, score)) in svd_results.iter().enumerate() {
-                println!("{}. {} (score: {:.4})", i+1, documents_arc[*doc_idx].title, score);
+/// Adds document IDs and URL patterns to the blocklist; they drop out of results immediately.
+#[post("/admin/blocklist")]
+async fn add_to_blocklist(data: web::Data<AppState>, req: web::Json<util::blocklist::BlocklistEntries>) -> impl Responder {
+    edit_blocklist(&data, |entries| {
+        entries.document_uuids.extend(req.document_uuids.iter().map(|uuid| uuid.to_ascii_lowercase()));
+        entries.document_ids.extend(&req.document_ids);
+        for pattern in &req.url_patterns {
+            if !entries.url_patterns.contains(pattern) {
+                entries.url_patterns.push(pattern.clone());
             }
-
-            // Force a small delay to let the system process memory
-            std::thread::sleep(std::time::Duration::from_millis(100));
->>>>>>> Stashed changes
         }
+    })
+}
 
-        // Clean up and free memory before next iteration
-        drop(svd);
-        println!("\nFinished testing with k={}, memory freed", k);
+#[delete("/admin/blocklist")]
+async fn remove_from_blocklist(data: web::Data<AppState>, req: web::Json<util::blocklist::BlocklistEntries>) -> impl Responder {
+    edit_blocklist(&data, |entries| {
+        entries.document_uuids.retain(|uuid| !req.document_uuids.iter().any(|removed| removed.eq_ignore_ascii_case(uuid)));
+        entries.document_ids.retain(|id| !req.document_ids.contains(id));
+        entries.url_patterns.retain(|pattern| !req.url_patterns.contains(pattern));
+    })
+}
 
-        // Force garbage collection by suggesting a heap compact
-        unsafe { libc::malloc_trim(0); }
+fn edit_blocklist(data: &AppState, edit: impl FnOnce(&mut util::blocklist::BlocklistEntries)) -> HttpResponse {
+    let _editing = data.blocklist_edits.lock().unwrap();
+    let mut entries = data.blocklist.load().entries().clone();
+    edit(&mut entries);
+
+    let pre = data.preprocessed_data.load_full();
+    let blocklist = match util::blocklist::Blocklist::new(entries, &pre.documents) {
+        Ok(blocklist) => blocklist,
+        Err(e) => return HttpResponse::BadRequest().body(format!("Invalid URL pattern: {}", e)),
+    };
+    let path = data.config.load().storage.resolve(util::blocklist::FILE_NAME);
+    if let Err(e) = blocklist.save(&path) {
+        return HttpResponse::InternalServerError().body(e.to_string());
     }
+    let response = HttpResponse::Ok().json(BlocklistResponse::of(&blocklist));
+    data.blocklist.store(Arc::new(blocklist));
+    response
+}
 
-<<<<<<< Updated upstream
-    fn to_csr(&self) -> CsrMatrix<f64> {
-        CsrMatrix::try_from_csr_data(
-            self.nrows,
-            self.ncols,
-            self.row_offsets.clone(),
-            self.col_indices.clone(),
-            self.values.clone(),
-        ).unwrap()
+/// Intersects `within` with the documents matching a filter query, lying in an area and having
+/// the given metadata values, whichever are given.
+fn narrow_scope(
+    data: &AppState,
+    pre: &PreprocessedData,
+    csr: &CsrMatrix<f64>,
+    within: &mut Option<DocSet>,
+    filter: Option<&str>,
+    geo: Option<&util::geo::GeoFilter>,
+    metadata: Option<&BTreeMap<String, String>>,
+) -> Result<(), String> {
+    let mut intersect = |set: DocSet| match within.as_mut() {
+        Some(within) => within.intersect_with(&set),
+        None => *within = Some(set),
+    };
+    if let Some(filter) = filter {
+        let filter_vec = util::search::create_query_vector(filter, &pre.term_dict, &pre.idf, &data.analyzer);
+        intersect(DocSet::matching(&filter_vec, csr));
+    }
+    if let Some(geo) = geo {
+        geo.validate()?;
+        intersect(geo.documents(&pre.documents));
+    }
+    if let Some(metadata) = metadata.filter(|metadata| !metadata.is_empty()) {
+        intersect(data.metadata_index.matching(metadata, pre.documents.len())?);
     }
+    Ok(())
 }
 
-impl SvdData {
-    fn u_k(&self) -> DMatrix<f64> {
-        DMatrix::from_row_slice(
-            self.u_ser.nrows,
-            self.u_ser.ncols,
-            &self.u_ser.data
-        )
+/// The rules at `server.boost_rules_path`, or none when it isn't set.
+fn load_boost_rules(config: &util::config::Config) -> Result<util::boost_rules::BoostRules, Box<dyn Error>> {
+    match &config.server.boost_rules_path {
+        Some(path) => util::boost_rules::BoostRules::load(&config.storage.resolve(path)),
+        None => Ok(util::boost_rules::BoostRules::default()),
     }
+}
+
+#[get("/admin/svd-models")]
+async fn get_svd_models(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(data.svd_models.models())
+}
 
-    fn doc_vectors(&self) -> DMatrix<f64> {
-        DMatrix::from_row_slice(
-            self.docs_ser.nrows,
-            self.docs_ser.ncols,
-            &self.docs_ser.data
-        )
+#[derive(Serialize)]
+struct ViewInfo {
+    #[serde(flatten)]
+    view: util::views::View,
+    /// Documents in the view, after the blocklist.
+    document_count: usize,
+    /// Searches using the view since the server started.
+    searches: u64,
+}
+
+fn view_info(data: &AppState, name: &str, view: &util::views::View) -> Result<ViewInfo, String> {
+    let pre = data.preprocessed_data.load_full();
+    let csr = pre.term_doc_csr.to_csr();
+    let mut scope = None;
+    narrow_scope(data, &pre, &csr, &mut scope, view.filter.as_deref(), view.geo.as_ref(), Some(&view.metadata))?;
+    if let Some(language) = &view.language
+        && data.config.load().languages.enabled
+    {
+        let languages = data.languages.load();
+        let partition = languages.documents(language)
+            .ok_or_else(|| format!("There are no documents in language {:?}", language))?;
+        match scope.as_mut() {
+            Some(set) => set.intersect_with(partition),
+            None => scope = Some(partition.clone()),
+        }
     }
+    let document_count = data.blocklist.load().exclude_from(scope, pre.documents.len())
+        .map_or(pre.documents.len(), |set| set.count());
+    Ok(ViewInfo { view: view.clone(), document_count, searches: data.view_stats.searches(name) })
+}
 
-    pub fn effective_rank(&self, requested_k: Option<usize>) -> usize {
-        requested_k.map(|k| k.min(self.rank)).unwrap_or(self.rank)
+/// Every view with how many documents it covers and how often it was searched.
+#[get("/admin/views")]
+async fn get_views(data: web::Data<AppState>) -> impl Responder {
+    let views = data.views.load();
+    let mut infos = BTreeMap::new();
+    for (name, view) in views.all() {
+        match view_info(&data, name, view) {
+            Ok(info) => {
+                infos.insert(name.clone(), info);
+            }
+            Err(e) => return HttpResponse::InternalServerError().body(format!("View {}: {}", name, e)),
+        }
     }
+    HttpResponse::Ok().json(infos)
+}
 
-    pub fn get_u_k(&self, requested_k: Option<usize>) -> DMatrix<f64> {
-        let k = self.effective_rank(requested_k);
-        self.u_k().columns(0, k).into_owned()
+/// Defines or replaces a view.
+#[put("/admin/views/{name}")]
+async fn put_view(data: web::Data<AppState>, name: web::Path<String>, view: web::Json<util::views::View>) -> impl Responder {
+    if let Err(e) = util::views::validate_name(&name) {
+        return HttpResponse::BadRequest().body(e);
     }
+    let info = match view_info(&data, &name, &view) {
+        Ok(info) => info,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
 
-    pub fn get_doc_vectors(&self, requested_k: Option<usize>) -> DMatrix<f64> {
-        let k = self.effective_rank(requested_k);
-        self.doc_vectors().rows(0, k).into_owned()
+    let _editing = data.view_edits.lock().unwrap();
+    let views = data.views.load().with(&name, view.into_inner());
+    if let Err(e) = views.save(&data.config.load().storage.resolve(util::views::FILE_NAME)) {
+        return HttpResponse::InternalServerError().body(e.to_string());
     }
+    data.views.store(Arc::new(views));
+    HttpResponse::Ok().json(info)
 }
 
-#[get("/stats")]
-async fn get_stats(data: web::Data<AppState>) -> impl Responder {
-    HttpResponse::Ok().json(StatsResponse {
-        document_count: data.preprocessed_data.documents.len(),
-        vocabulary_size: data.preprocessed_data.term_dict.len(),
-    })
-=======
-    // Now run a web server to provide the search functionality
-    println!("\nStarting web server at http://127.0.0.1:8080");
-
-    let app_state = web::Data::new(AppState {
-        documents: documents_arc.clone(),
-        terms: terms_arc,
-        tfidf_matrix: tfidf_matrix_arc,
-    });
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(app_state.clone())
-            .service(search)
-    })
-        .bind("127.0.0.1:8080")?
-        .run()
-        .await
+#[delete("/admin/views/{name}")]
+async fn delete_view(data: web::Data<AppState>, name: web::Path<String>) -> impl Responder {
+    let _editing = data.view_edits.lock().unwrap();
+    let Some(views) = data.views.load().without(&name) else {
+        return HttpResponse::NotFound().body("View not found");
+    };
+    if let Err(e) = views.save(&data.config.load().storage.resolve(util::views::FILE_NAME)) {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    data.views.store(Arc::new(views));
+    HttpResponse::NoContent().finish()
 }
 
-#[actix_web::post("/search")]
-pub async fn search(
-    query: web::Json<SearchQueryWithSvd>,
-    state: web::Data<AppState>,
-) -> impl Responder {
-    let limit = query.limit.unwrap_or(10);
-    let k_value = query.k_value.unwrap_or(25);
-
-    if query.use_svd {
-        // Load appropriate SVD model based on k_value
-        let svd_path = match k_value {
-            k if k <= 10 => SVD_CACHE_10,
-            k if k <= 25 => SVD_CACHE_25,
-            _ => SVD_CACHE_50,
-        };
+#[derive(Deserialize)]
+struct SlowQueriesQuery {
+    limit: Option<usize>,
+}
 
-        // Load SVD from cache
-        match matrix::TfIdfMatrix::load_svd(svd_path) {
-            Ok(svd) => {
-                let results = engine::search::search_with_svd(
-                    &query.query,
-                    &state.tfidf_matrix,
-                    &svd,
-                    limit
-                );
-
-                let search_results: Vec<SearchResult> = results
-                    .into_iter()
-                    .map(|(doc_idx, score)| {
-                        let doc = &state.documents[doc_idx];
-                        SearchResult {
-                            id: doc.id as i32,
-                            score,
-                            title: doc.title.clone(),
-                            text: doc.text.clone(),
-                        }
-                    })
-                    .collect();
-
-                HttpResponse::Ok().json(search_results)
-            },
-            Err(e) => {
-                HttpResponse::InternalServerError().body(format!("Failed to load SVD: {}", e))
-            }
-        }
-    } else {
-        // Regular search without SVD
-        let results = engine::search::search(&query.query, &state.tfidf_matrix, limit);
+/// Searches that exceeded `slow_queries.threshold_ms`, newest first.
+#[get("/admin/slow-queries")]
+async fn get_slow_queries(data: web::Data<AppState>, query: web::Query<SlowQueriesQuery>) -> impl Responder {
+    let Some(log) = &data.slow_queries else {
+        return HttpResponse::NotFound().body("The slow-query log is disabled");
+    };
+    match log.recent(query.limit.unwrap_or(50)) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
 
-        let search_results: Vec<SearchResult> = results
-            .into_iter()
-            .map(|(doc_idx, score)| {
-                let doc = &state.documents[doc_idx];
-                SearchResult {
-                    id: doc.id as i32,
-                    score,
-                    title: doc.title.clone(),
-                    text: doc.text.clone(),
-                }
-            })
-            .collect();
+#[derive(Deserialize)]
+struct JobsQuery {
+    limit: Option<usize>,
+}
 
-        HttpResponse::Ok().json(search_results)
+/// The most recent admin jobs, newest first.
+#[get("/admin/jobs")]
+async fn get_jobs(data: web::Data<AppState>, query: web::Query<JobsQuery>) -> impl Responder {
+    match data.jobs.list(query.limit.unwrap_or(50)) {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
->>>>>>> Stashed changes
 }
 
-async fn search_handler(
-    data: web::Data<AppState>,
-    req: web::Json<SearchRequest>,
-) -> impl Responder {
-    let query = &req.query;
-    let top_k = req.limit.unwrap_or(10);
-    let method = req.method.unwrap_or(2); // Domyślnie TF-IDF
-
-    let csr = data.preprocessed_data.term_doc_csr.to_csr();
+#[get("/admin/jobs/{id}")]
+async fn get_job(data: web::Data<AppState>, id: web::Path<i64>) -> impl Responder {
+    match data.jobs.get(*id) {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().body("Job not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
 
-    let results = match method {
-        2 => {
-            // Standard TF-IDF search
-            util::search::search(
-                query,
-                &data.preprocessed_data.term_dict,
-                &data.preprocessed_data.idf,
-                &csr,
-                &data.preprocessed_data.documents,
-                top_k,
-            )
-        }
-        3 => {
-            // SVD/LSI search
-            util::search::search_svd(
-                query,
-                &data.preprocessed_data.term_dict,
-                &data.preprocessed_data.idf,
-                &data.svd_data,
-                &data.preprocessed_data.documents,
-                top_k,
-            )
-        }
-        4 => {
-            // Low-rank approximation with noise filtering
-            util::search::search_with_low_rank(
-                query,
-                &data.preprocessed_data.term_dict,
-                &data.preprocessed_data.idf,
-                &data.svd_data,
-                &data.preprocessed_data.documents,
-                Some(data.noise_filter_k),
-                top_k,
-            )
-        }
-        _ => {
-            return HttpResponse::BadRequest().body("Invalid search method. Use 2 (TF-IDF), 3 (SVD/LSI), or 4 (Low-rank)");
+/// Queues a job, e.g. `{"kind": "build_svd", "rank": 50}`, `{"kind": "build_language_svd",
+/// "language": "de", "rank": 50}` or `{"kind": "snapshot"}`.
+#[post("/admin/jobs")]
+async fn create_job(data: web::Data<AppState>, kind: web::Json<util::jobs::JobKind>) -> impl Responder {
+    let max_rank = match &*kind {
+        util::jobs::JobKind::Snapshot => None,
+        util::jobs::JobKind::BuildSvd { rank } => {
+            let pre = data.preprocessed_data.load_full();
+            Some((*rank, pre.term_doc_csr.nrows.min(pre.term_doc_csr.ncols).saturating_sub(1)))
         }
+        util::jobs::JobKind::BuildLanguageSvd { language, rank } => match data.languages.load().get(language) {
+            Some(partition) => Some((*rank, partition.max_rank())),
+            None => return HttpResponse::BadRequest().body(format!("There is no partition for language {:?}", language)),
+        },
     };
+    if let Some((rank, max_rank)) = max_rank
+        && (rank == 0 || rank > max_rank)
+    {
+        return HttpResponse::BadRequest().body(format!("rank must be between 1 and {}", max_rank));
+    }
+    match data.jobs.enqueue(kind.into_inner()) {
+        Ok(job) => HttpResponse::Accepted().json(job),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
 
-    match results {
-        Ok(results) => HttpResponse::Ok().json(
-            results.into_iter()
-                .map(|(doc, score)| SearchResult {
-                    score,
-                    title: doc.title.clone(),
-                    url: doc.url.clone(),
-                    id: doc.id,
-                    text: doc.text.clone(),
-                })
-                .collect::<Vec<_>>()
-        ),
+/// Cancels a queued job, or asks a running one to stop at its next checkpoint.
+#[delete("/admin/jobs/{id}")]
+async fn cancel_job(data: web::Data<AppState>, id: web::Path<i64>) -> impl Responder {
+    match data.jobs.cancel(*id) {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().body("Job not found"),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
-<<<<<<< Updated upstream
-#[get("/document/{id}")]
-async fn get_document(
+#[derive(Serialize)]
+struct ReweightResponse {
+    weighting: util::weighting::Weighting,
+    elapsed_ms: u128,
+}
+
+/// Switches the weighting used by the TF-IDF method, recomputing it from the stored raw counts,
+/// and saves it with the index. BM25 searches (method 6) default to a BM25 weighting's k1 and b.
+/// SVD models factorize the weights they were built from; they are listed as stale until a
+/// `build_svd` job replaces them, and language partitions rebuild theirs on first use.
+#[post("/admin/reweight")]
+async fn reweight(
     data: web::Data<AppState>,
-    id: web::Path<i64>,
+    weighting: web::Json<util::weighting::Weighting>,
 ) -> impl Responder {
-    let doc_id = id.into_inner();
+    let weighting = weighting.into_inner();
+    if let Err(e) = weighting.validate() {
+        return HttpResponse::BadRequest().body(e);
+    }
+    if data.manifest.load().is_none() {
+        return HttpResponse::Conflict().body("This index has no manifest to record the weighting in; rebuild it to create one");
+    }
 
-    if let Some(doc) = data.preprocessed_data.documents.iter().find(|d| d.id == doc_id) {
-        HttpResponse::Ok().json(SearchResult {
-            score: 0.0,
-            title: doc.title.clone(),
-            url: doc.url.clone(),
-            id: doc.id,
-            text: doc.text.clone(),
-        })
+    let start = Instant::now();
+    // Recomputing every weight takes a while; it runs off the request threads.
+    let state = data.clone();
+    match web::block(move || reweight_index(&state, weighting)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return HttpResponse::InternalServerError().body(e),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    }
+
+    log::info!("Reweighted index with {:?} in {:?}", weighting, start.elapsed());
+    HttpResponse::Ok().json(ReweightResponse {
+        weighting,
+        elapsed_ms: start.elapsed().as_millis(),
+    })
+}
+
+/// Recomputes the index's weights, saves them and the manifest, and starts serving them. Holds
+/// the cache lock throughout, so concurrent reweights and model builds see one index at a time.
+fn reweight_index(state: &AppState, weighting: util::weighting::Weighting) -> Result<(), String> {
+    let config = state.config.load_full();
+    let preproc_index = config.storage.resolve("preprocessed.idx");
+    let _lock = util::data::CacheLock::acquire(&config.storage.resolve("cache.lock")).map_err(|e| e.to_string())?;
+
+    let current = state.preprocessed_data.load_full();
+    let (term_doc_csr, idf) = util::weighting::reweight(&current, weighting);
+    let reweighted = PreprocessedData {
+        term_dict: current.term_dict.clone(),
+        inverse_term_dict: current.inverse_term_dict.clone(),
+        idf,
+        collocations: current.collocations.clone(),
+        synonyms: current.synonyms.clone(),
+        subwords: current.subwords.clone(),
+        documents: current.documents.clone(),
+        term_doc_csr,
+        term_counts: current.term_counts.clone(),
+        texts: current.texts.clone(),
+    };
+    let manifest = state.manifest.load_full().ok_or("This index has no manifest")?;
+    let manifest = util::manifest::IndexManifest {
+        weighting: Some(weighting),
+        reweighted_at: Some(util::history::unix_now()),
+        ..util::manifest::IndexManifest::clone(&manifest)
+    };
+    util::data::save_weights(&reweighted, &preproc_index).map_err(|e| e.to_string())?;
+    util::manifest::save_manifest(&manifest, &util::manifest::manifest_path(&preproc_index)).map_err(|e| e.to_string())?;
+
+    let languages = if config.languages.enabled {
+        util::language::LanguagePartitions::build(&reweighted, config.languages.min_documents, weighting)
     } else {
-        HttpResponse::NotFound().body("Document not found")
+        util::language::LanguagePartitions::default()
+    };
+    if let Some(reweighted_at) = manifest.reweighted_at {
+        state.svd_models.mark_stale_before(reweighted_at);
+    }
+    state.preprocessed_data.store(Arc::new(reweighted));
+    state.languages.store(Arc::new(languages));
+    state.manifest.store(Some(Arc::new(manifest)));
+    Ok(())
+}
+
+/// Runs the canary queries over the embedded corpus, failing unless every check passes.
+fn self_test(config: &util::config::Config) -> Result<(), Box<dyn Error>> {
+    let checks = util::self_test::run(&config.analyzer, &config.storage.data_dir, &config.svd)?;
+    for check in &checks {
+        println!("{}\t{}\t{:?}\t{}", if check.passed { "ok" } else { "FAIL" }, check.method, check.query, check.detail);
+    }
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        return Err(format!("{} of {} self-test checks failed", failed, checks.len()).into());
     }
+    println!("All {} self-test checks passed", checks.len());
+    Ok(())
 }
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let db_path = "../Search-Engine/backend/data/articles.db";
-    let preproc_index = "preprocessed.idx";
-    let svd_index = |k| format!("svd_k{}.idx", k);
+    let config_path = std::env::var("SEARCH_ENGINE_CONFIG").unwrap_or_else(|_| "config.json".to_string());
+    // Everything passes the logger's own filter; the configured level is applied as the global
+    // maximum once the config is read, and messages before that are logged at `info`.
+    env_logger::Builder::new().filter_level(log::LevelFilter::Trace).init();
+    log::set_max_level(log::LevelFilter::Info);
+    let config = util::config::Config::load(&config_path)?;
+    log::set_max_level(config.server.log_level);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config.server.scoring_thread_count())
+        .build_global()?;
+    let storage = &config.storage;
+    let db_path = storage.resolve(&storage.database_path);
+    let preproc_index = storage.resolve("preprocessed.idx");
+    let svd_index = |k| storage.resolve(format!("svd_k{}.idx", k));
+    let corpus_stats_path = storage.resolve("corpus_stats.bin");
+    let autocomplete_path = storage.resolve("autocomplete.bin");
+    let k = 25;
+    let cross_language_index = storage.resolve(format!("svd_cl_k{}.idx", k));
+    let index_paths = IndexPaths {
+        preprocessed: preproc_index.clone(),
+        svd: svd_index(k),
+        cross_language: cross_language_index.clone(),
+        corpus_stats: corpus_stats_path.clone(),
+        autocomplete: autocomplete_path.clone(),
+    };
+    let lock_path = storage.resolve("cache.lock");
+    let snapshots = util::snapshot::SnapshotManager::new(&config.snapshots, &storage.data_dir);
+    let args: Vec<String> = std::env::args().collect();
+    // These leave the data directory alone, so they need no lock.
+    match args.as_slice() {
+        [_, command] if command == "self-test" => return self_test(&config),
+        // An engine written by `SearchEngine::save`, as the one file the wasm build loads.
+        [_, command, engine_dir, out] if command == "bundle" => {
+            let bundle = search_engine::SearchEngine::load(engine_dir)?.to_bundle()?;
+            std::fs::write(out, &bundle)?;
+            println!("Wrote {} ({} bytes)", out, bundle.len());
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    let pre = if Path::new(preproc_index).exists() {
-        println!("Loading preprocessed data...");
-        util::data::load_preprocessed_data(preproc_index)?
+    // Held until every cache artifact is loaded or written, so a concurrent build in another
+    // process can't swap files out from under this one.
+    let cache_lock = util::data::CacheLock::acquire(&lock_path)?;
+    match args.as_slice() {
+        [_, command] if command == "snapshot" => {
+            let entry = index_paths.snapshot(&snapshots)?;
+            println!("Created snapshot {} with {} files", entry.id, entry.files.len());
+            return Ok(());
+        }
+        [_, command] if command == "snapshots" => {
+            for entry in snapshots.list()? {
+                println!("{}\t{} files\tindex version {}", entry.id, entry.files.len(), entry.index_version.as_deref().unwrap_or("unknown"));
+            }
+            return Ok(());
+        }
+        // Point in time as a unix timestamp; the newest snapshot no later than it is restored.
+        [_, command, at] if command == "restore" => {
+            let at: i64 = at.parse().map_err(|_| format!("Expected a unix timestamp, got {:?}", at))?;
+            let entry = snapshots.restore(at, &storage.data_dir, &index_paths.existing_files()?)?;
+            println!("Restored snapshot {}", entry.id);
+            return Ok(());
+        }
+        _ => {}
+    }
+    let manifest_path = util::manifest::manifest_path(&preproc_index);
+    let (mut pre, corpus_stats, manifest) = if preproc_index.exists() {
+        log::info!("Loading preprocessed data...");
+        let pre = util::data::load_preprocessed_data(&preproc_index)?;
+        let corpus_stats = if corpus_stats_path.exists() {
+            Some(util::data::load_corpus_stats(&corpus_stats_path)?)
+        } else {
+            log::warn!("No corpus statistics at {}; rebuild the index to compute them", corpus_stats_path.display());
+            None
+        };
+        let manifest = if manifest_path.exists() {
+            Some(util::manifest::load_manifest(&manifest_path)?)
+        } else {
+            log::warn!("No index manifest at {}; can't check that the index matches the analyzer config", manifest_path.display());
+            None
+        };
+        (pre, corpus_stats, manifest)
     } else {
-        println!("Building index from SQLite...");
-        let docs = util::parser::parse_sqlite_documents(db_path)?;
-        let (term_dict, inv_term_dict, coo) = util::tokenizer::build_term_document_matrix(&docs);
-        let mut csr = CsrMatrix::from(&coo);
+        log::info!("Building index from SQLite...");
+        let mut docs = util::parser::parse_sqlite_documents(&db_path)?;
+        for doc in docs.iter_mut() {
+            doc.title = util::cleaning::clean_text(&doc.title);
+            doc.text = util::cleaning::clean_text(&doc.text);
+        }
+        let mut analyzer = util::analyzer::Analyzer::from_config(&config.analyzer, &storage.data_dir)?;
+        analyzer.learn_collocations(&config.analyzer, &docs);
+        analyzer.learn_subwords(&config.analyzer, &docs);
+        let (term_dict, inv_term_dict, coo) = util::tokenizer::build_term_document_matrix(&docs, &analyzer, config.analyzer.title_weight)?;
+        let counts = CsrMatrix::from(&coo);
+        let corpus_stats = util::corpus_stats::compute(&counts);
+        util::data::save_corpus_stats(&corpus_stats, &corpus_stats_path)?;
+        let mut csr = counts.clone();
         let idf = util::idf::calculate_idf(&csr);
         util::idf::apply_idf_weighting(&mut csr, &idf);
         util::norm::normalize_columns(&mut csr);
+        if csr.col_indices() != counts.col_indices() {
+            return Err("TF-IDF weighting changed the sparsity pattern of the term counts".into());
+        }
+        let term_counts: Vec<u32> = counts.values().iter().map(|&count| count as u32).collect();
+        let synonyms = match &config.analyzer.synonyms_path {
+            Some(path) => util::synonyms::Synonyms::load(&storage.data_dir.join(path), config.analyzer.synonym_weight, &analyzer, &term_dict)?,
+            None => util::synonyms::Synonyms::default(),
+        };
+
+        let keywords = util::keywords::extract_keywords(&csr, &inv_term_dict, 10);
+        for (doc, doc_keywords) in docs.iter_mut().zip(keywords) {
+            doc.keywords = doc_keywords;
+            doc.language = util::language::detect(&format!("{} {}", doc.title, doc.text)).unwrap_or_default().to_string();
+            doc.summary = util::summarizer::summarize(&doc.text, &doc.language, &analyzer, &term_dict, &idf, 3);
+            if config.entities.enabled {
+                doc.entities = util::entities::extract_entities(&doc.title, &doc.text, &doc.language, config.entities.max_per_document);
+            }
+        }
 
         let pre = PreprocessedData {
             term_dict,
             inverse_term_dict: inv_term_dict,
             idf,
-            documents: docs,
+            collocations: analyzer.collocations().clone(),
+            synonyms,
+            subwords: analyzer.subwords().clone(),
+            documents: Arc::new(docs),
             term_doc_csr: SerializableCsrMatrix::from_csr(&csr),
+            term_counts,
+            texts: None,
         };
-        util::data::save_preprocessed_data(&pre, preproc_index)?;
-        pre
+        util::data::save_preprocessed_data(&pre, &preproc_index)?;
+        util::data::save_autocomplete(&util::autocomplete::Autocomplete::build(&pre), &autocomplete_path)?;
+        let manifest = util::manifest::IndexManifest::new(&pre, &config.analyzer, Some(Weighting::default()), Some(&db_path))?;
+        util::manifest::save_manifest(&manifest, &manifest_path)?;
+        (pre, Some(corpus_stats), Some(manifest))
     };
 
-    let k = 25;
-    println!("Using SVD rank k={}", k);
+    if let Some(manifest) = &manifest
+        && let Some(mismatch) = manifest.analyzer_mismatch(&config.analyzer)?
+    {
+        match config.server.manifest_mismatch {
+            util::manifest::MismatchPolicy::Warn => {
+                log::warn!("{}; queries may not match indexed terms until the index is rebuilt", mismatch);
+            }
+            util::manifest::MismatchPolicy::Refuse => {
+                return Err(format!("Refusing to serve: {}. Rebuild the index (delete {}) or restore the config", mismatch, preproc_index.display()).into());
+            }
+        }
+    }
+
+    log::info!("Using SVD rank k={}", k);
 
-    let svd_data = if Path::new(&svd_index(k)).exists() {
-        println!("Loading SVD data (k={})...", k);
-        util::data::load_svd_data(&svd_index(k))?
+    match args.as_slice() {
+        [_, command, dir] if command == "export" => {
+            return util::export::export_index(&pre, dir);
+        }
+        [_, command, matrix, vocabulary] if command == "import" => {
+            util::import::import_term_document_matrix(&mut pre, matrix, vocabulary)?;
+            util::data::save_preprocessed_data(&pre, &preproc_index)?;
+            let imported = util::manifest::IndexManifest::new(&pre, &config.analyzer, None, Some(Path::new(matrix)))?;
+            util::manifest::save_manifest(&imported, &manifest_path)?;
+            // All were derived from the old matrix.
+            for stale in [svd_index(k), cross_language_index.clone(), corpus_stats_path.clone(), autocomplete_path.clone()] {
+                if stale.exists() {
+                    std::fs::remove_file(&stale)?;
+                }
+            }
+            log::info!("Imported term-document matrix; the SVD will be recomputed on next start");
+            return Ok(());
+        }
+        [_, command, doc_embeddings, vocabulary, term_embeddings] if command == "import" => {
+            let svd = util::import::import_embeddings(&pre, doc_embeddings, vocabulary, term_embeddings)?;
+            util::data::save_svd_data(&svd, &svd_index(k))?;
+            log::info!("Imported embeddings; methods 3 and 4 now search them");
+            return Ok(());
+        }
+        _ => {}
+    }
+    if config.tiering.enabled {
+        let docs: &mut Vec<Document> = Arc::make_mut(&mut pre.documents);
+        let texts = util::tiering::TextTier::open(&storage.resolve(util::tiering::FILE_NAME), docs, &config.tiering)?;
+        pre.texts = Some(Arc::new(texts));
+    }
+
+    let svd_data = if svd_index(k).exists() {
+        log::info!("Loading SVD data (k={})...", k);
+        let svd = util::data::load_svd_data(&svd_index(k))?;
+        util::document_ids::check_model_columns(&svd, &pre.documents, &svd_index(k))?;
+        svd
     } else {
-        println!("Performing SVD with k={}...", k);
+        // Other configured ranks without a cache come out of the same run.
+        let extra_ranks: Vec<usize> = config.svd.extra_ranks.iter().copied()
+            .filter(|&rank| rank > 0 && rank != k && !svd_index(rank).exists())
+            .collect();
+        let top = extra_ranks.iter().copied().fold(k, usize::max);
+        log::info!("Performing SVD with k={}...", top);
         let csr = pre.term_doc_csr.to_csr();
-        let svd = util::svd::perform_svd(&csr, k)?;
+        let mut svd = util::svd::perform_svd(&csr, top, &config.svd, Some(&util::svd::checkpoint_path(&svd_index(top))))?;
+        svd.doc_ids = util::document_ids::column_ids(&pre.documents);
+        for &rank in &extra_ranks {
+            util::data::save_svd_data(&svd.truncated(rank), &svd_index(rank))?;
+        }
+        let svd = if top == k { svd } else { svd.truncated(k) };
         util::data::save_svd_data(&svd, &svd_index(k))?;
         svd
     };
+    let cross_language = if !config.languages.cross_language {
+        None
+    } else if cross_language_index.exists() {
+        let model = util::data::load_svd_data(&cross_language_index)?;
+        util::document_ids::check_model_columns(&model, &pre.documents, &cross_language_index)?;
+        Some(Arc::new(model))
+    } else {
+        let links = util::parser::parse_interlanguage_links(&db_path)?;
+        let pairs = util::cl_lsi::parallel_pairs(&pre.documents, &links);
+        if pairs.len() < config.languages.cross_language_min_pairs {
+            log::warn!(
+                "Only {} interlanguage-linked document pairs (need {}); cross-language search is unavailable",
+                pairs.len(), config.languages.cross_language_min_pairs,
+            );
+            None
+        } else {
+            let mut model = util::cl_lsi::train(&pre.term_doc_csr.to_csr(), &pairs, k, &config.svd, Some(&util::svd::checkpoint_path(&cross_language_index)))?;
+            model.doc_ids = util::document_ids::column_ids(&pre.documents);
+            util::data::save_svd_data(&model, &cross_language_index)?;
+            Some(Arc::new(model))
+        }
+    };
+    drop(cache_lock);
+    let svd_models = util::svd_catalog::SvdCatalog::scan(&storage.data_dir, config.server.svd_cache_mb * 1024 * 1024, pre.documents.clone())?;
+    svd_models.insert(k, Arc::new(svd_data));
+    if let Some(reweighted_at) = manifest.as_ref().and_then(|manifest| manifest.reweighted_at) {
+        svd_models.mark_stale_before(reweighted_at);
+    }
 
     let noise_filter_k = k;
+    let title_index = util::title_index::TitleIndex::build(&pre.documents);
+    let document_ids = util::document_ids::DocumentIds::build(&pre.documents);
+    let entity_index = util::entities::EntityIndex::build(&pre.documents);
+    let metadata_index = util::metadata::MetadataIndex::build(&pre.documents, &config.metadata);
+    let exact_title_index = util::exact_index::ExactTitleIndex::build(&pre.documents);
+    let query_likelihood = util::qlm::QueryLikelihood::build(&pre);
+    let bm25 = util::bm25::Bm25::build(&pre);
+    let languages = if config.languages.enabled {
+        let weighting = manifest.as_ref().and_then(|manifest| manifest.weighting).unwrap_or_default();
+        let languages = util::language::LanguagePartitions::build(&pre, config.languages.min_documents, weighting);
+        languages.load_svd_models(&storage.data_dir, k, manifest.as_ref().and_then(|manifest| manifest.reweighted_at))?;
+        languages
+    } else {
+        util::language::LanguagePartitions::default()
+    };
+    let history = if config.history.enabled {
+        Some(util::history::SearchHistory::open(&config.history, &storage.data_dir)?)
+    } else {
+        None
+    };
+    let slow_queries = if config.slow_queries.enabled {
+        Some(util::slow_queries::SlowQueryLog::open(&config.slow_queries, &storage.data_dir)?)
+    } else {
+        None
+    };
+    let term_suggester = util::suggest::TermSuggester::build(&pre);
+    let autocomplete_index = if autocomplete_path.exists() {
+        util::data::load_autocomplete(&autocomplete_path)?
+    } else {
+        // Indexes built before the trie existed: cheap enough to build from the loaded index.
+        let trie = util::autocomplete::Autocomplete::build(&pre);
+        util::data::save_autocomplete(&trie, &autocomplete_path)?;
+        trie
+    };
+    let spell_corrector = util::spell::SpellCorrector::build(&pre);
+    let mut analyzer = util::analyzer::Analyzer::from_config(&config.analyzer, &storage.data_dir)?;
+    analyzer.set_collocations(pre.collocations.clone());
+    analyzer.set_synonyms(pre.synonyms.clone());
+    analyzer.set_subwords(pre.subwords.clone());
+    let title_terms = util::title_terms::TitleTermIndex::build(&pre.documents, &analyzer);
+    let query_suggester = util::suggest::QuerySuggester::new(config.history.suggestion_half_life_hours * 3600.0);
+    if let Some(history) = &history {
+        for (query, searched_at) in history.all_queries()? {
+            query_suggester.record(&query, searched_at);
+        }
+    }
+
+    let jobs = Arc::new(util::jobs::JobQueue::open(&storage.resolve(util::jobs::FILE_NAME))?);
+    let boost_rules = load_boost_rules(&config)?;
+    // Deployment-specific `ResultFilter`s are registered here with `ResultFilters::add`.
+    let result_filters = util::result_filter::ResultFilters::from_config(&config.content_filter, storage)?;
+    let blocklist = util::blocklist::Blocklist::load(&storage.resolve(util::blocklist::FILE_NAME), &pre.documents)?;
+    let views = util::views::Views::load(&storage.resolve(util::views::FILE_NAME))?;
 
     let state = web::Data::new(AppState {
-        preprocessed_data: Arc::new(pre),
-        svd_data: Arc::new(svd_data),
-        k,
+        preprocessed_data: ArcSwap::from_pointee(pre),
+        svd_models,
+        title_index: Arc::new(title_index),
+        document_ids,
+        entity_index: Arc::new(entity_index),
+        metadata_index,
+        exact_title_index: Arc::new(exact_title_index),
+        title_terms,
+        result_sets: util::doc_set::ResultSetStore::new(
+            Duration::from_secs(config.search.result_set_ttl_secs),
+            config.search.max_result_sets,
+        ),
+        query_cache: util::query_cache::QueryCache::new(
+            Duration::from_secs(config.search.query_cache_ttl_secs),
+            config.search.max_cached_queries,
+        ),
+        languages: ArcSwap::from_pointee(languages),
+        cross_language,
+        admission: util::admission::AdmissionControl::new(
+            config.server.concurrent_search_count(),
+            config.server.max_queued_searches,
+            Duration::from_millis(config.server.queue_timeout_ms),
+        ),
+        scrolls: util::scroll::ScrollStore::new(
+            Duration::from_secs(config.search.scroll_ttl_secs),
+            config.search.max_scrolls,
+        ),
+        config: ArcSwap::from_pointee(config),
+        query_likelihood,
+        bm25,
+        boost_rules: ArcSwap::from_pointee(boost_rules),
+        blocklist: ArcSwap::from_pointee(blocklist),
+        blocklist_edits: Mutex::new(()),
+        views: ArcSwap::from_pointee(views),
+        view_edits: Mutex::new(()),
+        view_stats: util::views::ViewStats::default(),
+        cost_model: util::explain::CostModel::default(),
+        result_filters,
+        history,
+        slow_queries,
+        svd_breaker: util::circuit_breaker::CircuitBreaker::new("SVD search"),
+        jobs,
+        term_suggester,
+        autocomplete: autocomplete_index,
+        query_suggester,
+        spell_corrector,
+        analyzer,
+        manifest: ArcSwapOption::from_pointee(manifest),
+        corpus_stats: corpus_stats.map(Arc::new),
         noise_filter_k,
     });
 
-    println!("Starting API server on http://127.0.0.1:8080");
+    let server_config = state.config.load().server.clone();
+    if server_config.warmup_enabled {
+        warm_up(&state, &server_config.warmup_queries);
+    }
+    if state.config.load().snapshots.enabled {
+        schedule_snapshots(snapshots.clone(), state.jobs.clone());
+    }
+    let worker_state = state.clone();
+    state.jobs.start_worker(move |kind, cancelled| match kind {
+        util::jobs::JobKind::Snapshot => {
+            let _lock = util::data::CacheLock::acquire(&lock_path)?;
+            if cancelled() {
+                return Err("Cancelled".into());
+            }
+            let entry = index_paths.snapshot(&snapshots)?;
+            log::info!("Created snapshot {}", entry.id);
+            Ok(())
+        }
+        util::jobs::JobKind::BuildSvd { rank } => build_svd_model(&worker_state, &lock_path, *rank, cancelled),
+        util::jobs::JobKind::BuildLanguageSvd { language, rank } => {
+            build_language_svd_model(&worker_state, &lock_path, language, *rank, cancelled)
+        }
+    });
+
+    let reload_state = state.clone();
+    let _config_watcher = util::config::watch(&config_path.clone(), move |new_config| {
+        let config = reload_state.config.load_full();
+        let mut updated = util::config::Config::clone(&config);
+        let rejected = updated.apply_reload(new_config);
+        if !rejected.is_empty() {
+            log::warn!(
+                "Config changes to {} need a restart (analyzer and entity settings also need an index rebuild: delete {}); keeping the current values",
+                rejected.join(", "),
+                preproc_index.display(),
+            );
+        }
+        if updated == *config {
+            return;
+        }
+
+        reload_state.result_sets.set_limits(
+            Duration::from_secs(updated.search.result_set_ttl_secs),
+            updated.search.max_result_sets,
+        );
+        reload_state.query_cache.set_limits(
+            Duration::from_secs(updated.search.query_cache_ttl_secs),
+            updated.search.max_cached_queries,
+        );
+        reload_state.scrolls.set_limits(
+            Duration::from_secs(updated.search.scroll_ttl_secs),
+            updated.search.max_scrolls,
+        );
+        reload_state.config.store(Arc::new(updated));
+        log::info!("Reloaded config from {}", config_path);
+    })?;
+
+    let _boost_rules_watcher = match &server_config.boost_rules_path {
+        Some(path) => {
+            let path = state.config.load().storage.resolve(path);
+            let reload_state = state.clone();
+            Some(util::config::watch_file(&path.clone(), move || {
+                match util::boost_rules::BoostRules::load(&path) {
+                    Ok(rules) => {
+                        reload_state.boost_rules.store(Arc::new(rules));
+                        log::info!("Reloaded boost rules from {}", path.display());
+                    }
+                    Err(e) => log::warn!("Ignoring change to {}: {}", path.display(), e),
+                }
+            })?)
+        }
+        None => None,
+    };
+
+    let static_dir = server_config.static_dir.clone();
+    if let Some(dir) = &static_dir {
+        log::info!("Serving frontend from {}", dir);
+    }
+
+    log::info!(
+        "Starting API server on http://{} with {} workers and {} scoring threads",
+        server_config.bind_address,
+        server_config.worker_count(),
+        server_config.scoring_thread_count(),
+    );
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_any_header()
             .max_age(3600);
 
         App::new()
             .wrap(cors)
             .app_data(state.clone())
-            .service(get_stats)
-            .service(get_document)
-            .route("/search", web::post().to(search_handler))
+            .service(web::scope("/v1").configure(api_v1))
+            // Unprefixed paths predate versioning and stay aliases of v1 for existing clients.
+            .configure(api_v1)
+            .configure(|cfg| {
+                if let Some(dir) = &static_dir {
+                    cfg.service(frontend_files(dir));
+                }
+            })
     })
-        .bind("127.0.0.1:8080")?
+        .workers(server_config.worker_count())
+        .bind(&server_config.bind_address)?
         .run()
         .await?;
 
     Ok(())
 }
 
-fn serialize_matrix(m: &DMatrix<f64>) -> SerMatrix {
-    SerMatrix {
-        nrows: m.nrows(),
-        ncols: m.ncols(),
-        data: m.iter().cloned().collect(),
+/// The cache files that make up the served index, as far as snapshots are concerned.
+struct IndexPaths {
+    preprocessed: PathBuf,
+    svd: PathBuf,
+    cross_language: PathBuf,
+    corpus_stats: PathBuf,
+    autocomplete: PathBuf,
+}
+
+impl IndexPaths {
+    /// Every artifact that exists on disk, with the component files of each index.
+    fn existing_files(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut files = Vec::new();
+        for index in [&self.preprocessed, &self.svd, &self.cross_language] {
+            if index.exists() {
+                files.extend(util::data::index_files(index)?);
+            }
+        }
+        for file in [util::manifest::manifest_path(&self.preprocessed), self.corpus_stats.clone(), self.autocomplete.clone()] {
+            if file.exists() {
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    /// The caller must hold the cache lock.
+    fn snapshot(&self, snapshots: &util::snapshot::SnapshotManager) -> Result<util::snapshot::SnapshotEntry, Box<dyn Error>> {
+        let files = self.existing_files()?;
+        if files.is_empty() {
+            return Err("There is no index to snapshot yet".into());
+        }
+        let manifest_path = util::manifest::manifest_path(&self.preprocessed);
+        let index_version = if manifest_path.exists() {
+            Some(util::manifest::load_manifest(&manifest_path)?.version())
+        } else {
+            None
+        };
+        snapshots.create(&files, index_version)
+    }
+}
+
+/// Computes the SVD model of `rank` from the index as currently weighted, saves it and starts
+/// serving it in place of any model of the same rank.
+fn build_svd_model(state: &AppState, lock_path: &Path, rank: usize, cancelled: &dyn Fn() -> bool) -> Result<(), Box<dyn Error>> {
+    let pre = state.preprocessed_data.load_full();
+    let config = state.config.load();
+    let path = config.storage.resolve(format!("svd_k{}.idx", rank));
+    let mut svd = util::svd::perform_svd(&pre.term_doc_csr.to_csr(), rank, &config.svd, Some(&util::svd::checkpoint_path(&path)))?;
+    if cancelled() {
+        return Err("Cancelled".into());
+    }
+    svd.doc_ids = util::document_ids::column_ids(&pre.documents);
+
+    let _lock = util::data::CacheLock::acquire(lock_path)?;
+    if !Arc::ptr_eq(&pre, &state.preprocessed_data.load()) {
+        return Err("The index was reweighted while the model was built; queue it again".into());
     }
+    util::data::save_svd_data(&svd, &path)?;
+    state.svd_models.register(rank, path, Arc::new(svd))?;
+    Ok(())
 }
-fn deserialize_matrix(s: &SerMatrix) -> DMatrix<f64> {
-    DMatrix::from_row_slice(s.nrows, s.ncols, &s.data)
-=======
-fn save_cached_data(data: &CachedData, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::create(path)?;
-    let writer = BufWriter::new(file);
-    bincode::serialize_into(writer, data)?;
+
+fn build_language_svd_model(state: &AppState, lock_path: &Path, language: &str, rank: usize, cancelled: &dyn Fn() -> bool) -> Result<(), Box<dyn Error>> {
+    let languages = state.languages.load_full();
+    let partition = languages.get(language)
+        .ok_or_else(|| format!("There is no partition for language {:?}", language))?;
+    let config = state.config.load();
+    let path = util::language::svd_path(&config.storage.data_dir, language, rank);
+    let svd = partition.compute_svd(rank, &config.svd, Some(&util::svd::checkpoint_path(&path)))?;
+    if cancelled() {
+        return Err("Cancelled".into());
+    }
+
+    let _lock = util::data::CacheLock::acquire(lock_path)?;
+    if !Arc::ptr_eq(&languages, &state.languages.load()) {
+        return Err("The index was reweighted while the model was built; it is rebuilt on next use".into());
+    }
+    util::data::save_svd_data(&svd, &path)?;
+    partition.set_svd(Arc::new(svd));
     Ok(())
->>>>>>> Stashed changes
-}
\ No newline at end of file
+}
+
+/// Queues a language partition's SVD model of `rank` unless it is already queued, for LSI
+/// searches routed to a partition without one.
+fn request_language_svd(data: &AppState, language: &str, rank: usize) {
+    let kind = util::jobs::JobKind::BuildLanguageSvd { language: language.to_string(), rank };
+    let queued = data.jobs.has_pending(&kind)
+        .and_then(|pending| if pending { Ok(None) } else { data.jobs.enqueue(kind).map(Some) });
+    match queued {
+        Ok(Some(job)) => log::info!("Queued the {} partition's SVD model (k={}) as job {}", language, rank, job.id),
+        Ok(None) => {}
+        Err(e) => log::warn!("Can't queue the {} partition's SVD model: {}", language, e),
+    }
+}
+
+/// Queues a snapshot job whenever the latest snapshot is `interval_hours` old.
+fn schedule_snapshots(snapshots: util::snapshot::SnapshotManager, jobs: Arc<util::jobs::JobQueue>) {
+    const RETRY_SECS: u64 = 3600;
+    std::thread::spawn(move || loop {
+        let wait = match snapshots.next_due() {
+            Ok(due) => (due - util::history::unix_now()).max(0) as u64,
+            Err(e) => {
+                log::warn!("Can't read the snapshot list: {}", e);
+                RETRY_SECS
+            }
+        };
+        std::thread::sleep(Duration::from_secs(wait));
+
+        // Until the job has run the snapshot list still says one is due; don't queue it twice.
+        let queued = jobs.has_pending(&util::jobs::JobKind::Snapshot)
+            .and_then(|pending| if pending { Ok(None) } else { jobs.enqueue(util::jobs::JobKind::Snapshot).map(Some) });
+        match queued {
+            Ok(Some(job)) => log::info!("Queued scheduled snapshot as job {}", job.id),
+            Ok(None) => {}
+            Err(e) => log::warn!("Can't queue a scheduled snapshot: {}", e),
+        }
+        // A failed snapshot is retried after this long.
+        std::thread::sleep(Duration::from_secs(RETRY_SECS));
+    });
+}
+
+/// Version 1 of the HTTP API. Breaking changes go under a new prefix instead of changing these.
+fn api_v1(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_stats)
+        .service(readyz)
+        .service(get_document)
+        .service(more_like_this)
+        .service(get_entity_facets)
+        .service(get_metadata_facets)
+        .service(get_trends)
+        .service(get_corpus_stats)
+        .service(reweight)
+        .service(analyze)
+        .service(get_manifest)
+        .service(get_svd_models)
+        .service(get_slow_queries)
+        .service(get_jobs)
+        .service(get_job)
+        .service(create_job)
+        .service(cancel_job)
+        .service(get_boost_rules)
+        .service(reload_boost_rules)
+        .service(get_blocklist)
+        .service(add_to_blocklist)
+        .service(remove_from_blocklist)
+        .service(get_views)
+        .service(put_view)
+        .service(delete_view)
+        .service(get_languages)
+        .service(get_history)
+        .service(delete_history)
+        .service(suggest)
+        .service(autocomplete)
+        .service(scroll_search)
+        .service(rescore)
+        .service(search_similar)
+        .service(document_similarity)
+        .service(close_scroll)
+        .route("/search", web::post().to(search_handler));
+}
+
+/// Runs each query through every ranking method, touching the term-document matrix, both SVD
+/// factorizations and the probabilistic models once before the listener starts.
+fn warm_up(state: &AppState, queries: &[String]) {
+    let start = Instant::now();
+    let pre = state.preprocessed_data.load_full();
+    let search_config = state.config.load().search.clone();
+    let queries: Vec<String> = if queries.is_empty() {
+        pre.documents.iter().take(3).map(|doc| doc.title.clone()).collect()
+    } else {
+        queries.to_vec()
+    };
+
+    let csr = pre.term_doc_csr.to_csr();
+    let top_k = search_config.default_limit;
+    let svd_models: Vec<Arc<SvdData>> = state.svd_models.models().into_iter()
+        .filter(|model| model.loaded)
+        .filter_map(|model| state.svd_models.get(model.rank).ok())
+        .chain(state.cross_language.clone())
+        .collect();
+    for query in &queries {
+        let mut runs = vec![
+            util::search::search(query, &pre, &state.analyzer, &csr, None, top_k),
+            util::search::search_qlm(query, &pre, &state.analyzer, &state.query_likelihood, &search_config, None, top_k),
+            util::search::search_bm25(query, &pre, &state.analyzer, &state.bm25, &search_config, None, top_k),
+        ];
+        for svd in &svd_models {
+            runs.push(util::search::search_svd(query, &pre, &state.analyzer, svd, &search_config, None, top_k));
+            runs.push(util::search::search_with_low_rank(query, &pre, &state.analyzer, svd, Some(svd.rank), &search_config, None, top_k));
+        }
+        if let Some(Err(e)) = runs.into_iter().find(Result::is_err) {
+            log::warn!("Warm-up query {:?} failed: {}", query, e);
+        }
+    }
+    log::info!("Warmed up with {} queries in {:?}", queries.len(), start.elapsed());
+}
+
+/// Static frontend assets; unknown paths get `index.html` so client-side routes work on reload.
+fn frontend_files(dir: &str) -> actix_files::Files {
+    let index_path = Path::new(dir).join("index.html");
+    actix_files::Files::new("/", dir)
+        .index_file("index.html")
+        .default_handler(web::to(move || {
+            let index_path = index_path.clone();
+            async move { actix_files::NamedFile::open_async(index_path).await }
+        }))
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Caps how many searches score at once. Up to `max_queued` more wait for a slot, for at most
+/// `queue_timeout`; anything beyond that is turned away so a burst can't pile up large SVD
+/// temporaries in memory.
+pub struct AdmissionControl {
+    slots: Semaphore,
+    queued: AtomicUsize,
+    max_queued: usize,
+    queue_timeout: Duration,
+}
+
+impl AdmissionControl {
+    pub fn new(max_concurrent: usize, max_queued: usize, queue_timeout: Duration) -> Self {
+        AdmissionControl {
+            slots: Semaphore::new(max_concurrent.max(1)),
+            queued: AtomicUsize::new(0),
+            max_queued,
+            queue_timeout,
+        }
+    }
+
+    /// A slot to score in, held until the permit is dropped, or `None` when overloaded.
+    pub async fn admit(&self) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.slots.try_acquire() {
+            return Some(permit);
+        }
+        if self.queued.fetch_add(1, Ordering::AcqRel) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        let permit = actix_web::rt::time::timeout(self.queue_timeout, self.slots.acquire()).await;
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+        permit.ok()?.ok()
+    }
+}
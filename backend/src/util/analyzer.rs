@@ -0,0 +1,325 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::error::Error;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::{util, Document};
+use crate::util::collocations::{Collocations, PHRASE_SEPARATOR};
+use crate::util::lemmatizer::Lemmatizer;
+use crate::util::subwords::Subwords;
+use crate::util::synonyms::Synonyms;
+use crate::util::tokenizer::{self, JoinerPolicy, SymbolPolicy, TokenizerOptions};
+use crate::util::transliterate::Transliterator;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    Porter,
+    Lemma,
+    /// Byte-pair-encoding pieces learned from the corpus (see `util::subwords`), for any language.
+    Subword,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AnalyzerConfig {
+    pub stop_words_path: String,
+    pub normalization: Normalization,
+    pub lemma_dictionary_path: Option<String>,
+    pub gazetteer_path: Option<String>,
+    /// One word per line; these are indexed as written, never stemmed or lemmatized.
+    pub protected_words_path: Option<String>,
+    /// `term<TAB>stem` per line, pinning the normalized form of a word.
+    pub stem_exceptions_path: Option<String>,
+    pub detect_collocations: bool,
+    pub collocation_min_count: usize,
+    pub collocation_min_dice: f64,
+    /// Transliteration rule sets applied before tokenizing, on both the index and the query side,
+    /// e.g. `["pl"]` so "zolw" finds "żółw". See `util::transliterate` for the available sets.
+    pub transliteration: Vec<String>,
+    /// Whether "state-of-the-art" is indexed as its parts, as one term, or both; same for
+    /// apostrophes as in "O'Brien". Applies to documents and queries alike.
+    pub hyphens: JoinerPolicy,
+    pub apostrophes: JoinerPolicy,
+    /// Whether emoji and symbols are dropped or indexed as tokens of their own.
+    pub symbols: SymbolPolicy,
+    /// Tokens shorter or longer than this many characters are dropped.
+    pub min_token_length: usize,
+    pub max_token_length: usize,
+    /// Terms indexed whatever their length and never stemmed, e.g. `["ai", "go", "c#", "c++"]`.
+    pub keep_terms: Vec<String>,
+    /// Synonyms queries are expanded with (see `util::synonyms`), read when the index is built.
+    pub synonyms_path: Option<String>,
+    /// Query weight of a synonym relative to the word it was expanded from.
+    pub synonym_weight: f64,
+    /// Merges learned for subword normalization; more keeps more words whole.
+    pub subword_merges: usize,
+    /// How many body occurrences a title term counts as in the term-document matrix; 0 indexes
+    /// body text only.
+    pub title_weight: u32,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        AnalyzerConfig {
+            stop_words_path: "english.txt".to_string(),
+            normalization: Normalization::Porter,
+            lemma_dictionary_path: None,
+            gazetteer_path: None,
+            protected_words_path: None,
+            stem_exceptions_path: None,
+            detect_collocations: true,
+            collocation_min_count: 20,
+            collocation_min_dice: 0.3,
+            transliteration: Vec::new(),
+            hyphens: JoinerPolicy::default(),
+            apostrophes: JoinerPolicy::default(),
+            symbols: SymbolPolicy::default(),
+            min_token_length: 3,
+            max_token_length: 40,
+            keep_terms: Vec::new(),
+            synonyms_path: None,
+            synonym_weight: 0.5,
+            subword_merges: 16000,
+            title_weight: 2,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AnalysisStages {
+    pub transliterated: String,
+    pub tokenized: Vec<String>,
+    pub collocations: Vec<String>,
+    pub stop_words_removed: Vec<String>,
+    pub normalized: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Normalizer {
+    Porter,
+    Lemma(Lemmatizer),
+    Subword,
+}
+
+/// Turns raw text into index terms: tokenize, join collocations, drop stop words,
+/// then stem, lemmatize or split into subwords. Serializes with its resources loaded, so it
+/// can be restored where the files aren't available.
+#[derive(Serialize, Deserialize)]
+pub struct Analyzer {
+    stop_words: HashSet<String>,
+    normalizer: Normalizer,
+    protected_words: HashSet<String>,
+    stem_exceptions: HashMap<String, String>,
+    collocations: Collocations,
+    synonyms: Synonyms,
+    subwords: Subwords,
+    transliterator: Transliterator,
+    tokenizer: TokenizerOptions,
+}
+
+impl Analyzer {
+    /// Relative resource paths in `config` are resolved against `data_dir`.
+    pub fn from_config(config: &AnalyzerConfig, data_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let stop_words = util::tokenizer::load_stop_words(&data_dir.join(&config.stop_words_path)).unwrap_or_else(|e| {
+            log::warn!("Could not load stop words file: {}. Continuing without stop words.", e);
+            HashSet::new()
+        });
+
+        let normalizer = match config.normalization {
+            Normalization::Porter => Normalizer::Porter,
+            Normalization::Lemma => {
+                let path = config.lemma_dictionary_path.as_deref()
+                    .ok_or("Lemma normalization requires `lemma_dictionary_path` in the analyzer config")?;
+                let path = data_dir.join(path);
+                log::info!("Loading lemma dictionary from {}...", path.display());
+                let lemmatizer = Lemmatizer::load(&path)?;
+                log::info!("Lemma dictionary loaded with {} forms", lemmatizer.form_count());
+                Normalizer::Lemma(lemmatizer)
+            }
+            // Learned from the corpus by `learn_subwords`, or loaded with the index.
+            Normalization::Subword => Normalizer::Subword,
+        };
+
+        let collocations = match &config.gazetteer_path {
+            Some(path) => {
+                let path = data_dir.join(path);
+                log::info!("Loading gazetteer from {}...", path.display());
+                Collocations::load_gazetteer(&path)?
+            }
+            None => Collocations::default(),
+        };
+
+        let protected_words = match &config.protected_words_path {
+            Some(path) => {
+                let path = data_dir.join(path);
+                log::info!("Loading protected words from {}...", path.display());
+                load_protected_words(&path)?
+            }
+            None => HashSet::new(),
+        };
+
+        let stem_exceptions = match &config.stem_exceptions_path {
+            Some(path) => {
+                let path = data_dir.join(path);
+                log::info!("Loading stem exceptions from {}...", path.display());
+                load_stem_exceptions(&path)?
+            }
+            None => HashMap::new(),
+        };
+
+        let transliterator = Transliterator::new(&config.transliteration)?;
+
+        Ok(Analyzer {
+            stop_words,
+            normalizer,
+            protected_words,
+            stem_exceptions,
+            collocations,
+            synonyms: Synonyms::default(),
+            subwords: Subwords::default(),
+            transliterator,
+            tokenizer: TokenizerOptions {
+                hyphens: config.hyphens,
+                apostrophes: config.apostrophes,
+                symbols: config.symbols,
+                min_length: config.min_token_length,
+                max_length: config.max_token_length,
+                keep: config.keep_terms.iter().map(|term| term.to_lowercase()).collect(),
+            },
+        })
+    }
+
+    /// Adds collocations found in the corpus to the gazetteer ones, if the config asks for it.
+    pub fn learn_collocations(&mut self, config: &AnalyzerConfig, documents: &[Document]) {
+        if config.detect_collocations {
+            let detected = Collocations::detect(documents, config.collocation_min_count, config.collocation_min_dice);
+            self.collocations.extend(detected);
+        }
+    }
+
+    /// Learns the subword vocabulary from the corpus, if the config normalizes to subwords.
+    /// Run after `learn_collocations`, since phrases are kept whole.
+    pub fn learn_subwords(&mut self, config: &AnalyzerConfig, documents: &[Document]) {
+        if !matches!(self.normalizer, Normalizer::Subword) {
+            return;
+        }
+        let mut word_counts: HashMap<String, usize> = HashMap::new();
+        for doc in documents {
+            for token in self.collocations.merge(self.tokenize(&self.transliterator.apply(&doc.text))) {
+                if !self.stop_words.contains(&token) && !self.is_kept_whole(&token) {
+                    *word_counts.entry(token).or_insert(0) += 1;
+                }
+            }
+        }
+        self.subwords = Subwords::learn(&word_counts, config.subword_merges);
+    }
+
+    pub fn subwords(&self) -> &Subwords {
+        &self.subwords
+    }
+
+    /// Uses the subword vocabulary an index was built with instead of relearning it.
+    pub fn set_subwords(&mut self, subwords: Subwords) {
+        self.subwords = subwords;
+    }
+
+    pub fn collocations(&self) -> &Collocations {
+        &self.collocations
+    }
+
+    /// Uses the collocations an index was built with instead of re-detecting them.
+    pub fn set_collocations(&mut self, collocations: Collocations) {
+        self.collocations = collocations;
+    }
+
+    pub fn synonyms(&self) -> &Synonyms {
+        &self.synonyms
+    }
+
+    /// Uses the synonyms an index was built with for expanding queries.
+    pub fn set_synonyms(&mut self, synonyms: Synonyms) {
+        self.synonyms = synonyms;
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.collocations.merge(self.tokenize(&self.transliterator.apply(text)))
+            .into_iter()
+            .filter(|token| !self.stop_words.contains(token))
+            .flat_map(|token| self.normalize(&token))
+            .collect()
+    }
+
+    /// Same as `analyze`, keeping the token stream after every stage.
+    pub fn analyze_stages(&self, text: &str) -> AnalysisStages {
+        let transliterated = self.transliterator.apply(text).into_owned();
+        let tokenized = self.tokenize(&transliterated);
+        let collocations = self.collocations.merge(tokenized.clone());
+        let stop_words_removed: Vec<String> = collocations.iter()
+            .filter(|token| !self.stop_words.contains(*token))
+            .cloned()
+            .collect();
+        let normalized = stop_words_removed.iter().flat_map(|token| self.normalize(token)).collect();
+
+        AnalysisStages { transliterated, tokenized, collocations, stop_words_removed, normalized }
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        util::tokenizer::tokenize_with(text, &self.tokenizer)
+    }
+
+    // Subword normalization may split a token into several terms.
+    fn normalize(&self, token: &str) -> Vec<String> {
+        if self.is_kept_whole(token) {
+            return vec![token.to_string()];
+        }
+        if let Some(stem) = self.stem_exceptions.get(token) {
+            return vec![stem.clone()];
+        }
+
+        match &self.normalizer {
+            Normalizer::Porter => vec![util::steming::porter_stem(token)],
+            Normalizer::Lemma(lemmatizer) => vec![lemmatizer.lemmatize(token)],
+            Normalizer::Subword => self.subwords.split(token),
+        }
+    }
+
+    fn is_kept_whole(&self, token: &str) -> bool {
+        // Phrases are names and protected words are curated as written; stemming would only mangle
+        // them. Hyphenated and apostrophized words kept whole, and kept terms, are treated the same way.
+        token.contains(PHRASE_SEPARATOR)
+            || token.contains(['-', '\''])
+            || self.protected_words.contains(token)
+            || self.tokenizer.keep.contains(token)
+            || tokenizer::is_symbol(token)
+    }
+}
+
+fn load_protected_words(filename: &Path) -> std::io::Result<HashSet<String>> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut words = HashSet::new();
+    for line in reader.lines() {
+        let word = line?.trim().to_lowercase();
+        if !word.is_empty() {
+            words.insert(word);
+        }
+    }
+    Ok(words)
+}
+
+fn load_stem_exceptions(filename: &Path) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut exceptions = HashMap::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (term, stem) = line.split_once('\t')
+            .ok_or_else(|| format!("{}: line {} is not `term<TAB>stem`", filename.display(), line_no + 1))?;
+        exceptions.insert(term.trim().to_lowercase(), stem.trim().to_lowercase());
+    }
+    Ok(exceptions)
+}
@@ -0,0 +1,211 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use serde::{Deserialize, Serialize};
+use crate::PreprocessedData;
+use crate::util::title_index::normalize_title;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    /// An indexed (normalized) term.
+    Term,
+    /// A document title.
+    Title,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Completion {
+    pub text: String,
+    pub kind: CompletionKind,
+    /// Documents containing the term, or with the title.
+    pub frequency: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Node {
+    // (edge label, child), sorted by the label's first character; labels never share one
+    children: Vec<(String, u32)>,
+    // indices into `completions` of the keys ending here
+    entries: Vec<u32>,
+    // highest frequency in this subtree, so lookups can visit the best branches first
+    best: usize,
+}
+
+/// Radix trie over vocabulary terms and lowercased document titles, for prefix completion.
+/// Built with the index and cached next to it.
+#[derive(Serialize, Deserialize)]
+pub struct Autocomplete {
+    nodes: Vec<Node>,
+    completions: Vec<Completion>,
+}
+
+impl Autocomplete {
+    pub fn build(data: &PreprocessedData) -> Self {
+        let mut trie = Autocomplete { nodes: vec![Node::default()], completions: Vec::new() };
+
+        let matrix = &data.term_doc_csr;
+        let terms: BTreeMap<&String, usize> = data.term_dict.iter()
+            .map(|(term, &idx)| (term, matrix.row_offsets[idx + 1] - matrix.row_offsets[idx]))
+            .collect();
+        for (term, frequency) in terms {
+            trie.insert(term, Completion { text: term.clone(), kind: CompletionKind::Term, frequency });
+        }
+
+        // Documents sharing a title (up to case and spacing) count towards one completion.
+        let mut titles: BTreeMap<String, (&str, usize)> = BTreeMap::new();
+        for doc in data.documents.iter() {
+            let key = normalize_title(&doc.title);
+            if !key.is_empty() {
+                titles.entry(key).or_insert((doc.title.trim(), 0)).1 += 1;
+            }
+        }
+        for (key, (title, frequency)) in titles {
+            trie.insert(&key, Completion { text: title.to_string(), kind: CompletionKind::Title, frequency });
+        }
+
+        trie.compute_best();
+        trie
+    }
+
+    pub fn len(&self) -> usize {
+        self.completions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.completions.is_empty()
+    }
+
+    /// Terms and titles starting with `prefix` (compared lowercased, with runs of whitespace as
+    /// one space), most frequent first; only those of `kind` when given.
+    pub fn complete(&self, prefix: &str, kind: Option<CompletionKind>, limit: usize) -> Vec<&Completion> {
+        let prefix = normalize_title(prefix);
+        let Some(root) = self.subtree(&prefix).filter(|_| !prefix.is_empty()) else {
+            return Vec::new();
+        };
+
+        // Best-first over subtrees and entries keyed by frequency; ties in insertion order,
+        // which follows the keys' order.
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        enum Item {
+            Node(u32),
+            Entry(u32),
+        }
+        let mut queue = BinaryHeap::new();
+        let mut pushed = 0u64;
+        let mut push = |queue: &mut BinaryHeap<_>, frequency: usize, item: Item| {
+            queue.push((frequency, Reverse(pushed), item));
+            pushed += 1;
+        };
+        push(&mut queue, self.nodes[root].best, Item::Node(root as u32));
+
+        let mut results = Vec::new();
+        while results.len() < limit {
+            let Some((_, _, item)) = queue.pop() else {
+                break;
+            };
+            match item {
+                Item::Entry(idx) => results.push(&self.completions[idx as usize]),
+                Item::Node(node) => {
+                    let node = &self.nodes[node as usize];
+                    for &idx in &node.entries {
+                        let completion = &self.completions[idx as usize];
+                        if kind.is_none_or(|kind| completion.kind == kind) {
+                            push(&mut queue, completion.frequency, Item::Entry(idx));
+                        }
+                    }
+                    for &(_, child) in &node.children {
+                        push(&mut queue, self.nodes[child as usize].best, Item::Node(child));
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    fn insert(&mut self, key: &str, completion: Completion) {
+        let entry = self.completions.len() as u32;
+        self.completions.push(completion);
+
+        let mut node = 0;
+        let mut rest = key;
+        loop {
+            let Some(first) = rest.chars().next() else {
+                self.nodes[node].entries.push(entry);
+                return;
+            };
+            match self.child_position(node, first) {
+                Err(pos) => {
+                    let leaf = self.push_node();
+                    self.nodes[leaf].entries.push(entry);
+                    self.nodes[node].children.insert(pos, (rest.to_string(), leaf as u32));
+                    return;
+                }
+                Ok(pos) => {
+                    let (label, child) = self.nodes[node].children[pos].clone();
+                    let common = common_prefix_len(&label, rest);
+                    if common < label.len() {
+                        // Split the edge where the key leaves it.
+                        let middle = self.push_node();
+                        self.nodes[middle].children.push((label[common..].to_string(), child));
+                        self.nodes[node].children[pos] = (label[..common].to_string(), middle as u32);
+                        node = middle;
+                    } else {
+                        node = child as usize;
+                    }
+                    rest = &rest[common..];
+                }
+            }
+        }
+    }
+
+    fn compute_best(&mut self) {
+        // Preorder, so reversed every child comes before its parent.
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0usize];
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            stack.extend(self.nodes[node].children.iter().map(|&(_, child)| child as usize));
+        }
+        for &node in order.iter().rev() {
+            let own = self.nodes[node].entries.iter().map(|&idx| self.completions[idx as usize].frequency).max();
+            let children = self.nodes[node].children.iter().map(|&(_, child)| self.nodes[child as usize].best).max();
+            self.nodes[node].best = own.into_iter().chain(children).max().unwrap_or(0);
+        }
+    }
+
+    // The node whose subtree holds exactly the keys starting with `prefix`.
+    fn subtree(&self, prefix: &str) -> Option<usize> {
+        let mut node = 0;
+        let mut rest = prefix;
+        while let Some(first) = rest.chars().next() {
+            let pos = self.child_position(node, first).ok()?;
+            let (label, child) = &self.nodes[node].children[pos];
+            if let Some(remaining) = rest.strip_prefix(label.as_str()) {
+                rest = remaining;
+                node = *child as usize;
+            } else if label.starts_with(rest) {
+                return Some(*child as usize);
+            } else {
+                return None;
+            }
+        }
+        Some(node)
+    }
+
+    fn child_position(&self, node: usize, first: char) -> Result<usize, usize> {
+        self.nodes[node].children.binary_search_by_key(&first, |(label, _)| label.chars().next().unwrap_or_default())
+    }
+
+    fn push_node(&mut self) -> usize {
+        self.nodes.push(Node::default());
+        self.nodes.len() - 1
+    }
+}
+
+// Length in bytes of the longest common prefix, ending on a character boundary.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or_else(|| a.len().min(b.len()), |((idx, _), _)| idx)
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::Document;
+use crate::util::doc_set::DocSet;
+
+/// Where the blocklist is kept, in the data directory.
+pub const FILE_NAME: &str = "blocklist.json";
+
+/// Documents to keep out of every result, by ID or by a regex over the URL.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct BlocklistEntries {
+    pub document_uuids: BTreeSet<String>,
+    /// SQLite rowids; these change when the database is rebuilt, unlike `document_uuids`.
+    pub document_ids: BTreeSet<i64>,
+    pub url_patterns: Vec<String>,
+}
+
+/// The entries with the documents they block resolved to a bitset, so scoring can skip them
+/// without touching the index.
+pub struct Blocklist {
+    entries: BlocklistEntries,
+    blocked: DocSet,
+    blocked_count: usize,
+}
+
+impl Blocklist {
+    pub fn new(entries: BlocklistEntries, documents: &[Document]) -> Result<Self, regex::Error> {
+        let patterns = entries.url_patterns.iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut blocked = DocSet::empty(documents.len());
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            if entries.document_uuids.contains(&doc.uuid) || entries.document_ids.contains(&doc.id) || patterns.iter().any(|re| re.is_match(&doc.url)) {
+                blocked.insert(doc_idx);
+            }
+        }
+        let blocked_count = blocked.count();
+        Ok(Blocklist { entries, blocked, blocked_count })
+    }
+
+    /// Reads the entries saved at `path`; none when the file doesn't exist yet.
+    pub fn load(path: &Path, documents: &[Document]) -> Result<Self, Box<dyn Error>> {
+        let entries = if path.exists() {
+            serde_json::from_reader(BufReader::new(File::open(path)?))?
+        } else {
+            BlocklistEntries::default()
+        };
+        let blocklist = Blocklist::new(entries, documents)?;
+        if blocklist.blocked_count > 0 {
+            log::info!("Blocklist excludes {} documents", blocklist.blocked_count);
+        }
+        Ok(blocklist)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Written aside and renamed so a crash never leaves a truncated list.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.entries)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &BlocklistEntries {
+        &self.entries
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.blocked_count
+    }
+
+    /// `scope` without the blocked documents; `None` (everything) stays `None` when nothing is blocked.
+    pub fn exclude_from(&self, scope: Option<DocSet>, num_docs: usize) -> Option<DocSet> {
+        if self.blocked_count == 0 {
+            return scope;
+        }
+        let mut scope = scope.unwrap_or_else(|| DocSet::full(num_docs));
+        scope.subtract(&self.blocked);
+        Some(scope)
+    }
+}
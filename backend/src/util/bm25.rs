@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use crate::{util, PreprocessedData};
+use crate::util::doc_set::DocSet;
+
+/// BM25 scored from raw term counts at query time, so k1 and b can differ per request.
+pub struct Bm25 {
+    doc_lengths: Vec<f64>,
+    avg_doc_length: f64,
+    idf: Vec<f64>,
+}
+
+impl Bm25 {
+    pub fn build(data: &PreprocessedData) -> Self {
+        let matrix = &data.term_doc_csr;
+        let num_docs = matrix.ncols as f64;
+
+        let mut doc_lengths = vec![0.0; matrix.ncols];
+        for (&j, &count) in matrix.col_indices.iter().zip(&data.term_counts) {
+            doc_lengths[j] += count as f64;
+        }
+        let avg_doc_length = (doc_lengths.iter().sum::<f64>() / num_docs.max(1.0)).max(1.0);
+
+        let idf = (0..matrix.nrows)
+            .map(|term_idx| {
+                let df = (matrix.row_offsets[term_idx + 1] - matrix.row_offsets[term_idx]) as f64;
+                (1.0 + (num_docs - df + 0.5) / (df + 0.5)).ln()
+            })
+            .collect();
+
+        Bm25 { doc_lengths, avg_doc_length, idf }
+    }
+
+    /// Ranks documents containing at least one query term, best first.
+    pub fn rank(
+        &self,
+        query_terms: &HashMap<usize, f64>,
+        data: &PreprocessedData,
+        k1: f64,
+        b: f64,
+        within: Option<&DocSet>,
+        top_k: usize,
+    ) -> Vec<(usize, f64)> {
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for (&term_idx, &qtf) in query_terms {
+            let row_start = data.term_doc_csr.row_offsets[term_idx];
+            let row_end = data.term_doc_csr.row_offsets[term_idx + 1];
+            for idx in row_start..row_end {
+                let doc_idx = data.term_doc_csr.col_indices[idx];
+                if within.is_some_and(|set| !set.contains(doc_idx)) {
+                    continue;
+                }
+                let tf = data.term_counts[idx] as f64;
+                let length_norm = 1.0 - b + b * self.doc_lengths[doc_idx] / self.avg_doc_length;
+                *scores.entry(doc_idx).or_insert(0.0) += qtf * self.idf[term_idx] * tf * (k1 + 1.0) / (tf + k1 * length_norm);
+            }
+        }
+
+        let mut scores: Vec<(usize, f64)> = scores.into_iter().collect();
+        let terms: Vec<usize> = query_terms.keys().copied().collect();
+        util::search::sort_ranked(&mut scores, &terms, data, top_k);
+        scores.truncate(top_k);
+        scores
+    }
+}
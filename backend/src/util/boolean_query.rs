@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use nalgebra_sparse::CsrMatrix;
+use crate::util::analyzer::Analyzer;
+use crate::util::doc_set::DocSet;
+
+/// A boolean query: words combined with `AND`, `OR` and `NOT` (upper case), grouped with
+/// parentheses. Adjacent words are ANDed, and `a NOT b` means `a AND NOT b`. `NOT` binds
+/// tightest, then `AND`, then `OR`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Word(String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn text(&self) -> &str {
+        match self {
+            Token::Word(word) => word,
+            Token::And => "AND",
+            Token::Or => "OR",
+            Token::Not => "NOT",
+            Token::Open => "(",
+            Token::Close => ")",
+        }
+    }
+}
+
+/// Whether the query uses any boolean syntax; plain queries keep their ranked meaning.
+pub fn is_boolean(query: &str) -> bool {
+    tokenize(query).iter().any(|token| !matches!(token, Token::Word(_)))
+}
+
+pub fn parse(query: &str) -> Result<Expr, String> {
+    let tokens = tokenize(query);
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(Token::Close) => Err("Unbalanced \")\" in the query".to_string()),
+        Some(token) => Err(format!("Unexpected {:?} in the query", token.text())),
+    }
+}
+
+impl Expr {
+    /// The documents satisfying the expression. A word matches the documents containing every
+    /// term it analyzes to, so `e-mail` behaves like the rest of the search.
+    pub fn evaluate(
+        &self,
+        analyzer: &Analyzer,
+        term_dict: &HashMap<String, usize>,
+        term_doc_matrix: &CsrMatrix<f64>,
+    ) -> Result<DocSet, String> {
+        let num_docs = term_doc_matrix.ncols();
+        match self {
+            Expr::Word(word) => {
+                let terms = analyzer.analyze(word);
+                if terms.is_empty() {
+                    return Err(format!("{:?} is a stop word and can't be searched for", word));
+                }
+                let mut set = DocSet::full(num_docs);
+                for term in terms {
+                    let mut docs = DocSet::empty(num_docs);
+                    if let Some(&term_idx) = term_dict.get(&term) {
+                        let row = term_doc_matrix.row_offsets()[term_idx]..term_doc_matrix.row_offsets()[term_idx + 1];
+                        for &doc_idx in &term_doc_matrix.col_indices()[row] {
+                            docs.insert(doc_idx);
+                        }
+                    }
+                    set.intersect_with(&docs);
+                }
+                Ok(set)
+            }
+            Expr::And(left, right) => {
+                let mut set = left.evaluate(analyzer, term_dict, term_doc_matrix)?;
+                set.intersect_with(&right.evaluate(analyzer, term_dict, term_doc_matrix)?);
+                Ok(set)
+            }
+            Expr::Or(left, right) => {
+                let mut set = left.evaluate(analyzer, term_dict, term_doc_matrix)?;
+                set.union_with(&right.evaluate(analyzer, term_dict, term_doc_matrix)?);
+                Ok(set)
+            }
+            Expr::Not(inner) => {
+                let mut set = DocSet::full(num_docs);
+                set.subtract(&inner.evaluate(analyzer, term_dict, term_doc_matrix)?);
+                Ok(set)
+            }
+        }
+    }
+
+    /// The words not under a `NOT`, which rank the matching documents.
+    pub fn positive_words(&self) -> Vec<&str> {
+        let mut words = Vec::new();
+        self.collect_words(false, &mut words);
+        words
+    }
+
+    fn collect_words<'a>(&'a self, negated: bool, words: &mut Vec<&'a str>) {
+        match self {
+            Expr::Word(word) if !negated => words.push(word),
+            Expr::Word(_) => {}
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.collect_words(negated, words);
+                right.collect_words(negated, words);
+            }
+            Expr::Not(inner) => inner.collect_words(!negated, words),
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for chunk in query.split_whitespace() {
+        let mut word = String::new();
+        for c in chunk.chars() {
+            if c == '(' || c == ')' {
+                if !word.is_empty() {
+                    tokens.push(word_token(std::mem::take(&mut word)));
+                }
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            } else {
+                word.push(c);
+            }
+        }
+        if !word.is_empty() {
+            tokens.push(word_token(word));
+        }
+    }
+    tokens
+}
+
+fn word_token(word: String) -> Token {
+    match word.as_str() {
+        "AND" => Token::And,
+        "OR" => Token::Or,
+        "NOT" => Token::Not,
+        _ => Token::Word(word),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.get(self.pos) == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.tokens.get(self.pos) {
+                None | Some(Token::Or | Token::Close) => return Ok(expr),
+                Some(Token::And) => self.pos += 1,
+                // Implicit AND, including `a NOT b`.
+                Some(_) => {}
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Word(word)) => Ok(Expr::Word(word)),
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("Unbalanced \"(\" in the query".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Close) => Err("Empty parentheses or a misplaced \")\" in the query".to_string()),
+            Some(token) => Err(format!("{:?} needs a word or group on both sides", token.text())),
+            None => Err("The query ends where a word or group was expected".to_string()),
+        }
+    }
+}
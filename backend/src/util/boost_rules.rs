@@ -0,0 +1,93 @@
+use std::cell::OnceCell;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{Document, PreprocessedData};
+
+/// One line of the rules file. A rule applies to documents matching all of its conditions and
+/// multiplies their score by `factor`: above 1 to boost, below 1 to bury.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BoostRule {
+    /// Shown in the admin API and logs.
+    #[serde(default)]
+    pub name: String,
+    /// Regexes over the document URL and title.
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    /// Bounds on the number of words in the document text.
+    pub min_words: Option<usize>,
+    pub max_words: Option<usize>,
+    pub factor: f64,
+}
+
+struct CompiledRule {
+    url: Option<Regex>,
+    title: Option<Regex>,
+    rule: BoostRule,
+}
+
+impl CompiledRule {
+    fn matches(&self, doc: &Document, data: &PreprocessedData) -> bool {
+        // Counted once at most; a tiered text is read from disk.
+        let word_count = OnceCell::new();
+        let words = || *word_count.get_or_init(|| data.text(doc).split_whitespace().count());
+        self.url.as_ref().is_none_or(|re| re.is_match(&doc.url))
+            && self.title.as_ref().is_none_or(|re| re.is_match(&doc.title))
+            && self.rule.language.as_ref().is_none_or(|language| *language == doc.language)
+            && self.rule.min_words.is_none_or(|min| words() >= min)
+            && self.rule.max_words.is_none_or(|max| words() <= max)
+    }
+}
+
+/// Score adjustments applied after ranking, read from a JSON array of `BoostRule`s, e.g.
+/// `[{"name": "docs", "url": "^https://docs\\.", "factor": 1.5}, {"max_words": 99, "factor": 0.2}]`.
+#[derive(Default)]
+pub struct BoostRules {
+    rules: Vec<CompiledRule>,
+}
+
+impl BoostRules {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let rules: Vec<BoostRule> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            if !(rule.factor.is_finite() && rule.factor > 0.0) {
+                return Err(format!("Boost rule {:?}: factor must be a positive number", rule.name).into());
+            }
+            compiled.push(CompiledRule {
+                url: rule.url.as_deref().map(Regex::new).transpose()?,
+                title: rule.title.as_deref().map(Regex::new).transpose()?,
+                rule,
+            });
+        }
+        Ok(BoostRules { rules: compiled })
+    }
+
+    pub fn rules(&self) -> Vec<BoostRule> {
+        self.rules.iter().map(|compiled| compiled.rule.clone()).collect()
+    }
+
+    /// Applies every matching rule to each result and re-sorts. Negative scores (query
+    /// likelihood) are divided instead, so a boost still moves a document up.
+    pub fn apply<'a>(&self, ranked: Vec<(&'a Document, f64)>, data: &PreprocessedData) -> Vec<(&'a Document, f64)> {
+        if self.rules.is_empty() {
+            return ranked;
+        }
+        let mut adjusted: Vec<(&'a Document, f64)> = ranked.into_iter()
+            .map(|(doc, score)| {
+                let factor: f64 = self.rules.iter()
+                    .filter(|compiled| compiled.matches(doc, data))
+                    .map(|compiled| compiled.rule.factor)
+                    .product();
+                (doc, if score < 0.0 { score / factor } else { score * factor })
+            })
+            .collect();
+        adjusted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        adjusted
+    }
+}
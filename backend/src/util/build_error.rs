@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::fmt;
+
+/// Corpus problems that stop an index from being built, reported instead of panicking partway
+/// through the pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// The source has no documents.
+    EmptyCorpus,
+    /// Analysis left no terms, e.g. every word is a stop word or shorter than three characters.
+    EmptyVocabulary { documents: usize },
+    /// Every weight in the term-document matrix is zero, so there is nothing to factorize.
+    /// Happens when each term occurs in every document (IDF is ln(1) = 0), e.g. a single document.
+    ZeroWeights { terms: usize, documents: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::EmptyCorpus => write!(f, "the corpus has no documents"),
+            BuildError::EmptyVocabulary { documents } => write!(
+                f,
+                "analyzing {} documents produced no terms; check the stop word list and the analyzer config",
+                documents,
+            ),
+            BuildError::ZeroWeights { terms, documents } => write!(
+                f,
+                "all {} terms have zero weight across {} documents (every term occurs in every document)",
+                terms, documents,
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
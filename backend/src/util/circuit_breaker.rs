@@ -0,0 +1,160 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+// Failures in a row that take the path out of service.
+const FAILURE_THRESHOLD: u32 = 3;
+// How long it stays out before a search may try it again.
+const COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// In service.
+    Closed,
+    /// Out of service; searches use the fallback.
+    Open,
+    /// The cooldown is over; one search at a time tries the path again while the rest keep using
+    /// the fallback.
+    HalfOpen,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// How many times the path was taken out of service since startup.
+    pub trips: u64,
+    pub last_error: Option<String>,
+    /// Seconds until searches try the path again, while open.
+    pub retry_in_secs: Option<u64>,
+}
+
+#[derive(Default)]
+struct Inner {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trips: u64,
+    last_error: Option<String>,
+    // When the search trying the path while half-open was let through.
+    probe_started: Option<Instant>,
+}
+
+/// Takes a failing code path out of service after repeated failures, so searches fall back
+/// instead of failing, and puts it back once a trial after the cooldown succeeds.
+pub struct CircuitBreaker {
+    /// Names the path in log messages.
+    name: &'static str,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str) -> Self {
+        CircuitBreaker { name, inner: Mutex::new(Inner::default()) }
+    }
+
+    /// Whether a search should use the path. Once the cooldown is over, only the first search is
+    /// let through as a trial and should report back with `record_success` or `record_failure`;
+    /// a trial that never does is given up on after another cooldown.
+    pub fn allows(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(opened_at) = inner.opened_at else {
+            return true;
+        };
+        if opened_at.elapsed() < COOLDOWN {
+            return false;
+        }
+        if inner.probe_started.is_some_and(|started| started.elapsed() < COOLDOWN) {
+            return false;
+        }
+        inner.probe_started = Some(Instant::now());
+        true
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.probe_started = None;
+        if inner.opened_at.take().is_some() {
+            log::info!("{} recovered; back in service", self.name);
+        }
+        inner.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.last_error = Some(error.to_string());
+        inner.probe_started = None;
+        // A failed trial starts another cooldown.
+        if inner.opened_at.is_some() || inner.consecutive_failures >= FAILURE_THRESHOLD {
+            if inner.opened_at.is_none() {
+                inner.trips += 1;
+                log::warn!("{} failed {} times in a row ({}); using the fallback for {:?}", self.name, inner.consecutive_failures, error, COOLDOWN);
+            }
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        let remaining = inner.opened_at.map(|opened_at| COOLDOWN.saturating_sub(opened_at.elapsed()));
+        let state = match remaining {
+            None => BreakerState::Closed,
+            Some(remaining) if remaining.is_zero() => BreakerState::HalfOpen,
+            Some(_) => BreakerState::Open,
+        };
+        BreakerStatus {
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            trips: inner.trips,
+            last_error: inner.last_error.clone(),
+            retry_in_secs: remaining.filter(|remaining| !remaining.is_zero()).map(|remaining| remaining.as_secs().max(1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A breaker whose cooldown ended `ago` ago.
+    fn half_open(ago: Duration) -> CircuitBreaker {
+        let breaker = CircuitBreaker::new("test");
+        for _ in 0..FAILURE_THRESHOLD {
+            breaker.record_failure("broken");
+        }
+        breaker.inner.lock().unwrap().opened_at = Some(Instant::now() - COOLDOWN - ago);
+        breaker
+    }
+
+    #[test]
+    fn half_open_admits_one_trial() {
+        let breaker = half_open(Duration::ZERO);
+        assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+        assert!(breaker.allows());
+        assert!(!breaker.allows());
+
+        breaker.record_success();
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+        assert!(breaker.allows());
+        assert!(breaker.allows());
+    }
+
+    #[test]
+    fn failed_trial_reopens() {
+        let breaker = half_open(Duration::ZERO);
+        assert!(breaker.allows());
+        breaker.record_failure("still broken");
+        assert_eq!(breaker.status().state, BreakerState::Open);
+        assert!(!breaker.allows());
+    }
+
+    #[test]
+    fn abandoned_trial_is_replaced_after_a_cooldown() {
+        let breaker = half_open(COOLDOWN);
+        assert!(breaker.allows());
+        breaker.inner.lock().unwrap().probe_started = Some(Instant::now() - COOLDOWN);
+        assert!(breaker.allows());
+        assert!(!breaker.allows());
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use nalgebra::DMatrix;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use crate::{serialize_matrix, Document, SvdData};
+use crate::util::svd::{perform_svd, SvdBuildConfig};
+
+/// Pairs of document indices that are versions of the same article in two languages, from
+/// `(article id, linked url)` rows recorded by the scraper. Each pair is listed once.
+pub fn parallel_pairs(documents: &[Document], links: &[(i64, String)]) -> Vec<(usize, usize)> {
+    let by_id: HashMap<i64, usize> = documents.iter().enumerate().map(|(idx, doc)| (doc.id, idx)).collect();
+    let by_url: HashMap<&str, usize> = documents.iter().enumerate()
+        .map(|(idx, doc)| (doc.url.trim_end_matches('/'), idx))
+        .collect();
+
+    let mut pairs: Vec<(usize, usize)> = links.iter()
+        .filter_map(|(article_id, url)| {
+            let a = *by_id.get(article_id)?;
+            let b = *by_url.get(url.trim_end_matches('/'))?;
+            (a != b).then_some((a.min(b), a.max(b)))
+        })
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+/// Cross-language LSI: factorizes a matrix with one column per pair (the sum of both versions'
+/// term vectors), so terms from either language that describe the same subjects share latent
+/// dimensions. Every document is then folded into that space, so a query in one language ranks
+/// documents in the other with the ordinary SVD search.
+pub fn train(
+    term_doc_csr: &CsrMatrix<f64>,
+    pairs: &[(usize, usize)],
+    k: usize,
+    config: &SvdBuildConfig,
+    checkpoint: Option<&Path>,
+) -> Result<SvdData, Box<dyn Error>> {
+    let mut pairs_of_doc: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (pair_idx, &(a, b)) in pairs.iter().enumerate() {
+        pairs_of_doc.entry(a).or_default().push(pair_idx);
+        pairs_of_doc.entry(b).or_default().push(pair_idx);
+    }
+
+    // Duplicate entries are summed when converting to CSR, merging each pair's two columns.
+    let mut joint = CooMatrix::new(term_doc_csr.nrows(), pairs.len());
+    for (term_idx, row) in term_doc_csr.row_iter().enumerate() {
+        for (&doc_idx, &value) in row.col_indices().iter().zip(row.values()) {
+            for &pair_idx in pairs_of_doc.get(&doc_idx).into_iter().flatten() {
+                joint.push(term_idx, pair_idx, value);
+            }
+        }
+    }
+    log::info!("Training cross-language LSI on {} document pairs...", pairs.len());
+    let mut svd = perform_svd(&CsrMatrix::from(&joint), k, config, checkpoint)?;
+
+    // Folding in: a document's coordinates are U_k^T d.
+    let u = svd.u_k();
+    let mut doc_vectors = DMatrix::zeros(svd.rank, term_doc_csr.ncols());
+    for (term_idx, row) in term_doc_csr.row_iter().enumerate() {
+        let term_vec = u.row(term_idx).transpose();
+        for (&doc_idx, &value) in row.col_indices().iter().zip(row.values()) {
+            doc_vectors.column_mut(doc_idx).axpy(value, &term_vec, 1.0);
+        }
+    }
+    svd.docs_ser = serialize_matrix(&doc_vectors);
+    Ok(svd)
+}
@@ -0,0 +1,47 @@
+use std::sync::LazyLock;
+use regex::{Captures, Regex};
+
+static ENTITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"&(?:#(\d{1,7})|#[xX]([0-9a-fA-F]{1,6})|([a-zA-Z]+));").unwrap());
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"</?[a-zA-Z][^<>]*>").unwrap());
+// Numbered references ("[12]", "[1, 3]", "[a]") and editorial notes left from Wikipedia markup.
+static REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\[(?:\d+(?:\s*[,–-]\s*\d+)*|[a-z]|note \d+|nb \d+|citation needed|edit|edytuj|potrzebny przypis|przypis potrzebny)\]").unwrap()
+});
+static SPACES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[ \t\u{a0}]+").unwrap());
+static SPACE_BEFORE_PUNCTUATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r" ([.,;:!?)])").unwrap());
+
+const NAMED_ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"), ("lt", "<"), ("gt", ">"), ("quot", "\""), ("apos", "'"), ("nbsp", " "),
+    ("ndash", "–"), ("mdash", "—"), ("hellip", "…"), ("laquo", "«"), ("raquo", "»"),
+    ("lsquo", "‘"), ("rsquo", "’"), ("ldquo", "“"), ("rdquo", "”"), ("bdquo", "„"),
+    ("shy", ""), ("zwj", ""), ("zwnj", ""), ("thinsp", " "), ("ensp", " "), ("emsp", " "),
+    ("deg", "°"), ("middot", "·"), ("times", "×"), ("copy", "©"), ("reg", "®"), ("euro", "€"),
+];
+
+/// Text as stored by the scraper, cleaned for indexing: markup tags and reference markers
+/// removed, HTML entities decoded and runs of spaces collapsed. Newlines are kept.
+pub fn clean_text(text: &str) -> String {
+    // Tags go first so that an encoded "&lt;b&gt;" in the text survives as literal "<b>".
+    let text = TAG.replace_all(text, "");
+    let text = decode_entities(&text);
+    let text = REFERENCE.replace_all(&text, "");
+    let text = SPACES.replace_all(&text, " ");
+    let text = SPACE_BEFORE_PUNCTUATION.replace_all(&text, "$1");
+    text.lines().map(str::trim).collect::<Vec<_>>().join("\n").trim().to_string()
+}
+
+pub fn decode_entities(text: &str) -> String {
+    ENTITY.replace_all(text, |caps: &Captures| {
+        let decoded = if let Some(dec) = caps.get(1) {
+            dec.as_str().parse().ok().and_then(char::from_u32).map(String::from)
+        } else if let Some(hex) = caps.get(2) {
+            u32::from_str_radix(hex.as_str(), 16).ok().and_then(char::from_u32).map(String::from)
+        } else {
+            NAMED_ENTITIES.iter()
+                .find(|(name, _)| *name == &caps[3])
+                .map(|(_, value)| value.to_string())
+        };
+        // Unknown entities are left as written.
+        decoded.unwrap_or_else(|| caps[0].to_string())
+    }).into_owned()
+}
@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::Document;
+
+pub const PHRASE_SEPARATOR: &str = "_";
+
+/// Multi-word terms ("new york") that are kept together as a single token.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Collocations {
+    #[serde(serialize_with = "serialize_sorted")]
+    phrases: HashSet<String>,
+    max_words: usize,
+}
+
+impl Collocations {
+    /// Loads a gazetteer with one phrase per line.
+    pub fn load_gazetteer(filename: &Path) -> std::io::Result<Self> {
+        let file = File::open(filename)?;
+        let reader = BufReader::new(file);
+        let mut collocations = Collocations::default();
+
+        for line in reader.lines() {
+            let words: Vec<String> = line?.split_whitespace().map(|w| w.to_lowercase()).collect();
+            collocations.insert(&words);
+        }
+
+        Ok(collocations)
+    }
+
+    /// Finds pairs of capitalized words that occur together at least `min_count` times
+    /// and make up most of either word's occurrences (Dice coefficient >= `min_dice`).
+    pub fn detect(documents: &[Document], min_count: usize, min_dice: f64) -> Self {
+        let re = Regex::new(r"[^a-zA-Z0-9]+").unwrap();
+        let mut unigram_counts: HashMap<String, usize> = HashMap::new();
+        let mut bigram_counts: HashMap<(String, String), usize> = HashMap::new();
+
+        for doc in documents {
+            let mut previous: Option<String> = None;
+            for word in re.split(&doc.text).filter(|s| s.len() > 2) {
+                if !word.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    previous = None;
+                    continue;
+                }
+
+                let word = word.to_lowercase();
+                *unigram_counts.entry(word.clone()).or_insert(0) += 1;
+                if let Some(prev) = previous.take() {
+                    *bigram_counts.entry((prev, word.clone())).or_insert(0) += 1;
+                }
+                previous = Some(word);
+            }
+        }
+
+        let mut collocations = Collocations::default();
+        for ((first, second), count) in bigram_counts {
+            if count < min_count {
+                continue;
+            }
+            let dice = 2.0 * count as f64 / (unigram_counts[&first] + unigram_counts[&second]) as f64;
+            if dice >= min_dice {
+                collocations.insert(&[first, second]);
+            }
+        }
+
+        log::info!("Detected {} collocations", collocations.phrases.len());
+        collocations
+    }
+
+    pub fn extend(&mut self, other: Collocations) {
+        self.phrases.extend(other.phrases);
+        self.max_words = self.max_words.max(other.max_words);
+    }
+
+    fn insert(&mut self, words: &[String]) {
+        if words.len() < 2 {
+            return;
+        }
+        self.phrases.insert(words.join(PHRASE_SEPARATOR));
+        self.max_words = self.max_words.max(words.len());
+    }
+
+    /// Greedily replaces the longest known phrase starting at each token with one joined token.
+    pub fn merge(&self, tokens: Vec<String>) -> Vec<String> {
+        if self.phrases.is_empty() {
+            return tokens;
+        }
+
+        let mut merged = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let longest = (2..=self.max_words.min(tokens.len() - i))
+                .rev()
+                .map(|len| (len, tokens[i..i + len].join(PHRASE_SEPARATOR)))
+                .find(|(_, phrase)| self.phrases.contains(phrase));
+
+            match longest {
+                Some((len, phrase)) => {
+                    merged.push(phrase);
+                    i += len;
+                }
+                None => {
+                    merged.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+
+        merged
+    }
+}
+
+// Sorted so the saved index is byte-for-byte the same across builds of the same corpus.
+fn serialize_sorted<S: Serializer>(phrases: &HashSet<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut sorted: Vec<&String> = phrases.iter().collect();
+    sorted.sort_unstable();
+    sorted.serialize(serializer)
+}
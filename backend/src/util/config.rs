@@ -0,0 +1,442 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use log::LevelFilter;
+#[cfg(feature = "server")]
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use crate::util::analyzer::AnalyzerConfig;
+use crate::util::entities::EntityConfig;
+use crate::util::history::HistoryConfig;
+use crate::util::language::LanguageConfig;
+use crate::util::manifest::MismatchPolicy;
+use crate::util::metadata::MetadataConfig;
+use crate::util::result_filter::ContentFilterConfig;
+use crate::util::search::LsiCosine;
+use crate::util::slow_queries::SlowQueryConfig;
+use crate::util::snapshot::SnapshotConfig;
+use crate::util::svd::SvdBuildConfig;
+use crate::util::tiering::TieringConfig;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    pub analyzer: AnalyzerConfig,
+    pub entities: EntityConfig,
+    pub search: SearchConfig,
+    pub server: ServerConfig,
+    pub history: HistoryConfig,
+    pub slow_queries: SlowQueryConfig,
+    pub storage: StorageConfig,
+    pub snapshots: SnapshotConfig,
+    pub languages: LanguageConfig,
+    pub content_filter: ContentFilterConfig,
+    pub metadata: MetadataConfig,
+    pub svd: SvdBuildConfig,
+    pub tiering: TieringConfig,
+}
+
+/// Where the server reads its inputs and keeps its caches.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Relative paths elsewhere in the config (analyzer resources, `history.db_path`) and the
+    /// cache files are resolved against this directory.
+    pub data_dir: PathBuf,
+    /// SQLite database with the `articles` table to index.
+    pub database_path: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            data_dir: PathBuf::from("."),
+            database_path: ["..", "Search-Engine", "backend", "data", "articles.db"].iter().collect(),
+        }
+    }
+}
+
+impl StorageConfig {
+    /// `path` joined onto the data directory; absolute paths are returned unchanged.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.data_dir.join(path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    /// HTTP worker threads; one per available core when unset.
+    pub workers: Option<usize>,
+    /// Threads that score documents in parallel within a request (SVD and low-rank methods);
+    /// one per available core when unset. Lower this or `workers` on shared machines.
+    pub scoring_threads: Option<usize>,
+    /// Built frontend (e.g. `../Frotend/dist`) to serve under `/`; the API routes take precedence.
+    pub static_dir: Option<String>,
+    /// Whether to warn or refuse to start when the index manifest's analyzer config differs from this one.
+    pub manifest_mismatch: MismatchPolicy,
+    /// Run through every ranking method before accepting connections, so the first real request
+    /// doesn't pay for cold caches. Uses the first few document titles when empty.
+    pub warmup_queries: Vec<String>,
+    pub warmup_enabled: bool,
+    /// Searches scoring at the same time; one per scoring thread when unset.
+    pub max_concurrent_searches: Option<usize>,
+    /// Searches allowed to wait for a slot. Beyond this, or after waiting `queue_timeout_ms`,
+    /// requests get 429 with `Retry-After: retry_after_secs`.
+    pub max_queued_searches: usize,
+    pub queue_timeout_ms: u64,
+    pub retry_after_secs: u64,
+    /// SVD models kept in memory beyond this are dropped, least recently used first, and
+    /// reloaded from disk when next searched.
+    pub svd_cache_mb: usize,
+    /// JSON file of score adjustments (see `util::boost_rules`), relative to the data directory.
+    /// Edits are picked up while serving.
+    pub boost_rules_path: Option<String>,
+    /// Most verbose messages logged: `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub log_level: LevelFilter,
+}
+
+impl ServerConfig {
+    pub fn worker_count(&self) -> usize {
+        self.workers.unwrap_or_else(available_cores)
+    }
+
+    pub fn scoring_thread_count(&self) -> usize {
+        self.scoring_threads.unwrap_or_else(available_cores)
+    }
+
+    pub fn concurrent_search_count(&self) -> usize {
+        self.max_concurrent_searches.unwrap_or_else(|| self.scoring_thread_count())
+    }
+}
+
+fn available_cores() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "127.0.0.1:8080".to_string(),
+            workers: None,
+            scoring_threads: None,
+            static_dir: None,
+            manifest_mismatch: MismatchPolicy::default(),
+            warmup_queries: Vec::new(),
+            warmup_enabled: true,
+            max_concurrent_searches: None,
+            max_queued_searches: 64,
+            queue_timeout_ms: 2000,
+            retry_after_secs: 1,
+            svd_cache_mb: 2048,
+            boost_rules_path: None,
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+/// Settings read per request; all of them can be changed by editing the config file while serving.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Number of results when the request has no `limit`.
+    pub default_limit: usize,
+    /// Upper bound on a request's `limit`.
+    pub max_limit: usize,
+    /// Added to a result's score when `exact: true` and all query words appear in its title with the same case.
+    pub exact_title_boost: f64,
+    /// Added to a result's score, scaled by the share of query terms found in its title, on top of
+    /// the index-time `analyzer.title_weight`. 0 adds nothing.
+    pub title_boost: f64,
+    pub result_set_ttl_secs: u64,
+    pub max_result_sets: usize,
+    /// How long a query's analysis and scope are reused by searches repeating it, e.g. with
+    /// another method; 0 cached queries turns this off.
+    pub query_cache_ttl_secs: u64,
+    pub max_cached_queries: usize,
+    /// How deep paging may reach: a request's `offset` plus its `limit`.
+    pub max_result_window: usize,
+    /// How many ranked results a `scroll: true` search keeps for its later pages.
+    pub max_scroll_window: usize,
+    /// A scroll is dropped once it hasn't been read for this long.
+    pub scroll_ttl_secs: u64,
+    pub max_scrolls: usize,
+    /// Dirichlet prior for query-likelihood ranking; roughly the length of a typical document.
+    pub qlm_mu: f64,
+    pub bm25_k1: f64,
+    pub bm25_b: f64,
+    /// Signed or absolute cosine for the LSI methods.
+    pub lsi_cosine: LsiCosine,
+    /// Top TF-IDF hits taken as relevant when a request asks for `expand`.
+    pub feedback_docs: usize,
+    /// New terms an expanded query may gain from them.
+    pub feedback_terms: usize,
+    /// Rocchio weights of the original query and of the feedback documents' centroid.
+    pub rocchio_alpha: f64,
+    pub rocchio_beta: f64,
+    /// Length in characters of the excerpt a request asking for `snippet` gets as a result's
+    /// text; 0 returns the whole text anyway.
+    pub snippet_chars: usize,
+    /// Weight of relevance against novelty when a request asks to `diversify`: 1 keeps the
+    /// ranking, 0 picks each result as unlike the ones before it as possible.
+    pub mmr_lambda: f64,
+    /// Top results a diversified page is picked from.
+    pub mmr_candidates: usize,
+    /// Share of the TF-IDF cosine in hybrid scores (method 8), the rest going to the LSI cosine.
+    pub hybrid_alpha: f64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            default_limit: 10,
+            max_limit: 1000,
+            exact_title_boost: 0.5,
+            title_boost: 0.0,
+            result_set_ttl_secs: 600,
+            max_result_sets: 256,
+            query_cache_ttl_secs: 30,
+            max_cached_queries: 256,
+            max_result_window: 10000,
+            max_scroll_window: 1000,
+            scroll_ttl_secs: 300,
+            max_scrolls: 64,
+            qlm_mu: 2000.0,
+            bm25_k1: 1.2,
+            bm25_b: 0.75,
+            lsi_cosine: LsiCosine::default(),
+            feedback_docs: 10,
+            feedback_terms: 20,
+            rocchio_alpha: 1.0,
+            rocchio_beta: 0.75,
+            snippet_chars: 300,
+            mmr_lambda: 0.7,
+            mmr_candidates: 100,
+            hybrid_alpha: 0.5,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the JSON config file, falling back to defaults when it doesn't exist, then applies
+    /// any `SEARCH_ENGINE_*` environment overrides (see `apply_env`).
+    pub fn load(filepath: &str) -> Result<Config, Box<dyn Error>> {
+        let mut config = if Path::new(filepath).exists() {
+            log::info!("Loading config from {}...", filepath);
+            let file = File::open(filepath)?;
+            serde_json::from_reader(BufReader::new(file))?
+        } else {
+            log::info!("No config file at {}, using defaults", filepath);
+            Config::default()
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Overrides settings from environment variables, so a container can point the server at
+    /// mounted volumes without a config file:
+    /// `SEARCH_ENGINE_DATABASE_PATH`, `SEARCH_ENGINE_DATA_DIR`, `SEARCH_ENGINE_BIND_ADDRESS`,
+    /// `SEARCH_ENGINE_WORKERS`, `SEARCH_ENGINE_SCORING_THREADS`, `SEARCH_ENGINE_LOG_LEVEL`.
+    pub fn apply_env(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = env_var("SEARCH_ENGINE_DATABASE_PATH") {
+            self.storage.database_path = PathBuf::from(path);
+        }
+        if let Some(dir) = env_var("SEARCH_ENGINE_DATA_DIR") {
+            self.storage.data_dir = PathBuf::from(dir);
+        }
+        if let Some(address) = env_var("SEARCH_ENGINE_BIND_ADDRESS") {
+            self.server.bind_address = address;
+        }
+        if let Some(workers) = env_var("SEARCH_ENGINE_WORKERS") {
+            self.server.workers = Some(positive_count("SEARCH_ENGINE_WORKERS", &workers)?);
+        }
+        if let Some(threads) = env_var("SEARCH_ENGINE_SCORING_THREADS") {
+            self.server.scoring_threads = Some(positive_count("SEARCH_ENGINE_SCORING_THREADS", &threads)?);
+        }
+        if let Some(level) = env_var("SEARCH_ENGINE_LOG_LEVEL") {
+            self.server.log_level = level.parse()
+                .map_err(|_| format!("SEARCH_ENGINE_LOG_LEVEL must be off, error, warn, info, debug or trace, got {:?}", level))?;
+        }
+        Ok(())
+    }
+
+    /// Takes the settings from `new` that can change while serving. Returns the changed settings
+    /// that need a restart (and, except for `server`, an index rebuild); those keep their current values.
+    pub fn apply_reload(&mut self, new: Config) -> Vec<&'static str> {
+        let mut rejected = Vec::new();
+        if new.analyzer != self.analyzer {
+            rejected.push("analyzer");
+        }
+        if new.entities.enabled != self.entities.enabled {
+            rejected.push("entities.enabled");
+        }
+        if new.entities.max_per_document != self.entities.max_per_document {
+            rejected.push("entities.max_per_document");
+        }
+        if new.server != self.server {
+            rejected.push("server");
+        }
+        if new.history != self.history {
+            rejected.push("history");
+        }
+        if new.slow_queries.enabled != self.slow_queries.enabled {
+            rejected.push("slow_queries.enabled");
+        }
+        if new.slow_queries.db_path != self.slow_queries.db_path {
+            rejected.push("slow_queries.db_path");
+        }
+        if new.slow_queries.max_entries != self.slow_queries.max_entries {
+            rejected.push("slow_queries.max_entries");
+        }
+        if new.storage != self.storage {
+            rejected.push("storage");
+        }
+        if new.snapshots != self.snapshots {
+            rejected.push("snapshots");
+        }
+        if new.languages != self.languages {
+            rejected.push("languages");
+        }
+        if new.content_filter != self.content_filter {
+            rejected.push("content_filter");
+        }
+        if new.metadata != self.metadata {
+            rejected.push("metadata");
+        }
+        if new.tiering != self.tiering {
+            rejected.push("tiering");
+        }
+
+        self.entities.boost = new.entities.boost;
+        self.slow_queries.threshold_ms = new.slow_queries.threshold_ms;
+        self.svd = new.svd;
+        self.search = new.search;
+        rejected
+    }
+}
+
+/// Ranking parameters a search request may override for experimentation.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RankingOverrides {
+    pub bm25_k1: Option<f64>,
+    pub bm25_b: Option<f64>,
+    pub qlm_mu: Option<f64>,
+    pub exact_title_boost: Option<f64>,
+    pub title_boost: Option<f64>,
+    pub entity_boost: Option<f64>,
+    pub lsi_cosine: Option<LsiCosine>,
+    pub rocchio_alpha: Option<f64>,
+    pub rocchio_beta: Option<f64>,
+    pub mmr_lambda: Option<f64>,
+    pub hybrid_alpha: Option<f64>,
+}
+
+impl RankingOverrides {
+    /// Checks each override against its allowed range and applies it.
+    pub fn apply(&self, search: &mut SearchConfig, entity_boost: &mut f64) -> Result<(), String> {
+        if let Some(k1) = self.bm25_k1 {
+            search.bm25_k1 = in_range("bm25_k1", k1, 0.0, 10.0)?;
+        }
+        if let Some(b) = self.bm25_b {
+            search.bm25_b = in_range("bm25_b", b, 0.0, 1.0)?;
+        }
+        if let Some(mu) = self.qlm_mu {
+            search.qlm_mu = in_range("qlm_mu", mu, 1.0, 100_000.0)?;
+        }
+        if let Some(boost) = self.exact_title_boost {
+            search.exact_title_boost = in_range("exact_title_boost", boost, 0.0, 10.0)?;
+        }
+        if let Some(boost) = self.title_boost {
+            search.title_boost = in_range("title_boost", boost, 0.0, 10.0)?;
+        }
+        if let Some(boost) = self.entity_boost {
+            *entity_boost = in_range("entity_boost", boost, 0.0, 10.0)?;
+        }
+        if let Some(cosine) = self.lsi_cosine {
+            search.lsi_cosine = cosine;
+        }
+        if let Some(alpha) = self.rocchio_alpha {
+            search.rocchio_alpha = in_range("rocchio_alpha", alpha, 0.0, 10.0)?;
+        }
+        if let Some(beta) = self.rocchio_beta {
+            search.rocchio_beta = in_range("rocchio_beta", beta, 0.0, 10.0)?;
+        }
+        if let Some(lambda) = self.mmr_lambda {
+            search.mmr_lambda = in_range("mmr_lambda", lambda, 0.0, 1.0)?;
+        }
+        if let Some(alpha) = self.hybrid_alpha {
+            search.hybrid_alpha = in_range("hybrid_alpha", alpha, 0.0, 1.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The variable's value, treating an empty one as unset.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn positive_count(name: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().ok().filter(|&n| n > 0)
+        .ok_or_else(|| format!("{} must be a positive integer, got {:?}", name, value))
+}
+
+fn in_range(name: &str, value: f64, min: f64, max: f64) -> Result<f64, String> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} must be between {} and {}, got {}", name, min, max, value))
+    }
+}
+
+/// Calls `on_change` with the re-read config whenever the file is created or modified.
+/// Watches the containing directory, since editors often save by replacing the file.
+/// Reloading stops when the returned watcher is dropped.
+#[cfg(feature = "server")]
+pub fn watch<F>(filepath: &str, on_change: F) -> notify::Result<RecommendedWatcher>
+where
+    F: Fn(Config) + Send + 'static,
+{
+    let path = filepath.to_string();
+    watch_file(Path::new(filepath), move || match Config::load(&path) {
+        Ok(config) => on_change(config),
+        Err(e) => log::warn!("Ignoring change to {}: {}", path, e),
+    })
+}
+
+/// Calls `on_change` whenever the file at `path` is created or modified.
+#[cfg(feature = "server")]
+pub fn watch_file<F>(path: &Path, on_change: F) -> notify::Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let dir_display = path.display().to_string();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("Watch error on {}: {}", dir_display, e);
+                return;
+            }
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            || !event.paths.iter().any(|p| p.file_name() == file_name.as_deref())
+        {
+            return;
+        }
+
+        on_change();
+    })?;
+
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
@@ -0,0 +1,132 @@
+use nalgebra_sparse::CsrMatrix;
+use serde::{Deserialize, Serialize};
+
+const HEAPS_SAMPLES: usize = 100;
+const ZIPF_RANK_GROWTH: f64 = 1.25;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bucket {
+    pub min: usize,
+    pub max: usize,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ZipfPoint {
+    pub rank: usize,
+    pub frequency: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HeapsPoint {
+    pub documents: usize,
+    pub tokens: usize,
+    pub vocabulary: usize,
+}
+
+/// Corpus-level distributions that help pick document-frequency cut-offs and the SVD rank.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CorpusStats {
+    pub document_count: usize,
+    pub vocabulary_size: usize,
+    pub total_tokens: usize,
+    pub term_frequency_buckets: Vec<Bucket>,
+    pub document_frequency_buckets: Vec<Bucket>,
+    pub zipf: Vec<ZipfPoint>,
+    pub heaps: Vec<HeapsPoint>,
+    pub document_length_histogram: Vec<Bucket>,
+}
+
+/// Computes the statistics from the raw term-count matrix (before IDF weighting).
+pub fn compute(term_counts: &CsrMatrix<f64>) -> CorpusStats {
+    let num_terms = term_counts.nrows();
+    let num_docs = term_counts.ncols();
+
+    let mut collection_frequency = vec![0usize; num_terms];
+    let mut document_frequency = vec![0usize; num_terms];
+    let mut first_document = vec![usize::MAX; num_terms];
+    let mut document_lengths = vec![0usize; num_docs];
+
+    for term_idx in 0..num_terms {
+        let row_start = term_counts.row_offsets()[term_idx];
+        let row_end = term_counts.row_offsets()[term_idx + 1];
+
+        for idx in row_start..row_end {
+            let j = term_counts.col_indices()[idx];
+            let count = term_counts.values()[idx] as usize;
+            collection_frequency[term_idx] += count;
+            document_frequency[term_idx] += 1;
+            first_document[term_idx] = first_document[term_idx].min(j);
+            document_lengths[j] += count;
+        }
+    }
+
+    let total_tokens = document_lengths.iter().sum();
+
+    CorpusStats {
+        document_count: num_docs,
+        vocabulary_size: num_terms,
+        total_tokens,
+        term_frequency_buckets: log2_buckets(&collection_frequency),
+        document_frequency_buckets: log2_buckets(&document_frequency),
+        zipf: zipf_curve(collection_frequency),
+        heaps: heaps_curve(first_document, &document_lengths),
+        document_length_histogram: log2_buckets(&document_lengths),
+    }
+}
+
+/// Groups values into power-of-two ranges: [0, 0], [1, 1], [2, 3], [4, 7], ...
+fn log2_buckets(values: &[usize]) -> Vec<Bucket> {
+    let mut buckets: Vec<Bucket> = Vec::new();
+    for &value in values {
+        let bucket_idx = if value == 0 { 0 } else { value.ilog2() as usize + 1 };
+        while buckets.len() <= bucket_idx {
+            let i = buckets.len();
+            let (min, max) = if i == 0 { (0, 0) } else { (1 << (i - 1), (1 << i) - 1) };
+            buckets.push(Bucket { min, max, count: 0 });
+        }
+        buckets[bucket_idx].count += 1;
+    }
+    buckets.retain(|bucket| bucket.count > 0);
+    buckets
+}
+
+/// Frequency by rank, sampled at geometrically spaced ranks.
+fn zipf_curve(mut frequencies: Vec<usize>) -> Vec<ZipfPoint> {
+    frequencies.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut points = Vec::new();
+    let mut rank = 1.0_f64;
+    while (rank as usize) <= frequencies.len() {
+        let r = rank as usize;
+        if points.last().is_none_or(|p: &ZipfPoint| p.rank != r) {
+            points.push(ZipfPoint { rank: r, frequency: frequencies[r - 1] });
+        }
+        rank *= ZIPF_RANK_GROWTH;
+        rank = rank.max(r as f64 + 1.0);
+    }
+    points
+}
+
+/// Vocabulary size as documents are added in corpus order.
+fn heaps_curve(mut first_document: Vec<usize>, document_lengths: &[usize]) -> Vec<HeapsPoint> {
+    first_document.sort_unstable();
+
+    let num_docs = document_lengths.len();
+    let step = num_docs.div_ceil(HEAPS_SAMPLES).max(1);
+
+    let mut points = Vec::new();
+    let mut tokens = 0;
+    for (doc_idx, &length) in document_lengths.iter().enumerate() {
+        tokens += length;
+        let documents = doc_idx + 1;
+        if documents % step == 0 || documents == num_docs {
+            points.push(HeapsPoint {
+                documents,
+                tokens,
+                vocabulary: first_document.partition_point(|&first| first < documents),
+            });
+        }
+    }
+    points
+}
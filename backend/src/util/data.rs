@@ -1,455 +1,585 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File, TryLockError};
 use std::io;
 use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 use crate::{Document, PreprocessedData, SerMatrix, SerializableCsrMatrix, SvdData};
-
-pub fn load_svd_data(filepath: &str) -> Result<SvdData, Box<dyn Error>> {
-    println!("Loading SVD data from {}...", filepath);
+use crate::util::collocations::Collocations;
+use crate::util::autocomplete::Autocomplete;
+use crate::util::corpus_stats::CorpusStats;
+use crate::util::integrity;
+use crate::util::subwords::Subwords;
+use crate::util::synonyms::Synonyms;
+
+pub fn load_svd_data(filepath: &Path) -> Result<SvdData, Box<dyn Error>> {
+    log::info!("Loading SVD data from {}...", filepath.display());
     let start_total = Instant::now();
 
     let index_file = File::open(filepath)?;
     let reader = BufReader::new(index_file);
-    let (meta_path, u_path, vt_path, docs_path): (String, String, String, String) =
+    let (meta_name, u_name, vt_name, docs_name): (String, String, String, String) =
         bincode::deserialize_from(reader)?;
+    let [meta_path, u_path, vt_path, docs_path] =
+        [meta_name, u_name, vt_name, docs_name].map(|name| component_path(filepath, &name));
 
-    println!("Found component files in index.");
+    log::debug!("Found component files in index.");
 
-    println!("Loading SVD metadata from {}...", meta_path);
+    log::debug!("Loading SVD metadata from {}...", meta_path.display());
     let meta_start = Instant::now();
     let meta_file = File::open(&meta_path)?;
-    let meta_reader = BufReader::new(meta_file);
-    let (rank, sigma_k): (usize, Vec<f64>) = bincode::deserialize_from(meta_reader)?;
-    println!("Metadata loaded in {:?}", meta_start.elapsed());
+    let mut meta_reader = BufReader::new(meta_file);
+    let (rank, sigma_k): (usize, Vec<f64>) = bincode::deserialize_from(&mut meta_reader)?;
+    // Files written before the column mapping was recorded end here.
+    let doc_ids: Vec<String> = match bincode::deserialize_from(&mut meta_reader) {
+        Ok(doc_ids) => doc_ids,
+        Err(e) if matches!(&*e, bincode::ErrorKind::Io(io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    log::debug!("Metadata loaded in {:?}", meta_start.elapsed());
 
-    println!("Loading U matrix from {}...", u_path);
+    log::debug!("Loading U matrix from {}...", u_path.display());
     let u_start = Instant::now();
+    let u_ser = read_ser_matrix(&u_path)?;
+    log::debug!("U matrix ({}x{}) loaded in {:?}", u_ser.nrows, u_ser.ncols, u_start.elapsed());
 
-    let mut u_file = File::open(&u_path)?;
-    let u_file_size = u_file.metadata()?.len() as usize;
-    println!("U matrix file size: {} bytes", u_file_size);
-
-    let mut u_reader = BufReader::with_capacity(8 * 1024 * 1024, u_file);
+    log::debug!("Loading V^T matrix from {}...", vt_path.display());
+    let vt_start = Instant::now();
+    let vt_ser = read_ser_matrix(&vt_path)?;
+    log::debug!("V^T matrix ({}x{}) loaded in {:?}", vt_ser.nrows, vt_ser.ncols, vt_start.elapsed());
 
-    let u_nrows: usize = match bincode::deserialize_from(&mut u_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading U matrix nrows: {}", e);
-            return Err(Box::new(e));
-        }
-    };
+    log::debug!("Loading document vectors from {}...", docs_path.display());
+    let docs_start = Instant::now();
+    let docs_ser = read_ser_matrix(&docs_path)?;
+    log::debug!("Document vectors ({}x{}) loaded in {:?}", docs_ser.nrows, docs_ser.ncols, docs_start.elapsed());
+    // Older builds wrote one row per document; those files can't be searched.
+    if docs_ser.nrows != rank {
+        return Err(format!(
+            "Document vectors in {} are {}x{} but should have one row per dimension ({}); delete {} to recompute it",
+            docs_path.display(), docs_ser.nrows, docs_ser.ncols, rank, filepath.display(),
+        ).into());
+    }
 
-    let u_ncols: usize = match bincode::deserialize_from(&mut u_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading U matrix ncols: {}", e);
-            return Err(Box::new(e));
-        }
+    let svd_data = SvdData {
+        rank,
+        sigma_k,
+        u_ser,
+        vt_ser,
+        docs_ser,
+        doc_ids,
     };
 
-    println!("U matrix dimensions: {}x{}", u_nrows, u_ncols);
+    log::info!("All SVD data loaded successfully in {:?}!", start_total.elapsed());
+    Ok(svd_data)
+}
 
-    let u_total_size = u_nrows * u_ncols;
-    let expected_data_bytes = u_total_size * size_of::<f64>();
-    println!("Expected U matrix data size: {} elements ({} bytes)", u_total_size, expected_data_bytes);
+pub fn load_preprocessed_data(filepath: &Path) -> Result<PreprocessedData, Box<dyn Error>> {
+    log::info!("Loading preprocessed data from {}...", filepath.display());
+    let start_total = Instant::now();
 
-    let mut u_data = Vec::with_capacity(u_total_size);
+    let index_file = File::open(filepath)?;
+    let reader = BufReader::with_capacity(1024 * 1024, index_file); // 1MB buffer
+    let (dict_name, docs_name, matrix_name, counts_name): (String, String, String, String) =
+        bincode::deserialize_from(reader)?;
+    let [dict_path, docs_path, matrix_path, counts_path] =
+        [dict_name, docs_name, matrix_name, counts_name].map(|name| component_path(filepath, &name));
+    log::debug!("Found component files in index.");
 
-    let result: Result<Vec<f64>, _> = bincode::deserialize_from(&mut u_reader);
+    log::debug!("Loading term dictionary from {}...", dict_path.display());
+    let dict_start = Instant::now();
+    let dict_file = File::open(&dict_path)?;
+    let mut dict_reader = BufReader::with_capacity(1024 * 1024, dict_file);
+    let (term_dict, inverse_term_dict, idf, collocations): (
+        HashMap<String, usize>,
+        HashMap<usize, String>,
+        Vec<f64>,
+        Collocations,
+    ) = bincode::deserialize_from(&mut dict_reader)?;
+    // Files written before synonyms were saved with the index end here.
+    let synonyms: Synonyms = match bincode::deserialize_from(&mut dict_reader) {
+        Ok(synonyms) => synonyms,
+        Err(e) if matches!(&*e, bincode::ErrorKind::Io(io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof) => Synonyms::default(),
+        Err(e) => return Err(e),
+    };
+    let subwords: Subwords = match bincode::deserialize_from(&mut dict_reader) {
+        Ok(subwords) => subwords,
+        Err(e) if matches!(&*e, bincode::ErrorKind::Io(io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof) => Subwords::default(),
+        Err(e) => return Err(e),
+    };
+    log::debug!("Dictionary loaded in {:?}", dict_start.elapsed());
 
-    match result {
-        Ok(data) => {
-            println!("Successfully read U matrix data: {} elements", data.len());
-            u_data = data;
-        },
-        Err(e) => {
-            println!("Error deserializing U matrix data: {}", e);
+    log::debug!("Loading documents from {}...", docs_path.display());
+    let docs_start = Instant::now();
+    let docs_file = File::open(&docs_path)?;
+    let docs_reader = BufReader::with_capacity(1024 * 1024, docs_file);
+    let documents: Vec<Document> = bincode::deserialize_from(docs_reader)?;
+    log::debug!("Documents loaded in {:?}", docs_start.elapsed());
 
-            println!("Creating empty U matrix with zeros");
-            u_data = vec![0.0; u_total_size];
-        }
+    log::debug!("Loading term-document matrix from {}...", matrix_path.display());
+    let matrix_start = Instant::now();
+    let term_doc_csr = read_csr(&matrix_path)?;
+    // Matrix columns are documents in the order of the documents file.
+    if term_doc_csr.ncols != documents.len() {
+        return Err(format!(
+            "{} has {} document columns but {} lists {} documents; delete {} to rebuild the index",
+            matrix_path.display(), term_doc_csr.ncols, docs_path.display(), documents.len(), filepath.display(),
+        ).into());
     }
-
-    if u_data.len() != u_total_size {
-        println!("Warning: U matrix data size mismatch. Expected: {}, Found: {}",
-                 u_total_size, u_data.len());
-
-        if u_data.len() < u_total_size {
-            println!("Padding with zeros to reach correct size");
-            u_data.resize(u_total_size, 0.0);
-        } else {
-            println!("Truncating to correct size");
-            u_data.truncate(u_total_size);
-        }
+    log::debug!("Matrix loaded in {:?}", matrix_start.elapsed());
+
+    log::debug!("Loading term counts from {}...", counts_path.display());
+    let counts_start = Instant::now();
+    let counts_file = File::open(&counts_path)?;
+    let term_counts: Vec<u32> = bincode::deserialize_from(BufReader::with_capacity(8 * 1024 * 1024, counts_file))?;
+    if term_counts.len() != term_doc_csr.values.len() {
+        return Err(format!("Term counts in {} don't match the term-document matrix", counts_path.display()).into());
     }
+    log::debug!("Term counts loaded in {:?}", counts_start.elapsed());
 
-    let u_ser = SerMatrix {
-        nrows: u_nrows,
-        ncols: u_ncols,
-        data: u_data,
+    let preprocessed_data = PreprocessedData {
+        term_dict,
+        inverse_term_dict,
+        idf,
+        collocations,
+        synonyms,
+        subwords,
+        documents: Arc::new(documents),
+        term_doc_csr,
+        term_counts,
+        texts: None,
     };
-    println!("U matrix loaded in {:?}", u_start.elapsed());
-
-    println!("Loading V^T matrix from {}...", vt_path);
-    let vt_start = Instant::now();
-
-    let vt_file = File::open(&vt_path)?;
-    let vt_file_size = vt_file.metadata()?.len() as usize;
-    println!("V^T matrix file size: {} bytes", vt_file_size);
+    if let Err(problems) = integrity::check_preprocessed(&preprocessed_data) {
+        return Err(format!(
+            "The index at {} is inconsistent; delete it to rebuild:\n  {}",
+            filepath.display(), problems.join("\n  "),
+        ).into());
+    }
 
-    let mut vt_reader = BufReader::with_capacity(8 * 1024 * 1024, vt_file);
+    log::info!("All data loaded successfully in {:?}!", start_total.elapsed());
+    Ok(preprocessed_data)
+}
 
-    let vt_nrows: usize = match bincode::deserialize_from(&mut vt_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading V^T matrix nrows: {}", e);
-            return Err(Box::new(e));
-        }
-    };
+pub fn save_svd_data(
+    data: &SvdData,
+    filepath: &Path,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving SVD data to {}...", filepath.display());
+    let start_total = Instant::now();
 
-    let vt_ncols: usize = match bincode::deserialize_from(&mut vt_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading V^T matrix ncols: {}", e);
-            return Err(Box::new(e));
-        }
-    };
+    let stem = filepath.file_stem().unwrap_or_default().to_string_lossy();
 
-    println!("V^T matrix dimensions: {}x{}", vt_nrows, vt_ncols);
+    let meta_name = format!("{}_meta.bin", stem);
+    let meta_path = component_path(filepath, &meta_name);
+    log::debug!("Saving SVD metadata to {}...", meta_path.display());
+    let meta_start = Instant::now();
+    let mut meta_file = AtomicFile::create(&meta_path)?;
+    let meta_data = (data.rank, &data.sigma_k, &data.doc_ids);
+    bincode::serialize_into(&mut meta_file, &meta_data)?;
+    meta_file.commit()?;
+    log::debug!("Metadata saved in {:?}", meta_start.elapsed());
+
+    let u_name = format!("{}_u.bin", stem);
+    let u_path = component_path(filepath, &u_name);
+    log::debug!("Saving U matrix ({}x{}) to {}...",
+             data.u_ser.nrows, data.u_ser.ncols, u_path.display());
+    let u_start = Instant::now();
+    write_ser_matrix(&data.u_ser, &u_path)?;
+    log::debug!("U matrix saved in {:?}", u_start.elapsed());
 
-    let vt_total_size = vt_nrows * vt_ncols;
-    let expected_vt_bytes = vt_total_size * std::mem::size_of::<f64>();
-    println!("Expected V^T matrix data size: {} elements ({} bytes)", vt_total_size, expected_vt_bytes);
+    let vt_name = format!("{}_vt.bin", stem);
+    let vt_path = component_path(filepath, &vt_name);
+    log::debug!("Saving V^T matrix to {}...", vt_path.display());
+    let vt_start = Instant::now();
+    write_ser_matrix(&data.vt_ser, &vt_path)?;
+    log::debug!("V^T matrix saved in {:?}", vt_start.elapsed());
 
-    let mut vt_data = Vec::with_capacity(vt_total_size);
-    let vt_result: Result<Vec<f64>, _> = bincode::deserialize_from(&mut vt_reader);
+    let docs_name = format!("{}_docs.bin", stem);
+    let docs_path = component_path(filepath, &docs_name);
+    log::debug!("Saving document vectors to {}...", docs_path.display());
+    let docs_start = Instant::now();
+    write_ser_matrix(&data.docs_ser, &docs_path)?;
+    log::debug!("Document vectors saved in {:?}", docs_start.elapsed());
 
-    match vt_result {
-        Ok(data) => {
-            println!("Successfully read V^T matrix data: {} elements", data.len());
-            vt_data = data;
-        },
-        Err(e) => {
-            println!("Error deserializing V^T matrix data: {}", e);
+    log::debug!("Creating index file at {}...", filepath.display());
+    let mut index_file = AtomicFile::create(filepath)?;
+    let index_data = (
+        meta_name,
+        u_name,
+        vt_name,
+        docs_name,
+    );
+    bincode::serialize_into(&mut index_file, &index_data)?;
+    index_file.commit()?;
 
-            println!("Creating empty V^T matrix with zeros");
-            vt_data = vec![0.0; vt_total_size];
-        }
-    }
+    log::info!("All SVD data saved successfully in {:?}!", start_total.elapsed());
+    Ok(())
+}
 
-    if vt_data.len() != vt_total_size {
-        println!("Warning: V^T matrix data size mismatch. Expected: {}, Found: {}",
-                 vt_total_size, vt_data.len());
+pub fn save_preprocessed_data(
+    data: &PreprocessedData,
+    filepath: &Path,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving preprocessed data to {}...", filepath.display());
+    let start_total = Instant::now();
 
-        if vt_data.len() < vt_total_size {
-            println!("Padding with zeros to reach correct size");
-            vt_data.resize(vt_total_size, 0.0);
-        } else {
-            println!("Truncating to correct size");
-            vt_data.truncate(vt_total_size);
-        }
-    }
+    let stem = filepath.file_stem().unwrap_or_default().to_string_lossy();
 
-    let vt_ser = SerMatrix {
-        nrows: vt_nrows,
-        ncols: vt_ncols,
-        data: vt_data,
-    };
-    println!("V^T matrix loaded in {:?}", vt_start.elapsed());
+    let dict_name = format!("{}_terms.bin", stem);
+    write_dictionary(data, &component_path(filepath, &dict_name))?;
 
-    println!("Loading document vectors from {}...", docs_path);
+    let docs_name = format!("{}_docs.bin", stem);
+    let docs_path = component_path(filepath, &docs_name);
+    log::debug!("Saving documents to {}...", docs_path.display());
     let docs_start = Instant::now();
+    let mut docs_file = AtomicFile::create(&docs_path)?;
+    bincode::serialize_into(&mut docs_file, &data.documents)?;
+    docs_file.commit()?;
+    log::debug!("Documents saved in {:?}", docs_start.elapsed());
+
+    let matrix_name = format!("{}_matrix.bin", stem);
+    let matrix_path = component_path(filepath, &matrix_name);
+    log::debug!("Saving term-document matrix to {}...", matrix_path.display());
+    let matrix_start = Instant::now();
 
-    let docs_file = File::open(&docs_path)?;
-    let docs_file_size = docs_file.metadata()?.len() as usize;
-    println!("Document vectors file size: {} bytes", docs_file_size);
+    write_csr(&data.term_doc_csr, &matrix_path)?;
+    log::debug!("Matrix saved in {:?}", matrix_start.elapsed());
 
-    let mut docs_reader = BufReader::with_capacity(8 * 1024 * 1024, docs_file);
+    let counts_name = format!("{}_counts.bin", stem);
+    let counts_path = component_path(filepath, &counts_name);
+    log::debug!("Saving term counts to {}...", counts_path.display());
+    let counts_start = Instant::now();
+    let mut counts_file = AtomicFile::create(&counts_path)?;
+    bincode::serialize_into(&mut counts_file, &data.term_counts)?;
+    counts_file.commit()?;
+    log::debug!("Term counts saved in {:?}", counts_start.elapsed());
 
-    let docs_nrows: usize = match bincode::deserialize_from(&mut docs_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading document vectors nrows: {}", e);
-            return Err(Box::new(e));
-        }
-    };
+    log::debug!("Creating index file at {}...", filepath.display());
+    let mut index_file = AtomicFile::create(filepath)?;
+    let index_data = (
+        dict_name,
+        docs_name,
+        matrix_name,
+        counts_name,
+    );
+    bincode::serialize_into(&mut index_file, &index_data)?;
+    index_file.commit()?;
 
-    let docs_ncols: usize = match bincode::deserialize_from(&mut docs_reader) {
-        Ok(val) => val,
-        Err(e) => {
-            println!("Error reading document vectors ncols: {}", e);
-            return Err(Box::new(e));
-        }
-    };
+    log::info!("All data saved successfully in {:?}!", start_total.elapsed());
+    Ok(())
+}
 
-    println!("Document vectors dimensions: {}x{}", docs_nrows, docs_ncols);
+/// Rewrites the weights of the index saved at `filepath`: its term dictionary (which holds the
+/// IDF) and term-document matrix. The documents, whose texts tiering may have taken out of
+/// `data`, and the raw counts are left as they are.
+pub fn save_weights(data: &PreprocessedData, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving reweighted index to {}...", filepath.display());
+    let (dict_name, _, matrix_name, _): (String, String, String, String) = bincode::deserialize_from(BufReader::new(File::open(filepath)?))?;
+    write_dictionary(data, &component_path(filepath, &dict_name))?;
+    write_csr(&data.term_doc_csr, &component_path(filepath, &matrix_name))?;
+    Ok(())
+}
 
-    let docs_total_size = docs_nrows * docs_ncols;
-    let expected_docs_bytes = docs_total_size * std::mem::size_of::<f64>();
-    println!("Expected document vectors data size: {} elements ({} bytes)", docs_total_size, expected_docs_bytes);
+fn write_dictionary(data: &PreprocessedData, dict_path: &Path) -> Result<(), Box<dyn Error>> {
+    log::debug!("Saving term dictionary to {}...", dict_path.display());
+    let dict_start = Instant::now();
+    let mut dict_file = AtomicFile::create(dict_path)?;
+    // Written in key order (same encoding as the HashMaps they load into) so rebuilding the same
+    // corpus reproduces the file exactly.
+    let term_dict: BTreeMap<&String, &usize> = data.term_dict.iter().collect();
+    let inverse_term_dict: BTreeMap<&usize, &String> = data.inverse_term_dict.iter().collect();
+    let dict_data = (term_dict, inverse_term_dict, &data.idf, &data.collocations);
+    bincode::serialize_into(&mut dict_file, &dict_data)?;
+    bincode::serialize_into(&mut dict_file, &data.synonyms)?;
+    bincode::serialize_into(&mut dict_file, &data.subwords)?;
+    dict_file.commit()?;
+    log::debug!("Dictionary saved in {:?}", dict_start.elapsed());
+    Ok(())
+}
 
-    let mut docs_data = Vec::with_capacity(docs_total_size);
-    let docs_result: Result<Vec<f64>, _> = bincode::deserialize_from(&mut docs_reader);
+/// The index file at `filepath` followed by the component files it lists. Both index formats
+/// list four components.
+pub fn index_files(filepath: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let names: (String, String, String, String) = bincode::deserialize_from(BufReader::new(File::open(filepath)?))?;
+    let mut files = vec![filepath.to_path_buf()];
+    files.extend([names.0, names.1, names.2, names.3].map(|name| component_path(filepath, &name)));
+    Ok(files)
+}
 
-    match docs_result {
-        Ok(data) => {
-            println!("Successfully read document vectors data: {} elements", data.len());
-            docs_data = data;
-        },
-        Err(e) => {
-            println!("Error deserializing document vectors data: {}", e);
+/// Index files list their components by file name; they live in the index's directory.
+fn component_path(index_path: &Path, name: &str) -> PathBuf {
+    index_path.with_file_name(name)
+}
 
-            println!("Creating empty document vectors with zeros");
-            docs_data = vec![0.0; docs_total_size];
+const CHUNK_SIZE: usize = 1_000_000;
+
+/// Reads a dense matrix written by `write_ser_matrix`. Fails rather than padding when the file
+/// holds fewer values than its dimensions call for.
+fn read_ser_matrix(filepath: &Path) -> Result<SerMatrix, Box<dyn Error>> {
+    let file = File::open(filepath)?;
+    let mut reader = BufReader::with_capacity(8 * 1024 * 1024, file);
+
+    let nrows: usize = bincode::deserialize_from(&mut reader)?;
+    let ncols: usize = bincode::deserialize_from(&mut reader)?;
+    let total = nrows.checked_mul(ncols)
+        .ok_or_else(|| format!("{}: dimensions {}x{} overflow", filepath.display(), nrows, ncols))?;
+
+    let mut data = Vec::with_capacity(total);
+    while data.len() < total {
+        let chunk: Vec<f64> = bincode::deserialize_from(&mut reader)
+            .map_err(|e| format!("{}: expected {} values, read {} before: {}", filepath.display(), total, data.len(), e))?;
+        if chunk.is_empty() || data.len() + chunk.len() > total {
+            return Err(format!("{}: chunk of {} values doesn't fit a {}x{} matrix", filepath.display(), chunk.len(), nrows, ncols).into());
         }
+        data.extend_from_slice(&chunk);
     }
 
-    if docs_data.len() != docs_total_size {
-        println!("Warning: Document vectors data size mismatch. Expected: {}, Found: {}",
-                 docs_total_size, docs_data.len());
+    Ok(SerMatrix { nrows, ncols, data })
+}
 
-        if docs_data.len() < docs_total_size {
-            println!("Padding with zeros to reach correct size");
-            docs_data.resize(docs_total_size, 0.0);
-        } else {
-            println!("Truncating to correct size");
-            docs_data.truncate(docs_total_size);
-        }
+fn write_ser_matrix(matrix: &SerMatrix, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    let mut buffer = AtomicFile::create(filepath)?;
+    bincode::serialize_into(&mut buffer, &matrix.nrows)?;
+    bincode::serialize_into(&mut buffer, &matrix.ncols)?;
+    for chunk in matrix.data.chunks(CHUNK_SIZE) {
+        bincode::serialize_into(&mut buffer, &chunk)?;
     }
-
-    let docs_ser = SerMatrix {
-        nrows: docs_nrows,
-        ncols: docs_ncols,
-        data: docs_data,
-    };
-    println!("Document vectors loaded in {:?}", docs_start.elapsed());
-
-    let svd_data = SvdData {
-        rank,
-        sigma_k,
-        u_ser,
-        vt_ser,
-        docs_ser,
-    };
-
-    println!("All SVD data loaded successfully in {:?}!", start_total.elapsed());
-    Ok(svd_data)
+    buffer.commit()?;
+    Ok(())
 }
 
-pub fn load_preprocessed_data(filepath: &str) -> Result<PreprocessedData, Box<dyn Error>> {
-    println!("Loading preprocessed data from {}...", filepath);
-    let start_total = Instant::now();
-
-    let index_file = File::open(filepath)?;
-    let reader = BufReader::with_capacity(1024 * 1024, index_file); // 1MB buffer
-    let (dict_path, docs_path, matrix_path): (String, String, String) =
-        bincode::deserialize_from(reader)?;
-    println!("Found component files in index.");
-
-    println!("Loading term dictionary from {}...", dict_path);
-    let dict_start = Instant::now();
-    let dict_file = File::open(dict_path)?;
-    let dict_reader = BufReader::with_capacity(1024 * 1024, dict_file);
-    let (term_dict, inverse_term_dict, idf): (
-        HashMap<String, usize>,
-        HashMap<usize, String>,
-        Vec<f64>
-    ) = bincode::deserialize_from(dict_reader)?;
-    println!("Dictionary loaded in {:?}", dict_start.elapsed());
-
-    println!("Loading documents from {}...", docs_path);
-    let docs_start = Instant::now();
-    let docs_file = File::open(docs_path)?;
-    let docs_reader = BufReader::with_capacity(1024 * 1024, docs_file);
-    let documents: Vec<Document> = bincode::deserialize_from(docs_reader)?;
-    println!("Documents loaded in {:?}", docs_start.elapsed());
-
-    println!("Loading term-document matrix from {}...", matrix_path);
-    let matrix_start = Instant::now();
-    let matrix_file = File::open(matrix_path)?;
-    let mut buffer = BufReader::with_capacity(8 * 1024 * 1024, matrix_file); // 8MB buffer dla większej macierzy
+fn read_csr(filepath: &Path) -> Result<SerializableCsrMatrix, Box<dyn Error>> {
+    let file = File::open(filepath)?;
+    let mut buffer = BufReader::with_capacity(8 * 1024 * 1024, file); // 8MB buffer dla większej macierzy
 
     let nrows: usize = bincode::deserialize_from(&mut buffer)?;
     let ncols: usize = bincode::deserialize_from(&mut buffer)?;
-
-
     let row_offsets: Vec<usize> = bincode::deserialize_from(&mut buffer)?;
-
     let col_indices: Vec<usize> = bincode::deserialize_from(&mut buffer)?;
     let values: Vec<f64> = bincode::deserialize_from(&mut buffer)?;
-    println!("Matrix loaded in {:?}", matrix_start.elapsed());
 
-    let term_doc_csr = SerializableCsrMatrix {
+    Ok(SerializableCsrMatrix {
         nrows,
         ncols,
         row_offsets,
         col_indices,
         values,
-    };
+    })
+}
 
-    let preprocessed_data = PreprocessedData {
-        term_dict,
-        inverse_term_dict,
-        idf,
-        documents,
-        term_doc_csr,
-    };
+fn write_csr(matrix: &SerializableCsrMatrix, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    let mut buffer = AtomicFile::create(filepath)?;
 
-    println!("All data loaded successfully in {:?}!", start_total.elapsed());
-    Ok(preprocessed_data)
+    bincode::serialize_into(&mut buffer, &matrix.nrows)?;
+    bincode::serialize_into(&mut buffer, &matrix.ncols)?;
+    bincode::serialize_into(&mut buffer, &matrix.row_offsets)?;
+    bincode::serialize_into(&mut buffer, &matrix.col_indices)?;
+    bincode::serialize_into(&mut buffer, &matrix.values)?;
+
+    buffer.commit()?;
+    Ok(())
 }
 
-pub fn save_svd_data(
-    data: &SvdData,
-    filepath: &str,
-) -> Result<(), Box<dyn Error>> {
-    println!("Saving SVD data to {}...", filepath);
-    let start_total = Instant::now();
+pub fn load_corpus_stats(filepath: &Path) -> Result<CorpusStats, Box<dyn Error>> {
+    log::info!("Loading corpus statistics from {}...", filepath.display());
+    let file = File::open(filepath)?;
+    let stats = bincode::deserialize_from(BufReader::new(file))?;
+    Ok(stats)
+}
+
+pub fn save_corpus_stats(stats: &CorpusStats, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving corpus statistics to {}...", filepath.display());
+    let mut file = AtomicFile::create(filepath)?;
+    bincode::serialize_into(&mut file, stats)?;
+    file.commit()?;
+    Ok(())
+}
 
-    let base_path = Path::new(filepath).with_extension("");
-    let base_path_str = base_path.to_string_lossy();
+pub fn load_autocomplete(filepath: &Path) -> Result<Autocomplete, Box<dyn Error>> {
+    log::info!("Loading autocomplete trie from {}...", filepath.display());
+    let file = File::open(filepath)?;
+    let trie = bincode::deserialize_from(BufReader::new(file))?;
+    Ok(trie)
+}
 
-    let meta_path = format!("{}_meta.bin", base_path_str);
-    println!("Saving SVD metadata to {}...", meta_path);
-    let meta_start = Instant::now();
-    let meta_file = File::create(&meta_path)?;
-    let meta_data = (data.rank, &data.sigma_k);
-    bincode::serialize_into(meta_file, &meta_data)?;
-    println!("Metadata saved in {:?}", meta_start.elapsed());
-
-    let u_path = format!("{}_u.bin", base_path_str);
-    println!("Saving U matrix ({}x{}) to {}...",
-             data.u_ser.nrows, data.u_ser.ncols, u_path);
-    let u_start = Instant::now();
-    let u_file = File::create(&u_path)?;
-    let mut u_buffer = BufWriter::with_capacity(4 * 1024 * 1024, u_file);
-
-    bincode::serialize_into(&mut u_buffer, &data.u_ser.nrows)?;
-    bincode::serialize_into(&mut u_buffer, &data.u_ser.ncols)?;
-
-    const CHUNK_SIZE: usize = 1_000_000;
-    let u_data = &data.u_ser.data;
-    let mut i = 0;
-    while i < u_data.len() {
-        let end = (i + CHUNK_SIZE).min(u_data.len());
-        let chunk = &u_data[i..end];
-        bincode::serialize_into(&mut u_buffer, &chunk)?;
-        i = end;
+pub fn save_autocomplete(trie: &Autocomplete, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving autocomplete trie to {}...", filepath.display());
+    let mut file = AtomicFile::create(filepath)?;
+    bincode::serialize_into(&mut file, trie)?;
+    file.commit()?;
+    Ok(())
+}
+
+/// Advisory lock serializing cache builds across processes; released when dropped.
+/// Hold it while loading or writing any cache artifact so a load never mixes files from
+/// two different builds.
+pub struct CacheLock {
+    _file: File,
+}
+
+impl CacheLock {
+    pub fn acquire(lock_path: &Path) -> io::Result<Self> {
+        let file = File::create(lock_path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                log::info!("Waiting for another process to release {}...", lock_path.display());
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(CacheLock { _file: file })
     }
-    u_buffer.flush()?;
-    println!("U matrix saved in {:?}", u_start.elapsed());
+}
 
-    let vt_path = format!("{}_vt.bin", base_path_str);
-    println!("Saving V^T matrix to {}...", vt_path);
-    let vt_start = Instant::now();
-    let vt_file = File::create(&vt_path)?;
-    let mut vt_buffer = io::BufWriter::with_capacity(4 * 1024 * 1024, vt_file); // 4MB buffer
-
-    bincode::serialize_into(&mut vt_buffer, &data.vt_ser.nrows)?;
-    bincode::serialize_into(&mut vt_buffer, &data.vt_ser.ncols)?;
-
-    let vt_data = &data.vt_ser.data;
-    let mut i = 0;
-    while i < vt_data.len() {
-        let end = (i + CHUNK_SIZE).min(vt_data.len());
-        let chunk = &vt_data[i..end];
-        bincode::serialize_into(&mut vt_buffer, &chunk)?;
-        i = end;
+/// Buffered writer to `<path>.tmp` that is renamed over `path` on `commit`, so the final
+/// path only ever holds a complete file. Dropping it uncommitted leaves the old file alone.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let file = File::create(&tmp_path)?;
+        Ok(AtomicFile {
+            path: path.to_path_buf(),
+            tmp_path,
+            writer: BufWriter::with_capacity(4 * 1024 * 1024, file),
+        })
     }
-    vt_buffer.flush()?;
-    println!("V^T matrix saved in {:?}", vt_start.elapsed());
 
-    let docs_path = format!("{}_docs.bin", base_path_str);
-    println!("Saving document vectors to {}...", docs_path);
-    let docs_start = Instant::now();
-    let docs_file = File::create(&docs_path)?;
-    let mut docs_buffer = io::BufWriter::with_capacity(4 * 1024 * 1024, docs_file); // 4MB buffer
-
-    bincode::serialize_into(&mut docs_buffer, &data.docs_ser.nrows)?;
-    bincode::serialize_into(&mut docs_buffer, &data.docs_ser.ncols)?;
-
-    let docs_data = &data.docs_ser.data;
-    let mut i = 0;
-    while i < docs_data.len() {
-        let end = (i + CHUNK_SIZE).min(docs_data.len());
-        let chunk = &docs_data[i..end];
-        bincode::serialize_into(&mut docs_buffer, &chunk)?;
-        i = end;
+    pub fn commit(self) -> io::Result<()> {
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&self.tmp_path, &self.path)
     }
-    docs_buffer.flush()?;
-    println!("Document vectors saved in {:?}", docs_start.elapsed());
+}
 
-    let index_path = filepath;
-    println!("Creating index file at {}...", index_path);
-    let index_file = File::create(index_path)?;
-    let index_data = (
-        meta_path,
-        u_path,
-        vt_path,
-        docs_path
-    );
-    bincode::serialize_into(index_file, &index_data)?;
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
 
-    println!("All SVD data saved successfully in {:?}!", start_total.elapsed());
-    Ok(())
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
 }
 
-pub fn save_preprocessed_data(
-    data: &PreprocessedData,
-    filepath: &str,
-) -> Result<(), Box<dyn Error>> {
-    println!("Saving preprocessed data to {}...", filepath);
-    let start_total = Instant::now();
-
-    let base_path = Path::new(filepath).with_extension("");
-    let base_path_str = base_path.to_string_lossy();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Method, SearchEngine};
+    use crate::util::analyzer::AnalyzerConfig;
 
-    let dict_path = format!("{}_terms.bin", base_path_str);
-    println!("Saving term dictionary to {}...", dict_path);
-    let dict_start = Instant::now();
-    let dict_file = File::create(&dict_path)?;
-    let dict_data = (&data.term_dict, &data.inverse_term_dict, &data.idf);
-    bincode::serialize_into(dict_file, &dict_data)?;
-    println!("Dictionary saved in {:?}", dict_start.elapsed());
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("search-engine-data-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
 
-    let docs_path = format!("{}_docs.bin", base_path_str);
-    println!("Saving documents to {}...", docs_path);
-    let docs_start = Instant::now();
-    let docs_file = File::create(&docs_path)?;
-    bincode::serialize_into(docs_file, &data.documents)?;
-    println!("Documents saved in {:?}", docs_start.elapsed());
+    fn corpus() -> Vec<Document> {
+        let texts = [
+            "New York is the most populous city in the United States",
+            "The United States declared independence in New York and Philadelphia",
+            "Subway trains carry commuters across New York every morning",
+            "Rivers and lakes supply drinking water to the city",
+            "The Hudson river flows past New York into the harbor",
+            "Philadelphia hosted the constitutional convention of the United States",
+            "Commuters crowd the subway platforms during the morning rush",
+            "Harbor cranes unload container ships arriving from Europe",
+        ];
+        texts.iter().enumerate()
+            .map(|(i, text)| Document::new(i as i64 + 1, format!("Article {}", i + 1), format!("https://example.org/{}", i + 1), *text))
+            .collect()
+    }
 
-    let matrix_path = format!("{}_matrix.bin", base_path_str);
-    println!("Saving term-document matrix to {}...", matrix_path);
-    let matrix_start = Instant::now();
+    #[test]
+    fn rebuilding_a_corpus_writes_identical_files() {
+        let config = AnalyzerConfig { collocation_min_count: 2, ..AnalyzerConfig::default() };
+        let dirs = [temp_dir("rebuild-first"), temp_dir("rebuild-second")];
+        for dir in &dirs {
+            let engine = SearchEngine::builder().corpus(corpus()).analyzer(config.clone()).with_lsi(2).build().unwrap();
+            engine.save(dir).unwrap();
+        }
 
-    let matrix_file = File::create(&matrix_path)?;
-    let mut buffer = io::BufWriter::with_capacity(1024 * 1024, matrix_file); // 1MB buffer
+        let mut names: Vec<_> = fs::read_dir(&dirs[0]).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        names.sort();
+        assert!(names.len() > 1, "expected the index and its components, got {:?}", names);
+        for name in &names {
+            let first = fs::read(dirs[0].join(name)).unwrap();
+            let second = fs::read(dirs[1].join(name)).unwrap();
+            assert!(first == second, "{:?} differs between builds", name);
+        }
+        for dir in &dirs {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
 
-    bincode::serialize_into(&mut buffer, &data.term_doc_csr.nrows)?;
-    bincode::serialize_into(&mut buffer, &data.term_doc_csr.ncols)?;
+    // Past i32::MAX, and past 2^53 where an f64 (and so a JavaScript number) loses precision.
+    const LARGE_IDS: [i64; 2] = [3_000_000_000, (1 << 53) + 1];
 
-    bincode::serialize_into(&mut buffer, &data.term_doc_csr.row_offsets)?;
+    fn corpus_with_large_ids() -> Vec<Document> {
+        let mut documents = corpus();
+        for (doc, id) in documents.iter_mut().zip(LARGE_IDS) {
+            doc.id = id;
+        }
+        documents
+    }
 
-    bincode::serialize_into(&mut buffer, &data.term_doc_csr.col_indices)?;
-    bincode::serialize_into(&mut buffer, &data.term_doc_csr.values)?;
+    #[test]
+    fn large_ids_survive_save_and_load() {
+        let dir = temp_dir("large-ids");
+        let engine = SearchEngine::builder().corpus(corpus_with_large_ids()).build().unwrap();
+        engine.save(&dir).unwrap();
+
+        let loaded = load_preprocessed_data(&dir.join("preprocessed.idx")).unwrap();
+        let ids: Vec<i64> = loaded.documents.iter().map(|doc| doc.id).collect();
+        let expected: Vec<i64> = engine.documents().iter().map(|doc| doc.id).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(&ids[..2], &LARGE_IDS);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-    buffer.flush()?;
-    println!("Matrix saved in {:?}", matrix_start.elapsed());
+    #[test]
+    fn search_results_carry_large_ids() {
+        let dir = temp_dir("large-id-search");
+        SearchEngine::builder().corpus(corpus_with_large_ids()).build().unwrap().save(&dir).unwrap();
+        let engine = SearchEngine::load(&dir).unwrap();
+
+        let ranked = engine.search("populous", Method::TfIdf, 5).unwrap();
+        assert_eq!(ranked.first().map(|(doc, _)| doc.id), Some(LARGE_IDS[0]));
+        let ranked = engine.search("independence", Method::Bm25, 5).unwrap();
+        assert_eq!(ranked.first().map(|(doc, _)| doc.id), Some(LARGE_IDS[1]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-    let index_path = filepath;
-    println!("Creating index file at {}...", index_path);
-    let index_file = File::create(index_path)?;
-    let index_data = (
-        dict_path,
-        docs_path,
-        matrix_path
-    );
-    bincode::serialize_into(index_file, &index_data)?;
+    #[test]
+    fn saving_weights_keeps_the_documents() {
+        let dir = temp_dir("save-weights");
+        SearchEngine::builder().corpus(corpus()).build().unwrap().save(&dir).unwrap();
+        let path = dir.join("preprocessed.idx");
+        let mut data = load_preprocessed_data(&path).unwrap();
 
-    println!("All data saved successfully in {:?}!", start_total.elapsed());
-    Ok(())
-}
\ No newline at end of file
+        // As tiering leaves them in memory.
+        for doc in Arc::make_mut(&mut data.documents).iter_mut() {
+            doc.text = String::new();
+        }
+        let weighting = crate::util::weighting::Weighting::Bm25 { k1: 1.2, b: 0.75 };
+        (data.term_doc_csr, data.idf) = crate::util::weighting::reweight(&data, weighting);
+        save_weights(&data, &path).unwrap();
+
+        let loaded = load_preprocessed_data(&path).unwrap();
+        assert_eq!(loaded.idf, data.idf);
+        assert_eq!(loaded.term_doc_csr.values, data.term_doc_csr.values);
+        let texts: Vec<&str> = loaded.documents.iter().map(|doc| doc.text.as_str()).collect();
+        let expected: Vec<Document> = corpus();
+        assert_eq!(texts, expected.iter().map(|doc| doc.text.as_str()).collect::<Vec<_>>());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
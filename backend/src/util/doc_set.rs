@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+
+/// Bitset over document (matrix column) indices.
+#[derive(Clone, Debug)]
+pub struct DocSet {
+    bits: Vec<u64>,
+}
+
+impl DocSet {
+    pub fn empty(num_docs: usize) -> Self {
+        DocSet { bits: vec![0; num_docs.div_ceil(64)] }
+    }
+
+    pub fn full(num_docs: usize) -> Self {
+        let mut set = DocSet { bits: vec![u64::MAX; num_docs.div_ceil(64)] };
+        if !num_docs.is_multiple_of(64)
+            && let Some(last) = set.bits.last_mut()
+        {
+            *last = (1 << (num_docs % 64)) - 1;
+        }
+        set
+    }
+
+    /// Documents containing at least one term with a non-zero weight in `query_vec`.
+    pub fn matching(query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f64>) -> Self {
+        let mut set = DocSet::empty(term_doc_matrix.ncols());
+        for (i, &weight) in query_vec.iter().enumerate() {
+            if weight != 0.0 {
+                let row_start = term_doc_matrix.row_offsets()[i];
+                let row_end = term_doc_matrix.row_offsets()[i + 1];
+                for &j in &term_doc_matrix.col_indices()[row_start..row_end] {
+                    set.insert(j);
+                }
+            }
+        }
+        set
+    }
+
+    pub fn insert(&mut self, doc_idx: usize) {
+        self.bits[doc_idx / 64] |= 1 << (doc_idx % 64);
+    }
+
+    pub fn contains(&self, doc_idx: usize) -> bool {
+        self.bits.get(doc_idx / 64).is_some_and(|word| word & (1 << (doc_idx % 64)) != 0)
+    }
+
+    pub fn intersect_with(&mut self, other: &DocSet) {
+        for (word, other_word) in self.bits.iter_mut().zip(&other.bits) {
+            *word &= other_word;
+        }
+    }
+
+    pub fn union_with(&mut self, other: &DocSet) {
+        for (word, other_word) in self.bits.iter_mut().zip(&other.bits) {
+            *word |= other_word;
+        }
+    }
+
+    pub fn subtract(&mut self, other: &DocSet) {
+        for (word, other_word) in self.bits.iter_mut().zip(&other.bits) {
+            *word &= !other_word;
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+/// Recent query result sets, addressable by token so follow-up queries can search within them.
+pub struct ResultSetStore {
+    sets: Mutex<HashMap<String, (Instant, Arc<DocSet>)>>,
+    limits: RwLock<(Duration, usize)>, // (ttl, capacity)
+}
+
+impl ResultSetStore {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        ResultSetStore {
+            sets: Mutex::new(HashMap::new()),
+            limits: RwLock::new((ttl, capacity)),
+        }
+    }
+
+    /// New limits apply from the next insert; sets that are already stored keep their tokens.
+    pub fn set_limits(&self, ttl: Duration, capacity: usize) {
+        *self.limits.write().unwrap() = (ttl, capacity);
+    }
+
+    pub fn insert(&self, set: DocSet) -> String {
+        let token = format!("{:016x}", rand::random::<u64>());
+        let (ttl, capacity) = *self.limits.read().unwrap();
+        let mut sets = self.sets.lock().unwrap();
+
+        let now = Instant::now();
+        sets.retain(|_, (created, _)| now.duration_since(*created) < ttl);
+        if sets.len() >= capacity
+            && let Some(oldest) = sets.iter().min_by_key(|(_, (created, _))| *created).map(|(t, _)| t.clone())
+        {
+            sets.remove(&oldest);
+        }
+
+        sets.insert(token.clone(), (now, Arc::new(set)));
+        token
+    }
+
+    pub fn get(&self, token: &str) -> Option<Arc<DocSet>> {
+        let (ttl, _) = *self.limits.read().unwrap();
+        let sets = self.sets.lock().unwrap();
+        sets.get(token)
+            .filter(|(created, _)| created.elapsed() < ttl)
+            .map(|(_, set)| set.clone())
+    }
+}
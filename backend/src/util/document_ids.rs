@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use crate::{Document, SvdData};
+
+/// Maps the identifiers clients use to document (matrix column) indices: stable UUIDs, and the
+/// SQLite rowids older clients still send.
+pub struct DocumentIds {
+    by_uuid: HashMap<String, usize>,
+    by_rowid: HashMap<i64, usize>,
+}
+
+impl DocumentIds {
+    pub fn build(documents: &[Document]) -> Self {
+        let mut by_uuid = HashMap::with_capacity(documents.len());
+        let mut by_rowid = HashMap::with_capacity(documents.len());
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            // The same URL scraped twice shares a UUID; the first copy answers for it.
+            by_uuid.entry(doc.uuid.clone()).or_insert(doc_idx);
+            by_rowid.entry(doc.id).or_insert(doc_idx);
+        }
+        DocumentIds { by_uuid, by_rowid }
+    }
+
+    /// The document with this UUID, or with this rowid when `id` is an integer.
+    pub fn resolve(&self, id: &str) -> Option<usize> {
+        match id.parse::<i64>() {
+            Ok(rowid) => self.by_rowid.get(&rowid).copied(),
+            Err(_) => self.by_uuid.get(&id.to_ascii_lowercase()).copied(),
+        }
+    }
+}
+
+/// The UUIDs of `documents` in column order, recorded with models that have a column per document.
+pub fn column_ids(documents: &[Document]) -> Vec<String> {
+    documents.iter().map(|doc| doc.uuid.clone()).collect()
+}
+
+/// Fails unless the model at `path` has one document column per document, in the same order.
+/// Models from before the mapping was recorded can only be checked for their column count.
+pub fn check_model_columns(model: &SvdData, documents: &[Document], path: &Path) -> Result<(), Box<dyn Error>> {
+    if model.docs_ser.ncols != documents.len() {
+        return Err(format!(
+            "{} has {} document columns but the index has {} documents; delete it to recompute the model",
+            path.display(), model.docs_ser.ncols, documents.len(),
+        ).into());
+    }
+    if model.doc_ids.is_empty() {
+        log::warn!("{} doesn't record its document order; delete it to recompute a verifiable one", path.display());
+        return Ok(());
+    }
+    if model.doc_ids.len() != documents.len() {
+        return Err(format!("{} records {} document IDs for {} columns", path.display(), model.doc_ids.len(), documents.len()).into());
+    }
+    if let Some(column) = model.doc_ids.iter().zip(documents).position(|(id, doc)| *id != doc.uuid) {
+        return Err(format!(
+            "Column {} of {} is document {} but the index has {} there; delete it to recompute the model",
+            column, path.display(), model.doc_ids[column], documents[column].uuid,
+        ).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::Document;
+use crate::util::sentences;
+
+const MAX_ENTITY_WORDS: usize = 4;
+// Capitalized only because they start a sentence or clause, never entities on their own.
+const COMMON_CAPITALIZED: &[&str] = &[
+    "The", "A", "An", "In", "On", "At", "Of", "For", "From", "By", "With", "This", "That",
+    "These", "Those", "It", "Its", "He", "She", "They", "We", "I", "His", "Her", "Their",
+    "After", "Before", "During", "When", "While", "But", "And", "Or", "As", "If",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct EntityConfig {
+    pub enabled: bool,
+    pub boost: f64,
+    pub max_per_document: usize,
+}
+
+impl Default for EntityConfig {
+    fn default() -> Self {
+        EntityConfig {
+            enabled: false,
+            boost: 0.2,
+            max_per_document: 20,
+        }
+    }
+}
+
+/// Rule-based entity extraction: the document title plus runs of capitalized words
+/// in the text, ranked by how often they occur.
+pub fn extract_entities(title: &str, text: &str, language: &str, max_entities: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for sentence in sentences::split(text, language) {
+        let mut run: Vec<&str> = Vec::new();
+        let mut run_at_sentence_start = true;
+
+        for (position, raw_word) in sentence.split_whitespace().enumerate() {
+            let word = raw_word.trim_matches(|c: char| !c.is_alphanumeric());
+            let capitalized = word.chars().count() > 1
+                && word.chars().next().is_some_and(char::is_uppercase);
+
+            if capitalized {
+                if run.len() == MAX_ENTITY_WORDS {
+                    flush_run(&mut run, run_at_sentence_start, &mut counts);
+                }
+                if run.is_empty() {
+                    run_at_sentence_start = position == 0;
+                }
+                run.push(word);
+            } else {
+                flush_run(&mut run, run_at_sentence_start, &mut counts);
+            }
+
+            if raw_word.ends_with([',', ';', ':', ')']) {
+                flush_run(&mut run, run_at_sentence_start, &mut counts);
+            }
+        }
+        flush_run(&mut run, run_at_sentence_start, &mut counts);
+    }
+
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut entities = Vec::new();
+    let mut seen = HashSet::new();
+    let title = title.trim();
+    if !title.is_empty() {
+        seen.insert(title.to_lowercase());
+        entities.push(title.to_string());
+    }
+
+    for (entity, _) in ranked {
+        if entities.len() >= max_entities {
+            break;
+        }
+        if seen.insert(entity.to_lowercase()) {
+            entities.push(entity);
+        }
+    }
+
+    entities
+}
+
+fn flush_run(run: &mut Vec<&str>, at_sentence_start: bool, counts: &mut HashMap<String, usize>) {
+    let mut words: &[&str] = run;
+    while let Some((first, rest)) = words.split_first() {
+        if COMMON_CAPITALIZED.contains(first) {
+            words = rest;
+        } else {
+            break;
+        }
+    }
+
+    // A lone capitalized word opening a sentence tells us nothing.
+    let skip = words.is_empty() || (at_sentence_start && words.len() == 1 && words.len() == run.len());
+    if !skip {
+        *counts.entry(words.join(" ")).or_insert(0) += 1;
+    }
+    run.clear();
+}
+
+#[derive(Serialize)]
+pub struct EntityFacet {
+    pub entity: String,
+    pub document_count: usize,
+}
+
+/// Maps lowercased entities to the documents that mention them.
+pub struct EntityIndex {
+    postings: HashMap<String, (String, Vec<usize>)>,
+}
+
+impl EntityIndex {
+    pub fn build(documents: &[Document]) -> Self {
+        let mut postings: HashMap<String, (String, Vec<usize>)> = HashMap::new();
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            for entity in &doc.entities {
+                postings.entry(entity.to_lowercase())
+                    .or_insert_with(|| (entity.clone(), Vec::new()))
+                    .1
+                    .push(doc_idx);
+            }
+        }
+
+        EntityIndex { postings }
+    }
+
+    /// Documents mentioning any entity that appears verbatim (ignoring case) in the query.
+    pub fn matching_documents(&self, query: &str) -> HashSet<usize> {
+        let words: Vec<String> = query.split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut matches = HashSet::new();
+        for start in 0..words.len() {
+            for end in (start + 1)..=(start + MAX_ENTITY_WORDS).min(words.len()) {
+                if let Some((_, doc_indices)) = self.postings.get(&words[start..end].join(" ")) {
+                    matches.extend(doc_indices.iter().copied());
+                }
+            }
+        }
+        matches
+    }
+
+    pub fn facets(&self, limit: usize) -> Vec<EntityFacet> {
+        let mut facets: Vec<EntityFacet> = self.postings.values()
+            .map(|(entity, doc_indices)| EntityFacet {
+                entity: entity.clone(),
+                document_count: doc_indices.len(),
+            })
+            .collect();
+        facets.sort_by(|a, b| b.document_count.cmp(&a.document_count).then_with(|| a.entity.cmp(&b.entity)));
+        facets.truncate(limit);
+        facets
+    }
+}
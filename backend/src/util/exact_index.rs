@@ -0,0 +1,52 @@
+use std::collections::{HashMap, HashSet};
+use crate::Document;
+
+/// Case-preserving index over title words, so "US" and "us" stay distinct.
+/// Short tokens are kept here even though the main tokenizer drops them.
+pub struct ExactTitleIndex {
+    postings: HashMap<String, Vec<usize>>,
+}
+
+impl ExactTitleIndex {
+    pub fn build(documents: &[Document]) -> Self {
+        let mut postings: HashMap<String, Vec<usize>> = HashMap::new();
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            let tokens: HashSet<String> = exact_tokens(&doc.title).into_iter().collect();
+            for token in tokens {
+                postings.entry(token).or_default().push(doc_idx);
+            }
+        }
+
+        ExactTitleIndex { postings }
+    }
+
+    /// Boost per document: `weight` scaled by the share of distinct query tokens
+    /// found with exactly the same case in its title.
+    pub fn boosts(&self, query: &str, weight: f64) -> HashMap<usize, f64> {
+        let query_tokens: HashSet<String> = exact_tokens(query).into_iter().collect();
+        if query_tokens.is_empty() {
+            return HashMap::new();
+        }
+
+        let mut matched: HashMap<usize, usize> = HashMap::new();
+        for token in &query_tokens {
+            if let Some(doc_indices) = self.postings.get(token) {
+                for &doc_idx in doc_indices {
+                    *matched.entry(doc_idx).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let total = query_tokens.len() as f64;
+        matched.into_iter()
+            .map(|(doc_idx, count)| (doc_idx, weight * count as f64 / total))
+            .collect()
+    }
+}
+
+pub fn exact_tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+use serde::Serialize;
+
+// Assumed speeds until searches of a method have been timed.
+const DEFAULT_NS_PER_POSTING: f64 = 20.0;
+const DEFAULT_NS_PER_DENSE_OP: f64 = 1.0;
+// Weight of each new observation in the running average.
+const SMOOTHING: f64 = 0.1;
+
+#[derive(Serialize, Debug)]
+pub struct TermCost {
+    pub term: String,
+    /// Documents containing the term: the postings a scan of it touches.
+    pub postings: usize,
+}
+
+/// What a search would do, reported by a `dry_run` search instead of running it.
+#[derive(Serialize, Debug)]
+pub struct QueryPlan {
+    pub method: &'static str,
+    /// Analyzed query terms found in the vocabulary.
+    pub terms: Vec<TermCost>,
+    /// Analyzed query terms the index doesn't have; they can't match anything.
+    pub unknown_terms: Vec<String>,
+    pub documents_in_scope: usize,
+    /// The indexes the search reads, e.g. `inverted`, `lsi k=100`, `title`.
+    pub indexes: Vec<String>,
+    pub work: Work,
+    pub estimated_ms: f64,
+    /// Whether the estimate comes from timings of earlier searches with this method rather
+    /// than built-in defaults.
+    pub calibrated: bool,
+}
+
+/// The work a search does, in the two units its cost scales with.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Work {
+    /// Entries of the term-document matrix visited: for scoring, the result set and matched terms.
+    pub postings: usize,
+    /// Multiply-adds of dense LSI algebra: projecting the query and comparing it to each document.
+    pub dense_ops: usize,
+}
+
+impl Work {
+    /// `lsi_rank` is the SVD rank the method uses, if it is an LSI method.
+    pub fn estimate(method: &str, query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f64>, lsi_rank: Option<usize>) -> Self {
+        let query_postings: usize = query_terms(query_vec)
+            .map(|term_idx| postings(term_doc_matrix, term_idx))
+            .sum();
+        // Every search builds its result set and matched terms from the postings.
+        let mut postings = 2 * query_postings;
+        let mut dense_ops = 0;
+        match (method, lsi_rank) {
+            ("tfidf", _) => postings += query_postings + term_doc_matrix.ncols(),
+            ("bm25" | "qlm", _) => postings += query_postings,
+            ("hybrid", Some(rank)) => {
+                postings += query_postings + term_doc_matrix.ncols();
+                dense_ops = (term_doc_matrix.nrows() + term_doc_matrix.ncols()) * rank;
+            }
+            (_, Some(rank)) => dense_ops = (term_doc_matrix.nrows() + term_doc_matrix.ncols()) * rank,
+            _ => {}
+        }
+        Work { postings, dense_ops }
+    }
+}
+
+/// The query's terms and the postings each one has, most common first.
+pub fn term_costs(query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f64>, inverse_term_dict: &HashMap<usize, String>) -> Vec<TermCost> {
+    let mut terms: Vec<TermCost> = query_terms(query_vec)
+        .map(|term_idx| TermCost {
+            term: inverse_term_dict.get(&term_idx).cloned().unwrap_or_default(),
+            postings: postings(term_doc_matrix, term_idx),
+        })
+        .collect();
+    terms.sort_by(|a, b| b.postings.cmp(&a.postings).then_with(|| a.term.cmp(&b.term)));
+    terms
+}
+
+/// Indices of the terms with a weight in the query vector.
+pub fn query_terms(query_vec: &DVector<f64>) -> impl Iterator<Item = usize> + '_ {
+    query_vec.iter().enumerate().filter(|(_, weight)| **weight != 0.0).map(|(term_idx, _)| term_idx)
+}
+
+pub fn postings(term_doc_matrix: &CsrMatrix<f64>, term_idx: usize) -> usize {
+    term_doc_matrix.row_offsets()[term_idx + 1] - term_doc_matrix.row_offsets()[term_idx]
+}
+
+/// Nanoseconds per posting and per dense operation for each method, learned from timed searches.
+#[derive(Default)]
+pub struct CostModel {
+    // method -> (ns per unit of work, timed searches)
+    speeds: Mutex<HashMap<String, (f64, u64)>>,
+}
+
+impl CostModel {
+    /// Estimated scoring time in milliseconds, and whether it is based on observed searches.
+    pub fn estimate_ms(&self, method: &str, work: Work) -> (f64, bool) {
+        let default_ns = default_ns(work);
+        let units = units(work);
+        match self.speeds.lock().unwrap().get(method) {
+            Some(&(ns_per_unit, _)) if units > 0.0 => (ns_per_unit * units / 1e6, true),
+            _ => (default_ns / 1e6, false),
+        }
+    }
+
+    pub fn observe(&self, method: &str, work: Work, elapsed_ms: f64) {
+        let units = units(work);
+        if units <= 0.0 {
+            return;
+        }
+        let ns_per_unit = elapsed_ms * 1e6 / units;
+        let mut speeds = self.speeds.lock().unwrap();
+        let entry = speeds.entry(method.to_string()).or_insert((ns_per_unit, 0));
+        entry.0 += SMOOTHING * (ns_per_unit - entry.0);
+        entry.1 += 1;
+    }
+}
+
+// Postings and dense operations in one scale, weighted by their default relative cost.
+fn units(work: Work) -> f64 {
+    work.postings as f64 + work.dense_ops as f64 * DEFAULT_NS_PER_DENSE_OP / DEFAULT_NS_PER_POSTING
+}
+
+fn default_ns(work: Work) -> f64 {
+    work.postings as f64 * DEFAULT_NS_PER_POSTING + work.dense_ops as f64 * DEFAULT_NS_PER_DENSE_OP
+}
@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+use nalgebra_sparse::CsrMatrix;
+use crate::PreprocessedData;
+
+/// Writes the index to `dir` in formats that numpy/scipy and Matlab read directly:
+/// `matrix.mtx` (terms x documents, Matrix Market), `documents.svmlight` (one row per document),
+/// `vocabulary.tsv` and `documents.tsv` (row/column labels, 0-based like the bincode cache).
+pub fn export_index(data: &PreprocessedData, dir: &str) -> Result<(), Box<dyn Error>> {
+    log::info!("Exporting index to {}...", dir);
+    let start = Instant::now();
+    fs::create_dir_all(dir)?;
+    let dir = Path::new(dir);
+
+    let csr = data.term_doc_csr.to_csr();
+    write_matrix_market(&csr, &dir.join("matrix.mtx"))?;
+    write_svmlight(&csr, data, &dir.join("documents.svmlight"))?;
+    write_vocabulary(&csr, data, &dir.join("vocabulary.tsv"))?;
+    write_documents(data, &dir.join("documents.tsv"))?;
+
+    log::info!("Export finished in {:?}", start.elapsed());
+    Ok(())
+}
+
+fn write_matrix_market(csr: &CsrMatrix<f64>, path: &Path) -> Result<(), Box<dyn Error>> {
+    log::debug!("Writing {}...", path.display());
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "%%MatrixMarket matrix coordinate real general")?;
+    writeln!(out, "% rows: terms (vocabulary.tsv), columns: documents (documents.tsv), values: normalized TF-IDF")?;
+    writeln!(out, "{} {} {}", csr.nrows(), csr.ncols(), csr.nnz())?;
+    for (i, j, value) in csr.triplet_iter() {
+        writeln!(out, "{} {} {}", i + 1, j + 1, value)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_svmlight(csr: &CsrMatrix<f64>, data: &PreprocessedData, path: &Path) -> Result<(), Box<dyn Error>> {
+    log::debug!("Writing {}...", path.display());
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // SVMlight rows are samples, so write the document-term matrix with the document id as the label.
+    let doc_term = csr.transpose();
+    for (doc, row) in data.documents.iter().zip(doc_term.row_iter()) {
+        write!(out, "{}", doc.id)?;
+        for (&term_idx, value) in row.col_indices().iter().zip(row.values()) {
+            write!(out, " {}:{}", term_idx + 1, value)?;
+        }
+        writeln!(out)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_vocabulary(csr: &CsrMatrix<f64>, data: &PreprocessedData, path: &Path) -> Result<(), Box<dyn Error>> {
+    log::debug!("Writing {}...", path.display());
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "index\tterm\tidf\tdocument_frequency")?;
+    for (i, row) in csr.row_iter().enumerate() {
+        let term = data.inverse_term_dict.get(&i).map(String::as_str).unwrap_or("");
+        writeln!(out, "{}\t{}\t{}\t{}", i, term, data.idf[i], row.nnz())?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_documents(data: &PreprocessedData, path: &Path) -> Result<(), Box<dyn Error>> {
+    log::debug!("Writing {}...", path.display());
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "index\tid\ttitle\turl")?;
+    for (i, doc) in data.documents.iter().enumerate() {
+        writeln!(out, "{}\t{}\t{}\t{}", i, doc.id, tsv_field(&doc.title), tsv_field(&doc.url))?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use crate::Document;
+use crate::util::doc_set::DocSet;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Restricts a search to documents located in an area, e.g. `{"radius": {"lat": 52.23,
+/// "lon": 21.01, "km": 50}}` or `{"bounding_box": {"min_lat": ..., "min_lon": ..., "max_lat": ...,
+/// "max_lon": ...}}`. Documents without coordinates never match.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GeoFilter {
+    /// A box crossing the antimeridian has `min_lon` greater than `max_lon`.
+    BoundingBox { min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64 },
+    Radius { lat: f64, lon: f64, km: f64 },
+}
+
+impl GeoFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_point = |lat: f64, lon: f64| (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon);
+        match *self {
+            GeoFilter::BoundingBox { min_lat, min_lon, max_lat, max_lon } => {
+                if !valid_point(min_lat, min_lon) || !valid_point(max_lat, max_lon) {
+                    return Err("Bounding box corners must be within ±90° latitude and ±180° longitude".to_string());
+                }
+                if min_lat > max_lat {
+                    return Err("min_lat must not exceed max_lat".to_string());
+                }
+            }
+            GeoFilter::Radius { lat, lon, km } => {
+                if !valid_point(lat, lon) {
+                    return Err("The center must be within ±90° latitude and ±180° longitude".to_string());
+                }
+                if !km.is_finite() || km <= 0.0 {
+                    return Err("km must be positive".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, (lat, lon): (f64, f64)) -> bool {
+        match *self {
+            GeoFilter::BoundingBox { min_lat, min_lon, max_lat, max_lon } => {
+                let within_lon = if min_lon <= max_lon {
+                    (min_lon..=max_lon).contains(&lon)
+                } else {
+                    lon >= min_lon || lon <= max_lon
+                };
+                (min_lat..=max_lat).contains(&lat) && within_lon
+            }
+            GeoFilter::Radius { lat: center_lat, lon: center_lon, km } => {
+                distance_km((center_lat, center_lon), (lat, lon)) <= km
+            }
+        }
+    }
+
+    /// The documents located inside the area.
+    pub fn documents(&self, documents: &[Document]) -> DocSet {
+        let mut set = DocSet::empty(documents.len());
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            if doc.coordinates.is_some_and(|point| self.contains(point)) {
+                set.insert(doc_idx);
+            }
+        }
+        set
+    }
+}
+
+/// Great-circle distance between two `(latitude, longitude)` points (haversine formula).
+pub fn distance_km((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let half_dlat = (lat2 - lat1) / 2.0;
+    let half_dlon = (lon2 - lon1).to_radians() / 2.0;
+    let a = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlon.sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
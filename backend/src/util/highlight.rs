@@ -0,0 +1,70 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+use regex::Regex;
+use serde::Serialize;
+use crate::util::analyzer::Analyzer;
+use crate::util::collocations::PHRASE_SEPARATOR;
+
+static WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[\p{L}\p{N}]+(?:['’-][\p{L}\p{N}]+)*").unwrap());
+
+/// A stretch of text matching a query term, as byte offsets (for Rust and Go clients) and
+/// character offsets (for JavaScript-style strings) into the same text.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+    /// The analyzed query term the words matched.
+    pub term: String,
+    /// Index of the word in the text, for telling how close matches are.
+    #[serde(skip)]
+    pub position: usize,
+}
+
+/// The words of `text` that analyze to one of `query_terms`, in order. Words of a phrase term
+/// are marked individually.
+pub fn spans(text: &str, query_terms: &HashSet<String>, analyzer: &Analyzer) -> Vec<Span> {
+    // Phrase terms match through their parts, which went through the same stages when indexed.
+    let mut wanted: HashMap<String, String> = HashMap::new();
+    for term in query_terms {
+        wanted.insert(term.clone(), term.clone());
+        if term.contains(PHRASE_SEPARATOR) {
+            for part in term.split(PHRASE_SEPARATOR) {
+                for analyzed in analyzer.analyze(part) {
+                    wanted.entry(analyzed).or_insert_with(|| term.clone());
+                }
+            }
+        }
+    }
+
+    let mut analyzed_words: HashMap<String, Option<String>> = HashMap::new();
+    let mut spans = Vec::new();
+    let mut chars_before = 0;
+    let mut last_end = 0;
+    for (position, found) in WORD.find_iter(text).enumerate() {
+        let word = found.as_str().to_lowercase();
+        let term = analyzed_words.entry(word)
+            .or_insert_with_key(|word| {
+                analyzer.analyze(word).into_iter().find_map(|analyzed| wanted.get(&analyzed).cloned())
+            })
+            .clone();
+        let Some(term) = term else {
+            continue;
+        };
+
+        chars_before += text[last_end..found.start()].chars().count();
+        let char_len = found.as_str().chars().count();
+        spans.push(Span {
+            start: found.start(),
+            end: found.end(),
+            char_start: chars_before,
+            char_end: chars_before + char_len,
+            term,
+            position,
+        });
+        chars_before += char_len;
+        last_end = found.end();
+    }
+    spans
+}
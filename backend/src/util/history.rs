@@ -0,0 +1,121 @@
+#[cfg(feature = "server")]
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "server")]
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    pub db_path: String,
+    /// Older queries beyond this many are dropped per session.
+    pub max_per_session: usize,
+    /// How quickly past queries lose weight as `/suggest` completions.
+    pub suggestion_half_life_hours: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enabled: true,
+            db_path: "history.db".to_string(),
+            max_per_session: 100,
+            suggestion_half_life_hours: 168.0,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub query: String,
+    pub method: Option<u8>,
+    pub result_count: usize,
+    /// Unix timestamp in seconds.
+    pub searched_at: i64,
+}
+
+/// Per-session query log kept in a SQLite file, so it survives restarts.
+#[cfg(feature = "server")]
+pub struct SearchHistory {
+    conn: Mutex<Connection>,
+    max_per_session: usize,
+}
+
+#[cfg(feature = "server")]
+impl SearchHistory {
+    /// A relative `db_path` is resolved against `data_dir`.
+    pub fn open(config: &HistoryConfig, data_dir: &Path) -> SqliteResult<Self> {
+        let conn = Connection::open(data_dir.join(&config.db_path))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS search_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session TEXT NOT NULL,
+                query TEXT NOT NULL,
+                method INTEGER,
+                result_count INTEGER NOT NULL,
+                searched_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS search_history_session ON search_history (session, id);",
+        )?;
+
+        Ok(SearchHistory {
+            conn: Mutex::new(conn),
+            max_per_session: config.max_per_session,
+        })
+    }
+
+    pub fn record(&self, session: &str, query: &str, method: Option<u8>, result_count: usize) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO search_history (session, query, method, result_count, searched_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![session, query, method, result_count as i64, unix_now()],
+        )?;
+        conn.execute(
+            "DELETE FROM search_history WHERE session = ?1 AND id NOT IN (
+                SELECT id FROM search_history WHERE session = ?1 ORDER BY id DESC LIMIT ?2
+            )",
+            params![session, self.max_per_session as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent first.
+    pub fn recent(&self, session: &str, limit: usize) -> SqliteResult<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT query, method, result_count, searched_at FROM search_history
+             WHERE session = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let entries = stmt.query_map(params![session, limit as i64], |row| {
+            Ok(HistoryEntry {
+                query: row.get(0)?,
+                method: row.get(1)?,
+                result_count: row.get::<_, i64>(2)? as usize,
+                searched_at: row.get(3)?,
+            })
+        })?;
+        entries.collect()
+    }
+
+    /// Every stored query across all sessions with its timestamp, oldest first.
+    pub fn all_queries(&self) -> SqliteResult<Vec<(String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT query, searched_at FROM search_history ORDER BY id")?;
+        let queries = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        queries.collect()
+    }
+
+    /// Returns the number of entries removed.
+    pub fn clear(&self, session: &str) -> SqliteResult<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM search_history WHERE session = ?1", params![session])
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
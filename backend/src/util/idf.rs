@@ -7,7 +7,7 @@ pub fn calculate_idf(term_doc_matrix: &CsrMatrix<f64>) -> Vec<f64> {
 
     let mut idf = vec![0.0; num_terms];
 
-    for term_idx in 0..num_terms {
+    for (term_idx, term_idf) in idf.iter_mut().enumerate() {
         let row_start = term_doc_matrix.row_offsets()[term_idx];
         let row_end = term_doc_matrix.row_offsets()[term_idx + 1];
         let mut doc_set = std::collections::HashSet::new();
@@ -17,7 +17,7 @@ pub fn calculate_idf(term_doc_matrix: &CsrMatrix<f64>) -> Vec<f64> {
         let doc_count = doc_set.len() as f64;
 
         if doc_count > 0.0 {
-            idf[term_idx] = (num_docs_f64 / doc_count).ln();
+            *term_idf = (num_docs_f64 / doc_count).ln();
         }
     }
 
@@ -27,14 +27,14 @@ pub fn calculate_idf(term_doc_matrix: &CsrMatrix<f64>) -> Vec<f64> {
 pub fn apply_idf_weighting(term_doc_matrix: &mut CsrMatrix<f64>, idf: &[f64]) {
     let mut triplets = Vec::new();
 
-    for i in 0..term_doc_matrix.nrows() {
+    for (i, &term_idf) in idf.iter().enumerate() {
         let row_start = term_doc_matrix.row_offsets()[i];
         let row_end = term_doc_matrix.row_offsets()[i + 1];
 
         for idx in row_start..row_end {
             let j = term_doc_matrix.col_indices()[idx];
             let val = term_doc_matrix.values()[idx];
-            triplets.push((i, j, val * idf[i]));
+            triplets.push((i, j, val * term_idf));
         }
     }
 
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use nalgebra::DMatrix;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use regex::Regex;
+use crate::{util, PreprocessedData, SerializableCsrMatrix, SvdData};
+
+/// Terms in row order, with IDF values when the file has an `idf` column.
+pub struct Vocabulary {
+    pub terms: Vec<String>,
+    pub idf: Option<Vec<f64>>,
+}
+
+/// Reads a vocabulary with one term per line, or a TSV with a header naming
+/// `term` (and optionally `idf`) columns, as written by the exporter.
+pub fn load_vocabulary(path: &str) -> Result<Vocabulary, Box<dyn Error>> {
+    log::info!("Loading vocabulary from {}...", path);
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines().peekable();
+
+    let (term_col, idf_col) = match lines.peek() {
+        Some(Ok(first)) if first.split('\t').any(|c| c == "term") => {
+            let header: Vec<String> = first.split('\t').map(str::to_string).collect();
+            lines.next();
+            let term_col = header.iter().position(|c| c == "term").unwrap();
+            (term_col, header.iter().position(|c| c == "idf"))
+        }
+        _ => (0, None),
+    };
+
+    let mut terms = Vec::new();
+    let mut idf = Vec::new();
+    for (line_no, line) in lines.enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        let term = fields.get(term_col)
+            .ok_or_else(|| format!("{}: line {} has no term column", path, line_no + 1))?;
+        terms.push(term.trim().to_string());
+        if let Some(col) = idf_col {
+            let value = fields.get(col)
+                .ok_or_else(|| format!("{}: line {} has no idf column", path, line_no + 1))?;
+            idf.push(value.trim().parse::<f64>()?);
+        }
+    }
+
+    log::info!("Loaded {} terms", terms.len());
+    Ok(Vocabulary { terms, idf: idf_col.map(|_| idf) })
+}
+
+/// Reads a sparse Matrix Market file (`coordinate real|integer general`).
+pub fn load_matrix_market(path: &str) -> Result<CsrMatrix<f64>, Box<dyn Error>> {
+    log::info!("Loading Matrix Market file from {}...", path);
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+
+    let banner = lines.next().ok_or("Empty Matrix Market file")??.to_lowercase();
+    if !banner.starts_with("%%matrixmarket matrix coordinate")
+        || !(banner.contains("real") || banner.contains("integer"))
+        || !banner.contains("general")
+    {
+        return Err(format!("Unsupported Matrix Market header: {}", banner).into());
+    }
+
+    let mut coo: Option<CooMatrix<f64>> = None;
+    for line in lines {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('%') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match &mut coo {
+            None => {
+                let nrows: usize = fields.first().ok_or("Missing matrix size line")?.parse()?;
+                let ncols: usize = fields.get(1).ok_or("Missing matrix size line")?.parse()?;
+                coo = Some(CooMatrix::new(nrows, ncols));
+            }
+            Some(coo) => {
+                if fields.len() < 3 {
+                    return Err(format!("Malformed Matrix Market entry: {}", line).into());
+                }
+                let i: usize = fields[0].parse()?;
+                let j: usize = fields[1].parse()?;
+                if i == 0 || j == 0 || i > coo.nrows() || j > coo.ncols() {
+                    return Err(format!("Matrix Market entry out of bounds: {}", line).into());
+                }
+                coo.push(i - 1, j - 1, fields[2].parse()?);
+            }
+        }
+    }
+
+    let coo = coo.ok_or("Missing matrix size line")?;
+    log::info!("Loaded {}x{} matrix with {} entries", coo.nrows(), coo.ncols(), coo.nnz());
+    Ok(CsrMatrix::from(&coo))
+}
+
+/// Reads a 2-D little-endian float32/float64 NumPy array.
+pub fn load_npy(path: &str) -> Result<DMatrix<f64>, Box<dyn Error>> {
+    log::info!("Loading NumPy array from {}...", path);
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(format!("{} is not a .npy file", path).into());
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+        version => return Err(format!("Unsupported .npy version {}", version).into()),
+    };
+    let data_start = header_start + header_len;
+    let header = std::str::from_utf8(bytes.get(header_start..data_start).ok_or("Truncated .npy header")?)?;
+
+    let descr = Regex::new(r"'descr':\s*'([<|]?)(f4|f8)'").unwrap()
+        .captures(header)
+        .map(|c| c[2].to_string())
+        .ok_or_else(|| format!("Unsupported .npy dtype (expected little-endian f4 or f8): {}", header))?;
+    let fortran_order = header.contains("'fortran_order': True");
+    let shape = Regex::new(r"'shape':\s*\((\d+),\s*(\d+)\)").unwrap()
+        .captures(header)
+        .ok_or_else(|| format!("Expected a 2-D array: {}", header))?;
+    let nrows: usize = shape[1].parse()?;
+    let ncols: usize = shape[2].parse()?;
+
+    let data = &bytes[data_start..];
+    let values: Vec<f64> = if descr == "f8" {
+        data.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect()
+    } else {
+        data.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64).collect()
+    };
+    if values.len() != nrows * ncols {
+        return Err(format!("{}: expected {} values, found {}", path, nrows * ncols, values.len()).into());
+    }
+
+    log::info!("Loaded {}x{} array", nrows, ncols);
+    Ok(if fortran_order {
+        DMatrix::from_column_slice(nrows, ncols, &values)
+    } else {
+        DMatrix::from_row_slice(nrows, ncols, &values)
+    })
+}
+
+/// Replaces the term-document matrix and vocabulary with external ones. Rows must follow the
+/// vocabulary file and columns the current document order.
+pub fn import_term_document_matrix(
+    data: &mut PreprocessedData,
+    matrix_path: &str,
+    vocabulary_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let vocabulary = load_vocabulary(vocabulary_path)?;
+    let mut csr = load_matrix_market(matrix_path)?;
+
+    if csr.nrows() != vocabulary.terms.len() {
+        return Err(format!("Matrix has {} rows but the vocabulary has {} terms", csr.nrows(), vocabulary.terms.len()).into());
+    }
+    if csr.ncols() != data.documents.len() {
+        return Err(format!("Matrix has {} columns but the index has {} documents", csr.ncols(), data.documents.len()).into());
+    }
+
+    let idf = vocabulary.idf.unwrap_or_else(|| util::idf::calculate_idf(&csr));
+    // Query likelihood treats the imported values as counts.
+    if csr.values().iter().any(|v| v.fract() != 0.0) {
+        log::warn!("Imported matrix has non-integer values; query-likelihood ranking expects raw counts");
+    }
+    let term_counts = csr.values().iter().map(|&v| v.round().max(0.0) as u32).collect();
+    util::norm::normalize_columns(&mut csr);
+
+    data.term_dict = vocabulary.terms.iter().cloned().enumerate().map(|(i, t)| (t, i)).collect();
+    data.inverse_term_dict = vocabulary.terms.into_iter().enumerate().collect();
+    data.idf = idf;
+    data.term_doc_csr = SerializableCsrMatrix::from_csr(&csr);
+    data.term_counts = term_counts;
+    Ok(())
+}
+
+/// Builds the dense search space from external embeddings: one row per document (current
+/// document order) and one row per vocabulary term. Queries are embedded as the IDF-weighted
+/// sum of their term vectors, the same way LSI queries are projected.
+pub fn import_embeddings(
+    data: &PreprocessedData,
+    doc_embeddings_path: &str,
+    vocabulary_path: &str,
+    term_embeddings_path: &str,
+) -> Result<SvdData, Box<dyn Error>> {
+    let doc_embeddings = load_npy(doc_embeddings_path)?;
+    let vocabulary = load_vocabulary(vocabulary_path)?;
+    let term_embeddings = load_npy(term_embeddings_path)?;
+
+    if doc_embeddings.nrows() != data.documents.len() {
+        return Err(format!("Document embeddings have {} rows but the index has {} documents", doc_embeddings.nrows(), data.documents.len()).into());
+    }
+    if term_embeddings.nrows() != vocabulary.terms.len() {
+        return Err(format!("Term embeddings have {} rows but the vocabulary has {} terms", term_embeddings.nrows(), vocabulary.terms.len()).into());
+    }
+    if term_embeddings.ncols() != doc_embeddings.ncols() {
+        return Err(format!("Term embeddings have dimension {} but document embeddings have {}", term_embeddings.ncols(), doc_embeddings.ncols()).into());
+    }
+
+    let dim = doc_embeddings.ncols();
+    let by_term: HashMap<&str, usize> = vocabulary.terms.iter().enumerate().map(|(i, t)| (t.as_str(), i)).collect();
+    let mut u = DMatrix::zeros(data.term_dict.len(), dim);
+    let mut matched = 0;
+    for (term, &term_idx) in &data.term_dict {
+        if let Some(&row) = by_term.get(term.as_str()) {
+            u.row_mut(term_idx).copy_from(&term_embeddings.row(row));
+            matched += 1;
+        }
+    }
+    log::info!("Matched {} of {} index terms to imported embeddings", matched, data.term_dict.len());
+
+    Ok(SvdData {
+        rank: dim,
+        sigma_k: vec![1.0; dim],
+        u_ser: crate::serialize_matrix(&u),
+        vt_ser: crate::serialize_matrix(&doc_embeddings.transpose()),
+        docs_ser: crate::serialize_matrix(&doc_embeddings.transpose()),
+        doc_ids: util::document_ids::column_ids(&data.documents),
+    })
+}
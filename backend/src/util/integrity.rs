@@ -0,0 +1,75 @@
+use crate::PreprocessedData;
+
+// Enough of a broken cache to see what went wrong without flooding the log.
+const MAX_REPORTED: usize = 10;
+
+/// Invariants the search code relies on without checking: vocabulary sizes agree, the
+/// dictionaries are inverse bijections and the matrix is well-formed CSR with sorted columns.
+/// Returns every violation found, so a mismatched pair of cache files is reported in one go.
+pub fn check_preprocessed(data: &PreprocessedData) -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+    let matrix = &data.term_doc_csr;
+    let num_terms = data.term_dict.len();
+
+    if data.inverse_term_dict.len() != num_terms {
+        problems.push(format!("term_dict has {} terms but inverse_term_dict {}", num_terms, data.inverse_term_dict.len()));
+    }
+    if data.idf.len() != num_terms {
+        problems.push(format!("idf has {} values for {} terms", data.idf.len(), num_terms));
+    }
+    if matrix.nrows != num_terms {
+        problems.push(format!("the term-document matrix has {} rows for {} terms", matrix.nrows, num_terms));
+    }
+    if matrix.ncols != data.documents.len() {
+        problems.push(format!("the term-document matrix has {} columns for {} documents", matrix.ncols, data.documents.len()));
+    }
+
+    for (term, &term_idx) in &data.term_dict {
+        if term_idx >= num_terms {
+            problems.push(format!("term {:?} has index {} beyond the vocabulary", term, term_idx));
+        } else if data.inverse_term_dict.get(&term_idx) != Some(term) {
+            problems.push(format!(
+                "term {:?} has index {} but inverse_term_dict maps that to {:?}",
+                term, term_idx, data.inverse_term_dict.get(&term_idx),
+            ));
+        }
+        if problems.len() >= MAX_REPORTED {
+            break;
+        }
+    }
+
+    if matrix.row_offsets.len() != matrix.nrows + 1 {
+        problems.push(format!("the matrix has {} row offsets for {} rows", matrix.row_offsets.len(), matrix.nrows));
+    } else if matrix.row_offsets.first() != Some(&0)
+        || matrix.row_offsets.last() != Some(&matrix.col_indices.len())
+        || matrix.col_indices.len() != matrix.values.len()
+    {
+        problems.push(format!(
+            "the matrix row offsets span {:?}..{:?} over {} column indices and {} values",
+            matrix.row_offsets.first(), matrix.row_offsets.last(), matrix.col_indices.len(), matrix.values.len(),
+        ));
+    } else {
+        for (row, bounds) in matrix.row_offsets.windows(2).enumerate() {
+            if bounds[0] > bounds[1] {
+                problems.push(format!("matrix row offsets decrease at row {}", row));
+            } else if let Some(&col) = matrix.col_indices[bounds[0]..bounds[1]].iter().find(|&&col| col >= matrix.ncols) {
+                problems.push(format!("matrix row {} has column {} beyond {} documents", row, col, matrix.ncols));
+            } else if matrix.col_indices[bounds[0]..bounds[1]].windows(2).any(|pair| pair[0] >= pair[1]) {
+                problems.push(format!("matrix row {} has unsorted or repeated columns", row));
+            }
+            if problems.len() >= MAX_REPORTED {
+                break;
+            }
+        }
+    }
+    if let Some(pos) = matrix.values.iter().chain(&data.idf).position(|value| !value.is_finite()) {
+        problems.push(format!("the matrix or idf holds a non-finite value (at position {})", pos));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        problems.truncate(MAX_REPORTED);
+        Err(problems)
+    }
+}
@@ -0,0 +1,259 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult, Row};
+use serde::{Deserialize, Serialize};
+use crate::util::history::unix_now;
+
+/// Where the job table is kept, in the data directory.
+pub const FILE_NAME: &str = "jobs.db";
+
+// A job interrupted by this many restarts is failed instead of retried again.
+const MAX_ATTEMPTS: u32 = 3;
+// How often an idle worker looks for jobs queued by another process.
+const POLL_SECS: u64 = 60;
+
+/// An admin operation run by the job worker.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobKind {
+    /// Snapshot of the index files.
+    Snapshot,
+    /// (Re)compute the SVD model of this rank and start serving it.
+    BuildSvd { rank: usize },
+    /// (Re)compute a language partition's own SVD model of this rank and start serving it.
+    BuildLanguageSvd { language: String, rank: usize },
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn as_str(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(state: &str) -> Self {
+        match state {
+            "queued" => JobState::Queued,
+            "running" => JobState::Running,
+            "done" => JobState::Done,
+            "cancelled" => JobState::Cancelled,
+            _ => JobState::Failed,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Job {
+    pub id: i64,
+    #[serde(flatten)]
+    pub kind: JobKind,
+    pub state: JobState,
+    /// Unix timestamps in seconds.
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// How many times the job was started; above 1 after a restart interrupted it.
+    pub attempts: u32,
+    pub cancel_requested: bool,
+    pub error: Option<String>,
+}
+
+/// Admin operations queued in a SQLite table and run one at a time on a background thread, so
+/// their progress and failures survive restarts. Jobs a crash interrupted are queued again.
+pub struct JobQueue {
+    conn: Mutex<Connection>,
+    queued: Condvar,
+}
+
+impl JobQueue {
+    pub fn open(path: &Path) -> SqliteResult<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                state TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                started_at INTEGER,
+                finished_at INTEGER,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                cancel_requested INTEGER NOT NULL DEFAULT 0,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS jobs_state ON jobs (state, id);",
+        )?;
+
+        // Whatever was running when the last process died never finished.
+        let failed = conn.execute(
+            "UPDATE jobs SET state = 'failed', finished_at = ?1, error = 'Interrupted by restarts too many times'
+             WHERE state = 'running' AND attempts >= ?2",
+            params![unix_now(), MAX_ATTEMPTS],
+        )?;
+        let cancelled = conn.execute(
+            "UPDATE jobs SET state = 'cancelled', finished_at = ?1 WHERE state = 'running' AND cancel_requested = 1",
+            params![unix_now()],
+        )?;
+        let requeued = conn.execute("UPDATE jobs SET state = 'queued' WHERE state = 'running'", [])?;
+        if failed + cancelled + requeued > 0 {
+            log::info!("Recovered interrupted jobs: {} requeued, {} cancelled, {} failed", requeued, cancelled, failed);
+        }
+
+        Ok(JobQueue { conn: Mutex::new(conn), queued: Condvar::new() })
+    }
+
+    pub fn enqueue(&self, kind: JobKind) -> Result<Job, Box<dyn Error>> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO jobs (kind, state, created_at) VALUES (?1, 'queued', ?2)",
+            params![serde_json::to_string(&kind)?, unix_now()],
+        )?;
+        let job = fetch(&conn, conn.last_insert_rowid())?.ok_or("The new job disappeared")?;
+        self.queued.notify_all();
+        Ok(job)
+    }
+
+    /// The most recent jobs, newest first.
+    pub fn list(&self, limit: usize) -> SqliteResult<Vec<Job>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("{} ORDER BY id DESC LIMIT ?1", SELECT_JOBS))?;
+        let jobs = stmt.query_map(params![limit as i64], job_from_row)?;
+        jobs.collect()
+    }
+
+    pub fn get(&self, id: i64) -> SqliteResult<Option<Job>> {
+        fetch(&self.conn.lock().unwrap(), id)
+    }
+
+    /// Whether a job of this kind is waiting or running.
+    pub fn has_pending(&self, kind: &JobKind) -> Result<bool, Box<dyn Error>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM jobs WHERE kind = ?1 AND state IN ('queued', 'running'))",
+            params![serde_json::to_string(kind)?],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Cancels a queued job outright; a running one stops at its next checkpoint. Finished
+    /// jobs are left as they are. `None` when there is no such job.
+    pub fn cancel(&self, id: i64) -> SqliteResult<Option<Job>> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE jobs SET state = 'cancelled', cancel_requested = 1, finished_at = ?2 WHERE id = ?1 AND state = 'queued'",
+            params![id, unix_now()],
+        )?;
+        conn.execute("UPDATE jobs SET cancel_requested = 1 WHERE id = ?1 AND state = 'running'", params![id])?;
+        fetch(&conn, id)
+    }
+
+    pub fn is_cancel_requested(&self, id: i64) -> bool {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT cancel_requested FROM jobs WHERE id = ?1", params![id], |row| row.get(0))
+            .unwrap_or(false)
+    }
+
+    /// Runs queued jobs oldest first on a background thread. `run` gets a check to call between
+    /// steps and should return `Err` once it reports the job was cancelled.
+    pub fn start_worker<F>(self: &Arc<Self>, run: F)
+    where
+        F: Fn(&JobKind, &dyn Fn() -> bool) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        let queue = self.clone();
+        std::thread::spawn(move || loop {
+            // Claimed under the same lock the wait releases, so an enqueue can't slip in between.
+            let claimed = {
+                let mut conn = queue.conn.lock().unwrap();
+                loop {
+                    match claim_next(&conn) {
+                        Ok(None) => conn = queue.queued.wait_timeout(conn, Duration::from_secs(POLL_SECS)).unwrap().0,
+                        other => break other,
+                    }
+                }
+            };
+            let job = match claimed {
+                Ok(Some(job)) => job,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("Can't read the job queue: {}", e);
+                    std::thread::sleep(Duration::from_secs(POLL_SECS));
+                    continue;
+                }
+            };
+
+            log::info!("Starting job {} ({:?})", job.id, job.kind);
+            let result = run(&job.kind, &|| queue.is_cancel_requested(job.id));
+            let (state, error) = match result {
+                Ok(()) => (JobState::Done, None),
+                Err(_) if queue.is_cancel_requested(job.id) => (JobState::Cancelled, None),
+                Err(e) => (JobState::Failed, Some(e.to_string())),
+            };
+            log::info!("Job {} {}{}", job.id, state.as_str(), error.as_deref().map(|e| format!(": {}", e)).unwrap_or_default());
+            if let Err(e) = queue.finish(job.id, state, error) {
+                log::warn!("Can't record the outcome of job {}: {}", job.id, e);
+            }
+        });
+    }
+
+    fn finish(&self, id: i64, state: JobState, error: Option<String>) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE jobs SET state = ?2, finished_at = ?3, error = ?4 WHERE id = ?1",
+            params![id, state.as_str(), unix_now(), error],
+        )?;
+        Ok(())
+    }
+}
+
+const SELECT_JOBS: &str = "SELECT id, kind, state, created_at, started_at, finished_at, attempts, cancel_requested, error FROM jobs";
+
+fn claim_next(conn: &Connection) -> SqliteResult<Option<Job>> {
+    let Some(id) = conn.query_row(
+        "SELECT id FROM jobs WHERE state = 'queued' ORDER BY id LIMIT 1",
+        [],
+        |row| row.get::<_, i64>(0),
+    ).optional()? else {
+        return Ok(None);
+    };
+    conn.execute(
+        "UPDATE jobs SET state = 'running', started_at = ?2, finished_at = NULL, error = NULL, attempts = attempts + 1 WHERE id = ?1",
+        params![id, unix_now()],
+    )?;
+    fetch(conn, id)
+}
+
+fn fetch(conn: &Connection, id: i64) -> SqliteResult<Option<Job>> {
+    conn.query_row(&format!("{} WHERE id = ?1", SELECT_JOBS), params![id], job_from_row).optional()
+}
+
+fn job_from_row(row: &Row) -> SqliteResult<Job> {
+    let kind: String = row.get(1)?;
+    let state: String = row.get(2)?;
+    Ok(Job {
+        id: row.get(0)?,
+        kind: serde_json::from_str(&kind)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e)))?,
+        state: JobState::parse(&state),
+        created_at: row.get(3)?,
+        started_at: row.get(4)?,
+        finished_at: row.get(5)?,
+        attempts: row.get(6)?,
+        cancel_requested: row.get(7)?,
+        error: row.get(8)?,
+    })
+}
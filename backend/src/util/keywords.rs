@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+use nalgebra_sparse::CsrMatrix;
+
+/// Returns, for every document column, its `top_n` terms ranked by TF-IDF weight.
+pub fn extract_keywords(
+    term_doc_matrix: &CsrMatrix<f64>,
+    inverse_term_dict: &HashMap<usize, String>,
+    top_n: usize,
+) -> Vec<Vec<String>> {
+    let mut doc_terms: Vec<Vec<(usize, f64)>> = vec![Vec::new(); term_doc_matrix.ncols()];
+
+    for term_idx in 0..term_doc_matrix.nrows() {
+        let row_start = term_doc_matrix.row_offsets()[term_idx];
+        let row_end = term_doc_matrix.row_offsets()[term_idx + 1];
+
+        for idx in row_start..row_end {
+            let j = term_doc_matrix.col_indices()[idx];
+            let val = term_doc_matrix.values()[idx];
+            doc_terms[j].push((term_idx, val));
+        }
+    }
+
+    doc_terms
+        .into_iter()
+        .map(|mut terms| {
+            terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+            terms.into_iter()
+                .take(top_n)
+                .filter_map(|(term_idx, _)| inverse_term_dict.get(&term_idx).cloned())
+                .collect()
+        })
+        .collect()
+}
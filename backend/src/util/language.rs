@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+use nalgebra_sparse::CsrMatrix;
+use serde::{Deserialize, Serialize};
+use crate::{Document, PreprocessedData, SerializableCsrMatrix, SvdData};
+use crate::util::{data, document_ids, search, svd, weighting};
+use crate::util::bm25::Bm25;
+use crate::util::doc_set::DocSet;
+use crate::util::qlm::QueryLikelihood;
+use crate::util::svd::SvdBuildConfig;
+use crate::util::weighting::Weighting;
+
+// The most frequent function words of each language; which of them a text uses is a strong
+// signal of its language even for a few words.
+const FUNCTION_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "in", "is", "that", "for", "it", "with", "was", "on", "are", "as", "by", "this", "be", "from", "at", "which"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "den", "mit", "sich", "des", "auf", "dem", "ein", "eine", "auch", "wird", "für", "im", "zu", "von"]),
+    ("fr", &["de", "le", "la", "les", "et", "des", "est", "une", "du", "que", "dans", "pour", "qui", "pas", "sur", "au", "avec", "sont", "par", "il", "ce"]),
+    ("es", &["de", "la", "el", "los", "las", "del", "que", "y", "en", "una", "por", "con", "para", "es", "se", "su", "al", "como", "más", "pero", "sus", "fue"]),
+    ("it", &["il", "di", "che", "della", "per", "non", "sono", "gli", "una", "del", "nel", "alla", "anche", "come", "delle", "più", "ha", "questo", "dei", "è"]),
+    ("pl", &["i", "w", "się", "nie", "na", "jest", "że", "do", "to", "z", "o", "jak", "ale", "po", "co", "tak", "od", "jego", "przez", "być"]),
+    ("nl", &["de", "het", "een", "van", "en", "niet", "zijn", "op", "dat", "met", "voor", "ook", "er", "aan", "wordt", "bij", "naar", "om", "maar", "door"]),
+    ("pt", &["de", "a", "o", "os", "da", "do", "em", "um", "uma", "não", "para", "com", "por", "mais", "dos", "das", "ao", "foi", "são", "seu", "como", "também"]),
+];
+// Share of a text's function-word hits the best language needs to be trusted.
+const MIN_SHARE: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LanguageConfig {
+    /// Partition the index by document language and route queries to their language's partition.
+    pub enabled: bool,
+    /// Languages with fewer documents stay in the shared index only.
+    pub min_documents: usize,
+    /// Fill up a query's results from other languages when its own partition has too few.
+    pub federate: bool,
+    /// Train a cross-language LSI model (method 7) from interlanguage-linked article pairs.
+    pub cross_language: bool,
+    /// Fewer linked pairs than this in the database and no cross-language model is trained.
+    pub cross_language_min_pairs: usize,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        LanguageConfig {
+            enabled: false,
+            min_documents: 50,
+            federate: true,
+            cross_language: false,
+            cross_language_min_pairs: 50,
+        }
+    }
+}
+
+/// ISO 639-1 code of the language `text` is most likely written in, if any of the known ones
+/// clearly dominates.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut hits = vec![0usize; FUNCTION_WORDS.len()];
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        for (count, (_, words)) in hits.iter_mut().zip(FUNCTION_WORDS) {
+            if words.contains(&word.as_str()) {
+                *count += 1;
+            }
+        }
+    }
+
+    let total: usize = hits.iter().sum();
+    let (best, &count) = hits.iter().enumerate().max_by_key(|&(_, count)| count)?;
+    let tied = hits.iter().filter(|&&other| other == count).count() > 1;
+    if count == 0 || tied || (count as f64) < MIN_SHARE * total as f64 {
+        return None;
+    }
+    Some(FUNCTION_WORDS[best].0)
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LanguageStats {
+    pub language: String,
+    pub documents: usize,
+    /// Terms occurring in at least one of the language's documents.
+    pub vocabulary_size: usize,
+    /// Rank of the language's own LSI model, once one is built.
+    pub svd_rank: Option<usize>,
+}
+
+/// A language's documents as an index of their own. Its vocabulary, IDF and term weights come
+/// from those documents only, so a majority language doesn't decide them, and it gets its own
+/// LSI model once one is built (see `svd_path`).
+pub struct LanguagePartition {
+    /// The partition's documents, by their index in the shared index.
+    docs: DocSet,
+    /// Shared-index position of each of the partition's documents, ascending.
+    doc_indices: Vec<usize>,
+    /// The sub-index. Its documents carry no text; results are mapped back to the shared index
+    /// with `to_shared` before anything reads it.
+    pub index: PreprocessedData,
+    pub csr: CsrMatrix<f64>,
+    pub query_likelihood: QueryLikelihood,
+    pub bm25: Bm25,
+    svd: RwLock<Option<Arc<SvdData>>>,
+    stats: LanguageStats,
+}
+
+impl LanguagePartition {
+    /// Cuts the sub-index of `doc_indices` (ascending) out of the shared index's raw counts and
+    /// weights it by `weighting` over those documents alone.
+    fn build(data: &PreprocessedData, language: &str, doc_indices: Vec<usize>, weighting: Weighting) -> Self {
+        let num_docs = data.documents.len();
+        let mut docs = DocSet::empty(num_docs);
+        let mut local = vec![usize::MAX; num_docs];
+        for (local_idx, &doc_idx) in doc_indices.iter().enumerate() {
+            docs.insert(doc_idx);
+            local[doc_idx] = local_idx;
+        }
+
+        // Terms keep their relative order, so each row's columns stay sorted.
+        let matrix = &data.term_doc_csr;
+        let mut term_dict = HashMap::new();
+        let mut inverse_term_dict = HashMap::new();
+        let mut row_offsets = vec![0];
+        let mut col_indices = Vec::new();
+        let mut term_counts = Vec::new();
+        for term_idx in 0..matrix.nrows {
+            let row_start = col_indices.len();
+            for idx in matrix.row_offsets[term_idx]..matrix.row_offsets[term_idx + 1] {
+                let local_idx = local[matrix.col_indices[idx]];
+                if local_idx != usize::MAX {
+                    col_indices.push(local_idx);
+                    term_counts.push(data.term_counts[idx]);
+                }
+            }
+            if col_indices.len() > row_start {
+                let term = &data.inverse_term_dict[&term_idx];
+                term_dict.insert(term.clone(), row_offsets.len() - 1);
+                inverse_term_dict.insert(row_offsets.len() - 1, term.clone());
+                row_offsets.push(col_indices.len());
+            }
+        }
+        let counts = SerializableCsrMatrix {
+            nrows: row_offsets.len() - 1,
+            ncols: doc_indices.len(),
+            row_offsets,
+            values: vec![0.0; col_indices.len()],
+            col_indices,
+        };
+
+        // Only ids and order matter to the sub-index; the text stays in the shared one.
+        let documents = doc_indices.iter()
+            .map(|&doc_idx| {
+                let doc = &data.documents[doc_idx];
+                Document {
+                    id: doc.id,
+                    uuid: doc.uuid.clone(),
+                    title: doc.title.clone(),
+                    url: String::new(),
+                    text: String::new(),
+                    keywords: Vec::new(),
+                    summary: String::new(),
+                    entities: Vec::new(),
+                    language: doc.language.clone(),
+                    modified_at: doc.modified_at,
+                    coordinates: doc.coordinates,
+                    image_url: None,
+                    metadata: String::new(),
+                }
+            })
+            .collect();
+        let mut index = PreprocessedData {
+            term_dict,
+            inverse_term_dict,
+            idf: Vec::new(),
+            collocations: Default::default(),
+            synonyms: Default::default(),
+            subwords: Default::default(),
+            documents: Arc::new(documents),
+            term_doc_csr: counts,
+            term_counts,
+            texts: None,
+        };
+        (index.term_doc_csr, index.idf) = weighting::reweight(&index, weighting);
+        let csr = index.term_doc_csr.to_csr();
+
+        let stats = LanguageStats {
+            language: language.to_string(),
+            documents: doc_indices.len(),
+            vocabulary_size: index.term_dict.len(),
+            svd_rank: None,
+        };
+        LanguagePartition {
+            docs,
+            doc_indices,
+            query_likelihood: QueryLikelihood::build(&index),
+            bm25: Bm25::build(&index),
+            index,
+            csr,
+            svd: RwLock::new(None),
+            stats,
+        }
+    }
+
+    /// The partition's documents in the shared index.
+    pub fn documents(&self) -> &DocSet {
+        &self.docs
+    }
+
+    /// `scope` (shared-index documents) as the sub-index's documents it contains; `None`, all of
+    /// them, for no scope.
+    pub fn local_scope(&self, scope: Option<&DocSet>) -> Option<DocSet> {
+        let scope = scope?;
+        let mut local = DocSet::empty(self.doc_indices.len());
+        for (local_idx, &doc_idx) in self.doc_indices.iter().enumerate() {
+            if scope.contains(doc_idx) {
+                local.insert(local_idx);
+            }
+        }
+        Some(local)
+    }
+
+    /// Ranked sub-index documents as the shared index's, re-sorted with its tie-break (which
+    /// looks at `query_terms`, shared-index term indices) and cut to `top_k`.
+    pub fn to_shared<'a>(
+        &self,
+        ranked: Vec<(&Document, f64)>,
+        data: &'a PreprocessedData,
+        query_terms: &[usize],
+        top_k: usize,
+    ) -> Vec<(&'a Document, f64)> {
+        let mut scores: Vec<(usize, f64)> = ranked.into_iter()
+            .map(|(doc, score)| (self.doc_indices[search::document_index(&self.index.documents, doc)], score))
+            .collect();
+        search::sort_ranked(&mut scores, query_terms, data, top_k);
+        scores.into_iter()
+            .take(top_k)
+            .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+            .collect()
+    }
+
+    /// The partition's LSI model, once one is built or loaded.
+    pub fn svd(&self) -> Option<Arc<SvdData>> {
+        self.svd.read().unwrap().clone()
+    }
+
+    /// Starts serving `model`, which must have a column per sub-index document.
+    pub fn set_svd(&self, model: Arc<SvdData>) {
+        *self.svd.write().unwrap() = Some(model);
+    }
+
+    /// Highest rank an LSI model of the sub-index can have.
+    pub fn max_rank(&self) -> usize {
+        self.csr.nrows().min(self.csr.ncols()).saturating_sub(1)
+    }
+
+    /// Computes the sub-index's LSI model of `rank` (at most `max_rank`).
+    pub fn compute_svd(&self, rank: usize, config: &SvdBuildConfig, checkpoint: Option<&Path>) -> Result<SvdData, Box<dyn Error>> {
+        let mut model = svd::perform_svd(&self.csr, rank.min(self.max_rank()), config, checkpoint)?;
+        model.doc_ids = document_ids::column_ids(&self.index.documents);
+        Ok(model)
+    }
+
+    /// Loads the model at `path`, checking it has the partition's documents as its columns.
+    pub fn load_svd(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let model = data::load_svd_data(path)?;
+        document_ids::check_model_columns(&model, &self.index.documents, path)?;
+        self.set_svd(Arc::new(model));
+        Ok(())
+    }
+}
+
+/// Where the LSI model of rank `rank` for `language`'s partition is kept in the data directory.
+pub fn svd_path(data_dir: &Path, language: &str, rank: usize) -> PathBuf {
+    data_dir.join(format!("svd_{}_k{}.idx", language, rank))
+}
+
+/// The sub-indexes of each sufficiently common language.
+#[derive(Default)]
+pub struct LanguagePartitions {
+    partitions: HashMap<String, LanguagePartition>,
+}
+
+impl LanguagePartitions {
+    pub fn build(data: &PreprocessedData, min_documents: usize, weighting: Weighting) -> Self {
+        let mut by_language: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (doc_idx, doc) in data.documents.iter().enumerate() {
+            if !doc.language.is_empty() {
+                by_language.entry(doc.language.as_str()).or_default().push(doc_idx);
+            }
+        }
+
+        let mut partitions = HashMap::new();
+        for (language, doc_indices) in by_language {
+            if doc_indices.len() < min_documents {
+                continue;
+            }
+            let partition = LanguagePartition::build(data, language, doc_indices, weighting);
+            log::info!("Language partition {}: {} documents, {} terms", language, partition.stats.documents, partition.stats.vocabulary_size);
+            partitions.insert(language.to_string(), partition);
+        }
+
+        LanguagePartitions { partitions }
+    }
+
+    /// Loads the partitions' LSI models of `rank` that are already in `data_dir`, except those
+    /// written before `reweighted_at` (a unix timestamp), which factorize the old weights. The
+    /// others are built on first use.
+    pub fn load_svd_models(&self, data_dir: &Path, rank: usize, reweighted_at: Option<i64>) -> Result<(), Box<dyn Error>> {
+        for (language, partition) in &self.partitions {
+            let path = svd_path(data_dir, language, rank.min(partition.max_rank()));
+            if !path.exists() {
+                continue;
+            }
+            let written_at = fs::metadata(&path)?.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            if reweighted_at.is_some_and(|reweighted_at| written_at < reweighted_at) {
+                log::info!("{} predates the last reweight; it will be rebuilt on first use", path.display());
+                continue;
+            }
+            partition.load_svd(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, language: &str) -> Option<&LanguagePartition> {
+        self.partitions.get(language)
+    }
+
+    pub fn documents(&self, language: &str) -> Option<&DocSet> {
+        self.partitions.get(language).map(LanguagePartition::documents)
+    }
+
+    /// Largest partitions first.
+    pub fn stats(&self) -> Vec<LanguageStats> {
+        let mut stats: Vec<LanguageStats> = self.partitions.values()
+            .map(|partition| LanguageStats {
+                svd_rank: partition.svd().map(|model| model.rank),
+                ..partition.stats.clone()
+            })
+            .collect();
+        stats.sort_by(|a, b| b.documents.cmp(&a.documents).then_with(|| a.language.cmp(&b.language)));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_sparse::CooMatrix;
+
+    // Three English and two German documents; "bank" is in all of the English ones and one
+    // German one, "haus" in both German ones.
+    fn index() -> PreprocessedData {
+        let languages = ["en", "de", "en", "de", "en"];
+        let documents: Vec<Document> = languages.iter().enumerate()
+            .map(|(i, language)| Document {
+                language: language.to_string(),
+                ..Document::new(i as i64 + 1, format!("Document {}", i + 1), "", "")
+            })
+            .collect();
+        let terms = ["bank", "haus", "river"];
+        let mut coo = CooMatrix::new(terms.len(), documents.len());
+        for (term_idx, doc_idx, count) in [(0, 0, 1.0), (0, 2, 2.0), (0, 4, 1.0), (0, 3, 1.0), (1, 1, 3.0), (1, 3, 1.0), (2, 4, 1.0)] {
+            coo.push(term_idx, doc_idx, count);
+        }
+        let counts = CsrMatrix::from(&coo);
+        let mut data = PreprocessedData {
+            term_dict: terms.iter().enumerate().map(|(term_idx, term)| (term.to_string(), term_idx)).collect(),
+            inverse_term_dict: terms.iter().enumerate().map(|(term_idx, term)| (term_idx, term.to_string())).collect(),
+            idf: Vec::new(),
+            collocations: Default::default(),
+            synonyms: Default::default(),
+            subwords: Default::default(),
+            documents: Arc::new(documents),
+            term_doc_csr: SerializableCsrMatrix::from_csr(&counts),
+            term_counts: counts.values().iter().map(|&count| count as u32).collect(),
+            texts: None,
+        };
+        (data.term_doc_csr, data.idf) = weighting::reweight(&data, Weighting::default());
+        data
+    }
+
+    #[test]
+    fn partitions_have_their_own_vocabulary_and_idf() {
+        let data = index();
+        let partitions = LanguagePartitions::build(&data, 2, Weighting::default());
+        let german = partitions.get("de").unwrap();
+
+        assert_eq!(german.index.term_dict.len(), 2);
+        assert!(!german.index.term_dict.contains_key("river"));
+        // One of the two German documents has "bank"; in the whole index four of five do.
+        let bank = german.index.term_dict["bank"];
+        assert!((german.index.idf[bank] - 2f64.ln()).abs() < 1e-12);
+        assert!((data.idf[data.term_dict["bank"]] - 1.25f64.ln()).abs() < 1e-12);
+        assert_eq!(german.index.term_counts.iter().sum::<u32>(), 5);
+    }
+
+    #[test]
+    fn partition_results_map_to_the_shared_index() {
+        let data = index();
+        let partitions = LanguagePartitions::build(&data, 2, Weighting::default());
+        let german = partitions.get("de").unwrap();
+
+        let mut scope = DocSet::empty(data.documents.len());
+        scope.insert(3);
+        scope.insert(4);
+        let local = german.local_scope(Some(&scope)).unwrap();
+        assert!(!local.contains(0) && local.contains(1));
+
+        let ranked = vec![(&german.index.documents[1], 0.5), (&german.index.documents[0], 0.9)];
+        let shared = german.to_shared(ranked, &data, &[], 10);
+        assert_eq!(shared.iter().map(|(doc, score)| (doc.id, *score)).collect::<Vec<_>>(), vec![(2, 0.9), (4, 0.5)]);
+        assert_eq!(shared[0].0.title, "Document 2");
+    }
+}
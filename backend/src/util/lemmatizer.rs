@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// Dictionary-based lemmatizer for languages where suffix stripping falls apart.
+#[derive(Serialize, Deserialize)]
+pub struct Lemmatizer {
+    lemmas: HashMap<String, String>,
+}
+
+impl Lemmatizer {
+    /// Loads a dictionary in the Morfologik/polimorfologik text layout: one
+    /// `lemma;form;tags` entry per line (tabs are accepted as separators too).
+    /// When a form is ambiguous, the first lemma listed for it wins.
+    pub fn load(filename: &Path) -> std::io::Result<Self> {
+        let file = File::open(filename)?;
+        let reader = BufReader::new(file);
+        let mut lemmas = HashMap::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.split([';', '\t']);
+
+            let (Some(lemma), Some(form)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+
+            let lemma = lemma.trim().to_lowercase();
+            let form = form.trim().to_lowercase();
+            if lemma.is_empty() || form.is_empty() {
+                continue;
+            }
+
+            lemmas.entry(lemma.clone()).or_insert_with(|| lemma.clone());
+            lemmas.entry(form).or_insert(lemma);
+        }
+
+        Ok(Lemmatizer { lemmas })
+    }
+
+    pub fn form_count(&self) -> usize {
+        self.lemmas.len()
+    }
+
+    /// Returns the lemma for `token`, or the token itself when the dictionary doesn't know it.
+    pub fn lemmatize(&self, token: &str) -> String {
+        self.lemmas
+            .get(token)
+            .cloned()
+            .unwrap_or_else(|| token.to_string())
+    }
+}
@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::PreprocessedData;
+use crate::util::analyzer::{AnalyzerConfig, Normalization};
+use crate::util::data::AtomicFile;
+use crate::util::weighting::Weighting;
+
+/// What to do when the index was built with a different analyzer config than the server runs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MismatchPolicy {
+    #[default]
+    Warn,
+    Refuse,
+}
+
+/// Describes how an index was built, saved next to it so a server can tell whether its
+/// config still matches.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexManifest {
+    pub analyzer_config_hash: String,
+    pub normalization: Normalization,
+    pub stemmer_language: String,
+    /// `None` when the matrix was imported rather than weighted here.
+    pub weighting: Option<Weighting>,
+    pub vocabulary_size: usize,
+    pub document_count: usize,
+    /// Unix timestamp in seconds.
+    pub built_at: i64,
+    /// When the weights were last recomputed with another `weighting`, as a unix timestamp in
+    /// seconds. SVD models built before then factorize the old weights.
+    #[serde(default)]
+    pub reweighted_at: Option<i64>,
+    pub source_path: Option<String>,
+    pub source_checksum: Option<String>,
+}
+
+impl IndexManifest {
+    pub fn new(
+        data: &PreprocessedData,
+        analyzer: &AnalyzerConfig,
+        weighting: Option<Weighting>,
+        source_path: Option<&Path>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let source_checksum = match source_path {
+            Some(path) => Some(file_checksum(path)?),
+            None => None,
+        };
+
+        Ok(IndexManifest {
+            analyzer_config_hash: analyzer_config_hash(analyzer)?,
+            normalization: analyzer.normalization,
+            stemmer_language: match analyzer.normalization {
+                Normalization::Porter => "english".to_string(),
+                Normalization::Lemma => "dictionary".to_string(),
+                Normalization::Subword => "subword".to_string(),
+            },
+            weighting,
+            vocabulary_size: data.term_dict.len(),
+            document_count: data.documents.len(),
+            built_at: crate::util::history::unix_now(),
+            reweighted_at: None,
+            source_path: source_path.map(|path| path.to_string_lossy().into_owned()),
+            source_checksum,
+        })
+    }
+
+    /// Identifies this build of the index to clients: when it was built and with which analyzer.
+    pub fn version(&self) -> String {
+        format!("{}-{}", self.built_at, self.analyzer_config_hash)
+    }
+
+    /// Describes how `analyzer` differs from the config the index was built with, if it does.
+    pub fn analyzer_mismatch(&self, analyzer: &AnalyzerConfig) -> Result<Option<String>, Box<dyn Error>> {
+        let hash = analyzer_config_hash(analyzer)?;
+        if hash == self.analyzer_config_hash {
+            return Ok(None);
+        }
+        Ok(Some(format!(
+            "the index was built with analyzer config {} ({:?}) but the server runs {} ({:?})",
+            self.analyzer_config_hash, self.normalization, hash, analyzer.normalization,
+        )))
+    }
+}
+
+pub fn manifest_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension("manifest.json")
+}
+
+pub fn load_manifest(filepath: &Path) -> Result<IndexManifest, Box<dyn Error>> {
+    let file = File::open(filepath)?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+pub fn save_manifest(manifest: &IndexManifest, filepath: &Path) -> Result<(), Box<dyn Error>> {
+    log::info!("Saving index manifest to {}...", filepath.display());
+    let mut file = AtomicFile::create(filepath)?;
+    serde_json::to_writer_pretty(&mut file, manifest)?;
+    file.commit()?;
+    Ok(())
+}
+
+fn analyzer_config_hash(config: &AnalyzerConfig) -> Result<String, Box<dyn Error>> {
+    let json = serde_json::to_vec(config)?;
+    Ok(format!("{:016x}", fnv1a(0xcbf29ce484222325, &json)))
+}
+
+fn file_checksum(path: &Path) -> std::io::Result<String> {
+    let mut reader = BufReader::with_capacity(1024 * 1024, File::open(path)?);
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut hash = 0xcbf29ce484222325;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hash = fnv1a(hash, &buffer[..read]);
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+// FNV-1a: stable across builds and platforms, unlike std's DefaultHasher.
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::Document;
+use crate::util::doc_set::DocSet;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MetadataConfig {
+    /// Top-level metadata fields to index as keyword facets, e.g. `["venue", "year", "authors"]`.
+    /// Undeclared fields are still stored and returned, just not filterable.
+    pub facets: Vec<String>,
+}
+
+/// The metadata column's JSON object, re-serialized compactly; empty when there is none or it
+/// isn't an object. Kept as text because the index files' format can't hold arbitrary JSON.
+pub fn normalize(raw: Option<&str>, doc_id: i64) -> String {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return String::new();
+    };
+    match serde_json::from_str::<Value>(raw) {
+        Ok(Value::Object(fields)) if !fields.is_empty() => Value::Object(fields).to_string(),
+        Ok(Value::Object(_)) => String::new(),
+        _ => {
+            log::warn!("Ignoring metadata of article {} that isn't a JSON object", doc_id);
+            String::new()
+        }
+    }
+}
+
+/// The document's metadata as JSON, for responses.
+pub fn parse(doc: &Document) -> Option<Map<String, Value>> {
+    if doc.metadata.is_empty() {
+        return None;
+    }
+    match serde_json::from_str(&doc.metadata) {
+        Ok(Value::Object(fields)) => Some(fields),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+pub struct MetadataFacet {
+    pub value: String,
+    pub document_count: usize,
+}
+
+/// For each declared facet field, the documents having each of its values. Arrays contribute
+/// each element; numbers and booleans are matched by their JSON text.
+pub struct MetadataIndex {
+    fields: HashMap<String, HashMap<String, Vec<usize>>>,
+}
+
+impl MetadataIndex {
+    pub fn build(documents: &[Document], config: &MetadataConfig) -> Self {
+        let mut fields: HashMap<String, HashMap<String, Vec<usize>>> = config.facets.iter()
+            .map(|field| (field.clone(), HashMap::new()))
+            .collect();
+        if fields.is_empty() {
+            return MetadataIndex { fields };
+        }
+
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            let Some(metadata) = parse(doc) else {
+                continue;
+            };
+            for (field, postings) in fields.iter_mut() {
+                let values = match metadata.get(field) {
+                    Some(Value::Array(values)) => values.iter().collect(),
+                    Some(value) => vec![value],
+                    None => Vec::new(),
+                };
+                for value in values.into_iter().filter_map(keyword) {
+                    let docs = postings.entry(value).or_default();
+                    // A value repeated within one array still counts the document once.
+                    if docs.last() != Some(&doc_idx) {
+                        docs.push(doc_idx);
+                    }
+                }
+            }
+        }
+        MetadataIndex { fields }
+    }
+
+    /// The most common values of a facet field; `None` when it isn't declared.
+    pub fn facets(&self, field: &str, limit: usize) -> Option<Vec<MetadataFacet>> {
+        let postings = self.fields.get(field)?;
+        let mut facets: Vec<MetadataFacet> = postings.iter()
+            .map(|(value, docs)| MetadataFacet { value: value.clone(), document_count: docs.len() })
+            .collect();
+        facets.sort_by(|a, b| b.document_count.cmp(&a.document_count).then_with(|| a.value.cmp(&b.value)));
+        facets.truncate(limit);
+        Some(facets)
+    }
+
+    /// Documents having every `field: value` pair.
+    pub fn matching(&self, filters: &BTreeMap<String, String>, num_docs: usize) -> Result<DocSet, String> {
+        let mut matching = DocSet::full(num_docs);
+        for (field, value) in filters {
+            let postings = self.fields.get(field)
+                .ok_or_else(|| format!("Metadata field {:?} isn't declared in metadata.facets", field))?;
+            let mut docs = DocSet::empty(num_docs);
+            for &doc_idx in postings.get(value).into_iter().flatten() {
+                docs.insert(doc_idx);
+            }
+            matching.intersect_with(&docs);
+        }
+        Ok(matching)
+    }
+}
+
+fn keyword(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
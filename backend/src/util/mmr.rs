@@ -0,0 +1,44 @@
+use crate::Document;
+
+/// Maximal marginal relevance: reorders `ranked` so each pick has the best
+/// `lambda * relevance - (1 - lambda) * similarity` to the picks before it, where relevance is
+/// the score scaled to 0..1 over `ranked` and similarity the largest of `similarities` (pairwise,
+/// in the order of `ranked`) to an earlier pick. Keeps the first `top_k` with their own scores.
+pub fn rerank<'a>(
+    ranked: Vec<(&'a Document, f64)>,
+    similarities: &[Vec<f64>],
+    lambda: f64,
+    top_k: usize,
+) -> Vec<(&'a Document, f64)> {
+    let (min, max) = ranked.iter()
+        .map(|(_, score)| *score)
+        .filter(|score| score.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), score| (min.min(score), max.max(score)));
+    let relevance: Vec<f64> = ranked.iter()
+        .map(|(_, score)| if max > min && score.is_finite() { (score - min) / (max - min) } else { 1.0 })
+        .collect();
+
+    // Most similar earlier pick of each candidate, updated as picks are made.
+    let mut closest = vec![0.0_f64; ranked.len()];
+    let mut picked = vec![false; ranked.len()];
+    let mut order = Vec::with_capacity(top_k.min(ranked.len()));
+    while order.len() < top_k.min(ranked.len()) {
+        // Ties go to the higher ranked candidate, so lambda = 1 keeps the ranking.
+        let mut best: Option<(f64, usize)> = None;
+        for idx in (0..ranked.len()).filter(|&idx| !picked[idx]) {
+            let value = lambda * relevance[idx] - (1.0 - lambda) * closest[idx];
+            if best.is_none_or(|(best_value, _)| value > best_value) {
+                best = Some((value, idx));
+            }
+        }
+        let Some((_, pick)) = best else {
+            break;
+        };
+        picked[pick] = true;
+        order.push(pick);
+        for (idx, similarity) in similarities[pick].iter().enumerate() {
+            closest[idx] = closest[idx].max(*similarity);
+        }
+    }
+    order.into_iter().map(|idx| ranked[idx]).collect()
+}
@@ -5,4 +5,64 @@ pub mod idf;
 pub mod search;
 pub mod norm;
 pub mod data;
-pub mod svd;
\ No newline at end of file
+pub mod svd;
+pub mod analyzer;
+pub mod lemmatizer;
+pub mod config;
+pub mod keywords;
+pub mod summarizer;
+pub mod title_index;
+pub mod query_classifier;
+pub mod entities;
+pub mod collocations;
+pub mod exact_index;
+pub mod doc_set;
+pub mod corpus_stats;
+pub mod export;
+pub mod import;
+pub mod qlm;
+pub mod weighting;
+pub mod bm25;
+pub mod history;
+pub mod suggest;
+pub mod spell;
+pub mod manifest;
+pub mod build_error;
+pub mod scroll;
+pub mod snapshot;
+#[cfg(feature = "server")]
+pub mod admission;
+pub mod svd_catalog;
+pub mod language;
+pub mod cl_lsi;
+pub mod transliterate;
+pub mod cleaning;
+pub mod sentences;
+pub mod boost_rules;
+pub mod blocklist;
+pub mod result_filter;
+pub mod highlight;
+pub mod document_ids;
+pub mod integrity;
+pub mod sampling;
+pub mod trends;
+pub mod geo;
+pub mod similarity;
+#[cfg(feature = "server")]
+pub mod jobs;
+pub mod metadata;
+pub mod views;
+pub mod explain;
+pub mod boolean_query;
+pub mod slow_queries;
+pub mod circuit_breaker;
+pub mod title_terms;
+pub mod autocomplete;
+pub mod tiering;
+pub mod synonyms;
+pub mod snippet;
+pub mod subwords;
+pub mod query_cache;
+pub mod mmr;
+pub mod timing;
+pub mod self_test;
@@ -29,9 +29,9 @@ pub fn normalize_columns(term_doc_matrix: &mut CsrMatrix<f64>) {
             let j = term_doc_matrix.col_indices()[idx];
             let val = term_doc_matrix.values()[idx];
 
-            if col_norms[j] > 0.0 {
-                triplets.push((i, j, val / col_norms[j]));
-            }
+            // Keep entries of all-zero columns so the sparsity pattern matches the raw counts.
+            let normalized = if col_norms[j] > 0.0 { val / col_norms[j] } else { val };
+            triplets.push((i, j, normalized));
         }
     }
 
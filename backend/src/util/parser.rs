@@ -1,18 +1,56 @@
+#[cfg(feature = "server")]
 use std::path::Path;
+#[cfg(feature = "server")]
 use crate::Document;
-use rusqlite::{Connection, Result as SqliteResult};
+#[cfg(feature = "server")]
+use crate::util::metadata;
+#[cfg(feature = "server")]
+use rusqlite::{Connection, Error as SqliteError, Result as SqliteResult, Row};
+#[cfg(feature = "server")]
+use rusqlite::types::{Type, ValueRef};
+use uuid::Uuid;
 
 
-pub fn parse_sqlite_documents(db_path: &str) -> SqliteResult<Vec<Document>> {
-    let conn = Connection::open(Path::new(db_path))?;
+#[cfg(feature = "server")]
+pub fn parse_sqlite_documents(db_path: &Path) -> SqliteResult<Vec<Document>> {
+    let conn = Connection::open(db_path)?;
 
-    let mut stmt = conn.prepare("SELECT id, title, url, text FROM articles")?;
+    // Databases scraped by older versions lack the later columns; those read as NULL.
+    let mut optional_columns = Vec::new();
+    for column in ["modified_at", "lat", "lon", "image_url", "metadata"] {
+        let has_column: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('articles') WHERE name = ?)",
+            [column],
+            |row| row.get(0),
+        )?;
+        optional_columns.push(if has_column { column } else { "NULL" });
+    }
+    let sql = format!("SELECT id, title, url, text, {} FROM articles", optional_columns.join(", "));
+
+    let mut stmt = conn.prepare(&sql)?;
     let document_iter = stmt.query_map([], |row| {
+        let title: String = row.get(1)?;
+        let url: String = row.get(2)?;
+        let text: String = row.get(3)?;
+        let id = document_id(row, 0)?;
+        let metadata: Option<String> = row.get(8)?;
         Ok(Document {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            url: row.get(2)?,
-            text: row.get(3)?,
+            id,
+            uuid: document_uuid(&url, &title, &text),
+            title,
+            url,
+            text,
+            keywords: Vec::new(),
+            summary: String::new(),
+            entities: Vec::new(),
+            language: String::new(),
+            modified_at: row.get(4)?,
+            coordinates: match (row.get(5)?, row.get(6)?) {
+                (Some(lat), Some(lon)) => Some((lat, lon)),
+                _ => None,
+            },
+            image_url: row.get(7)?,
+            metadata: metadata::normalize(metadata.as_deref(), id),
         })
     })?;
 
@@ -22,4 +60,48 @@ pub fn parse_sqlite_documents(db_path: &str) -> SqliteResult<Vec<Document>> {
     }
 
     Ok(documents)
-}
\ No newline at end of file
+}
+
+/// `(article id, url)` rows of the scraper's `interlanguage_links` table: other-language versions
+/// of each article. Empty when the database predates the table.
+#[cfg(feature = "server")]
+pub fn parse_interlanguage_links(db_path: &Path) -> SqliteResult<Vec<(i64, String)>> {
+    let conn = Connection::open(db_path)?;
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'interlanguage_links')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare("SELECT article_id, url FROM interlanguage_links")?;
+    let links = stmt.query_map([], |row| Ok((document_id(row, 0)?, row.get(1)?)))?;
+    links.collect()
+}
+
+/// A name-based (v5) UUID of the URL, ignoring a trailing slash and the fragment. Articles
+/// without a URL get one from their title and text instead.
+pub fn document_uuid(url: &str, title: &str, text: &str) -> String {
+    let url = url.trim();
+    let url = url.split_once('#').map_or(url, |(base, _)| base).trim_end_matches('/');
+    let uuid = if url.is_empty() {
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("{}\n{}", title, text).as_bytes())
+    } else {
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, url.as_bytes())
+    };
+    uuid.to_string()
+}
+
+/// Reads an article ID stored either as an INTEGER or as TEXT holding one, as some scrapers write.
+#[cfg(feature = "server")]
+fn document_id(row: &Row, idx: usize) -> SqliteResult<i64> {
+    match row.get_ref(idx)? {
+        ValueRef::Integer(id) => Ok(id),
+        ValueRef::Text(text) => std::str::from_utf8(text).ok()
+            .and_then(|text| text.trim().parse().ok())
+            .ok_or_else(|| SqliteError::InvalidColumnType(idx, "id".to_string(), Type::Text)),
+        other => Err(SqliteError::InvalidColumnType(idx, "id".to_string(), other.data_type())),
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use crate::{util, PreprocessedData};
+use crate::util::doc_set::DocSet;
+
+/// Query-likelihood ranking with Dirichlet smoothing over raw term counts:
+/// score(d) = sum over query terms of qtf * ln((tf + mu * P(t|C)) / (|d| + mu)).
+pub struct QueryLikelihood {
+    doc_lengths: Vec<f64>,
+    collection_probs: Vec<f64>,
+}
+
+impl QueryLikelihood {
+    pub fn build(data: &PreprocessedData) -> Self {
+        let matrix = &data.term_doc_csr;
+        let mut doc_lengths = vec![0.0; matrix.ncols];
+        let mut collection_probs = vec![0.0; matrix.nrows];
+
+        for (term_idx, prob) in collection_probs.iter_mut().enumerate() {
+            let row_start = matrix.row_offsets[term_idx];
+            let row_end = matrix.row_offsets[term_idx + 1];
+            for idx in row_start..row_end {
+                let count = data.term_counts[idx] as f64;
+                doc_lengths[matrix.col_indices[idx]] += count;
+                *prob += count;
+            }
+        }
+
+        let total: f64 = collection_probs.iter().sum();
+        if total > 0.0 {
+            for prob in collection_probs.iter_mut() {
+                *prob /= total;
+            }
+        }
+
+        QueryLikelihood { doc_lengths, collection_probs }
+    }
+
+    /// Ranks documents containing at least one query term, best first.
+    pub fn rank(
+        &self,
+        query_terms: &HashMap<usize, f64>,
+        data: &PreprocessedData,
+        mu: f64,
+        within: Option<&DocSet>,
+        top_k: usize,
+    ) -> Vec<(usize, f64)> {
+        // Split into a part that only touches documents containing the term and a
+        // length penalty shared by all of them, so scoring stays proportional to the postings.
+        let mut matched: HashMap<usize, f64> = HashMap::new();
+        for (&term_idx, &qtf) in query_terms {
+            let background = mu * self.collection_probs[term_idx];
+            if background <= 0.0 {
+                continue;
+            }
+            let row_start = data.term_doc_csr.row_offsets[term_idx];
+            let row_end = data.term_doc_csr.row_offsets[term_idx + 1];
+            for idx in row_start..row_end {
+                let doc_idx = data.term_doc_csr.col_indices[idx];
+                *matched.entry(doc_idx).or_insert(0.0) += qtf * (1.0 + data.term_counts[idx] as f64 / background).ln();
+            }
+        }
+
+        let query_length: f64 = query_terms.values().sum();
+        let mut scores: Vec<(usize, f64)> = matched.into_iter()
+            .filter(|(doc_idx, _)| within.is_none_or(|set| set.contains(*doc_idx)))
+            .map(|(doc_idx, score)| (doc_idx, score + self.length_penalty(query_length, mu, doc_idx)))
+            .collect();
+
+        let terms: Vec<usize> = query_terms.keys().copied().collect();
+        util::search::sort_ranked(&mut scores, &terms, data, top_k);
+        scores.truncate(top_k);
+        scores
+    }
+
+    /// Score of a document containing none of the query terms, which `rank` leaves out: only
+    /// the length penalty.
+    pub fn unmatched_score(&self, query_terms: &HashMap<usize, f64>, mu: f64, doc_idx: usize) -> f64 {
+        self.length_penalty(query_terms.values().sum(), mu, doc_idx)
+    }
+
+    fn length_penalty(&self, query_length: f64, mu: f64, doc_idx: usize) -> f64 {
+        query_length * (mu / (self.doc_lengths[doc_idx] + mu)).ln()
+    }
+}
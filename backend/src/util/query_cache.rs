@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+use nalgebra::DVector;
+use crate::PreprocessedData;
+use crate::util::blocklist::Blocklist;
+use crate::util::doc_set::DocSet;
+use crate::util::views::Views;
+
+/// The part of a search that doesn't depend on its method: the analyzed query and the documents
+/// in scope after filters, views, the blocklist and language routing.
+pub struct PreparedQuery {
+    /// The words a boolean query is ranked by; `None` for other queries.
+    pub ranking_query: Option<String>,
+    pub terms: Vec<String>,
+    /// The query vector's non-zero `(term_idx, weight)` entries. Kept sparse, since a dense
+    /// vector spans the whole vocabulary and the cache holds many of them.
+    pub query_weights: Vec<(usize, f64)>,
+    pub num_terms: usize,
+    pub within: Option<DocSet>,
+    /// The routed language's documents, when other languages may fill up the results.
+    pub language_scope: Option<DocSet>,
+    pub routed_language: Option<String>,
+}
+
+impl PreparedQuery {
+    /// Keeps only the non-zero entries of `query_vec`.
+    pub fn sparse_weights(query_vec: &DVector<f64>) -> Vec<(usize, f64)> {
+        query_vec.iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight != 0.0)
+            .map(|(term_idx, &weight)| (term_idx, weight))
+            .collect()
+    }
+
+    /// The dense query vector, expanded for the request that is scoring it.
+    pub fn query_vec(&self) -> DVector<f64> {
+        let mut query_vec = DVector::zeros(self.num_terms);
+        for &(term_idx, weight) in &self.query_weights {
+            query_vec[term_idx] = weight;
+        }
+        query_vec
+    }
+}
+
+// What a prepared query was worked out against. Held weakly, so a cached query neither keeps a
+// replaced index alive nor, while it is cached, can its address be taken by the replacement.
+struct Snapshot {
+    index: Weak<PreprocessedData>,
+    blocklist: Weak<Blocklist>,
+    views: Weak<Views>,
+}
+
+impl Snapshot {
+    fn new(index: &Arc<PreprocessedData>, blocklist: &Arc<Blocklist>, views: &Arc<Views>) -> Self {
+        Snapshot {
+            index: Arc::downgrade(index),
+            blocklist: Arc::downgrade(blocklist),
+            views: Arc::downgrade(views),
+        }
+    }
+
+    fn matches(&self, index: &Arc<PreprocessedData>, blocklist: &Arc<Blocklist>, views: &Arc<Views>) -> bool {
+        self.index.as_ptr() == Arc::as_ptr(index)
+            && self.blocklist.as_ptr() == Arc::as_ptr(blocklist)
+            && self.views.as_ptr() == Arc::as_ptr(views)
+    }
+}
+
+struct CacheEntry {
+    inserted: Instant,
+    snapshot: Snapshot,
+    query: Arc<PreparedQuery>,
+}
+
+/// Recently prepared queries, so a client re-running a query with another method (as the
+/// frontend's method toggle does) skips analysis and candidate generation. Keyed by the request
+/// fields that decide scope; entries prepared against a since replaced index, blocklist or set
+/// of views don't match.
+pub struct QueryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    limits: RwLock<(Duration, usize)>, // (ttl, capacity)
+}
+
+impl QueryCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        QueryCache {
+            entries: Mutex::new(HashMap::new()),
+            limits: RwLock::new((ttl, capacity)),
+        }
+    }
+
+    pub fn set_limits(&self, ttl: Duration, capacity: usize) {
+        *self.limits.write().unwrap() = (ttl, capacity);
+    }
+
+    pub fn get(
+        &self,
+        key: &str,
+        index: &Arc<PreprocessedData>,
+        blocklist: &Arc<Blocklist>,
+        views: &Arc<Views>,
+    ) -> Option<Arc<PreparedQuery>> {
+        let (ttl, _) = *self.limits.read().unwrap();
+        let entries = self.entries.lock().unwrap();
+        entries.get(key)
+            .filter(|entry| entry.inserted.elapsed() < ttl && entry.snapshot.matches(index, blocklist, views))
+            .map(|entry| entry.query.clone())
+    }
+
+    pub fn insert(
+        &self,
+        key: String,
+        index: &Arc<PreprocessedData>,
+        blocklist: &Arc<Blocklist>,
+        views: &Arc<Views>,
+        prepared: PreparedQuery,
+    ) -> Arc<PreparedQuery> {
+        let prepared = Arc::new(prepared);
+        let (ttl, capacity) = *self.limits.read().unwrap();
+        if capacity == 0 {
+            return prepared;
+        }
+        let mut entries = self.entries.lock().unwrap();
+
+        let now = Instant::now();
+        entries.retain(|_, entry| {
+            now.duration_since(entry.inserted) < ttl && entry.snapshot.matches(index, blocklist, views)
+        });
+        if entries.len() >= capacity
+            && let Some(oldest) = entries.iter().min_by_key(|(_, entry)| entry.inserted).map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+
+        entries.insert(key, CacheEntry {
+            inserted: now,
+            snapshot: Snapshot::new(index, blocklist, views),
+            query: prepared.clone(),
+        });
+        prepared
+    }
+}
@@ -0,0 +1,34 @@
+use crate::util::title_index::TitleIndex;
+
+const MAX_NAVIGATIONAL_WORDS: usize = 4;
+const QUESTION_WORDS: &[&str] = &[
+    "what", "how", "why", "who", "when", "where", "which", "is", "are", "does", "do", "can",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueryKind {
+    /// The user is looking for one specific article, e.g. "Albert Einstein".
+    Navigational,
+    /// The user wants documents about a topic.
+    Informational,
+}
+
+/// Navigational queries are short, don't read like a question, and look like a title:
+/// either some document title starts with them or every word is capitalized.
+pub fn classify(query: &str, title_index: &TitleIndex) -> QueryKind {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if words.is_empty() || words.len() > MAX_NAVIGATIONAL_WORDS {
+        return QueryKind::Informational;
+    }
+
+    if QUESTION_WORDS.contains(&words[0].to_lowercase().as_str()) {
+        return QueryKind::Informational;
+    }
+
+    let capitalized = words.iter().all(|word| word.chars().next().is_some_and(char::is_uppercase));
+    if capitalized || title_index.has_prefix(query) {
+        QueryKind::Navigational
+    } else {
+        QueryKind::Informational
+    }
+}
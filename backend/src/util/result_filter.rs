@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{Document, PreprocessedData};
+use crate::util::config::StorageConfig;
+
+/// A content policy applied to every search's results just before they are returned.
+/// Deployments implement this for their own rules and register it with `ResultFilters::add`.
+pub trait ResultFilter: Send + Sync {
+    /// Shown in logs when the filter is registered.
+    fn name(&self) -> &str;
+    /// Whether `doc` may be shown for `query`. `text` is the document's text, which `doc` doesn't
+    /// hold when tiering keeps it on disk.
+    fn allow(&self, query: &str, doc: &Document, text: &str) -> bool;
+}
+
+/// The filters a search runs through, in order. Empty, it lets everything through.
+#[derive(Default)]
+pub struct ResultFilters {
+    filters: Vec<Box<dyn ResultFilter>>,
+}
+
+impl ResultFilters {
+    /// The built-in filters enabled in `config`.
+    pub fn from_config(config: &ContentFilterConfig, storage: &StorageConfig) -> Result<Self, Box<dyn Error>> {
+        let mut filters = ResultFilters::default();
+        if let Some(path) = &config.blocked_words_path {
+            filters.add(WordFilter::load(&storage.resolve(path))?);
+        }
+        if !config.blocked_url_patterns.is_empty() {
+            filters.add(UrlFilter::new(&config.blocked_url_patterns)?);
+        }
+        Ok(filters)
+    }
+
+    pub fn add(&mut self, filter: impl ResultFilter + 'static) {
+        log::info!("Result filter enabled: {}", filter.name());
+        self.filters.push(Box::new(filter));
+    }
+
+    pub fn allows(&self, query: &str, doc: &Document, data: &PreprocessedData) -> bool {
+        if self.filters.is_empty() {
+            return true;
+        }
+        let text = data.text(doc);
+        self.filters.iter().all(|filter| filter.allow(query, doc, &text))
+    }
+
+    pub fn apply<'a>(&self, query: &str, ranked: Vec<(&'a Document, f64)>, data: &PreprocessedData) -> Vec<(&'a Document, f64)> {
+        if self.filters.is_empty() {
+            return ranked;
+        }
+        ranked.into_iter()
+            .filter(|(doc, _)| self.allows(query, doc, data))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ContentFilterConfig {
+    /// One word per line; documents whose title or text contains any of them are dropped.
+    pub blocked_words_path: Option<String>,
+    /// Documents whose URL matches any of these regexes are dropped.
+    pub blocked_url_patterns: Vec<String>,
+}
+
+/// Drops documents containing a listed word, e.g. a profanity list.
+pub struct WordFilter {
+    words: HashSet<String>,
+}
+
+impl WordFilter {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let words = std::fs::read_to_string(path)?
+            .lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(WordFilter { words })
+    }
+}
+
+impl ResultFilter for WordFilter {
+    fn name(&self) -> &str {
+        "blocked words"
+    }
+
+    fn allow(&self, _query: &str, doc: &Document, text: &str) -> bool {
+        !format!("{} {}", doc.title, text)
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| !word.is_empty() && self.words.contains(&word.to_lowercase()))
+    }
+}
+
+pub struct UrlFilter {
+    patterns: Vec<Regex>,
+}
+
+impl UrlFilter {
+    pub fn new(patterns: &[String]) -> Result<Self, regex::Error> {
+        Ok(UrlFilter { patterns: patterns.iter().map(|pattern| Regex::new(pattern)).collect::<Result<_, _>>()? })
+    }
+}
+
+impl ResultFilter for UrlFilter {
+    fn name(&self) -> &str {
+        "blocked URL patterns"
+    }
+
+    fn allow(&self, _query: &str, doc: &Document, _text: &str) -> bool {
+        !self.patterns.iter().any(|re| re.is_match(&doc.url))
+    }
+}
@@ -0,0 +1,20 @@
+use rand::Rng;
+
+/// `n` items drawn uniformly from `items` in one pass (reservoir sampling, Algorithm R), in no
+/// particular order, along with how many items there were to draw from.
+pub fn reservoir<T>(items: impl IntoIterator<Item = T>, n: usize, rng: &mut impl Rng) -> (Vec<T>, usize) {
+    let mut sample = Vec::with_capacity(n);
+    let mut seen = 0;
+    for item in items {
+        if sample.len() < n {
+            sample.push(item);
+        } else {
+            let slot = rng.random_range(0..=seen);
+            if slot < n {
+                sample[slot] = item;
+            }
+        }
+        seen += 1;
+    }
+    (sample, seen)
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// A ranked list scored once against one version of the index, handed out a page at a time.
+pub struct Scroll<T> {
+    pub query: String,
+    pub method: &'static str,
+    pub language: Option<String>,
+    pub index_version: Option<String>,
+    pub total_candidates: usize,
+    pub page_size: usize,
+    results: Vec<T>,
+    position: usize,
+}
+
+impl<T: Clone> Scroll<T> {
+    pub fn new(
+        query: String,
+        method: &'static str,
+        language: Option<String>,
+        index_version: Option<String>,
+        total_candidates: usize,
+        page_size: usize,
+        results: Vec<T>,
+    ) -> Self {
+        Scroll { query, method, language, index_version, total_candidates, page_size, results, position: 0 }
+    }
+
+    /// The next `page_size` results, or an empty page once the list is exhausted.
+    pub fn next_page(&mut self) -> Vec<T> {
+        let end = (self.position + self.page_size).min(self.results.len());
+        let page = self.results[self.position..end].to_vec();
+        self.position = end;
+        page
+    }
+}
+
+/// Open scrolls by token. Later index rebuilds or reweights don't affect them, since each keeps
+/// its own results; a scroll expires `ttl` after it was last read.
+pub struct ScrollStore<T> {
+    scrolls: Mutex<HashMap<String, (Instant, Scroll<T>)>>,
+    limits: RwLock<(Duration, usize)>, // (ttl, capacity)
+}
+
+impl<T: Clone> ScrollStore<T> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        ScrollStore {
+            scrolls: Mutex::new(HashMap::new()),
+            limits: RwLock::new((ttl, capacity)),
+        }
+    }
+
+    pub fn set_limits(&self, ttl: Duration, capacity: usize) {
+        *self.limits.write().unwrap() = (ttl, capacity);
+    }
+
+    /// Stores `scroll` and returns its first page with the token for the rest.
+    pub fn open(&self, mut scroll: Scroll<T>) -> (String, Vec<T>) {
+        let token = format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>());
+        let page = scroll.next_page();
+        let (ttl, capacity) = *self.limits.read().unwrap();
+        let mut scrolls = self.scrolls.lock().unwrap();
+
+        let now = Instant::now();
+        scrolls.retain(|_, (last_read, _)| now.duration_since(*last_read) < ttl);
+        if scrolls.len() >= capacity
+            && let Some(oldest) = scrolls.iter().min_by_key(|(_, (last_read, _))| *last_read).map(|(t, _)| t.clone())
+        {
+            scrolls.remove(&oldest);
+        }
+
+        scrolls.insert(token.clone(), (now, scroll));
+        (token, page)
+    }
+
+    /// Runs `f` on the scroll behind `token`, if it is still open, and extends its lifetime.
+    pub fn with_scroll<R>(&self, token: &str, f: impl FnOnce(&mut Scroll<T>) -> R) -> Option<R> {
+        let (ttl, _) = *self.limits.read().unwrap();
+        let mut scrolls = self.scrolls.lock().unwrap();
+        let (last_read, scroll) = scrolls.get_mut(token)?;
+        if last_read.elapsed() >= ttl {
+            scrolls.remove(token);
+            return None;
+        }
+        *last_read = Instant::now();
+        Some(f(scroll))
+    }
+
+    pub fn close(&self, token: &str) -> bool {
+        self.scrolls.lock().unwrap().remove(token).is_some()
+    }
+}
@@ -1,42 +1,166 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+// std's clock panics in the browser.
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 use nalgebra::DVector;
 use nalgebra_sparse::CsrMatrix;
-use crate::{deserialize_matrix, util, Document, SvdData};
-
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{util, Document, PreprocessedData, SvdData};
+use crate::util::analyzer::Analyzer;
+use crate::util::config::SearchConfig;
+use crate::util::doc_set::DocSet;
+use crate::util::bm25::Bm25;
+use crate::util::qlm::QueryLikelihood;
+use crate::util::timing::{self, Phase};
+
+/// Ranked documents and their scores, best first.
+pub type Hits<'a> = Vec<(&'a Document, f64)>;
 
 pub fn search<'a>(
     query: &'a str,
-    term_dict: &'a HashMap<String, usize>,
-    idf: &'a [f64],
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
     term_doc_matrix: &'a CsrMatrix<f64>,
-    documents: &'a [Document],
+    within: Option<&DocSet>,
     top_k: usize,
 ) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
-    let query_vec = create_query_vector(query, term_dict, idf);
+    let query_vec = create_query_vector(query, &data.term_dict, &data.idf, analyzer);
+    Ok(rank_by_vector(&query_vec, &query_terms(&query_vec), data, term_doc_matrix, within, top_k))
+}
 
-    let scores = calculate_similarity(&query_vec, term_doc_matrix);
+/// TF-IDF ranking by a query vector from `rocchio_expand`. Ties are broken by the original
+/// `query_vec`'s terms, so the expansion doesn't decide which of equal hits comes first.
+pub fn search_expanded<'a>(
+    query_vec: &DVector<f64>,
+    expanded: &DVector<f64>,
+    data: &'a PreprocessedData,
+    term_doc_matrix: &CsrMatrix<f64>,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    Ok(rank_by_vector(expanded, &query_terms(query_vec), data, term_doc_matrix, within, top_k))
+}
 
-    let top_results = scores.iter()
+fn rank_by_vector<'a>(
+    query_vec: &DVector<f64>,
+    tie_terms: &[usize],
+    data: &'a PreprocessedData,
+    term_doc_matrix: &CsrMatrix<f64>,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Vec<(&'a Document, f64)> {
+    // Documents sharing no term with the query score 0 and aren't hits; leaving them out keeps
+    // a rare query from filling the selection, and its ties, with the rest of the corpus.
+    let mut top = TopK::new(top_k);
+    for (doc_idx, score) in similarity_scores(query_vec, term_doc_matrix).into_iter().enumerate() {
+        if score <= 0.0 {
+            continue;
+        }
+        if within.is_none_or(|set| set.contains(doc_idx)) {
+            top.push(doc_idx, score);
+        }
+    }
+    let mut scores = top.into_sorted_vec();
+    sort_ranked(&mut scores, tie_terms, data, top_k);
+
+    scores.iter()
         .take(top_k)
-        .map(|&(doc_idx, score)| (&documents[doc_idx], score))
+        .map(|&(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect()
+}
+
+/// Pseudo-relevance feedback (Rocchio): `rocchio_alpha * q + rocchio_beta * c`, where `c` is
+/// the centroid of the top `feedback_docs` TF-IDF hits for `query_vec`, normalized. Of the
+/// terms not in the query, only the centroid's `feedback_terms` strongest are added. Returns the
+/// expanded vector and the added term indices, strongest first; with no hits, the query as is.
+pub fn rocchio_expand(
+    query_vec: &DVector<f64>,
+    data: &PreprocessedData,
+    term_doc_matrix: &CsrMatrix<f64>,
+    within: Option<&DocSet>,
+    config: &SearchConfig,
+) -> (DVector<f64>, Vec<usize>) {
+    let mut scores = calculate_similarity(query_vec, term_doc_matrix);
+    scores.retain(|&(doc_idx, score)| score > 0.0 && within.is_none_or(|set| set.contains(doc_idx)));
+    sort_ranked(&mut scores, &query_terms(query_vec), data, config.feedback_docs);
+    scores.truncate(config.feedback_docs);
+    if scores.is_empty() {
+        return (query_vec.clone(), Vec::new());
+    }
+
+    // The feedback documents' columns, gathered in one pass over the rows.
+    let mut slot_of = vec![None; term_doc_matrix.ncols()];
+    for (slot, &(doc_idx, _)) in scores.iter().enumerate() {
+        slot_of[doc_idx] = Some(slot);
+    }
+    let mut columns = vec![Vec::new(); scores.len()];
+    for (term_idx, row) in term_doc_matrix.row_iter().enumerate() {
+        for (&doc_idx, &value) in row.col_indices().iter().zip(row.values()) {
+            if let Some(slot) = slot_of[doc_idx] {
+                columns[slot].push((term_idx, value));
+            }
+        }
+    }
+    let mut centroid: HashMap<usize, f64> = HashMap::new();
+    for column in &columns {
+        let norm = column.iter().map(|(_, value)| value * value).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            for &(term_idx, value) in column {
+                *centroid.entry(term_idx).or_insert(0.0) += value / norm / columns.len() as f64;
+            }
+        }
+    }
+
+    let mut new_terms: Vec<(usize, f64)> = centroid.iter()
+        .filter(|&(&term_idx, &weight)| weight > 0.0 && query_vec[term_idx] == 0.0)
+        .map(|(&term_idx, &weight)| (term_idx, weight))
         .collect();
+    new_terms.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    new_terms.truncate(config.feedback_terms);
 
-    Ok(top_results)
+    let mut expanded = query_vec * config.rocchio_alpha;
+    for term_idx in query_terms(query_vec) {
+        expanded[term_idx] += config.rocchio_beta * centroid.get(&term_idx).copied().unwrap_or(0.0);
+    }
+    for &(term_idx, weight) in &new_terms {
+        expanded[term_idx] += config.rocchio_beta * weight;
+    }
+    let norm = expanded.norm();
+    if norm > 0.0 {
+        expanded /= norm;
+    }
+    (expanded, new_terms.into_iter().map(|(term_idx, _)| term_idx).collect())
+}
+
+/// Counts of the query's terms that are in the vocabulary, keyed by term index. The query goes
+/// through the same analyzer as the documents, so stop words drop out and inflections match.
+/// Each term's synonyms count too, with the synonym weight.
+pub fn query_term_counts(query: &str, term_dict: &HashMap<String, usize>, analyzer: &Analyzer) -> HashMap<usize, f64> {
+    let mut counts = HashMap::new();
+    for token in analyzer.analyze(query) {
+        if let Some(&term_idx) = term_dict.get(&token) {
+            *counts.entry(term_idx).or_insert(0.0) += 1.0;
+        }
+        for (synonym, weight) in analyzer.synonyms().expand(&token) {
+            if let Some(&term_idx) = term_dict.get(synonym) {
+                *counts.entry(term_idx).or_insert(0.0) += weight;
+            }
+        }
+    }
+    counts
 }
 
-pub fn create_query_vector(query: &str, term_dict: &HashMap<String, usize>, idf: &[f64]) -> DVector<f64> {
+pub fn create_query_vector(query: &str, term_dict: &HashMap<String, usize>, idf: &[f64], analyzer: &Analyzer) -> DVector<f64> {
     let num_terms = term_dict.len();
     let mut query_vec = DVector::zeros(num_terms);
 
-    let tokens = util::tokenizer::tokenize(query);
-
-    for token in tokens {
-        if let Some(&term_idx) = term_dict.get(&token) {
-            query_vec[term_idx] += 1.0;
-        }
+    for (term_idx, count) in query_term_counts(query, term_dict, analyzer) {
+        query_vec[term_idx] = count;
     }
 
     for term_idx in 0..num_terms {
@@ -51,7 +175,50 @@ pub fn create_query_vector(query: &str, term_dict: &HashMap<String, usize>, idf:
     query_vec
 }
 
+#[derive(Serialize, Clone, Debug)]
+pub struct MatchedTerm {
+    pub term: String,
+    /// The term's share of the TF-IDF cosine between the query and the document.
+    pub score: f64,
+}
+
+/// For each of the documents with the given IDs, the analyzed query terms it contains, best
+/// first. Scores are TF-IDF contributions whatever method ranked the results, so they compare
+/// terms within a hit rather than explain its overall score.
+pub fn matched_terms(
+    query_vec: &DVector<f64>,
+    term_doc_matrix: &CsrMatrix<f64>,
+    data: &PreprocessedData,
+    doc_ids: &HashSet<i64>,
+) -> HashMap<i64, Vec<MatchedTerm>> {
+    let mut matched: HashMap<i64, Vec<MatchedTerm>> = HashMap::new();
+    for (term_idx, &weight) in query_vec.iter().enumerate() {
+        if weight == 0.0 {
+            continue;
+        }
+        let row = term_doc_matrix.row(term_idx);
+        for (&doc_idx, &value) in row.col_indices().iter().zip(row.values()) {
+            let doc_id = data.documents[doc_idx].id;
+            if doc_ids.contains(&doc_id) {
+                matched.entry(doc_id).or_default().push(MatchedTerm {
+                    term: data.inverse_term_dict[&term_idx].clone(),
+                    score: weight * value,
+                });
+            }
+        }
+    }
+    for terms in matched.values_mut() {
+        terms.sort_by(|a, b| b.score.total_cmp(&a.score));
+    }
+    matched
+}
+
 fn calculate_similarity(query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f64>) -> Vec<(usize, f64)> {
+    similarity_scores(query_vec, term_doc_matrix).into_iter().enumerate().collect()
+}
+
+// Each document's dot product with the query, by document index.
+fn similarity_scores(query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f64>) -> Vec<f64> {
     let num_docs = term_doc_matrix.ncols();
     let mut scores = vec![0.0; num_docs];
 
@@ -68,132 +235,761 @@ fn calculate_similarity(query_vec: &DVector<f64>, term_doc_matrix: &CsrMatrix<f6
         }
     }
 
-    let mut doc_scores = Vec::with_capacity(num_docs);
-    for (doc_idx, &score) in scores.iter().enumerate() {
-        doc_scores.push((doc_idx, score));
+    scores
+}
+
+fn query_terms(query_vec: &DVector<f64>) -> Vec<usize> {
+    query_vec.iter().enumerate().filter(|&(_, &weight)| weight != 0.0).map(|(term_idx, _)| term_idx).collect()
+}
+
+/// Sets NaN and infinite scores, as degenerate vectors produce, to 0 so they rank with the
+/// non-matches. Returns how many there were.
+pub fn zero_non_finite(scores: &mut [(usize, f64)]) -> usize {
+    let mut replaced = 0;
+    for (_, score) in scores.iter_mut().filter(|(_, score)| !score.is_finite()) {
+        *score = 0.0;
+        replaced += 1;
     }
+    replaced
+}
 
-    doc_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    doc_scores
+/// Moves the best `top_k` `(document index, score)` pairs to the front of `scores`, best first,
+/// non-finite scores counting as 0; the rest are left in no particular order. Documents with
+/// equal scores, common among short documents, are ordered by how many distinct `query_terms`
+/// they contain, then shorter text first, then by index.
+pub fn sort_ranked(scores: &mut [(usize, f64)], query_terms: &[usize], data: &PreprocessedData, top_k: usize) {
+    let _sort = timing::span(Phase::Sort);
+    zero_non_finite(scores);
+    select_best(scores, top_k);
+
+    let matrix = &data.term_doc_csr;
+    let coverage = |doc_idx: usize| {
+        query_terms.iter()
+            .filter(|&&term_idx| {
+                matrix.col_indices[matrix.row_offsets[term_idx]..matrix.row_offsets[term_idx + 1]]
+                    .binary_search(&doc_idx)
+                    .is_ok()
+            })
+            .count()
+    };
+
+    let mut start = 0;
+    while start < scores.len().min(top_k) {
+        let score = scores[start].1;
+        let tolerance = 1e-12 * score.abs().max(1.0);
+        let end = start + scores[start..].iter().take_while(|(_, other)| (score - other).abs() <= tolerance).count();
+        if end - start > 1 {
+            scores[start..end].sort_by_cached_key(|&(doc_idx, _)| {
+                (std::cmp::Reverse(coverage(doc_idx)), data.text_len(&data.documents[doc_idx]), doc_idx)
+            });
+        }
+        start = end;
+    }
+}
+
+// Orders a heap of scores so the worst kept one is on top.
+struct Ranked {
+    score: f64,
+    doc_idx: usize,
+}
+
+impl Ord for Ranked {
+    // Better ranks first: higher score, then lower index.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score).then(self.doc_idx.cmp(&other.doc_idx))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+/// The best `k` of a stream of `(document index, score)` pairs, plus up to `k` of those tied
+/// with the last of them (which the tie-break in `sort_ranked` may still prefer), in a heap
+/// bounded to `k` rather than a vector of every score. Of more ties than that, the lowest
+/// indices are kept. Non-finite scores are all kept, for the caller to notice.
+struct TopK {
+    k: usize,
+    heap: BinaryHeap<Ranked>,
+    ties: Vec<(usize, f64)>,
+    non_finite: Vec<(usize, f64)>,
 }
 
-pub(crate) fn search_with_low_rank<'a>(
+impl TopK {
+    fn new(k: usize) -> Self {
+        TopK { k, heap: BinaryHeap::with_capacity(k), ties: Vec::new(), non_finite: Vec::new() }
+    }
+
+    fn push(&mut self, doc_idx: usize, score: f64) {
+        if !score.is_finite() {
+            self.non_finite.push((doc_idx, score));
+            return;
+        }
+        if self.k == 0 {
+            return;
+        }
+        let entry = Ranked { score, doc_idx };
+        if self.heap.len() < self.k {
+            self.heap.push(entry);
+            return;
+        }
+        let Some(mut worst) = self.heap.peek_mut() else {
+            return;
+        };
+        if entry < *worst {
+            let evicted = std::mem::replace(&mut *worst, entry);
+            drop(worst);
+            let threshold = self.heap.peek().map_or(0.0, |worst| worst.score);
+            self.ties.retain(|&(_, score)| tied(score, threshold));
+            if tied(evicted.score, threshold) {
+                self.ties.push((evicted.doc_idx, evicted.score));
+            }
+        } else if tied(score, worst.score) {
+            drop(worst);
+            self.ties.push((doc_idx, score));
+            if self.ties.len() >= 2 * self.k {
+                self.trim_ties();
+            }
+        }
+    }
+
+    // Keeps the best `k` ties, by score and then index.
+    fn trim_ties(&mut self) {
+        self.ties.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        self.ties.truncate(self.k);
+    }
+
+    fn merge(mut self, other: TopK) -> TopK {
+        for entry in other.heap {
+            self.push(entry.doc_idx, entry.score);
+        }
+        for (doc_idx, score) in other.ties.into_iter().chain(other.non_finite) {
+            self.push(doc_idx, score);
+        }
+        self
+    }
+
+    /// The kept pairs, best first, then the non-finite ones.
+    fn into_sorted_vec(mut self) -> Vec<(usize, f64)> {
+        let _sort = timing::span(Phase::Sort);
+        self.trim_ties();
+        self.heap.into_sorted_vec()
+            .into_iter()
+            .map(|entry| (entry.doc_idx, entry.score))
+            .chain(self.ties)
+            .chain(self.non_finite)
+            .collect()
+    }
+}
+
+fn tied(score: f64, threshold: f64) -> bool {
+    (threshold - score).abs() <= 1e-12 * threshold.abs().max(1.0)
+}
+
+// Moves the best `top_k` scores, and any tied with the last of them, to the front in order,
+// without sorting every score.
+fn select_best(scores: &mut [(usize, f64)], top_k: usize) {
+    if scores.len() <= top_k {
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        return;
+    }
+
+    let mut top = TopK::new(top_k);
+    for &(doc_idx, score) in scores.iter() {
+        top.push(doc_idx, score);
+    }
+    let best = top.into_sorted_vec();
+    let taken: HashSet<usize> = best.iter().map(|&(doc_idx, _)| doc_idx).collect();
+    let mut reordered = best;
+    reordered.extend(scores.iter().filter(|(doc_idx, _)| !taken.contains(doc_idx)));
+    scores.copy_from_slice(&reordered);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn search_with_low_rank<'a>(
     query: &'a str,
-    term_dict: &'a HashMap<String, usize>,
-    idf: &'a [f64],
-    svd_data: &'a SvdData,
-    documents: &'a [Document],
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
+    svd_data: &SvdData,
     noise_filter_k: Option<usize>,
+    config: &SearchConfig,
+    within: Option<&DocSet>,
     top_k: usize,
 ) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
-    let query_vec = util::search::create_query_vector(query, term_dict, idf);
+    let query_vec = util::search::create_query_vector(query, &data.term_dict, &data.idf, analyzer);
 
-    let scores = calculate_similarity_low_rank_optimized(&query_vec, svd_data, noise_filter_k, top_k);
+    let mut scores = lsi_similarities(&query_vec, svd_data, noise_filter_k, within, config.lsi_cosine, Some(top_k));
+    check_model_scores(&mut scores)?;
+    sort_ranked(&mut scores, &query_terms(&query_vec), data, top_k);
+    scores.truncate(top_k);
 
     let top_results = scores.iter()
-        .map(|&(doc_idx, score)| (&documents[doc_idx], score))
+        .map(|&(doc_idx, score)| (&data.documents[doc_idx], score))
         .collect();
 
     Ok(top_results)
 }
 
-fn calculate_similarity_low_rank_optimized(
-    query_vec: &DVector<f64>,
-    svd_data: &SvdData,
-    reduced_k: Option<usize>,
-    top_k: usize
-) -> Vec<(usize, f64)> {
-    println!("Calculating similarity using optimized low-rank approximation...");
-    let start = Instant::now();
+pub fn search_qlm<'a>(
+    query: &'a str,
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
+    model: &QueryLikelihood,
+    config: &SearchConfig,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    let query_terms = query_term_counts(query, &data.term_dict, analyzer);
+    let scores = model.rank(&query_terms, data, config.qlm_mu, within, top_k);
 
-    let orig_k = svd_data.rank;
+    Ok(scores.into_iter()
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect())
+}
 
-    let effective_k = match reduced_k {
-        Some(k) => k.min(orig_k),
-        None => orig_k
-    };
+pub fn search_bm25<'a>(
+    query: &'a str,
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
+    model: &Bm25,
+    config: &SearchConfig,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    let query_terms = query_term_counts(query, &data.term_dict, analyzer);
+    let scores = model.rank(&query_terms, data, config.bm25_k1, config.bm25_b, within, top_k);
+
+    Ok(scores.into_iter()
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect())
+}
 
-    let u_k = deserialize_matrix(&svd_data.u_ser).columns(0, effective_k).into_owned();
+pub fn search_svd<'a>(
+    query: &'a str,
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
+    svd_data: &SvdData,
+    config: &SearchConfig,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    let query_vec = util::search::create_query_vector(query, &data.term_dict, &data.idf, analyzer);
+    let mut scores = lsi_similarities(&query_vec, svd_data, None, within, config.lsi_cosine, Some(top_k));
+    check_model_scores(&mut scores)?;
+    sort_ranked(&mut scores, &query_terms(&query_vec), data, top_k);
 
-    let doc_vecs = deserialize_matrix(&svd_data.docs_ser).rows(0, effective_k).into_owned();
-    let num_docs = doc_vecs.ncols();
+    let top_results = scores.into_iter()
+        .take(top_k)
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect();
 
-    let query_lsi = u_k.transpose() * query_vec;
+    Ok(top_results)
+}
 
-    let query_norm = query_lsi.norm();
-    let normalized_query = if query_norm > 1e-10 {
-        &query_lsi / query_norm
-    } else {
-        println!("Warning: Query has near-zero norm in LSI space");
-        return Vec::new();
-    };
+/// TF-IDF and LSI blended: `search.hybrid_alpha` times the TF-IDF cosine plus the rest times
+/// the LSI cosine, both from the one query vector. Documents sharing no terms with the query can
+/// still rank through LSI; a query with no weight in the LSI space scores by TF-IDF alone.
+#[allow(clippy::too_many_arguments)]
+pub fn search_hybrid<'a>(
+    query: &'a str,
+    data: &'a PreprocessedData,
+    analyzer: &Analyzer,
+    term_doc_matrix: &CsrMatrix<f64>,
+    svd_data: &SvdData,
+    config: &SearchConfig,
+    within: Option<&DocSet>,
+    top_k: usize,
+) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
+    let query_vec = util::search::create_query_vector(query, &data.term_dict, &data.idf, analyzer);
+    let tfidf = similarity_scores(&query_vec, term_doc_matrix);
+    let mut lsi = lsi_similarities(&query_vec, svd_data, None, within, config.lsi_cosine, None);
+    check_model_scores(&mut lsi)?;
+    let mut lsi_scores = vec![0.0; tfidf.len()];
+    for (doc_idx, score) in lsi {
+        if let Some(slot) = lsi_scores.get_mut(doc_idx) {
+            *slot = score;
+        }
+    }
 
-    let mut scores = Vec::with_capacity(num_docs);
-    for j in 0..num_docs {
-        let doc_vec = doc_vecs.column(j);
-        let doc_norm = doc_vec.norm();
+    let alpha = config.hybrid_alpha;
+    let mut top = TopK::new(top_k);
+    for (doc_idx, (tfidf, lsi)) in tfidf.into_iter().zip(lsi_scores).enumerate() {
+        if within.is_none_or(|set| set.contains(doc_idx)) {
+            top.push(doc_idx, alpha * tfidf + (1.0 - alpha) * lsi);
+        }
+    }
+    let mut scores = top.into_sorted_vec();
+    sort_ranked(&mut scores, &query_terms(&query_vec), data, top_k);
 
-        let sim = if doc_norm > 1e-10 {
-            normalized_query.dot(&(doc_vec / doc_norm))
-        } else {
-            0.0
-        };
+    Ok(scores.into_iter()
+        .take(top_k)
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect())
+}
 
-        scores.push((j, sim));
+/// Weight of the negative examples' centroid when a request doesn't give one.
+pub const DEFAULT_NEGATIVE_WEIGHT: f64 = 0.5;
+
+/// Documents nearest to the centroid of several normalized query vectors ("find documents like
+/// these"), by TF-IDF cosine or, given a model, in its LSI space. The centroid of `negative_vecs`,
+/// scaled by `negative_weight`, is subtracted first to steer away from a subtopic. Documents in
+/// `exclude`, usually the examples themselves, are left out. Also returns how many documents had
+/// a positive score.
+#[allow(clippy::too_many_arguments)]
+pub fn search_by_examples<'a>(
+    example_vecs: &[DVector<f64>],
+    negative_vecs: &[DVector<f64>],
+    negative_weight: f64,
+    data: &'a PreprocessedData,
+    term_doc_matrix: &CsrMatrix<f64>,
+    svd_data: Option<&SvdData>,
+    within: Option<&DocSet>,
+    exclude: &HashSet<usize>,
+    top_k: usize,
+) -> (Hits<'a>, usize) {
+    let mut centroid = unit_centroid(example_vecs, data.term_dict.len());
+    if !negative_vecs.is_empty() {
+        centroid -= unit_centroid(negative_vecs, data.term_dict.len()) * negative_weight;
+        let norm = centroid.norm();
+        if norm > 0.0 {
+            centroid /= norm;
+        }
     }
 
-    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    scores.truncate(top_k);
+    let mut scores = match svd_data {
+        // Signed, so the negative examples' direction scores low.
+        Some(svd_data) => lsi_similarities(&centroid, svd_data, None, None, LsiCosine::Signed, None),
+        None => calculate_similarity(&centroid, term_doc_matrix),
+    };
+    scores.retain(|(doc_idx, score)| {
+        *score > 0.0 && !exclude.contains(doc_idx) && within.is_none_or(|set| set.contains(*doc_idx))
+    });
+    let candidates = scores.len();
+    // Only the terms pulled towards count as coverage when breaking ties.
+    let positive_terms: Vec<usize> = query_terms(&centroid).into_iter().filter(|&term_idx| centroid[term_idx] > 0.0).collect();
+    sort_ranked(&mut scores, &positive_terms, data, top_k);
 
-    println!("Optimized similarity calculation completed in {:?}", start.elapsed());
-    scores
+    let top_results = scores.into_iter()
+        .take(top_k)
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect();
+    (top_results, candidates)
 }
 
-pub(crate) fn search_svd<'a>(
-    query: &'a str,
-    term_dict: &'a HashMap<String, usize>,
-    idf: &'a [f64],
-    svd_data: &'a SvdData,
-    documents: &'a [Document],
+/// The documents most like the one at `doc_idx`, best first: by cosine of their LSI document
+/// vectors with `svd_data`, otherwise of their TF-IDF columns. The document, copies of it (same
+/// UUID) and documents scoring 0 or less are left out. Also returns how many scored above 0.
+pub fn more_like_this<'a>(
+    doc_idx: usize,
+    data: &'a PreprocessedData,
+    term_doc_matrix: &CsrMatrix<f64>,
+    svd_data: Option<&SvdData>,
+    within: Option<&DocSet>,
     top_k: usize,
-) -> Result<Vec<(&'a Document, f64)>, Box<dyn Error>> {
-    let query_vec = util::search::create_query_vector(query, term_dict, idf);
-    let scores = calculate_similarity_svd(&query_vec, svd_data);
+) -> Result<(Hits<'a>, usize), Box<dyn Error>> {
+    let column = document_column(term_doc_matrix, doc_idx);
+    let mut scores: Vec<(usize, f64)> = match svd_data {
+        Some(svd_data) => {
+            let doc_vecs = svd_data.doc_vectors();
+            let own = doc_vecs.column(doc_idx);
+            let own_norm = own.norm();
+            (0..doc_vecs.ncols()).into_par_iter()
+                .map(|j| {
+                    let doc_vec = doc_vecs.column(j);
+                    let norm = own_norm * doc_vec.norm();
+                    (j, if norm > 1e-12 { (own.dot(&doc_vec) / norm).clamp(-1.0, 1.0) } else { 0.0 })
+                })
+                .collect()
+        }
+        // Columns are normalized, so their dot products are cosines.
+        None => similarity_scores(&column, term_doc_matrix).into_iter().enumerate().collect(),
+    };
+    if svd_data.is_some() {
+        check_model_scores(&mut scores)?;
+    }
+
+    let uuid = &data.documents[doc_idx].uuid;
+    scores.retain(|&(other, score)| {
+        score > 0.0 && data.documents[other].uuid != *uuid && within.is_none_or(|set| set.contains(other))
+    });
+    let candidates = scores.len();
+    sort_ranked(&mut scores, &query_terms(&column), data, top_k);
 
     let top_results = scores.into_iter()
         .take(top_k)
-        .map(|(doc_idx, score)| (&documents[doc_idx], score))
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
         .collect();
+    Ok((top_results, candidates))
+}
 
-    Ok(top_results)
+// The document's TF-IDF weights as a query vector.
+fn document_column(matrix: &CsrMatrix<f64>, doc_idx: usize) -> DVector<f64> {
+    let mut column = DVector::zeros(matrix.nrows());
+    for term_idx in 0..matrix.nrows() {
+        let (start, end) = (matrix.row_offsets()[term_idx], matrix.row_offsets()[term_idx + 1]);
+        if let Ok(pos) = matrix.col_indices()[start..end].binary_search(&doc_idx) {
+            column[term_idx] = matrix.values()[start + pos];
+        }
+    }
+    column
+}
+
+fn unit_centroid(vectors: &[DVector<f64>], num_terms: usize) -> DVector<f64> {
+    let mut centroid = DVector::zeros(num_terms);
+    for vector in vectors {
+        centroid += vector;
+    }
+    let norm = centroid.norm();
+    if norm > 0.0 {
+        centroid /= norm;
+    }
+    centroid
 }
 
-fn calculate_similarity_svd(
+// Cosines are guarded against zero norms, so a non-finite score means the model itself holds
+// NaN or infinite values; that is reported rather than ranked around.
+fn check_model_scores(scores: &mut [(usize, f64)]) -> Result<(), Box<dyn Error>> {
+    match zero_non_finite(scores) {
+        0 => Ok(()),
+        broken => Err(format!("The SVD model produced {} non-finite scores", broken).into()),
+    }
+}
+
+/// How a search's result scores are presented.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreNormalization {
+    /// The method's own scores, e.g. cosines for TF-IDF and LSI.
+    #[default]
+    Raw,
+    /// Scaled over the results to 0..1, the best at 1 and the worst at 0.
+    MinMax,
+    /// `exp(score)` over its sum for all the results, so they add up to 1.
+    Softmax,
+}
+
+/// Rescales the scores of `results` relative to each other. Their order doesn't change.
+pub fn normalize_scores(results: &mut [(&Document, f64)], normalization: ScoreNormalization) {
+    let (min, max) = results.iter()
+        .map(|&(_, score)| score)
+        .filter(|score| score.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), score| (min.min(score), max.max(score)));
+    match normalization {
+        ScoreNormalization::Raw => {}
+        ScoreNormalization::MinMax => {
+            for (_, score) in results.iter_mut() {
+                *score = if max > min { ((*score - min) / (max - min)).clamp(0.0, 1.0) } else { 1.0 };
+            }
+        }
+        ScoreNormalization::Softmax => {
+            // Shifted by the maximum so exp can't overflow.
+            let total: f64 = results.iter().map(|&(_, score)| (score - max).exp()).sum();
+            for (_, score) in results.iter_mut() {
+                *score = (*score - max).exp() / total;
+            }
+        }
+    }
+}
+
+/// How LSI cosines become scores.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LsiCosine {
+    /// Documents pointing away from the query in LSI space score below unrelated ones.
+    #[default]
+    Signed,
+    /// Only the angle's distance from orthogonal counts, whichever side a document is on.
+    Absolute,
+}
+
+/// Cosine between the query and each document in `within` in the rank-`k` LSI space (the
+/// model's full rank when `None`), clamped to [-1, 1] against rounding drift. With `top_k`, only
+/// the best that many (and ties) are kept, each thread selecting its own before they merge. A
+/// query with no weight in the space gives no scores.
+fn lsi_similarities(
     query_vec: &DVector<f64>,
-    svd_data: &SvdData
+    svd_data: &SvdData,
+    k: Option<usize>,
+    within: Option<&DocSet>,
+    cosine: LsiCosine,
+    top_k: Option<usize>,
 ) -> Vec<(usize, f64)> {
-    let u_k = svd_data.u_k();
-    let doc_vecs = svd_data.doc_vectors();
-    let num_docs = doc_vecs.ncols();
+    let start = Instant::now();
+    let u_k = svd_data.get_u_k(k);
+    let doc_vecs = svd_data.get_doc_vectors(k);
 
     let query_lsi = u_k.transpose() * query_vec;
     let query_norm = query_lsi.norm();
+    if query_norm <= 1e-12 {
+        log::warn!("Query has near-zero norm in LSI space");
+        return Vec::new();
+    }
+    let query_lsi = query_lsi / query_norm;
 
-    let mut scores = Vec::with_capacity(num_docs);
-    for j in 0..num_docs {
+    let in_scope = (0..doc_vecs.ncols()).into_par_iter()
+        .filter(|&j| within.is_none_or(|set| set.contains(j)));
+    let similarity = |j: usize| {
         let doc_vec = doc_vecs.column(j);
         let doc_norm = doc_vec.norm();
-
-        let sim = if doc_norm > 1e-12 && query_norm > 1e-12 {
-            query_lsi.dot(&doc_vec) / (query_norm * doc_norm)
+        let sim = if doc_norm > 1e-12 {
+            (query_lsi.dot(&doc_vec) / doc_norm).clamp(-1.0, 1.0)
         } else {
             0.0
         };
-        scores.push((j, sim));
-    }
+        (j, match cosine {
+            LsiCosine::Signed => sim,
+            LsiCosine::Absolute => sim.abs(),
+        })
+    };
+    let scores = match top_k {
+        Some(top_k) => in_scope.map(similarity)
+            .fold(|| TopK::new(top_k), |mut top, (j, score)| {
+                top.push(j, score);
+                top
+            })
+            .reduce(|| TopK::new(top_k), TopK::merge)
+            .into_sorted_vec(),
+        None => in_scope.map(similarity).collect(),
+    };
 
-    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    log::debug!("LSI similarity (k={}) computed in {:?}", svd_data.effective_rank(k), start.elapsed());
     scores
 }
 
+/// Adds per-document boosts (keyed by document index) to the ranked results, pulling in
+/// boosted documents the ranking missed, and returns the best `top_k` in `sort_ranked`'s order,
+/// so equal scores come out the same way on every request.
+pub fn apply_document_boosts<'a>(
+    ranked: Vec<(&'a Document, f64)>,
+    boosts: &HashMap<usize, f64>,
+    query_vec: &DVector<f64>,
+    data: &'a PreprocessedData,
+    top_k: usize,
+) -> Vec<(&'a Document, f64)> {
+    if boosts.is_empty() {
+        return ranked;
+    }
+
+    let mut scores: HashMap<usize, f64> = ranked.into_iter()
+        .map(|(doc, score)| (document_index(&data.documents, doc), score))
+        .collect();
+    for (&doc_idx, &boost) in boosts {
+        *scores.entry(doc_idx).or_insert(0.0) += boost;
+    }
+
+    let mut boosted: Vec<(usize, f64)> = scores.into_iter().collect();
+    sort_ranked(&mut boosted, &query_terms(query_vec), data, top_k);
+    boosted.into_iter()
+        .take(top_k)
+        .map(|(doc_idx, score)| (&data.documents[doc_idx], score))
+        .collect()
+}
+
+/// Index of a document borrowed from `documents`, as ranked results are.
+pub fn document_index(documents: &[Document], doc: &Document) -> usize {
+    let offset = (doc as *const Document as usize) - (documents.as_ptr() as usize);
+    let doc_idx = offset / std::mem::size_of::<Document>();
+    debug_assert!(std::ptr::eq(&documents[doc_idx], doc));
+    doc_idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::analyzer::{AnalyzerConfig, Normalization};
+    use std::sync::Arc;
+    use nalgebra::DMatrix;
+    use crate::{serialize_matrix, SerializableCsrMatrix};
+    use crate::util::tokenizer::build_term_document_matrix;
+    use crate::util::weighting::{self, Weighting};
+
+    // An analyzer with a small stop word list and lemma dictionary, written to a directory of the
+    // test's own.
+    fn analyzer(test: &str, normalization: Normalization) -> Analyzer {
+        let dir = std::env::temp_dir().join(format!("search-engine-search-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stop_words.txt"), "the\nand\npast\nwhile\n").unwrap();
+        std::fs::write(dir.join("lemmas.txt"), "run;running;verb\nrun;ran;verb\napple;apples;subst\nmouse;mice;subst\n").unwrap();
+        let config = AnalyzerConfig {
+            stop_words_path: "stop_words.txt".to_string(),
+            normalization,
+            lemma_dictionary_path: Some("lemmas.txt".to_string()),
+            ..AnalyzerConfig::default()
+        };
+        let analyzer = Analyzer::from_config(&config, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        analyzer
+    }
 
+    // Indexes `text` as a document and checks that the same text as a query counts exactly the
+    // document's terms; returns those terms.
+    fn assert_query_matches_document(analyzer: &Analyzer, text: &str) -> HashSet<String> {
+        let documents = [Document::new(1, "Document", "", text)];
+        let (term_dict, inverse_term_dict, coo) = build_term_document_matrix(&documents, analyzer, 0).unwrap();
+        let document_counts: HashMap<usize, f64> = coo.triplet_iter().map(|(term_idx, _, &count)| (term_idx, count)).collect();
+        assert_eq!(query_term_counts(text, &term_dict, analyzer), document_counts);
+        inverse_term_dict.into_values().collect()
+    }
+
+    fn terms(terms: &[&str]) -> HashSet<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn queries_are_stemmed_like_documents() {
+        let analyzer = analyzer("porter", Normalization::Porter);
+        let indexed = assert_query_matches_document(&analyzer, "The runners were running past THE apples and engines");
+        assert_eq!(indexed, terms(&["runner", "were", "run", "appl", "engin"]));
+    }
+
+    #[test]
+    fn queries_are_lemmatized_like_documents() {
+        let analyzer = analyzer("lemma", Normalization::Lemma);
+        let indexed = assert_query_matches_document(&analyzer, "The mice ran past apples while running");
+        assert_eq!(indexed, terms(&["mouse", "run", "apple"]));
+    }
+
+    // An index of `texts`, weighted as the engine weights it.
+    fn index(analyzer: &Analyzer, texts: &[&str]) -> PreprocessedData {
+        let documents: Vec<Document> = texts.iter().enumerate()
+            .map(|(i, text)| Document::new(i as i64 + 1, format!("Document {}", i + 1), "", *text))
+            .collect();
+        index_documents(analyzer, documents, 0)
+    }
+
+    fn index_documents(analyzer: &Analyzer, documents: Vec<Document>, title_weight: u32) -> PreprocessedData {
+        let (term_dict, inverse_term_dict, coo) = build_term_document_matrix(&documents, analyzer, title_weight).unwrap();
+        let counts = CsrMatrix::from(&coo);
+        let mut data = PreprocessedData {
+            term_dict,
+            inverse_term_dict,
+            idf: Vec::new(),
+            collocations: Default::default(),
+            synonyms: Default::default(),
+            subwords: Default::default(),
+            documents: Arc::new(documents),
+            term_doc_csr: SerializableCsrMatrix::from_csr(&counts),
+            term_counts: counts.values().iter().map(|&count| count as u32).collect(),
+            texts: None,
+        };
+        (data.term_doc_csr, data.idf) = weighting::reweight(&data, Weighting::default());
+        data
+    }
+
+    #[test]
+    fn empty_query_vector_ranks_nothing_above_zero() {
+        let analyzer = analyzer("empty-query", Normalization::Porter);
+        let data = index(&analyzer, &["Rivers flow into the sea", "Mountains rise above the valley"]);
+        let csr = data.term_doc_csr.to_csr();
+
+        // Only stop words, so the query vector is all zeros.
+        let ranked = search("the and while", &data, &analyzer, &csr, None, 10).unwrap();
+        assert!(ranked.iter().all(|(_, score)| *score == 0.0), "{:?}", ranked);
+
+        let query_vec = DVector::zeros(data.term_dict.len());
+        let svd = SvdData {
+            rank: 1,
+            sigma_k: vec![1.0],
+            u_ser: serialize_matrix(&DMatrix::from_element(data.term_dict.len(), 1, 1.0)),
+            vt_ser: serialize_matrix(&DMatrix::from_element(1, 2, 1.0)),
+            docs_ser: serialize_matrix(&DMatrix::from_element(1, 2, 1.0)),
+            doc_ids: Vec::new(),
+        };
+        assert!(lsi_similarities(&query_vec, &svd, None, None, LsiCosine::Signed, None).is_empty());
+    }
+
+    #[test]
+    fn zero_norm_documents_score_zero() {
+        let analyzer = analyzer("zero-norm", Normalization::Porter);
+        // The second document has no terms left after analysis, so its column is empty.
+        let data = index(&analyzer, &["Rivers flow into the sea", "the and", "Mountains rise above the valley"]);
+        let csr = data.term_doc_csr.to_csr();
+        let ranked = search("rivers", &data, &analyzer, &csr, None, 10).unwrap();
+        assert_eq!(ranked.first().map(|(doc, _)| doc.id), Some(1));
+        assert!(ranked.iter().all(|(doc, score)| score.is_finite() && (doc.id != 2 || *score == 0.0)), "{:?}", ranked);
+
+        // The same in LSI space: the middle document's vector is zero.
+        let svd = SvdData {
+            rank: 2,
+            sigma_k: vec![1.0, 1.0],
+            u_ser: serialize_matrix(&DMatrix::identity(2, 2)),
+            vt_ser: serialize_matrix(&DMatrix::zeros(2, 3)),
+            docs_ser: serialize_matrix(&DMatrix::from_row_slice(2, 3, &[1.0, 0.0, 0.0, 0.0, 0.0, 1.0])),
+            doc_ids: Vec::new(),
+        };
+        let query_vec = DVector::from_vec(vec![1.0, 0.5]);
+        for cosine in [LsiCosine::Signed, LsiCosine::Absolute] {
+            let scores: HashMap<usize, f64> = lsi_similarities(&query_vec, &svd, None, None, cosine, None).into_iter().collect();
+            assert_eq!(scores.len(), 3);
+            assert_eq!(scores[&1], 0.0);
+            assert!(scores.values().all(|score| score.is_finite()));
+        }
+    }
+
+    #[test]
+    fn non_finite_scores_rank_as_zero() {
+        let analyzer = analyzer("non-finite", Normalization::Porter);
+        let data = index(&analyzer, &["alpha", "bravo", "charlie", "delta", "echo"]);
+        let mut scores = vec![(0, f64::NAN), (1, 0.5), (2, f64::INFINITY), (3, 0.9), (4, f64::NEG_INFINITY)];
+        sort_ranked(&mut scores, &[], &data, 5);
+
+        assert_eq!(&scores[..2], &[(3, 0.9), (1, 0.5)]);
+        let mut zeroed: Vec<(usize, f64)> = scores[2..].to_vec();
+        zeroed.sort_by_key(|&(doc_idx, _)| doc_idx);
+        assert_eq!(zeroed, vec![(0, 0.0), (2, 0.0), (4, 0.0)]);
+    }
+
+    #[test]
+    fn rare_queries_rank_only_matching_documents() {
+        let analyzer = analyzer("rare-query", Normalization::Porter);
+        let data = index(&analyzer, &["Rivers flow into the sea", "Mountains rise above the valley", "Forests cover the hills", "Deserts stretch for miles"]);
+        let csr = data.term_doc_csr.to_csr();
+        let ranked = search("rivers", &data, &analyzer, &csr, None, 3).unwrap();
+        assert_eq!(ranked.iter().map(|(doc, _)| doc.id).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn title_matches_outrank_body_matches() {
+        let analyzer = analyzer("title-weight", Normalization::Porter);
+        let documents = vec![
+            Document::new(1, "Mountain trails", "", "Rivers carve the valley floor"),
+            Document::new(2, "Rivers of Europe", "", "Rivers carve the valley floor"),
+            Document::new(3, "Desert roads", "", "Dunes stretch for miles"),
+        ];
+        let data = index_documents(&analyzer, documents, AnalyzerConfig::default().title_weight);
+        let csr = data.term_doc_csr.to_csr();
+        let ranked = search("rivers", &data, &analyzer, &csr, None, 3).unwrap();
+        assert_eq!(ranked.iter().map(|(doc, _)| doc.id).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn top_k_keeps_at_most_k_ties() {
+        let mut top = TopK::new(2);
+        for doc_idx in (0..100).rev() {
+            top.push(doc_idx, 0.5);
+        }
+        assert_eq!(top.into_sorted_vec(), vec![(0, 0.5), (1, 0.5), (2, 0.5), (3, 0.5)]);
+    }
+
+    #[test]
+    fn boosted_ties_keep_a_stable_order() {
+        let analyzer = analyzer("boosted-ties", Normalization::Porter);
+        let data = index(&analyzer, &["alpha", "bravo", "delta", "gamma", "kappa", "omega"]);
+        let query_vec = DVector::zeros(data.term_dict.len());
+        let boosts: HashMap<usize, f64> = (0..6).map(|doc_idx| (doc_idx, 1.0)).collect();
+        for _ in 0..5 {
+            let boosted = apply_document_boosts(Vec::new(), &boosts, &query_vec, &data, 4);
+            assert_eq!(boosted.iter().map(|(doc, _)| doc.id).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        }
+    }
+}
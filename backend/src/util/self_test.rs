@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use serde::Deserialize;
+use crate::{util, Document, Method, SearchEngine};
+use crate::util::analyzer::AnalyzerConfig;
+use crate::util::svd::SvdBuildConfig;
+
+/// Short library and information science abstracts in the CISI collection's format, six on each
+/// of eight topics.
+const DOCUMENTS: &str = include_str!("../../canary/documents.all");
+const QUERIES: &str = include_str!("../../canary/queries.json");
+
+const LSI_RANK: usize = 10;
+/// How deep LSI, which ranks by topic rather than by words, may put the first related document.
+const LSI_DEPTH: usize = 3;
+// Cosines may come out a rounding error past 1.
+const COSINE_SLACK: f64 = 1e-9;
+
+#[derive(Deserialize)]
+struct CanaryQuery {
+    query: String,
+    /// `.I` numbers of the documents the lexical methods may rank first.
+    expect: Vec<i64>,
+    /// The query's topic, one of which LSI must rank within `LSI_DEPTH`.
+    related: Vec<i64>,
+}
+
+/// One canary query run with one method.
+pub struct Check {
+    pub query: String,
+    pub method: &'static str,
+    pub passed: bool,
+    /// What was found, or why the check failed.
+    pub detail: String,
+}
+
+/// Builds an index of the canary corpus in memory with the configured analyzer, runs the canary
+/// queries with TF-IDF, BM25, query likelihood, LSI and the TF-IDF/LSI hybrid, and checks the
+/// top results and their scores. Only the analyzer's resources are read from `data_dir`;
+/// nothing is written.
+pub fn run(analyzer_config: &AnalyzerConfig, data_dir: &Path, svd_config: &SvdBuildConfig) -> Result<Vec<Check>, Box<dyn Error>> {
+    let queries: Vec<CanaryQuery> = serde_json::from_str(QUERIES)?;
+    let engine = SearchEngine::builder()
+        .corpus(parse_cisi(DOCUMENTS)?)
+        .analyzer(analyzer_config.clone())
+        .resources(data_dir)
+        .svd_config(svd_config.clone())
+        .with_lsi(LSI_RANK)
+        .build()?;
+
+    let mut checks = Vec::new();
+    for canary in &queries {
+        let query = canary.query.as_str();
+        checks.push(check(canary, "tfidf", 1, f64::MIN_POSITIVE..=1.0 + COSINE_SLACK, engine.search(query, Method::TfIdf, LSI_DEPTH)));
+        checks.push(check(canary, "bm25", 1, f64::MIN_POSITIVE..=f64::MAX, engine.search(query, Method::Bm25, LSI_DEPTH)));
+        checks.push(check(canary, "qlm", 1, f64::MIN..=f64::MAX, engine.search(query, Method::QueryLikelihood, LSI_DEPTH)));
+        checks.push(check(
+            canary, "svd", LSI_DEPTH, -1.0 - COSINE_SLACK..=1.0 + COSINE_SLACK,
+            engine.search(query, Method::Lsi, LSI_DEPTH),
+        ));
+        checks.push(check(
+            canary, "hybrid", LSI_DEPTH, -1.0 - COSINE_SLACK..=1.0 + COSINE_SLACK,
+            engine.search(query, Method::Hybrid, LSI_DEPTH),
+        ));
+    }
+    Ok(checks)
+}
+
+// Passes when one of the expected documents is within `depth` and the top score is in `scores`.
+// The lexical methods expect `canary.expect` first, LSI any of `canary.related`.
+fn check(
+    canary: &CanaryQuery,
+    method: &'static str,
+    depth: usize,
+    scores: RangeInclusive<f64>,
+    ranked: Result<Vec<(&Document, f64)>, Box<dyn Error>>,
+) -> Check {
+    let expected = if depth == 1 { &canary.expect } else { &canary.related };
+    let (passed, detail) = match ranked {
+        Err(e) => (false, format!("search failed: {}", e)),
+        Ok(ranked) => match ranked.first() {
+            None => (false, "no results".to_string()),
+            Some(&(_, top_score)) if !scores.contains(&top_score) => {
+                (false, format!("top score {} is outside {:?}", top_score, scores))
+            }
+            Some(_) => {
+                let found = ranked.iter()
+                    .take(depth)
+                    .position(|(doc, _)| expected.contains(&doc.id));
+                let top: Vec<String> = ranked.iter()
+                    .take(depth)
+                    .map(|(doc, score)| format!("{} ({:.4})", doc.id, score))
+                    .collect();
+                match found {
+                    Some(rank) => (true, format!("rank {}: {}", rank + 1, top.join(", "))),
+                    None => (false, format!("expected one of {:?} in the top {}, got {}", expected, depth, top.join(", "))),
+                }
+            }
+        },
+    };
+    Check { query: canary.query.clone(), method, passed, detail }
+}
+
+// Documents in the CISI format: `.I <number>` starts a document, followed by fields such as
+// `.T` (title), `.A` (authors) and `.W` (abstract), each marker on a line of its own with the
+// field's lines after it. Only the title and abstract are kept.
+fn parse_cisi(contents: &str) -> Result<Vec<Document>, Box<dyn Error>> {
+    // (number, title, abstract)
+    let mut entries: Vec<(i64, String, String)> = Vec::new();
+    let mut field = "";
+    for (line_idx, line) in contents.lines().enumerate() {
+        if let Some(number) = line.strip_prefix(".I ") {
+            let id = number.trim().parse()
+                .map_err(|_| format!("line {}: expected a document number, got {:?}", line_idx + 1, number.trim()))?;
+            entries.push((id, String::new(), String::new()));
+            field = "";
+            continue;
+        }
+        if line.len() == 2 && line.starts_with('.') {
+            field = line;
+            continue;
+        }
+        let Some((_, title, text)) = entries.last_mut() else {
+            return Err(format!("line {}: text before the first .I", line_idx + 1).into());
+        };
+        let target = match field {
+            ".T" => title,
+            ".W" => text,
+            _ => continue,
+        };
+        if !target.is_empty() {
+            target.push(' ');
+        }
+        target.push_str(line.trim());
+    }
+
+    Ok(entries.into_iter()
+        .map(|(id, title, text)| Document::new(id, util::cleaning::clean_text(&title), "", util::cleaning::clean_text(&text)))
+        .collect())
+}
@@ -0,0 +1,90 @@
+// Abbreviations whose period doesn't end a sentence, lowercased and without the final period.
+const ABBREVIATIONS: &[(&str, &[&str])] = &[
+    ("en", &[
+        "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "e.g", "i.e", "inc", "ltd", "co",
+        "corp", "no", "nos", "fig", "figs", "vol", "approx", "dept", "est", "gen", "gov", "lt", "col",
+        "capt", "sgt", "rev", "u.s", "u.k", "a.m", "p.m", "jan", "feb", "mar", "apr", "jun", "jul",
+        "aug", "sep", "sept", "oct", "nov", "dec", "cf", "al", "ed", "eds", "pp",
+    ]),
+    ("pl", &[
+        "np", "tzw", "m.in", "itd", "itp", "dr", "prof", "ul", "al", "pl", "godz", "r", "w", "ok",
+        "tj", "wg", "św", "gen", "płk", "ppłk", "kpt", "por", "tys", "mln", "mld", "im", "ds", "nr",
+        "p.n.e", "n.e", "ks", "abp", "bp", "inż", "mgr", "hab", "zob", "tzn", "jw", "ww",
+        "br", "ub", "wyd", "red", "oprac", "s", "t", "cz", "rozdz", "woj", "pow", "gm",
+    ]),
+    ("de", &[
+        "z.b", "bzw", "usw", "ca", "dr", "nr", "s", "vgl", "u.a", "d.h", "str", "hr", "fr", "prof",
+        "evtl", "ggf", "inkl", "jh", "mio", "mrd", "u.ä", "o.ä", "sog", "u.s.w", "z.t",
+    ]),
+    ("fr", &["m", "mm", "mme", "mlle", "dr", "etc", "env", "p.ex", "av", "bd", "st", "ste", "cf", "vol", "éd"]),
+    ("es", &["sr", "sra", "srta", "dr", "dra", "etc", "p.ej", "ud", "uds", "av", "núm", "pág", "vol"]),
+    ("it", &["sig", "sigg", "dott", "prof", "ecc", "es", "pag", "vol", "ca"]),
+    ("nl", &["dhr", "mevr", "dr", "bijv", "enz", "o.a", "d.w.z", "nr", "ca", "vgl"]),
+    ("pt", &["sr", "sra", "dr", "dra", "etc", "p.ex", "av", "pág", "vol"]),
+];
+
+// Closing quotes and brackets that belong to the sentence they follow.
+const CLOSERS: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+/// Splits `text` into sentences at `.`, `!`, `?`, `…` and newlines. A period doesn't end the
+/// sentence after an abbreviation of `language` (of any known language when it is empty or
+/// unknown), after an initial such as "J.", or when the next word starts in lowercase.
+pub fn split<'a>(text: &'a str, language: &str) -> Vec<&'a str> {
+    let abbreviations: Vec<&str> = match ABBREVIATIONS.iter().find(|(code, _)| *code == language) {
+        Some((_, words)) => words.to_vec(),
+        None => ABBREVIATIONS.iter().flat_map(|(_, words)| words.iter().copied()).collect(),
+    };
+
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?' | '…' | '\n') {
+            continue;
+        }
+        // Runs like "?!" or "..." end where the last of them does.
+        if chars.peek().is_some_and(|&(_, next)| matches!(next, '.' | '!' | '?' | '…')) {
+            continue;
+        }
+
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if !CLOSERS.contains(&next) {
+                break;
+            }
+            end = j + next.len_utf8();
+            chars.next();
+        }
+        if c != '\n' && chars.peek().is_some_and(|&(_, next)| !next.is_whitespace()) {
+            continue;
+        }
+        if c == '.' && !ends_sentence(&text[start..i], &text[end..], &abbreviations) {
+            continue;
+        }
+
+        let sentence = text[start..end].trim();
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+        start = end;
+    }
+
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+
+    sentences
+}
+
+fn ends_sentence(before: &str, after: &str, abbreviations: &[&str]) -> bool {
+    let word = before.rsplit(char::is_whitespace).next().unwrap_or("")
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    let is_initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase);
+    if is_initial || abbreviations.contains(&word.to_lowercase().as_str()) {
+        return false;
+    }
+    // A following word in lowercase continues the sentence ("approx. five", "12. maja").
+    !after.trim_start().chars().next().is_some_and(char::is_lowercase)
+}
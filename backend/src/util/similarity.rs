@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use crate::{PreprocessedData, SvdData};
+
+/// Most documents a similarity matrix is computed for; the response grows with the square.
+pub const MAX_DOCUMENTS: usize = 500;
+
+/// Pairwise cosine similarities of the TF-IDF vectors of `doc_indices`, in the given order.
+pub fn tfidf_matrix(data: &PreprocessedData, doc_indices: &[usize]) -> Vec<Vec<f64>> {
+    // One pass over the matrix collects the selected columns as sparse vectors sorted by term.
+    let mut positions: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (pos, &doc_idx) in doc_indices.iter().enumerate() {
+        positions.entry(doc_idx).or_default().push(pos);
+    }
+    let matrix = &data.term_doc_csr;
+    let mut columns: Vec<Vec<(usize, f64)>> = vec![Vec::new(); doc_indices.len()];
+    for term_idx in 0..matrix.nrows {
+        for entry in matrix.row_offsets[term_idx]..matrix.row_offsets[term_idx + 1] {
+            for &pos in positions.get(&matrix.col_indices[entry]).into_iter().flatten() {
+                columns[pos].push((term_idx, matrix.values[entry]));
+            }
+        }
+    }
+
+    let norms: Vec<f64> = columns.iter().map(|column| column.iter().map(|(_, value)| value * value).sum::<f64>().sqrt()).collect();
+    cosine_matrix(doc_indices.len(), |a, b| {
+        let dot = sparse_dot(&columns[a], &columns[b]);
+        if norms[a] > 1e-12 && norms[b] > 1e-12 { dot / (norms[a] * norms[b]) } else { 0.0 }
+    })
+}
+
+/// Pairwise cosine similarities of the LSI document vectors of `doc_indices`, in the given order.
+pub fn lsi_matrix(svd: &SvdData, doc_indices: &[usize]) -> Vec<Vec<f64>> {
+    // Stored row-major with a column per document.
+    let docs = &svd.docs_ser;
+    let vectors: Vec<Vec<f64>> = doc_indices.iter()
+        .map(|&doc_idx| (0..docs.nrows).map(|dim| docs.data[dim * docs.ncols + doc_idx]).collect())
+        .collect();
+    let norms: Vec<f64> = vectors.iter().map(|vector| vector.iter().map(|x| x * x).sum::<f64>().sqrt()).collect();
+    cosine_matrix(doc_indices.len(), |a, b| {
+        let dot: f64 = vectors[a].iter().zip(&vectors[b]).map(|(x, y)| x * y).sum();
+        if norms[a] > 1e-12 && norms[b] > 1e-12 { dot / (norms[a] * norms[b]) } else { 0.0 }
+    })
+}
+
+fn cosine_matrix(n: usize, cosine: impl Fn(usize, usize) -> f64) -> Vec<Vec<f64>> {
+    let mut matrix: Vec<Vec<f64>> = (0..n)
+        .map(|a| (0..n).map(|b| if b >= a { cosine(a, b) } else { 0.0 }).collect())
+        .collect();
+    // Mirror the upper triangle.
+    for a in 1..n {
+        let (above, below) = matrix.split_at_mut(a);
+        for (b, row) in above.iter().enumerate() {
+            below[0][b] = row[a];
+        }
+    }
+    matrix
+}
+
+fn sparse_dot(a: &[(usize, f64)], b: &[(usize, f64)]) -> f64 {
+    let (mut i, mut j, mut dot) = (0, 0, 0.0);
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                dot += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    dot
+}
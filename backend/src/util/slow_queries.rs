@@ -0,0 +1,120 @@
+#[cfg(feature = "server")]
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::sync::Mutex;
+#[cfg(feature = "server")]
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SlowQueryConfig {
+    pub enabled: bool,
+    /// Searches taking longer than this, from admission to response, are logged.
+    /// Can be changed while serving.
+    pub threshold_ms: f64,
+    pub db_path: String,
+    /// Older entries beyond this many are dropped.
+    pub max_entries: usize,
+}
+
+impl Default for SlowQueryConfig {
+    fn default() -> Self {
+        SlowQueryConfig {
+            enabled: true,
+            threshold_ms: 500.0,
+            db_path: "slow_queries.db".to_string(),
+            max_entries: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SlowQuery {
+    pub query: String,
+    pub method: String,
+    /// The search request as received, to replay it.
+    pub request: serde_json::Value,
+    pub result_count: usize,
+    pub analyze_ms: f64,
+    pub score_ms: f64,
+    pub fetch_ms: f64,
+    pub total_ms: f64,
+    /// Unix timestamp in seconds.
+    pub logged_at: i64,
+}
+
+/// Searches over the latency threshold, kept in a SQLite file so they can be examined later.
+#[cfg(feature = "server")]
+pub struct SlowQueryLog {
+    conn: Mutex<Connection>,
+    max_entries: usize,
+}
+
+#[cfg(feature = "server")]
+impl SlowQueryLog {
+    /// A relative `db_path` is resolved against `data_dir`.
+    pub fn open(config: &SlowQueryConfig, data_dir: &Path) -> SqliteResult<Self> {
+        let conn = Connection::open(data_dir.join(&config.db_path))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS slow_queries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                query TEXT NOT NULL,
+                method TEXT NOT NULL,
+                request TEXT NOT NULL,
+                result_count INTEGER NOT NULL,
+                analyze_ms REAL NOT NULL,
+                score_ms REAL NOT NULL,
+                fetch_ms REAL NOT NULL,
+                total_ms REAL NOT NULL,
+                logged_at INTEGER NOT NULL
+            );",
+        )?;
+
+        Ok(SlowQueryLog {
+            conn: Mutex::new(conn),
+            max_entries: config.max_entries,
+        })
+    }
+
+    pub fn record(&self, entry: &SlowQuery) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO slow_queries (query, method, request, result_count, analyze_ms, score_ms, fetch_ms, total_ms, logged_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                entry.query, entry.method, entry.request.to_string(), entry.result_count as i64,
+                entry.analyze_ms, entry.score_ms, entry.fetch_ms, entry.total_ms, entry.logged_at,
+            ],
+        )?;
+        conn.execute(
+            "DELETE FROM slow_queries WHERE id NOT IN (SELECT id FROM slow_queries ORDER BY id DESC LIMIT ?1)",
+            params![self.max_entries as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent first.
+    pub fn recent(&self, limit: usize) -> SqliteResult<Vec<SlowQuery>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT query, method, request, result_count, analyze_ms, score_ms, fetch_ms, total_ms, logged_at
+             FROM slow_queries ORDER BY id DESC LIMIT ?1",
+        )?;
+        let entries = stmt.query_map(params![limit as i64], |row| {
+            let request: String = row.get(2)?;
+            Ok(SlowQuery {
+                query: row.get(0)?,
+                method: row.get(1)?,
+                request: serde_json::from_str(&request).unwrap_or(serde_json::Value::String(request)),
+                result_count: row.get::<_, i64>(3)? as usize,
+                analyze_ms: row.get(4)?,
+                score_ms: row.get(5)?,
+                fetch_ms: row.get(6)?,
+                total_ms: row.get(7)?,
+                logged_at: row.get(8)?,
+            })
+        })?;
+        entries.collect()
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::util::data::AtomicFile;
+use crate::util::history::unix_now;
+
+const DAY_SECS: i64 = 24 * 3600;
+const WEEK_SECS: i64 = 7 * DAY_SECS;
+const MANIFEST_NAME: &str = "snapshots.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Take snapshots on a schedule while serving. The `snapshot` command works either way.
+    pub enabled: bool,
+    /// Relative to `storage.data_dir`.
+    pub dir: PathBuf,
+    pub interval_hours: u64,
+    /// The most recent snapshots to keep regardless of age.
+    pub keep_last: usize,
+    /// Also keep the newest snapshot of each of this many most recent days...
+    pub keep_daily: usize,
+    /// ...and of each of this many most recent weeks.
+    pub keep_weekly: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: false,
+            dir: PathBuf::from("snapshots"),
+            interval_hours: 24,
+            keep_last: 3,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotEntry {
+    /// Also the name of the snapshot's directory.
+    pub id: String,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    /// File names, restored into the data directory.
+    pub files: Vec<String>,
+    pub index_version: Option<String>,
+}
+
+/// Copies of the index files in numbered directories, listed oldest first in `snapshots.json`.
+#[derive(Clone)]
+pub struct SnapshotManager {
+    dir: PathBuf,
+    config: SnapshotConfig,
+}
+
+impl SnapshotManager {
+    pub fn new(config: &SnapshotConfig, data_dir: &Path) -> Self {
+        SnapshotManager {
+            dir: data_dir.join(&config.dir),
+            config: config.clone(),
+        }
+    }
+
+    /// Oldest first.
+    pub fn list(&self) -> Result<Vec<SnapshotEntry>, Box<dyn Error>> {
+        let path = self.dir.join(MANIFEST_NAME);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// When the next scheduled snapshot should be taken, as a unix timestamp.
+    pub fn next_due(&self) -> Result<i64, Box<dyn Error>> {
+        Ok(match self.list()?.last() {
+            Some(latest) => latest.created_at + self.config.interval_hours as i64 * 3600,
+            None => unix_now(),
+        })
+    }
+
+    /// Copies `files` into a new snapshot, then applies the retention rules.
+    pub fn create(&self, files: &[PathBuf], index_version: Option<String>) -> Result<SnapshotEntry, Box<dyn Error>> {
+        let created_at = unix_now();
+        let id = created_at.to_string();
+        let snapshot_dir = self.dir.join(&id);
+        if snapshot_dir.exists() {
+            return Err(format!("Snapshot {} already exists", id).into());
+        }
+        log::info!("Creating snapshot {} in {}...", id, snapshot_dir.display());
+        fs::create_dir_all(&snapshot_dir)?;
+
+        let mut names = Vec::new();
+        for file in files {
+            let name = file.file_name()
+                .ok_or_else(|| format!("{} is not a file", file.display()))?
+                .to_string_lossy()
+                .into_owned();
+            copy_atomically(file, &snapshot_dir.join(&name))?;
+            names.push(name);
+        }
+
+        let entry = SnapshotEntry { id, created_at, files: names, index_version };
+        let mut snapshots = self.list()?;
+        snapshots.push(entry.clone());
+        self.save(&snapshots)?;
+        self.prune()?;
+        Ok(entry)
+    }
+
+    /// Deletes the snapshots the retention rules don't keep and returns them.
+    pub fn prune(&self) -> Result<Vec<SnapshotEntry>, Box<dyn Error>> {
+        let snapshots = self.list()?;
+        let keep = self.retained(&snapshots);
+        let (kept, removed): (Vec<_>, Vec<_>) = snapshots.into_iter()
+            .zip(keep)
+            .partition(|(_, keep)| *keep);
+        let kept: Vec<SnapshotEntry> = kept.into_iter().map(|(entry, _)| entry).collect();
+        let removed: Vec<SnapshotEntry> = removed.into_iter().map(|(entry, _)| entry).collect();
+
+        // The manifest is rewritten first so it never lists a half-deleted snapshot.
+        self.save(&kept)?;
+        for entry in &removed {
+            log::info!("Removing snapshot {}", entry.id);
+            fs::remove_dir_all(self.dir.join(&entry.id))?;
+        }
+        Ok(removed)
+    }
+
+    /// Restores the newest snapshot taken at or before `at` into `data_dir`. Files in `current`
+    /// that the snapshot doesn't have are removed, so nothing derived from a later index survives.
+    pub fn restore(&self, at: i64, data_dir: &Path, current: &[PathBuf]) -> Result<SnapshotEntry, Box<dyn Error>> {
+        let entry = self.list()?
+            .into_iter()
+            .rev()
+            .find(|entry| entry.created_at <= at)
+            .ok_or_else(|| format!("No snapshot was taken at or before {}", at))?;
+
+        log::info!("Restoring snapshot {}...", entry.id);
+        let snapshot_dir = self.dir.join(&entry.id);
+        for name in &entry.files {
+            copy_atomically(&snapshot_dir.join(name), &data_dir.join(name))?;
+        }
+        for file in current {
+            let restored = file.file_name().is_some_and(|name| entry.files.iter().any(|f| name == f.as_str()));
+            if !restored && file.exists() {
+                fs::remove_file(file)?;
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Which of `snapshots` (oldest first) to keep: the `keep_last` newest, plus the newest of each
+    /// of the last `keep_daily` days and `keep_weekly` weeks that have a snapshot.
+    fn retained(&self, snapshots: &[SnapshotEntry]) -> Vec<bool> {
+        let mut keep = vec![false; snapshots.len()];
+        let mut days = BTreeSet::new();
+        let mut weeks = BTreeSet::new();
+        for (i, (entry, keep)) in snapshots.iter().zip(keep.iter_mut()).rev().enumerate() {
+            if i < self.config.keep_last {
+                *keep = true;
+            }
+            if days.len() < self.config.keep_daily && days.insert(entry.created_at.div_euclid(DAY_SECS)) {
+                *keep = true;
+            }
+            if weeks.len() < self.config.keep_weekly && weeks.insert(entry.created_at.div_euclid(WEEK_SECS)) {
+                *keep = true;
+            }
+        }
+        keep
+    }
+
+    fn save(&self, snapshots: &[SnapshotEntry]) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = AtomicFile::create(&self.dir.join(MANIFEST_NAME))?;
+        serde_json::to_writer_pretty(&mut file, snapshots)?;
+        file.commit()?;
+        Ok(())
+    }
+}
+
+fn copy_atomically(from: &Path, to: &Path) -> io::Result<()> {
+    let mut file = AtomicFile::create(to)?;
+    io::copy(&mut File::open(from)?, &mut file)?;
+    file.commit()
+}
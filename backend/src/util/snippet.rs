@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use crate::util::analyzer::Analyzer;
+use crate::util::highlight::{self, Span};
+
+/// Most words allowed between two query terms for them to read as a phrase, e.g. the "of" in
+/// "bank of england".
+const PHRASE_GAP: usize = 2;
+
+/// The stretch of about `max_chars` characters of `text` with the most distinct `query_terms`,
+/// then the most of them following each other in query order, then the most matches.
+/// HTML-escaped, with the matches wrapped in `<em>` and `…` where the text was cut. `None` when
+/// no query term occurs in the text.
+pub fn snippet(text: &str, query_terms: &[String], analyzer: &Analyzer, max_chars: usize) -> Option<String> {
+    let spans = highlight::spans(text, &query_terms.iter().cloned().collect(), analyzer);
+    let first = &spans[best_window(&spans, query_terms, max_chars)?];
+
+    // Some context before the first match, the rest of the excerpt after it.
+    let context = max_chars / 4;
+    let mut start = chars_before(text, first.start, context);
+    let mut end = chars_after(text, start, max_chars).max(first.end);
+    if start > 0
+        && let Some(space) = text[start..first.start].find(char::is_whitespace)
+    {
+        start += space;
+    }
+    if end < text.len()
+        && let Some(space) = text[first.end..end].rfind(char::is_whitespace)
+    {
+        end = first.end + space;
+    }
+    Some(excerpt(text, start, end, &spans))
+}
+
+/// The start of `text` as a snippet, for results the query terms don't occur in.
+pub fn lead(text: &str, max_chars: usize) -> String {
+    let mut end = chars_after(text, 0, max_chars);
+    if end < text.len()
+        && let Some(space) = text[..end].rfind(char::is_whitespace)
+    {
+        end = space;
+    }
+    excerpt(text, 0, end, &[])
+}
+
+// Index of the first span of the best window, each window starting at a span and taking the
+// following ones that end within `max_chars` of its start.
+fn best_window(spans: &[Span], query_terms: &[String], max_chars: usize) -> Option<usize> {
+    // Phrase links: a match followed closely by the query's next term. `links[i]` counts those
+    // among the first `i` matches.
+    let mut order: HashMap<&str, usize> = HashMap::new();
+    for (idx, term) in query_terms.iter().enumerate() {
+        order.entry(term.as_str()).or_insert(idx);
+    }
+    let mut links = vec![0; spans.len() + 1];
+    for (idx, span) in spans.iter().enumerate() {
+        let linked = spans.get(idx + 1).is_some_and(|next| {
+            next.position - span.position <= PHRASE_GAP + 1
+                && order.get(next.term.as_str()) == order.get(span.term.as_str()).map(|&prev| prev + 1).as_ref()
+        });
+        links[idx + 1] = links[idx] + usize::from(linked);
+    }
+
+    let mut best: Option<((usize, usize, usize), usize)> = None;
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut end = 0;
+    for (first, span) in spans.iter().enumerate() {
+        end = end.max(first);
+        while end < spans.len() && spans[end].char_end - span.char_start <= max_chars {
+            *counts.entry(spans[end].term.as_str()).or_insert(0) += 1;
+            end += 1;
+        }
+        // A match longer than the snippet still makes a window of its own.
+        let phrase_links = if end > first { links[end - 1] - links[first] } else { 0 };
+        let score = (counts.len().max(1), phrase_links, (end - first).max(1));
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, first));
+        }
+        if end > first
+            && let Some(count) = counts.get_mut(span.term.as_str())
+        {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(span.term.as_str());
+            }
+        }
+    }
+    best.map(|(_, first)| first)
+}
+
+fn excerpt(text: &str, start: usize, end: usize, spans: &[Span]) -> String {
+    let mut out = String::with_capacity(end - start + 16);
+    if start > 0 {
+        out.push('…');
+    }
+    let start = end - text[start..end].trim_start().len();
+    let mut pos = start;
+    for span in spans.iter().filter(|span| span.start >= start && span.end <= end) {
+        escape(&text[pos..span.start], &mut out);
+        out.push_str("<em>");
+        escape(&text[span.start..span.end], &mut out);
+        out.push_str("</em>");
+        pos = span.end;
+    }
+    escape(&text[pos..end], &mut out);
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if end < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+// Byte offset `chars` characters before `from`, or 0.
+fn chars_before(text: &str, from: usize, chars: usize) -> usize {
+    if chars == 0 {
+        return from;
+    }
+    text[..from].char_indices().rev().nth(chars - 1).map_or(0, |(idx, _)| idx)
+}
+
+// Byte offset `chars` characters after `from`, or the end of the text.
+fn chars_after(text: &str, from: usize, chars: usize) -> usize {
+    text[from..].char_indices().nth(chars).map_or(text.len(), |(idx, _)| from + idx)
+}
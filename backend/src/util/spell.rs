@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::PreprocessedData;
+use crate::util::collocations::PHRASE_SEPARATOR;
+
+const MAX_DISTANCE: f64 = 2.0;
+const ADJACENT_SUBSTITUTION_COST: f64 = 0.5;
+const TRANSPOSITION_COST: f64 = 0.75;
+// How strongly edit distance outweighs frequency when ranking candidates.
+const DISTANCE_PENALTY: f64 = 2.0;
+const KEYBOARD_ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Correction {
+    pub original: String,
+    pub suggestion: String,
+    /// Share of the candidates' combined weight held by the suggestion, in (0, 1].
+    pub confidence: f64,
+}
+
+/// Corrects query words against the index vocabulary. Edits between neighbouring keys cost less,
+/// so fat-finger typos rank above other words at the same plain edit distance.
+pub struct SpellCorrector {
+    // term length -> (term, document frequency), sorted by term
+    terms_by_length: HashMap<usize, Vec<(String, usize)>>,
+    key_positions: HashMap<char, (i32, i32)>,
+}
+
+impl SpellCorrector {
+    pub fn build(data: &PreprocessedData) -> Self {
+        let matrix = &data.term_doc_csr;
+        let mut terms_by_length: HashMap<usize, Vec<(String, usize)>> = HashMap::new();
+        for (term, &idx) in &data.term_dict {
+            if term.contains(PHRASE_SEPARATOR) {
+                continue;
+            }
+            let df = matrix.row_offsets[idx + 1] - matrix.row_offsets[idx];
+            terms_by_length.entry(term.chars().count()).or_default().push((term.clone(), df));
+        }
+
+        for terms in terms_by_length.values_mut() {
+            terms.sort_unstable();
+        }
+
+        let mut key_positions = HashMap::new();
+        for (row, keys) in KEYBOARD_ROWS.iter().enumerate() {
+            for (col, key) in keys.chars().enumerate() {
+                key_positions.insert(key, (row as i32, col as i32));
+            }
+        }
+
+        SpellCorrector { terms_by_length, key_positions }
+    }
+
+    pub fn is_known(&self, word: &str) -> bool {
+        self.terms_by_length.get(&word.chars().count())
+            .is_some_and(|terms| terms.binary_search_by(|(term, _)| term.as_str().cmp(word)).is_ok())
+    }
+
+    /// Best correction for each query word that isn't in the vocabulary and has a close match.
+    pub fn correct_query(&self, query: &str) -> Vec<Correction> {
+        crate::util::tokenizer::tokenize(query)
+            .into_iter()
+            .filter(|word| !self.is_known(word))
+            .filter_map(|word| self.correct(&word))
+            .collect()
+    }
+
+    pub fn correct(&self, word: &str) -> Option<Correction> {
+        let chars: Vec<char> = word.chars().collect();
+        let max_len_diff = MAX_DISTANCE as usize;
+
+        let mut candidates: Vec<(&str, f64)> = Vec::new();
+        for len in chars.len().saturating_sub(max_len_diff)..=chars.len() + max_len_diff {
+            for (term, df) in self.terms_by_length.get(&len).into_iter().flatten() {
+                let distance = self.distance(&chars, term);
+                if distance <= MAX_DISTANCE {
+                    let weight = (1.0 + *df as f64).ln() * (-DISTANCE_PENALTY * distance).exp();
+                    candidates.push((term, weight));
+                }
+            }
+        }
+
+        let total: f64 = candidates.iter().map(|(_, weight)| weight).sum();
+        let (best, weight) = candidates.into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        Some(Correction {
+            original: word.to_string(),
+            suggestion: best.to_string(),
+            confidence: if total > 0.0 { weight / total } else { 0.0 },
+        })
+    }
+
+    /// Damerau-Levenshtein (optimal string alignment) distance with keyboard-weighted substitutions.
+    fn distance(&self, a: &[char], b: &str) -> f64 {
+        let b: Vec<char> = b.chars().collect();
+        let mut d = vec![vec![0.0; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i as f64;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j as f64;
+        }
+
+        let mut previous_row_min = 0.0;
+        for i in 1..=a.len() {
+            let mut row_min = f64::MAX;
+            for j in 1..=b.len() {
+                let substitution = if a[i - 1] == b[j - 1] { 0.0 } else { self.substitution_cost(a[i - 1], b[j - 1]) };
+                let mut cost = (d[i - 1][j] + 1.0)
+                    .min(d[i][j - 1] + 1.0)
+                    .min(d[i - 1][j - 1] + substitution);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    cost = cost.min(d[i - 2][j - 2] + TRANSPOSITION_COST);
+                }
+                d[i][j] = cost;
+                row_min = row_min.min(cost);
+            }
+            // A transposition can reach back two rows, so both must be out of range to stop early.
+            if row_min > MAX_DISTANCE && previous_row_min > MAX_DISTANCE {
+                return f64::MAX;
+            }
+            previous_row_min = row_min;
+        }
+
+        d[a.len()][b.len()]
+    }
+
+    fn substitution_cost(&self, a: char, b: char) -> f64 {
+        match (self.key_positions.get(&a), self.key_positions.get(&b)) {
+            (Some(&(row_a, col_a)), Some(&(row_b, col_b))) => {
+                // Each row is shifted half a key right of the one above it.
+                let adjacent = match row_a - row_b {
+                    0 => (col_a - col_b).abs() == 1,
+                    1 => col_b == col_a || col_b == col_a + 1,
+                    -1 => col_a == col_b || col_a == col_b + 1,
+                    _ => false,
+                };
+                if adjacent { ADJACENT_SUBSTITUTION_COST } else { 1.0 }
+            }
+            _ => 1.0,
+        }
+    }
+}
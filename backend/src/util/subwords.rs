@@ -0,0 +1,149 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+/// Marks a piece that continues a word rather than starting it, so "##s" and "s" stay distinct terms.
+pub const CONTINUATION: &str = "##";
+
+/// Byte-pair encoding learned from the corpus, used instead of stemming when
+/// `analyzer.normalization` is "subword". Words are split into the pieces merged most often in
+/// training, so frequent words stay whole and rare ones share pieces with their relatives in any
+/// language, without stop word lists or stemmers for it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Subwords {
+    // Training order of each merge of two pieces; earlier merges apply first.
+    ranks: HashMap<(String, String), u32>,
+}
+
+impl Subwords {
+    /// Learns up to `merges` merges, each joining the pair of adjacent pieces that occurs most
+    /// often in `word_counts`. Stops early once no pair occurs twice.
+    pub fn learn(word_counts: &HashMap<String, usize>, merges: usize) -> Self {
+        let mut pieces = Pieces::default();
+
+        // Sorted, so the same corpus always learns the same merges.
+        let mut sorted: Vec<(&String, &usize)> = word_counts.iter().collect();
+        sorted.sort();
+        let mut words: Vec<(Vec<u32>, i64)> = sorted.into_iter()
+            .map(|(word, &count)| (initial_pieces(word).into_iter().map(|piece| pieces.intern(piece)).collect(), count as i64))
+            .collect();
+
+        let mut pair_counts: HashMap<(u32, u32), i64> = HashMap::new();
+        let mut pair_words: HashMap<(u32, u32), HashSet<usize>> = HashMap::new();
+        for (word_idx, (symbols, count)) in words.iter().enumerate() {
+            for pair in symbols.windows(2) {
+                *pair_counts.entry((pair[0], pair[1])).or_insert(0) += count;
+                pair_words.entry((pair[0], pair[1])).or_default().insert(word_idx);
+            }
+        }
+        // Entries go stale as counts change; one is current when it matches `pair_counts`.
+        let mut heap: BinaryHeap<(i64, Reverse<(u32, u32)>)> = pair_counts.iter()
+            .map(|(&pair, &count)| (count, Reverse(pair)))
+            .collect();
+
+        let mut ranks = HashMap::new();
+        while ranks.len() < merges {
+            let Some((count, Reverse(pair))) = heap.pop() else {
+                break;
+            };
+            if pair_counts.get(&pair) != Some(&count) {
+                continue;
+            }
+            if count < 2 {
+                break;
+            }
+
+            let (left, right) = (pieces.names[pair.0 as usize].clone(), pieces.names[pair.1 as usize].clone());
+            let merged = pieces.intern(merge(&left, &right));
+            ranks.insert((left, right), ranks.len() as u32);
+
+            let mut touched = HashSet::new();
+            for word_idx in pair_words.remove(&pair).unwrap_or_default() {
+                let (symbols, word_count) = &mut words[word_idx];
+                for old in symbols.windows(2) {
+                    *pair_counts.entry((old[0], old[1])).or_insert(0) -= *word_count;
+                    touched.insert((old[0], old[1]));
+                }
+                let replaced = replace_pair(symbols, pair, merged);
+                *symbols = replaced;
+                for new in symbols.windows(2) {
+                    *pair_counts.entry((new[0], new[1])).or_insert(0) += *word_count;
+                    pair_words.entry((new[0], new[1])).or_default().insert(word_idx);
+                    touched.insert((new[0], new[1]));
+                }
+            }
+            pair_counts.remove(&pair);
+            for pair in touched {
+                if let Some(&count) = pair_counts.get(&pair) {
+                    heap.push((count, Reverse(pair)));
+                }
+            }
+        }
+
+        log::info!("Learned {} subword merges from {} distinct words", ranks.len(), word_counts.len());
+        Subwords { ranks }
+    }
+
+    /// The pieces of `word`, applying the learned merges in training order.
+    pub fn split(&self, word: &str) -> Vec<String> {
+        let mut pieces = initial_pieces(word);
+        loop {
+            let best = pieces.windows(2)
+                .enumerate()
+                .filter_map(|(idx, pair)| self.ranks.get(&(pair[0].clone(), pair[1].clone())).map(|&rank| (rank, idx)))
+                .min();
+            let Some((_, idx)) = best else {
+                return pieces;
+            };
+            let right = pieces.remove(idx + 1);
+            let merged = merge(&pieces[idx], &right);
+            pieces[idx] = merged;
+        }
+    }
+}
+
+// Training works on piece ids rather than strings.
+#[derive(Default)]
+struct Pieces {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl Pieces {
+    fn intern(&mut self, piece: String) -> u32 {
+        if let Some(&id) = self.ids.get(&piece) {
+            return id;
+        }
+        self.names.push(piece.clone());
+        let id = self.names.len() as u32 - 1;
+        self.ids.insert(piece, id);
+        id
+    }
+}
+
+// One piece per character, all but the first marked as continuing the word.
+fn initial_pieces(word: &str) -> Vec<String> {
+    word.chars()
+        .enumerate()
+        .map(|(idx, c)| if idx == 0 { c.to_string() } else { format!("{}{}", CONTINUATION, c) })
+        .collect()
+}
+
+fn merge(left: &str, right: &str) -> String {
+    format!("{}{}", left, right.strip_prefix(CONTINUATION).unwrap_or(right))
+}
+
+fn replace_pair(symbols: &[u32], pair: (u32, u32), merged: u32) -> Vec<u32> {
+    let mut replaced = Vec::with_capacity(symbols.len());
+    let mut idx = 0;
+    while idx < symbols.len() {
+        if idx + 1 < symbols.len() && (symbols[idx], symbols[idx + 1]) == pair {
+            replaced.push(merged);
+            idx += 2;
+        } else {
+            replaced.push(symbols[idx]);
+            idx += 1;
+        }
+    }
+    replaced
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use serde::Serialize;
+use crate::PreprocessedData;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TermSuggestion {
+    pub term: String,
+    pub document_frequency: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QuerySuggestion {
+    pub query: String,
+    pub score: f64,
+}
+
+/// Vocabulary terms sorted for prefix lookups, with their document frequencies.
+pub struct TermSuggester {
+    terms: Vec<(String, usize)>,
+}
+
+impl TermSuggester {
+    pub fn build(data: &PreprocessedData) -> Self {
+        let matrix = &data.term_doc_csr;
+        let mut terms: Vec<(String, usize)> = data.term_dict.iter()
+            .map(|(term, &idx)| (term.clone(), matrix.row_offsets[idx + 1] - matrix.row_offsets[idx]))
+            .collect();
+        terms.sort_unstable();
+        TermSuggester { terms }
+    }
+
+    /// Completions of the last word of `prefix`, most frequent first.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<TermSuggestion> {
+        let Some(word) = prefix.split_whitespace().last().map(str::to_lowercase) else {
+            return Vec::new();
+        };
+
+        let start = self.terms.partition_point(|(term, _)| term.as_str() < word.as_str());
+        let mut matches: Vec<&(String, usize)> = self.terms[start..].iter()
+            .take_while(|(term, _)| term.starts_with(&word))
+            .collect();
+        matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        matches.into_iter()
+            .take(limit)
+            .map(|(term, df)| TermSuggestion { term: term.clone(), document_frequency: *df })
+            .collect()
+    }
+}
+
+/// Popular past queries. Each search adds 1 to its query's score and scores halve every
+/// `half_life_secs`, so recent popularity outweighs old.
+pub struct QuerySuggester {
+    // normalized query -> (score as of `updated_at`, updated_at in unix seconds)
+    queries: RwLock<BTreeMap<String, (f64, i64)>>,
+    half_life_secs: f64,
+}
+
+impl QuerySuggester {
+    pub fn new(half_life_secs: f64) -> Self {
+        QuerySuggester {
+            queries: RwLock::new(BTreeMap::new()),
+            half_life_secs,
+        }
+    }
+
+    pub fn record(&self, query: &str, at: i64) {
+        let query = normalize_query(query);
+        if query.is_empty() {
+            return;
+        }
+
+        let mut queries = self.queries.write().unwrap();
+        let (score, updated_at) = queries.entry(query).or_insert((0.0, at));
+        if at >= *updated_at {
+            *score = self.decay(*score, at - *updated_at) + 1.0;
+            *updated_at = at;
+        } else {
+            *score += self.decay(1.0, *updated_at - at);
+        }
+    }
+
+    pub fn suggest(&self, prefix: &str, now: i64, limit: usize) -> Vec<QuerySuggestion> {
+        let prefix = normalize_query(prefix);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let queries = self.queries.read().unwrap();
+        let mut matches: Vec<QuerySuggestion> = queries.range(prefix.clone()..)
+            .take_while(|(query, _)| query.starts_with(&prefix))
+            .map(|(query, &(score, updated_at))| QuerySuggestion {
+                query: query.clone(),
+                score: self.decay(score, now - updated_at),
+            })
+            .collect();
+
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit);
+        matches
+    }
+
+    fn decay(&self, score: f64, elapsed_secs: i64) -> f64 {
+        score * 0.5_f64.powf(elapsed_secs.max(0) as f64 / self.half_life_secs)
+    }
+}
+
+fn normalize_query(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
@@ -0,0 +1,94 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::util::analyzer::Analyzer;
+use crate::util::sentences;
+
+// TextRank is quadratic in the sentence count, so long articles are cut off here.
+const MAX_SENTENCES: usize = 50;
+const DAMPING: f64 = 0.85;
+const ITERATIONS: usize = 30;
+
+/// Extractive TextRank summary: sentences become TF-IDF vectors, edges are their
+/// cosine similarities, and the `max_sentences` best-ranked ones are kept in reading order.
+pub fn summarize(
+    text: &str,
+    language: &str,
+    analyzer: &Analyzer,
+    term_dict: &HashMap<String, usize>,
+    idf: &[f64],
+    max_sentences: usize,
+) -> String {
+    let sentences: Vec<&str> = sentences::split(text, language).into_iter().take(MAX_SENTENCES).collect();
+    if sentences.len() <= max_sentences {
+        return sentences.join(" ");
+    }
+
+    let vectors: Vec<BTreeMap<usize, f64>> = sentences.iter()
+        .map(|sentence| sentence_vector(sentence, analyzer, term_dict, idf))
+        .collect();
+
+    let n = sentences.len();
+    let mut weights = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let sim = cosine_similarity(&vectors[i], &vectors[j]);
+            weights[i][j] = sim;
+            weights[j][i] = sim;
+        }
+    }
+
+    let out_sums: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+    let mut scores = vec![1.0 / n as f64; n];
+
+    for _ in 0..ITERATIONS {
+        scores = (0..n)
+            .map(|i| {
+                let incoming: f64 = (0..n)
+                    .filter(|&j| out_sums[j] > 0.0)
+                    .map(|j| weights[j][i] / out_sums[j] * scores[j])
+                    .sum();
+                (1.0 - DAMPING) / n as f64 + DAMPING * incoming
+            })
+            .collect();
+    }
+
+    let mut ranked: Vec<usize> = (0..n).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    let mut selected: Vec<usize> = ranked.into_iter().take(max_sentences).collect();
+    selected.sort_unstable();
+
+    selected.iter()
+        .map(|&i| sentences[i])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn sentence_vector(
+    sentence: &str,
+    analyzer: &Analyzer,
+    term_dict: &HashMap<String, usize>,
+    idf: &[f64],
+) -> BTreeMap<usize, f64> {
+    // Ordered so similarity sums, and therefore the chosen sentences, don't vary between builds.
+    let mut vector = BTreeMap::new();
+    for term in analyzer.analyze(sentence) {
+        if let Some(&term_idx) = term_dict.get(&term) {
+            *vector.entry(term_idx).or_insert(0.0) += idf[term_idx];
+        }
+    }
+    vector
+}
+
+fn cosine_similarity(a: &BTreeMap<usize, f64>, b: &BTreeMap<usize, f64>) -> f64 {
+    let dot: f64 = a.iter()
+        .filter_map(|(term_idx, va)| b.get(term_idx).map(|vb| va * vb))
+        .sum();
+    let norm_a = a.values().map(|v| v * v).sum::<f64>().sqrt();
+    let norm_b = b.values().map(|v| v * v).sum::<f64>().sqrt();
+
+    if norm_a > 0.0 && norm_b > 0.0 {
+        dot / (norm_a * norm_b)
+    } else {
+        0.0
+    }
+}
@@ -1,9 +1,59 @@
 use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use nalgebra::{DMatrix, DVector};
 use nalgebra_sparse::CsrMatrix;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use crate::{serialize_matrix, SvdData};
+use crate::util::build_error::BuildError;
+use crate::util::data::AtomicFile;
+use crate::util::manifest::fnv1a;
+
+type SvdFactors = (DMatrix<f64>, Vec<f64>, DMatrix<f64>);
+
+const LANCZOS_SEED: u64 = 0x5eed;
+// Bumped whenever the checkpoint layout changes, so older files are discarded rather than misread.
+const CHECKPOINT_FORMAT: u32 = 1;
+
+/// Convergence settings for building SVD models. They only affect models built afterwards.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SvdBuildConfig {
+    /// Lanczos steps per requested singular value, before `max_steps` caps them.
+    pub steps_per_component: usize,
+    pub max_steps: usize,
+    /// Singular values at or below this are dropped, and iteration stops when the next Lanczos
+    /// vector's norm falls below it.
+    pub tolerance: f64,
+    /// Stop once every one of the top k relative residuals is below this; 0 always runs all steps.
+    pub residual_tolerance: f64,
+    /// Steps between residual checks, which each solve the tridiagonal eigenproblem so far.
+    pub check_every: usize,
+    /// Ranks cached along with the served model when it's built. The SVD runs once at the
+    /// largest and the smaller ranks are truncated from it.
+    pub extra_ranks: Vec<usize>,
+    /// Steps between saves of the Lanczos state, from which an interrupted build resumes;
+    /// 0 never saves it.
+    pub checkpoint_every: usize,
+}
+
+impl Default for SvdBuildConfig {
+    fn default() -> Self {
+        SvdBuildConfig {
+            steps_per_component: 2,
+            max_steps: 200,
+            tolerance: 1e-6,
+            residual_tolerance: 0.0,
+            check_every: 10,
+            extra_ranks: vec![10, 50],
+            checkpoint_every: 20,
+        }
+    }
+}
 
 pub fn sparse_svd<F1, F2>(
     matrix_op: F1,
@@ -11,9 +61,9 @@ pub fn sparse_svd<F1, F2>(
     nrows: usize,
     ncols: usize,
     k: usize,
-    max_iter: usize,
-    tolerance: f64,
-) -> Result<(DMatrix<f64>, Vec<f64>, DMatrix<f64>), Box<dyn Error>>
+    config: &SvdBuildConfig,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<SvdFactors, Box<dyn Error>>
 where
     F1: Fn(&[f64], &mut [f64]),
     F2: Fn(&[f64], &mut [f64]),
@@ -24,22 +74,46 @@ where
     // Adjust k if it's too large for the matrix dimensions
     let k = k.min(working_dim).min(1000);
 
-    let mut m = (2 * k).min(working_dim).min(max_iter);
+    let tolerance = config.tolerance;
+    let mut m = (config.steps_per_component.max(1) * k).min(working_dim).min(config.max_steps).max(1);
 
-    println!("Starting SVD computation for {k} components (working dim: {working_dim}, Lanczos steps: {m})");
+    log::info!("Starting SVD computation for {k} components (working dim: {working_dim}, Lanczos steps: {m})");
 
     let mut q = vec![DVector::zeros(working_dim); m + 1];
     let mut alpha = vec![0.0; m];
     let mut beta = vec![0.0; m + 1];
 
-    let mut rng = rand::thread_rng();
-    for i in 0..working_dim {
-        q[0][i] = rng.r#gen::<f64>() - 0.5;
+    let mut resumed = 0;
+    if let Some(checkpoint) = checkpoint {
+        match checkpoint.load(working_dim, k, m) {
+            Ok(Some(state)) => {
+                resumed = state.alpha.len();
+                alpha[..resumed].copy_from_slice(&state.alpha);
+                beta[..=resumed].copy_from_slice(&state.beta);
+                for (slot, vector) in q.iter_mut().zip(state.q) {
+                    *slot = vector;
+                }
+                log::info!("Resuming from Lanczos step {}/{} saved in {}", resumed, m, checkpoint.path.display());
+            }
+            Ok(None) => {}
+            Err(reason) => {
+                log::warn!("Discarding Lanczos checkpoint {}: {}", checkpoint.path.display(), reason);
+                checkpoint.remove();
+            }
+        }
+    }
+
+    if resumed == 0 {
+        // Fixed seed so rebuilding the same matrix gives the same factors.
+        let mut rng = StdRng::seed_from_u64(LANCZOS_SEED);
+        for x in q[0].iter_mut() {
+            *x = rng.random::<f64>() - 0.5;
+        }
+        q[0].normalize_mut();
     }
-    q[0].normalize_mut();
 
-    for i in 0..m {
-        println!("Lanczos iteration {}/{}", i+1, m);
+    for i in resumed..m {
+        log::debug!("Lanczos iteration {}/{}", i+1, m);
 
         let mut v = if work_on_at_a {
             let mut temp = vec![0.0; nrows];
@@ -57,9 +131,9 @@ where
             DVector::from_vec(result)
         };
 
-        for j in 0..=i {
-            let dot = v.dot(&q[j]);
-            v.axpy(-dot, &q[j], 1.0);
+        for q_j in &q[..=i] {
+            let dot = v.dot(q_j);
+            v.axpy(-dot, q_j, 1.0);
         }
 
         alpha[i] = v.dot(&q[i]);
@@ -70,44 +144,58 @@ where
         }
 
         for _ in 0..2 {
-            for j in 0..=i {
-                let dot = v.dot(&q[j]);
-                v.axpy(-dot, &q[j], 1.0); }
+            for q_j in &q[..=i] {
+                let dot = v.dot(q_j);
+                v.axpy(-dot, q_j, 1.0); }
 
         }
 
         beta[i+1] = v.norm();
 
         if beta[i+1] < tolerance || beta[i+1].is_nan() || beta[i+1].is_infinite() {
-            println!("Early termination at iteration {} (beta = {})", i, beta[i+1]);
+            log::info!("Early termination at iteration {} (beta = {})", i, beta[i+1]);
             m = i + 1;
             break;
         }
 
         q[i+1] = v / beta[i+1];
-    }
-    let mut t = DMatrix::zeros(m, m);
-    for i in 0..m {
-        t[(i, i)] = alpha[i];
-        if i > 0 {
-            t[(i, i-1)] = beta[i];
-            t[(i-1, i)] = beta[i];
+
+        let steps = i + 1;
+        if config.residual_tolerance > 0.0 && steps >= k && steps < m && steps % config.check_every.max(1) == 0 {
+            let (eigenvalues, eigenvectors, indices) = ritz_pairs(&alpha, &beta, steps);
+            let residuals = relative_residuals(&eigenvalues, &eigenvectors, &indices[..k], beta[steps]);
+            let worst = residuals.iter().copied().fold(0.0, f64::max);
+            log::debug!("Largest top-{} relative residual after {} steps: {:e}", k, steps, worst);
+            if worst < config.residual_tolerance {
+                log::info!("Converged after {} of {} Lanczos steps", steps, m);
+                m = steps;
+                break;
+            }
         }
-    }
 
-    println!("Computing eigenvalues of {}x{} tridiagonal matrix...", m, m);
-    let eig = t.symmetric_eigen();
-    let (eigenvalues, eigenvectors) = (eig.eigenvalues, eig.eigenvectors);
+        if let Some(checkpoint) = checkpoint
+            && config.checkpoint_every > 0
+            && steps < m
+            && steps.is_multiple_of(config.checkpoint_every)
+            // A failed save only costs the ability to resume, not the build.
+            && let Err(e) = checkpoint.save(working_dim, k, m, &alpha[..steps], &beta[..=steps], &q[..=steps])
+        {
+            log::warn!("Can't save Lanczos checkpoint to {}: {}", checkpoint.path.display(), e);
+        }
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove();
+    }
 
-    let mut indices: Vec<usize> = (0..m).collect();
-    indices.sort_by(|&a, &b| eigenvalues[b].partial_cmp(&eigenvalues[a]).unwrap());
+    log::debug!("Computing eigenvalues of {}x{} tridiagonal matrix...", m, m);
+    let (eigenvalues, eigenvectors, indices) = ritz_pairs(&alpha, &beta, m);
 
     let sigma: Vec<f64> = indices.iter()
         .take(k)
         .map(|&i| {
             let lambda = eigenvalues[i];
             if lambda < -tolerance {
-                println!("Warning: Found negative eigenvalue: {}", lambda);
+                log::warn!("Found negative eigenvalue: {}", lambda);
                 0.0
             } else {
                 lambda.max(0.0).sqrt()
@@ -118,13 +206,18 @@ where
 
     let actual_k = sigma.len();
     if actual_k < k {
-        println!("Warning: Only found {actual_k} non-zero singular values (requested {k})");
+        log::warn!("Only found {actual_k} non-zero singular values (requested {k})");
     }
     if actual_k == 0 {
         return Err("No significant singular values found. Try reducing the tolerance.".into());
     }
 
-    println!("Computing singular vectors...");
+    let residuals = relative_residuals(&eigenvalues, &eigenvectors, &indices[..actual_k], beta[m]);
+    for (component, (residual, value)) in residuals.iter().zip(&sigma).enumerate() {
+        log::debug!("Singular triplet {}: sigma = {:.6}, relative residual = {:e}", component, value, residual);
+    }
+
+    log::debug!("Computing singular vectors...");
     let mut u = DMatrix::zeros(nrows, actual_k);
     let mut vt = DMatrix::zeros(actual_k, ncols);
 
@@ -143,7 +236,7 @@ where
                 u_col /= sigma[col];
             } else {
                 u_col.fill(0.0);
-                println!("Warning: Small singular value {} at position {}", sigma[col], col);
+                log::warn!("Small singular value {} at position {}", sigma[col], col);
             }
 
             u.set_column(col, &u_col);
@@ -161,7 +254,7 @@ where
                 v_col /= sigma[col];
             } else {
                 v_col.fill(0.0);
-                println!("Warning: Small singular value {} at position {}", sigma[col], col);
+                log::warn!("Small singular value {} at position {}", sigma[col], col);
             }
 
             u.set_column(col, &u_col);
@@ -177,8 +270,7 @@ where
             dots.push(current_col.dot(&u.column(j)));
         }
 
-        for j in 0..i {
-            let dot = dots[j];
+        for (j, &dot) in dots.iter().enumerate() {
             let col_j = u.column(j);
             for k in 0..current_col.len() {
                 current_col[k] -= dot * col_j[k];
@@ -198,8 +290,7 @@ where
             dots.push(current_row.dot(&vt.row(j)));
         }
 
-        for j in 0..i {
-            let dot = dots[j];
+        for (j, &dot) in dots.iter().enumerate() {
             let row_j = vt.row(j);
             for k in 0..current_row.len() {
                 current_row[k] -= dot * row_j[k];
@@ -211,62 +302,223 @@ where
         vt.row_mut(i).copy_from(&current_row);
     }
 
-    println!("SVD computation completed (effective rank: {actual_k})");
+    log::info!("SVD computation completed (effective rank: {actual_k})");
     Ok((u, sigma, vt))
 }
 
-pub fn perform_svd(term_doc_csr: &CsrMatrix<f64>, k: usize) -> Result<SvdData, Box<dyn Error>> {
-    println!("Performing SVD with rank {}...", k);
+// Eigenvalues and eigenvectors of the tridiagonal matrix after `steps` Lanczos steps, with
+// their indices largest eigenvalue first.
+fn ritz_pairs(alpha: &[f64], beta: &[f64], steps: usize) -> (DVector<f64>, DMatrix<f64>, Vec<usize>) {
+    let mut t = DMatrix::zeros(steps, steps);
+    for i in 0..steps {
+        t[(i, i)] = alpha[i];
+        if i > 0 {
+            t[(i, i-1)] = beta[i];
+            t[(i-1, i)] = beta[i];
+        }
+    }
+    let eig = t.symmetric_eigen();
+    let mut indices: Vec<usize> = (0..steps).collect();
+    indices.sort_by(|&a, &b| eig.eigenvalues[b].total_cmp(&eig.eigenvalues[a]));
+    (eig.eigenvalues, eig.eigenvectors, indices)
+}
+
+// Residual norm of each Ritz pair, |beta * last component of its eigenvector|, relative to
+// its eigenvalue (the squared singular value).
+fn relative_residuals(eigenvalues: &DVector<f64>, eigenvectors: &DMatrix<f64>, indices: &[usize], next_beta: f64) -> Vec<f64> {
+    let last = eigenvectors.nrows() - 1;
+    indices.iter()
+        .map(|&idx| (next_beta * eigenvectors[(last, idx)]).abs() / eigenvalues[idx].abs().max(f64::MIN_POSITIVE))
+        .collect()
+}
+
+/// Where an interrupted build of the model saved at `index` keeps its Lanczos state.
+pub fn checkpoint_path(index: &Path) -> PathBuf {
+    let mut path = index.as_os_str().to_owned();
+    path.push(".lanczos");
+    PathBuf::from(path)
+}
+
+/// Lanczos state of a build in progress, saved to `path`. It only resumes a build of the same
+/// matrix, rank and step count, and only if it passes the integrity checks in `load`.
+pub struct Checkpoint {
+    path: PathBuf,
+    // Fingerprint of the matrix being factored.
+    matrix: u64,
+}
+
+struct LanczosState {
+    alpha: Vec<f64>,
+    beta: Vec<f64>,
+    q: Vec<DVector<f64>>,
+}
+
+impl Checkpoint {
+    pub fn new(path: PathBuf, matrix: &CsrMatrix<f64>) -> Self {
+        let mut hash = 0xcbf29ce484222325;
+        for dim in [matrix.nrows(), matrix.ncols()] {
+            hash = fnv1a(hash, &(dim as u64).to_le_bytes());
+        }
+        for &idx in matrix.row_offsets().iter().chain(matrix.col_indices()) {
+            hash = fnv1a(hash, &(idx as u64).to_le_bytes());
+        }
+        for value in matrix.values() {
+            hash = fnv1a(hash, &value.to_bits().to_le_bytes());
+        }
+        Checkpoint { path, matrix: hash }
+    }
+
+    // Header, then alpha and beta, then one Lanczos vector at a time.
+    fn save(&self, working_dim: usize, k: usize, m: usize, alpha: &[f64], beta: &[f64], q: &[DVector<f64>]) -> Result<(), Box<dyn Error>> {
+        let checksum = state_checksum(alpha, beta, q);
+        let mut file = AtomicFile::create(&self.path)?;
+        bincode::serialize_into(&mut file, &(CHECKPOINT_FORMAT, self.matrix, working_dim, k, m, checksum))?;
+        bincode::serialize_into(&mut file, alpha)?;
+        bincode::serialize_into(&mut file, beta)?;
+        for vector in q {
+            bincode::serialize_into(&mut file, vector.as_slice())?;
+        }
+        file.commit()?;
+        log::debug!("Saved Lanczos checkpoint after {} of {} steps", alpha.len(), m);
+        Ok(())
+    }
+
+    // `Ok(None)` without a checkpoint; `Err` with the reason one can't be resumed from.
+    fn load(&self, working_dim: usize, k: usize, m: usize) -> Result<Option<LanczosState>, String> {
+        match File::open(&self.path) {
+            Ok(file) => self.read(file, working_dim, k, m).map(Some).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn read(&self, file: File, working_dim: usize, k: usize, m: usize) -> Result<LanczosState, Box<dyn Error>> {
+        let mut reader = BufReader::new(file);
+        let (format, matrix, saved_dim, saved_k, saved_m, checksum): (u32, u64, usize, usize, usize, u64) =
+            bincode::deserialize_from(&mut reader)?;
+        if format != CHECKPOINT_FORMAT {
+            return Err(format!("format {} instead of {}", format, CHECKPOINT_FORMAT).into());
+        }
+        if matrix != self.matrix {
+            return Err("it was saved for a different matrix".into());
+        }
+        if (saved_dim, saved_k, saved_m) != (working_dim, k, m) {
+            return Err(format!(
+                "it was saved for k={} and {} steps in {} dimensions, not k={} and {} steps in {}",
+                saved_k, saved_m, saved_dim, k, m, working_dim,
+            ).into());
+        }
+
+        let alpha: Vec<f64> = bincode::deserialize_from(&mut reader)?;
+        let beta: Vec<f64> = bincode::deserialize_from(&mut reader)?;
+        let steps = alpha.len();
+        if steps == 0 || steps >= m || beta.len() != steps + 1 {
+            return Err(format!("{} alpha and {} beta coefficients for {} steps", steps, beta.len(), m).into());
+        }
+        let mut q = Vec::with_capacity(steps + 1);
+        for _ in 0..=steps {
+            let vector: Vec<f64> = bincode::deserialize_from(&mut reader)?;
+            if vector.len() != working_dim {
+                return Err(format!("a Lanczos vector has {} entries instead of {}", vector.len(), working_dim).into());
+            }
+            q.push(DVector::from_vec(vector));
+        }
+
+        if state_checksum(&alpha, &beta, &q) != checksum {
+            return Err("checksum mismatch".into());
+        }
+        if !alpha.iter().chain(&beta).chain(q.iter().flat_map(|vector| vector.iter())).all(|v| v.is_finite()) {
+            return Err("it holds non-finite values".into());
+        }
+        // The next step builds on the last vector, which must still be orthonormal to the rest.
+        let (last, earlier) = q.split_last().expect("at least two vectors were read");
+        if (last.norm() - 1.0).abs() > 1e-6 {
+            return Err(format!("the last Lanczos vector has norm {}", last.norm()).into());
+        }
+        if let Some(dot) = earlier.iter().map(|q_j| last.dot(q_j).abs()).find(|&dot| dot > 1e-6) {
+            return Err(format!("the Lanczos vectors have lost orthogonality (|dot| = {:e})", dot).into());
+        }
+        Ok(LanczosState { alpha, beta, q })
+    }
+
+    fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            log::warn!("Can't remove Lanczos checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn state_checksum(alpha: &[f64], beta: &[f64], q: &[DVector<f64>]) -> u64 {
+    let values = alpha.iter().chain(beta).chain(q.iter().flat_map(|vector| vector.iter()));
+    values.fold(0xcbf29ce484222325, |hash, value| fnv1a(hash, &value.to_bits().to_le_bytes()))
+}
+
+/// Factorizes `term_doc_csr`; with a `checkpoint` path, saves the Lanczos state there as it
+/// goes and resumes from whatever an interrupted build of the same matrix left.
+pub fn perform_svd(term_doc_csr: &CsrMatrix<f64>, k: usize, config: &SvdBuildConfig, checkpoint: Option<&Path>) -> Result<SvdData, Box<dyn Error>> {
+    let (nrows, ncols) = (term_doc_csr.nrows(), term_doc_csr.ncols());
+    if ncols == 0 {
+        return Err(BuildError::EmptyCorpus.into());
+    }
+    if nrows == 0 {
+        return Err(BuildError::EmptyVocabulary { documents: ncols }.into());
+    }
+    if term_doc_csr.values().iter().all(|&v| v == 0.0) {
+        return Err(BuildError::ZeroWeights { terms: nrows, documents: ncols }.into());
+    }
+
+    log::info!("Performing SVD with rank {}...", k);
     let start = Instant::now();
     let linear_op = |v: &[f64], result: &mut [f64]| {
-        for i in 0..result.len() {
-            result[i] = 0.0;
+        for (i, out) in result.iter_mut().enumerate() {
+            *out = 0.0;
             let row_start = term_doc_csr.row_offsets()[i];
             let row_end = term_doc_csr.row_offsets()[i + 1];
 
             for idx in row_start..row_end {
                 let j = term_doc_csr.col_indices()[idx];
                 let val = term_doc_csr.values()[idx];
-                result[i] += val * v[j];
+                *out += val * v[j];
             }
         }
     };
 
     let transpose_op = |v: &[f64], result: &mut [f64]| {
-        for i in 0..result.len() {
-            result[i] = 0.0;
-        }
+        result.fill(0.0);
 
-        for i in 0..term_doc_csr.nrows() {
+        for (i, &v_i) in v.iter().enumerate() {
             let row_start = term_doc_csr.row_offsets()[i];
             let row_end = term_doc_csr.row_offsets()[i + 1];
 
             for idx in row_start..row_end {
                 let j = term_doc_csr.col_indices()[idx];
                 let val = term_doc_csr.values()[idx];
-                result[j] += val * v[i];
+                result[j] += val * v_i;
             }
         }
     };
 
+    let checkpoint = checkpoint.map(|path| Checkpoint::new(path.to_path_buf(), term_doc_csr));
     let (u, sigma, vt) = sparse_svd(
         linear_op,
         transpose_op,
         term_doc_csr.nrows(),
         term_doc_csr.ncols(),
         k,
-        200, // 500
-        1e-6,
+        config,
+        checkpoint.as_ref(),
     )?;
 
-    println!("SVD computation completed in {:?}", start.elapsed());
+    log::info!("SVD computation completed in {:?}", start.elapsed());
 
 
     let actual_k = sigma.len();
-    let mut doc_vectors = DMatrix::zeros(vt.ncols(), actual_k); // [n_docs x k]
+    let mut doc_vectors = DMatrix::zeros(actual_k, vt.ncols()); // [k x n_docs], one column per document
     for j in 0..vt.ncols() {
         for i in 0..actual_k {
-            doc_vectors[(j, i)] = sigma[i] * vt[(i, j)]; // vt[i,j] is V^T's element
+            doc_vectors[(i, j)] = sigma[i] * vt[(i, j)]; // vt[i,j] is V^T's element
         }
     }
 
@@ -276,7 +528,39 @@ pub fn perform_svd(term_doc_csr: &CsrMatrix<f64>, k: usize) -> Result<SvdData, B
         u_ser: serialize_matrix(&u),
         vt_ser: serialize_matrix(&vt),
         docs_ser: serialize_matrix(&doc_vectors),
+        doc_ids: Vec::new(),
     };
 
     Ok(svd_data)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use nalgebra_sparse::CooMatrix;
+    use super::*;
+    use crate::util::idf::{apply_idf_weighting, calculate_idf};
+
+    fn build_error(matrix: &CsrMatrix<f64>) -> BuildError {
+        let err = perform_svd(matrix, 2, &SvdBuildConfig::default(), None).err().expect("the SVD should be refused");
+        err.downcast_ref::<BuildError>().expect("a BuildError").clone()
+    }
+
+    #[test]
+    fn empty_corpus_is_rejected() {
+        assert_eq!(build_error(&CsrMatrix::zeros(0, 0)), BuildError::EmptyCorpus);
+    }
+
+    #[test]
+    fn empty_vocabulary_is_rejected() {
+        // What an all-stop-word corpus would give: documents, but no terms.
+        assert_eq!(build_error(&CsrMatrix::zeros(0, 3)), BuildError::EmptyVocabulary { documents: 3 });
+    }
+
+    #[test]
+    fn single_document_has_zero_weights() {
+        let coo = CooMatrix::try_from_triplets(3, 1, vec![0, 1, 2], vec![0, 0, 0], vec![2.0, 1.0, 1.0]).unwrap();
+        let mut csr = CsrMatrix::from(&coo);
+        let idf = calculate_idf(&csr);
+        apply_idf_weighting(&mut csr, &idf);
+        assert_eq!(build_error(&csr), BuildError::ZeroWeights { terms: 3, documents: 1 });
+    }
+}
@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::UNIX_EPOCH;
+use serde::Serialize;
+use crate::{Document, SvdData};
+use crate::util::{data, document_ids};
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SvdModelInfo {
+    pub rank: usize,
+    pub path: PathBuf,
+    /// The index file and its components together.
+    pub size_bytes: u64,
+    /// Modification time of the index file, as a unix timestamp in seconds.
+    pub built_at: Option<i64>,
+    pub loaded: bool,
+    /// Built before the index was last reweighted, so it factorizes the old weights.
+    pub stale: bool,
+}
+
+/// The `svd_k*.idx` files in the data directory. Models are loaded on first use and kept in
+/// memory, least recently used first out once they take more than `memory_cap_bytes`.
+pub struct SvdCatalog {
+    models: RwLock<Vec<SvdModelInfo>>,
+    // (rank, model, estimated bytes), most recently used last
+    loaded: Mutex<Vec<(usize, Arc<SvdData>, usize)>>,
+    memory_cap_bytes: usize,
+    // Models must have a column per document, in this order.
+    documents: Arc<Vec<Document>>,
+}
+
+impl SvdCatalog {
+    pub fn scan(data_dir: &Path, memory_cap_bytes: usize, documents: Arc<Vec<Document>>) -> Result<Self, Box<dyn Error>> {
+        let mut models = Vec::new();
+        for entry in fs::read_dir(data_dir)? {
+            let path = entry?.path();
+            let Some(rank) = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("svd_k")?.strip_suffix(".idx")?.parse().ok())
+            else {
+                continue;
+            };
+
+            models.push(model_info(rank, path)?);
+        }
+        models.sort_by_key(|model| model.rank);
+        log::info!("Found SVD models with ranks {:?}", models.iter().map(|model| model.rank).collect::<Vec<_>>());
+
+        Ok(SvdCatalog { models: RwLock::new(models), loaded: Mutex::new(Vec::new()), memory_cap_bytes, documents })
+    }
+
+    pub fn models(&self) -> Vec<SvdModelInfo> {
+        let loaded = self.loaded.lock().unwrap();
+        self.models.read().unwrap().iter()
+            .map(|model| SvdModelInfo {
+                loaded: loaded.iter().any(|(rank, _, _)| *rank == model.rank),
+                ..model.clone()
+            })
+            .collect()
+    }
+
+    /// Flags the models built before `reweighted_at` as stale. They keep serving until a
+    /// `BuildSvd` job replaces them.
+    pub fn mark_stale_before(&self, reweighted_at: i64) {
+        for model in self.models.write().unwrap().iter_mut() {
+            model.stale = model.built_at.is_none_or(|built_at| built_at < reweighted_at);
+        }
+    }
+
+    /// The model of exactly `rank` if there is one, else the smallest larger one (searches can
+    /// truncate it), else the largest.
+    pub fn closest_rank(&self, rank: usize) -> Option<usize> {
+        let models = self.models.read().unwrap();
+        models.iter()
+            .map(|model| model.rank)
+            .find(|&available| available >= rank)
+            .or_else(|| models.last().map(|model| model.rank))
+    }
+
+    pub fn get(&self, rank: usize) -> Result<Arc<SvdData>, Box<dyn Error>> {
+        {
+            let mut loaded = self.loaded.lock().unwrap();
+            if let Some(pos) = loaded.iter().position(|(r, _, _)| *r == rank) {
+                let entry = loaded.remove(pos);
+                let model = entry.1.clone();
+                loaded.push(entry);
+                return Ok(model);
+            }
+        }
+
+        // Loaded without holding the lock so searches on other models aren't held up.
+        let path = self.models.read().unwrap().iter()
+            .find(|model| model.rank == rank)
+            .map(|model| model.path.clone())
+            .ok_or_else(|| format!("No SVD model with rank {}", rank))?;
+        let model = data::load_svd_data(&path)?;
+        document_ids::check_model_columns(&model, &self.documents, &path)?;
+        let model = Arc::new(model);
+        self.insert(rank, model.clone());
+        Ok(model)
+    }
+
+    /// Lists a model just written to `path` and starts serving it, replacing one of the same rank.
+    pub fn register(&self, rank: usize, path: PathBuf, model: Arc<SvdData>) -> Result<(), Box<dyn Error>> {
+        let info = model_info(rank, path)?;
+        {
+            let mut models = self.models.write().unwrap();
+            models.retain(|model| model.rank != rank);
+            models.push(info);
+            models.sort_by_key(|model| model.rank);
+        }
+        self.insert(rank, model);
+        Ok(())
+    }
+
+    /// Adds an already loaded model, evicting others if needed. The new one is never evicted here.
+    pub fn insert(&self, rank: usize, model: Arc<SvdData>) {
+        let bytes = estimated_bytes(&model);
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded.retain(|(r, _, _)| *r != rank);
+        loaded.push((rank, model, bytes));
+
+        let mut total: usize = loaded.iter().map(|(_, _, bytes)| bytes).sum();
+        while total > self.memory_cap_bytes && loaded.len() > 1 {
+            let (evicted, _, bytes) = loaded.remove(0);
+            log::info!("Evicting SVD model k={} from memory", evicted);
+            total -= bytes;
+        }
+    }
+}
+
+fn model_info(rank: usize, path: PathBuf) -> Result<SvdModelInfo, Box<dyn Error>> {
+    let mut size_bytes = 0;
+    for file in data::index_files(&path)? {
+        size_bytes += fs::metadata(file)?.len();
+    }
+    let built_at = fs::metadata(&path)?.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|age| age.as_secs() as i64);
+    Ok(SvdModelInfo { rank, path, size_bytes, built_at, loaded: false, stale: false })
+}
+
+fn estimated_bytes(model: &SvdData) -> usize {
+    let values = model.sigma_k.len() + model.u_ser.data.len() + model.vt_ser.data.len() + model.docs_ser.data.len();
+    values * size_of::<f64>()
+}
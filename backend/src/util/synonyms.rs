@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::util::analyzer::Analyzer;
+
+/// Query-time synonyms from `analyzer.synonyms_path`. A line is either `car => automobile, vehicle`
+/// (a query for "car" also looks for the others) or `couch, sofa, settee` (each looks for the
+/// rest); `#` starts a comment. Words are analyzed when the index is built and expansions
+/// outside its vocabulary are dropped, so the map is saved with the index and always matches it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Synonyms {
+    /// Query weight of an expansion, relative to an occurrence of the term it expands.
+    weight: f64,
+    expansions: HashMap<String, Vec<String>>,
+}
+
+impl Synonyms {
+    pub fn load(path: &Path, weight: f64, analyzer: &Analyzer, term_dict: &HashMap<String, usize>) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut expansions: HashMap<String, Vec<String>> = HashMap::new();
+        let mut skipped = 0;
+        for (line_idx, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (from, to) = match line.split_once("=>") {
+                Some((from, to)) => (words(from), words(to)),
+                None => (words(line), words(line)),
+            };
+            if from.is_empty() || to.is_empty() {
+                return Err(format!(
+                    "{}:{}: expected `word => synonym, ...` or `word, synonym, ...`",
+                    path.display(), line_idx + 1,
+                ).into());
+            }
+
+            // A synonym may analyze to several terms ("motor vehicle"); all of them are added.
+            let targets: Vec<String> = to.iter()
+                .flat_map(|word| analyzer.analyze(word))
+                .filter(|term| term_dict.contains_key(term))
+                .collect();
+            for word in from {
+                let terms = analyzer.analyze(word);
+                let [term] = terms.as_slice() else {
+                    skipped += 1;
+                    continue;
+                };
+                let expansion = expansions.entry(term.clone()).or_default();
+                for target in &targets {
+                    if target != term && !expansion.contains(target) {
+                        expansion.push(target.clone());
+                    }
+                }
+            }
+        }
+        expansions.retain(|_, targets| !targets.is_empty());
+
+        if skipped > 0 {
+            log::warn!("Skipped {} synonym entries that aren't one term after analysis", skipped);
+        }
+        log::info!("Loaded synonyms for {} terms from {}", expansions.len(), path.display());
+        Ok(Synonyms { weight, expansions })
+    }
+
+    /// The terms a query for `term` also looks for, with their weight.
+    pub fn expand(&self, term: &str) -> impl Iterator<Item = (&str, f64)> {
+        self.expansions.get(term).into_iter().flatten().map(|target| (target.as_str(), self.weight))
+    }
+}
+
+fn words(list: &str) -> Vec<&str> {
+    list.split(',').map(str::trim).filter(|word| !word.is_empty()).collect()
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use crate::Document;
+use crate::util::data::AtomicFile;
+use crate::util::manifest::fnv1a;
+
+pub const FILE_NAME: &str = "texts.bin";
+
+// Bumped whenever the file layout changes, so older files are rewritten rather than misread.
+const FORMAT: u32 = 1;
+
+/// Keeping only popular documents' texts in memory. At startup every text moves to `texts.bin`
+/// in the data directory and is read back when a search needs it; one retrieved `promote_after`
+/// times stays in memory from then on, the least retrieved giving way once the hot texts would
+/// take more than `hot_text_mb`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TieringConfig {
+    pub enabled: bool,
+    pub hot_text_mb: usize,
+    pub promote_after: u32,
+}
+
+impl Default for TieringConfig {
+    fn default() -> Self {
+        TieringConfig {
+            enabled: false,
+            hot_text_mb: 256,
+            promote_after: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TierStats {
+    pub documents: usize,
+    pub hot_documents: usize,
+    pub hot_bytes: usize,
+    pub hot_capacity_bytes: usize,
+    /// Texts read from disk since startup.
+    pub cold_reads: u64,
+    pub promotions: u64,
+}
+
+#[derive(Default)]
+struct HotTexts {
+    texts: HashMap<usize, Arc<str>>,
+    bytes: usize,
+    promotions: u64,
+}
+
+/// Document texts, cold ones on disk and hot ones in memory. Documents are looked up by rowid.
+pub struct TextTier {
+    file: Mutex<File>,
+    // Where the texts start in `file`.
+    data_start: u64,
+    // Byte range of each slot's text relative to `data_start`; one more than there are slots.
+    offsets: Vec<u64>,
+    slots: HashMap<i64, usize>,
+    // Retrievals per slot, hot or cold.
+    hits: Vec<AtomicU32>,
+    hot: Mutex<HotTexts>,
+    capacity_bytes: usize,
+    promote_after: u32,
+    cold_reads: AtomicU64,
+}
+
+impl TextTier {
+    /// Opens the texts file at `path`, rewriting it first unless it holds exactly these
+    /// documents' texts, then empties every document's `text`.
+    pub fn open(path: &Path, documents: &mut [Document], config: &TieringConfig) -> Result<Self, Box<dyn Error>> {
+        let fingerprint = fingerprint(documents);
+        let header = match read_header(path) {
+            Ok(header) if header.0 == FORMAT && header.1 == fingerprint && header.2.len() == documents.len() + 1 => header,
+            _ => {
+                write_texts(path, documents, fingerprint)?;
+                read_header(path)?
+            }
+        };
+        let data_start = bincode::serialized_size(&header)?;
+        let offsets = header.2;
+
+        let slots = documents.iter().enumerate().map(|(slot, doc)| (doc.id, slot)).collect();
+        for doc in documents.iter_mut() {
+            doc.text = String::new();
+        }
+        log::info!("Document texts moved to {}; up to {} MB of them stay in memory", path.display(), config.hot_text_mb);
+
+        Ok(TextTier {
+            file: Mutex::new(File::open(path)?),
+            data_start,
+            hits: (0..offsets.len() - 1).map(|_| AtomicU32::new(0)).collect(),
+            offsets,
+            slots,
+            hot: Mutex::new(HotTexts::default()),
+            capacity_bytes: config.hot_text_mb * 1024 * 1024,
+            promote_after: config.promote_after,
+            cold_reads: AtomicU64::new(0),
+        })
+    }
+
+    /// The document's text, from memory when it's hot. Counts as a retrieval, which may promote it.
+    pub fn text(&self, doc: &Document) -> io::Result<Arc<str>> {
+        let slot = self.slot(doc)?;
+        let hits = self.hits[slot].fetch_add(1, Ordering::Relaxed).saturating_add(1);
+        if let Some(text) = self.hot.lock().unwrap().texts.get(&slot) {
+            return Ok(text.clone());
+        }
+
+        let text: Arc<str> = self.read(slot)?.into();
+        self.cold_reads.fetch_add(1, Ordering::Relaxed);
+        if hits >= self.promote_after {
+            self.promote(slot, text.clone(), hits);
+        }
+        Ok(text)
+    }
+
+    /// Length of the document's text in bytes, without reading it.
+    pub fn len(&self, doc: &Document) -> usize {
+        self.slots.get(&doc.id).map_or(0, |&slot| (self.offsets[slot + 1] - self.offsets[slot]) as usize)
+    }
+
+    pub fn stats(&self) -> TierStats {
+        let hot = self.hot.lock().unwrap();
+        TierStats {
+            documents: self.slots.len(),
+            hot_documents: hot.texts.len(),
+            hot_bytes: hot.bytes,
+            hot_capacity_bytes: self.capacity_bytes,
+            cold_reads: self.cold_reads.load(Ordering::Relaxed),
+            promotions: hot.promotions,
+        }
+    }
+
+    fn slot(&self, doc: &Document) -> io::Result<usize> {
+        self.slots.get(&doc.id).copied()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("document {} isn't in the texts file", doc.id)))
+    }
+
+    fn read(&self, slot: usize) -> io::Result<String> {
+        let mut bytes = vec![0; (self.offsets[slot + 1] - self.offsets[slot]) as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(self.data_start + self.offsets[slot]))?;
+            file.read_exact(&mut bytes)?;
+        }
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Keeps the text in memory, demoting less retrieved ones to make room; if every hot text has
+    // been retrieved at least as often, it stays cold.
+    fn promote(&self, slot: usize, text: Arc<str>, hits: u32) {
+        if text.len() > self.capacity_bytes {
+            return;
+        }
+        let mut hot = self.hot.lock().unwrap();
+        if hot.texts.contains_key(&slot) {
+            return;
+        }
+        while hot.bytes + text.len() > self.capacity_bytes {
+            let coldest = hot.texts.keys()
+                .map(|&hot_slot| (self.hits[hot_slot].load(Ordering::Relaxed), hot_slot))
+                .min();
+            match coldest {
+                Some((coldest_hits, coldest)) if coldest_hits < hits => {
+                    let demoted = hot.texts.remove(&coldest).map_or(0, |text| text.len());
+                    hot.bytes -= demoted;
+                }
+                _ => return,
+            }
+        }
+        hot.bytes += text.len();
+        hot.promotions += 1;
+        hot.texts.insert(slot, text);
+    }
+}
+
+// Identifies the documents and their text lengths, so a texts file written for another index
+// isn't read.
+fn fingerprint(documents: &[Document]) -> u64 {
+    let mut hash = fnv1a(0xcbf29ce484222325, &(documents.len() as u64).to_le_bytes());
+    for doc in documents {
+        hash = fnv1a(hash, &doc.id.to_le_bytes());
+        hash = fnv1a(hash, doc.uuid.as_bytes());
+        hash = fnv1a(hash, &(doc.text.len() as u64).to_le_bytes());
+    }
+    hash
+}
+
+// (format, fingerprint, offsets)
+fn read_header(path: &Path) -> Result<(u32, u64, Vec<u64>), Box<dyn Error>> {
+    let file = File::open(path)?;
+    Ok(bincode::deserialize_from(BufReader::new(file))?)
+}
+
+fn write_texts(path: &Path, documents: &[Document], fingerprint: u64) -> Result<(), Box<dyn Error>> {
+    log::info!("Writing document texts to {}...", path.display());
+    let mut offsets = Vec::with_capacity(documents.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for doc in documents {
+        offset += doc.text.len() as u64;
+        offsets.push(offset);
+    }
+
+    let mut file = AtomicFile::create(path)?;
+    bincode::serialize_into(&mut file, &(FORMAT, fingerprint, offsets))?;
+    for doc in documents {
+        file.write_all(doc.text.as_bytes())?;
+    }
+    file.commit()?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::time::Instant;
+use serde::Serialize;
+
+/// The phases a search's time is broken down into for requests with `timings: true`.
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    /// Parsing the request and analyzing the query.
+    Analyze,
+    /// Working out the documents in scope: result sets, filters, views, the blocklist and
+    /// language routing.
+    Candidates,
+    Score,
+    /// Ordering scored documents, tie-breaking included.
+    Sort,
+    /// Loading texts and building the results.
+    Fetch,
+}
+
+/// Milliseconds spent in each phase. A phase entered while another is running counts towards
+/// itself only, e.g. sorting within scoring.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PhaseTimings {
+    pub analyze_ms: f64,
+    pub candidate_ms: f64,
+    pub score_ms: f64,
+    pub sort_ms: f64,
+    pub fetch_ms: f64,
+}
+
+impl PhaseTimings {
+    fn add(&mut self, phase: Phase, ms: f64) {
+        match phase {
+            Phase::Analyze => self.analyze_ms += ms,
+            Phase::Candidates => self.candidate_ms += ms,
+            Phase::Score => self.score_ms += ms,
+            Phase::Sort => self.sort_ms += ms,
+            Phase::Fetch => self.fetch_ms += ms,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    totals: PhaseTimings,
+    // Open phases, innermost last, with the time their nested phases took.
+    open: Vec<(Phase, Instant, f64)>,
+}
+
+thread_local! {
+    // Only set while a request on this thread is recording.
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Starts recording the phases entered on this thread until the returned `Recording` is
+/// finished or dropped. A request must not yield to other tasks while it records.
+pub fn record() -> Recording {
+    RECORDER.with(|recorder| *recorder.borrow_mut() = Some(Recorder::default()));
+    Recording(())
+}
+
+pub struct Recording(());
+
+impl Recording {
+    pub fn finish(self) -> PhaseTimings {
+        RECORDER.with(|recorder| recorder.borrow_mut().take().map(|recorder| recorder.totals).unwrap_or_default())
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        RECORDER.with(|recorder| *recorder.borrow_mut() = None);
+    }
+}
+
+/// Enters `phase` until the returned guard is dropped. Costs a thread-local lookup when nothing
+/// is recording.
+pub fn span(phase: Phase) -> Span {
+    let active = RECORDER.with(|recorder| match recorder.borrow_mut().as_mut() {
+        Some(recorder) => {
+            recorder.open.push((phase, Instant::now(), 0.0));
+            true
+        }
+        None => false,
+    });
+    Span { active }
+}
+
+pub struct Span {
+    active: bool,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        RECORDER.with(|recorder| {
+            let mut recorder = recorder.borrow_mut();
+            let Some(recorder) = recorder.as_mut() else {
+                return;
+            };
+            let Some((phase, start, nested_ms)) = recorder.open.pop() else {
+                return;
+            };
+            let ms = start.elapsed().as_secs_f64() * 1000.0;
+            recorder.totals.add(phase, ms - nested_ms);
+            if let Some((_, _, parent_nested_ms)) = recorder.open.last_mut() {
+                *parent_nested_ms += ms;
+            }
+        });
+    }
+}
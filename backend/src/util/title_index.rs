@@ -0,0 +1,56 @@
+use crate::Document;
+
+// Short prefixes can match a large share of the corpus; only this many are considered.
+const MAX_PREFIX_CANDIDATES: usize = 200;
+
+/// Document titles sorted in normalized form for exact and prefix lookups.
+pub struct TitleIndex {
+    titles: Vec<(String, usize)>,
+}
+
+impl TitleIndex {
+    pub fn build(documents: &[Document]) -> Self {
+        let mut titles: Vec<(String, usize)> = documents.iter()
+            .enumerate()
+            .map(|(doc_idx, doc)| (normalize_title(&doc.title), doc_idx))
+            .filter(|(title, _)| !title.is_empty())
+            .collect();
+        titles.sort();
+
+        TitleIndex { titles }
+    }
+
+    pub fn has_prefix(&self, prefix: &str) -> bool {
+        let prefix = normalize_title(prefix);
+        let start = self.titles.partition_point(|(title, _)| title.as_str() < prefix.as_str());
+        self.titles.get(start).is_some_and(|(title, _)| title.starts_with(&prefix))
+    }
+
+    /// Returns document indices whose title starts with `prefix`: exact matches first,
+    /// then shorter titles before longer ones.
+    pub fn lookup(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        let prefix = normalize_title(prefix);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self.titles.partition_point(|(title, _)| title.as_str() < prefix.as_str());
+        let mut matches: Vec<&(String, usize)> = self.titles[start..].iter()
+            .take_while(|(title, _)| title.starts_with(&prefix))
+            .take(MAX_PREFIX_CANDIDATES)
+            .collect();
+
+        matches.sort_by_key(|(title, _)| (title != &prefix, title.len()));
+        matches.into_iter()
+            .take(limit)
+            .map(|&(_, doc_idx)| doc_idx)
+            .collect()
+    }
+}
+
+pub fn normalize_title(title: &str) -> String {
+    title.split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
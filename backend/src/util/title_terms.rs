@@ -0,0 +1,46 @@
+use std::collections::{HashMap, HashSet};
+use crate::Document;
+use crate::util::analyzer::Analyzer;
+
+/// Postings of analyzed title terms. The term-document matrix folds titles into the body counts
+/// (see `AnalyzerConfig::title_weight`), so this is how a query term appearing in a title is told
+/// apart from one in the body.
+pub struct TitleTermIndex {
+    postings: HashMap<String, Vec<usize>>,
+}
+
+impl TitleTermIndex {
+    pub fn build(documents: &[Document], analyzer: &Analyzer) -> Self {
+        let mut postings: HashMap<String, Vec<usize>> = HashMap::new();
+        for (doc_idx, doc) in documents.iter().enumerate() {
+            let terms: HashSet<String> = analyzer.analyze(&doc.title).into_iter().collect();
+            for term in terms {
+                postings.entry(term).or_default().push(doc_idx);
+            }
+        }
+
+        TitleTermIndex { postings }
+    }
+
+    /// Boost per document: `weight` scaled by the share of distinct analyzed query terms in its title.
+    pub fn boosts(&self, query_terms: &[String], weight: f64) -> HashMap<usize, f64> {
+        let query_terms: HashSet<&String> = query_terms.iter().collect();
+        if query_terms.is_empty() || weight <= 0.0 {
+            return HashMap::new();
+        }
+
+        let mut matched: HashMap<usize, usize> = HashMap::new();
+        for term in &query_terms {
+            if let Some(doc_indices) = self.postings.get(*term) {
+                for &doc_idx in doc_indices {
+                    *matched.entry(doc_idx).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let total = query_terms.len() as f64;
+        matched.into_iter()
+            .map(|(doc_idx, count)| (doc_idx, weight * count as f64 / total))
+            .collect()
+    }
+}
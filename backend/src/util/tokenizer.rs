@@ -1,38 +1,40 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
+use std::sync::LazyLock;
 use nalgebra_sparse::CooMatrix;
 use regex::Regex;
-use crate::{util, Document};
+use serde::{Deserialize, Serialize};
+use crate::Document;
+use crate::util::analyzer::Analyzer;
+use crate::util::build_error::BuildError;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 
-pub fn build_term_document_matrix(documents: &[Document]) -> (HashMap<String, usize>, HashMap<usize, String>, CooMatrix<f64>) {
-    let stop_words = load_stop_words("english.txt").unwrap_or_else(|e| {
-        eprintln!("Warning: Could not load stop words file: {}. Continuing without stop words.", e);
-        HashSet::new()
-    });
+type TermDocumentMatrix = (HashMap<String, usize>, HashMap<usize, String>, CooMatrix<f64>);
 
-    let mut term_dict = HashMap::new();
-    let mut inverse_term_dict = HashMap::new();
-    let mut term_index = 0;
-
-    for doc in documents {
-        let tokens = tokenize(&doc.text);
-        for token in tokens {
-            if stop_words.contains(&token.to_lowercase()) {
-                continue;
-            }
-
-            let stemmed_token = util::steming::porter_stem(&token);
+/// Raw term counts per document. A title term counts `title_weight` times per occurrence, so the
+/// weighting sees it as that many body occurrences; 0 leaves titles out of the matrix.
+pub fn build_term_document_matrix(documents: &[Document], analyzer: &Analyzer, title_weight: u32) -> Result<TermDocumentMatrix, BuildError> {
+    if documents.is_empty() {
+        return Err(BuildError::EmptyCorpus);
+    }
 
-            if !term_dict.contains_key(&stemmed_token) {
-                term_dict.insert(stemmed_token.clone(), term_index);
-                inverse_term_dict.insert(term_index, stemmed_token);
-                term_index += 1;
-            }
-        }
+    // Indices follow sorted term order, so the same corpus always gets the same vocabulary.
+    let terms: BTreeSet<String> = documents.iter()
+        .flat_map(|doc| indexed_fields(doc, title_weight))
+        .flat_map(|(field, _)| analyzer.analyze(field))
+        .collect();
+    let mut term_dict = HashMap::with_capacity(terms.len());
+    let mut inverse_term_dict = HashMap::with_capacity(terms.len());
+    for (term_index, term) in terms.into_iter().enumerate() {
+        term_dict.insert(term.clone(), term_index);
+        inverse_term_dict.insert(term_index, term);
     }
 
-    println!("Dictionary built with {} terms (after stop words removal and stemming)", term_dict.len());
+    log::info!("Dictionary built with {} terms (after stop words removal and normalization)", term_dict.len());
+    if term_dict.is_empty() {
+        return Err(BuildError::EmptyVocabulary { documents: documents.len() });
+    }
 
     let num_terms = term_dict.len();
     let num_docs = documents.len();
@@ -42,19 +44,12 @@ pub fn build_term_document_matrix(documents: &[Document]) -> (HashMap<String, us
     let mut values = Vec::new();
 
     for (doc_idx, doc) in documents.iter().enumerate() {
-        let tokens = tokenize(&doc.text);
-
         let mut term_counts = HashMap::new();
-        for token in tokens {
-            // Skip stop words
-            if stop_words.contains(&token.to_lowercase()) {
-                continue;
-            }
-
-            // Apply Porter stemming to the token before counting
-            let stemmed_token = util::steming::porter_stem(&token);
-            if let Some(&term_idx) = term_dict.get(&stemmed_token) {
-                *term_counts.entry(term_idx).or_insert(0.0) += 1.0;
+        for (field, weight) in indexed_fields(doc, title_weight) {
+            for term in analyzer.analyze(field) {
+                if let Some(&term_idx) = term_dict.get(&term) {
+                    *term_counts.entry(term_idx).or_insert(0.0) += weight as f64;
+                }
             }
         }
 
@@ -71,20 +66,129 @@ pub fn build_term_document_matrix(documents: &[Document]) -> (HashMap<String, us
         row_indices,
         col_indices,
         values,
-    ).unwrap();
+    ).expect("triplet indices come from term_dict and documents");
+
+    Ok((term_dict, inverse_term_dict, coo))
+}
+
+/// The indexed fields of a document with the count each occurrence of a term in them adds.
+pub(crate) fn indexed_fields(doc: &Document, title_weight: u32) -> impl Iterator<Item = (&str, u32)> {
+    let title = (title_weight > 0).then_some((doc.title.as_str(), title_weight));
+    std::iter::once((doc.text.as_str(), 1)).chain(title)
+}
+
+// Alphanumeric runs, joined across single hyphens and apostrophes.
+static WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[a-zA-Z0-9]+(?:[-'’][a-zA-Z0-9]+)*").unwrap());
+// The same, or a single emoji (with its modifiers and zero-width-joined parts) or other symbol.
+static WORD_OR_SYMBOL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[a-zA-Z0-9]+(?:[-'’][a-zA-Z0-9]+)*|\p{Extended_Pictographic}(?:[\p{Emoji_Modifier}\u{FE0F}]|\u{200D}\p{Extended_Pictographic})*|\p{So}").unwrap()
+});
+
+/// What to do with a hyphen or apostrophe inside a word such as "state-of-the-art" or "O'Brien".
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinerPolicy {
+    /// Index the parts as separate words.
+    #[default]
+    Split,
+    /// Index the whole word as one term.
+    Keep,
+    /// Index the whole word and its parts.
+    Both,
+}
+
+/// What to do with emoji and other symbols such as "♥" or "™".
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolPolicy {
+    /// Treat them as separators between words.
+    #[default]
+    Strip,
+    /// Index each as a token of its own, whatever the length limits.
+    Index,
+}
+
+/// Whether a token is an emoji or symbol rather than a word.
+pub fn is_symbol(token: &str) -> bool {
+    !token.chars().any(char::is_alphanumeric)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenizerOptions {
+    pub hyphens: JoinerPolicy,
+    pub apostrophes: JoinerPolicy,
+    pub symbols: SymbolPolicy,
+    /// Shorter and longer tokens, in characters, are dropped.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Lowercase terms kept regardless of length, which may end in `#` or `+` ("c#", "c++").
+    pub keep: HashSet<String>,
+}
 
-    (term_dict, inverse_term_dict, coo)
+impl Default for TokenizerOptions {
+    fn default() -> Self {
+        TokenizerOptions {
+            hyphens: JoinerPolicy::Split,
+            apostrophes: JoinerPolicy::Split,
+            symbols: SymbolPolicy::Strip,
+            min_length: 3,
+            max_length: usize::MAX,
+            keep: HashSet::new(),
+        }
+    }
 }
 
 pub fn tokenize(text: &str) -> Vec<String> {
-    let re = Regex::new(r"[^a-zA-Z0-9]+").unwrap();
-    re.split(text)
-        .filter(|s| !s.is_empty() && s.len() > 2)
-        .map(|s| s.to_lowercase())
-        .collect()
+    tokenize_with(text, &TokenizerOptions::default())
+}
+
+/// Lowercased words within the length limits, with hyphenated and apostrophized words
+/// and symbols handled according to their policies. Typographic apostrophes are written as `'`.
+pub fn tokenize_with(text: &str, options: &TokenizerOptions) -> Vec<String> {
+    let policy = |joiner: char| if joiner == '-' { options.hyphens } else { options.apostrophes };
+    let mut tokens = Vec::new();
+    let mut push = |token: &str| {
+        let token = token.to_lowercase().replace('’', "'");
+        let length = token.chars().count();
+        if options.keep.contains(&token) || is_symbol(&token) || (options.min_length..=options.max_length).contains(&length) {
+            tokens.push(token);
+        }
+    };
+
+    let pattern = match options.symbols {
+        SymbolPolicy::Strip => &WORD,
+        SymbolPolicy::Index => &WORD_OR_SYMBOL,
+    };
+    for found in pattern.find_iter(text) {
+        let word = found.as_str();
+        if is_symbol(word) {
+            push(word);
+            continue;
+        }
+        // The regex stops before symbols, so keep-listed terms like "c#" are looked up here.
+        let suffix_len = text[found.end()..].chars().take(2).take_while(|&c| matches!(c, '#' | '+')).count();
+        if suffix_len > 0 {
+            let with_suffix = text[found.start()..found.end() + suffix_len].to_lowercase();
+            if options.keep.contains(&with_suffix) {
+                push(&with_suffix);
+                continue;
+            }
+        }
+
+        // Split at joiners whose policy says so; the rest stay inside the segments.
+        for segment in word.split(|c: char| matches!(c, '-' | '\'' | '’') && policy(c) == JoinerPolicy::Split) {
+            push(segment);
+            if segment.contains(|c: char| matches!(c, '-' | '\'' | '’') && policy(c) == JoinerPolicy::Both) {
+                for part in segment.split(['-', '\'', '’']) {
+                    push(part);
+                }
+            }
+        }
+    }
+    tokens
 }
 
-fn load_stop_words(filename: &str) -> std::io::Result<HashSet<String>> {
+pub fn load_stop_words(filename: &Path) -> std::io::Result<HashSet<String>> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
     let mut stop_words = HashSet::new();
@@ -97,4 +201,55 @@ fn load_stop_words(filename: &str) -> std::io::Result<HashSet<String>> {
     }
 
     Ok(stop_words)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::analyzer::AnalyzerConfig;
+
+    // An analyzer whose stop word list is `stop_words`, written to a file of the test's own.
+    fn analyzer(test: &str, stop_words: &[&str]) -> Analyzer {
+        let dir = std::env::temp_dir().join(format!("search-engine-tokenizer-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stop_words.txt"), stop_words.join("\n")).unwrap();
+        let config = AnalyzerConfig { stop_words_path: "stop_words.txt".to_string(), ..AnalyzerConfig::default() };
+        let analyzer = Analyzer::from_config(&config, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        analyzer
+    }
+
+    #[test]
+    fn empty_corpus_is_rejected() {
+        let result = build_term_document_matrix(&[], &analyzer("empty", &[]), 0);
+        assert_eq!(result.err(), Some(BuildError::EmptyCorpus));
+    }
+
+    #[test]
+    fn all_stop_word_corpus_is_rejected() {
+        let documents = [
+            Document::new(1, "First", "", "the and with"),
+            Document::new(2, "Second", "", "with the"),
+        ];
+        let result = build_term_document_matrix(&documents, &analyzer("stop-words", &["the", "and", "with"]), 0);
+        assert_eq!(result.err(), Some(BuildError::EmptyVocabulary { documents: 2 }));
+    }
+
+    #[test]
+    fn single_document_corpus_has_one_column() {
+        let documents = [Document::new(1, "Only", "", "search engines rank documents")];
+        let (term_dict, inverse_term_dict, coo) = build_term_document_matrix(&documents, &analyzer("single", &[]), 0).unwrap();
+        assert_eq!(coo.ncols(), 1);
+        assert_eq!(coo.nrows(), term_dict.len());
+        assert_eq!(coo.nnz(), term_dict.len());
+        assert!(term_dict.iter().all(|(term, idx)| inverse_term_dict[idx] == *term));
+    }
+
+    #[test]
+    fn title_terms_count_with_the_title_weight() {
+        let documents = [Document::new(1, "Compilers", "", "parsers build syntax trees")];
+        let (term_dict, _, coo) = build_term_document_matrix(&documents, &analyzer("title", &[]), 3).unwrap();
+        let count = |term: &str| coo.triplet_iter().find(|&(row, _, _)| row == term_dict[term]).map(|(_, _, &value)| value);
+        assert_eq!(count("compil"), Some(3.0));
+        assert_eq!(count("parser"), Some(1.0));
+    }
+}
@@ -0,0 +1,91 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+// Per-language rules mapping a lowercase letter to its plain ASCII spelling.
+const RULES: &[(&str, &[(char, &str)])] = &[
+    ("pl", &[('ą', "a"), ('ć', "c"), ('ę', "e"), ('ł', "l"), ('ń', "n"), ('ó', "o"), ('ś', "s"), ('ź', "z"), ('ż', "z")]),
+    ("de", &[('ä', "ae"), ('ö', "oe"), ('ü', "ue"), ('ß', "ss")]),
+    ("ru", &[
+        ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ё', "e"), ('ж', "zh"),
+        ('з', "z"), ('и', "i"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"), ('н', "n"), ('о', "o"),
+        ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"), ('ф', "f"), ('х', "kh"), ('ц', "ts"),
+        ('ч', "ch"), ('ш', "sh"), ('щ', "shch"), ('ъ', ""), ('ы', "y"), ('ь', ""), ('э', "e"), ('ю', "yu"),
+        ('я', "ya"),
+    ]),
+    // Only where Ukrainian differs from the Russian rules; list "uk" before "ru".
+    ("uk", &[('г', "h"), ('ґ', "g"), ('и', "y"), ('і', "i"), ('ї', "yi"), ('є', "ye")]),
+    ("el", &[
+        ('α', "a"), ('ά', "a"), ('β', "v"), ('γ', "g"), ('δ', "d"), ('ε', "e"), ('έ', "e"), ('ζ', "z"),
+        ('η', "i"), ('ή', "i"), ('θ', "th"), ('ι', "i"), ('ί', "i"), ('ϊ', "i"), ('κ', "k"), ('λ', "l"),
+        ('μ', "m"), ('ν', "n"), ('ξ', "x"), ('ο', "o"), ('ό', "o"), ('π', "p"), ('ρ', "r"), ('σ', "s"),
+        ('ς', "s"), ('τ', "t"), ('υ', "y"), ('ύ', "y"), ('ϋ', "y"), ('φ', "f"), ('χ', "ch"), ('ψ', "ps"),
+        ('ω', "o"), ('ώ', "o"),
+    ]),
+    // Accented Latin letters of other languages, folded to the bare letter.
+    ("latin", &[
+        ('à', "a"), ('á', "a"), ('â', "a"), ('ã', "a"), ('ä', "a"), ('å', "a"), ('ç', "c"), ('č', "c"),
+        ('ď', "d"), ('đ', "d"), ('è', "e"), ('é', "e"), ('ê', "e"), ('ë', "e"), ('ě', "e"), ('ì', "i"),
+        ('í', "i"), ('î', "i"), ('ï', "i"), ('ñ', "n"), ('ň', "n"), ('ò', "o"), ('ó', "o"), ('ô', "o"),
+        ('õ', "o"), ('ö', "o"), ('ø', "o"), ('ř', "r"), ('š', "s"), ('ť', "t"), ('ù', "u"), ('ú', "u"),
+        ('û', "u"), ('ü', "u"), ('ů', "u"), ('ý', "y"), ('ÿ', "y"), ('ž', "z"),
+    ]),
+];
+
+/// Rewrites letters outside ASCII with the rules of the configured languages, so text typed
+/// without diacritics or in a rough Latinization matches the same terms as the original.
+/// Where rule sets overlap, the language listed first wins.
+// Serialized as its languages, since the rules are static.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct Transliterator {
+    languages: Vec<String>,
+    rules: HashMap<char, &'static str>,
+}
+
+impl Transliterator {
+    pub fn new(languages: &[String]) -> Result<Self, String> {
+        let mut rules = HashMap::new();
+        for language in languages {
+            let (_, language_rules) = RULES.iter()
+                .find(|(name, _)| name == language)
+                .ok_or_else(|| format!(
+                    "Unknown transliteration rules {:?}; available: {}",
+                    language,
+                    RULES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "),
+                ))?;
+            for &(letter, replacement) in language_rules.iter() {
+                rules.entry(letter).or_insert(replacement);
+            }
+        }
+        Ok(Transliterator { languages: languages.to_vec(), rules })
+    }
+
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match self.rules.get(&c).or_else(|| c.to_lowercase().next().and_then(|lower| self.rules.get(&lower))) {
+                Some(replacement) => out.push_str(replacement),
+                None => out.push(c),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+impl TryFrom<Vec<String>> for Transliterator {
+    type Error = String;
+
+    fn try_from(languages: Vec<String>) -> Result<Self, String> {
+        Transliterator::new(&languages)
+    }
+}
+
+impl From<Transliterator> for Vec<String> {
+    fn from(transliterator: Transliterator) -> Self {
+        transliterator.languages
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use crate::PreprocessedData;
+
+/// How many documents of one month contain the term, next to how many documents that month has
+/// at all, so clients can plot either the count or the share.
+#[derive(Serialize, Debug)]
+pub struct MonthlyFrequency {
+    /// `YYYY-MM`, in UTC.
+    pub month: String,
+    pub documents: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Trend {
+    pub months: Vec<MonthlyFrequency>,
+    /// Matching documents without a timestamp, left out of `months`.
+    pub undated: usize,
+}
+
+/// Per-month document frequency of documents containing all of `terms` (vocabulary indices),
+/// from the first to the last month the corpus has dated documents in, without gaps.
+pub fn monthly_frequency(data: &PreprocessedData, terms: &[usize]) -> Trend {
+    let months: Vec<Option<i64>> = data.documents.iter()
+        .map(|doc| doc.modified_at.map(month_index))
+        .collect();
+    let Some((first, last)) = months.iter().flatten().fold(None, |range, &month| match range {
+        None => Some((month, month)),
+        Some((first, last)) => Some((month.min(first), month.max(last))),
+    }) else {
+        return Trend { months: Vec::new(), undated: 0 };
+    };
+
+    let mut frequencies: Vec<MonthlyFrequency> = (first..=last)
+        .map(|month| MonthlyFrequency { month: month_name(month), documents: 0, total: 0 })
+        .collect();
+    for month in months.iter().flatten() {
+        frequencies[(month - first) as usize].total += 1;
+    }
+
+    let mut undated = 0;
+    for doc_idx in documents_with_all(data, terms) {
+        match months[doc_idx] {
+            Some(month) => frequencies[(month - first) as usize].documents += 1,
+            None => undated += 1,
+        }
+    }
+    Trend { months: frequencies, undated }
+}
+
+fn documents_with_all(data: &PreprocessedData, terms: &[usize]) -> Vec<usize> {
+    let matrix = &data.term_doc_csr;
+    let row = |term: usize| &matrix.col_indices[matrix.row_offsets[term]..matrix.row_offsets[term + 1]];
+    // Walk the rarest term's documents and probe the other rows, whose columns are sorted.
+    let mut terms = terms.to_vec();
+    terms.sort_by_key(|&term| row(term).len());
+    let Some((&first, rest)) = terms.split_first() else {
+        return Vec::new();
+    };
+    row(first).iter()
+        .copied()
+        .filter(|doc_idx| rest.iter().all(|&term| row(term).binary_search(doc_idx).is_ok()))
+        .collect()
+}
+
+/// Months since January 1970 of a unix timestamp.
+fn month_index(timestamp: i64) -> i64 {
+    let (year, month) = year_month(timestamp.div_euclid(86_400));
+    (year - 1970) * 12 + month - 1
+}
+
+fn month_name(month_index: i64) -> String {
+    format!("{:04}-{:02}", 1970 + month_index.div_euclid(12), month_index.rem_euclid(12) + 1)
+}
+
+/// The proleptic Gregorian year and month of a day counted from 1970-01-01
+/// (Howard Hinnant's `civil_from_days`).
+fn year_month(days: i64) -> (i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
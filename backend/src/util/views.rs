@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::util::geo::GeoFilter;
+
+/// Where the views are kept, in the data directory.
+pub const FILE_NAME: &str = "views.json";
+
+/// A named scope that searches select with `view`, e.g. English science articles. Every part
+/// given must match, on top of whatever the search itself narrows to.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    /// Searches go to this language's partition only (when partitions are enabled).
+    pub language: Option<String>,
+    /// A filter query, as in a search's `filter`.
+    pub filter: Option<String>,
+    /// Values of fields declared in `metadata.facets`.
+    pub metadata: BTreeMap<String, String>,
+    pub geo: Option<GeoFilter>,
+}
+
+/// The views operators have defined, by name.
+#[derive(Default)]
+pub struct Views {
+    views: BTreeMap<String, View>,
+}
+
+impl Views {
+    /// Reads the views saved at `path`; none when the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Views::default());
+        }
+        let views: BTreeMap<String, View> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        log::info!("Loaded {} views", views.len());
+        Ok(Views { views })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Written aside and renamed so a crash never leaves a truncated file.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.views)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&View> {
+        self.views.get(name)
+    }
+
+    pub fn all(&self) -> &BTreeMap<String, View> {
+        &self.views
+    }
+
+    /// A copy with `name` defined as `view`, replacing any view of that name.
+    pub fn with(&self, name: &str, view: View) -> Views {
+        let mut views = self.views.clone();
+        views.insert(name.to_string(), view);
+        Views { views }
+    }
+
+    /// A copy without `name`; `None` when there is no such view.
+    pub fn without(&self, name: &str) -> Option<Views> {
+        let mut views = self.views.clone();
+        views.remove(name)?;
+        Some(Views { views })
+    }
+}
+
+/// Names are used in URLs and request bodies, so they are kept to a plain alphabet.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("View names are 1-64 letters, digits, '-' and '_'".to_string());
+    }
+    Ok(())
+}
+
+/// How many searches used each view since the server started.
+#[derive(Default)]
+pub struct ViewStats {
+    searches: Mutex<HashMap<String, u64>>,
+}
+
+impl ViewStats {
+    pub fn record(&self, name: &str) {
+        *self.searches.lock().unwrap().entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn searches(&self, name: &str) -> u64 {
+        self.searches.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use crate::{PreprocessedData, SerializableCsrMatrix};
+
+/// How raw term counts are turned into the weights the TF-IDF method ranks by.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "scheme", rename_all = "lowercase")]
+pub enum Weighting {
+    /// tf * ln(N / df), columns L2-normalized (what the index is built with).
+    TfIdf {
+        /// Use 1 + ln(tf) instead of tf.
+        #[serde(default)]
+        sublinear_tf: bool,
+    },
+    /// BM25 term saturation with its own IDF. Columns are left unnormalized, since BM25 already
+    /// normalizes for document length.
+    Bm25 {
+        #[serde(default = "default_k1")]
+        k1: f64,
+        #[serde(default = "default_b")]
+        b: f64,
+    },
+}
+
+fn default_k1() -> f64 {
+    1.2
+}
+
+fn default_b() -> f64 {
+    0.75
+}
+
+impl Default for Weighting {
+    fn default() -> Self {
+        Weighting::TfIdf { sublinear_tf: false }
+    }
+}
+
+impl Weighting {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Weighting::TfIdf { .. } => Ok(()),
+            Weighting::Bm25 { k1, b } => {
+                if !(0.0..=10.0).contains(&k1) {
+                    return Err(format!("k1 must be between 0 and 10, got {}", k1));
+                }
+                if !(0.0..=1.0).contains(&b) {
+                    return Err(format!("b must be between 0 and 1, got {}", b));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Recomputes the term-document weights and query-side IDF from the stored raw counts.
+/// The sparsity pattern is unchanged, so only the values are rebuilt.
+pub fn reweight(data: &PreprocessedData, weighting: Weighting) -> (SerializableCsrMatrix, Vec<f64>) {
+    let matrix = &data.term_doc_csr;
+    let num_docs = matrix.ncols as f64;
+
+    let mut doc_lengths = vec![0.0; matrix.ncols];
+    for (&j, &count) in matrix.col_indices.iter().zip(&data.term_counts) {
+        doc_lengths[j] += count as f64;
+    }
+    let avg_doc_length = (doc_lengths.iter().sum::<f64>() / num_docs.max(1.0)).max(1.0);
+
+    let mut idf = vec![0.0; matrix.nrows];
+    let mut values = vec![0.0; data.term_counts.len()];
+    for (term_idx, term_idf) in idf.iter_mut().enumerate() {
+        let row_start = matrix.row_offsets[term_idx];
+        let row_end = matrix.row_offsets[term_idx + 1];
+        let df = (row_end - row_start) as f64;
+        if df == 0.0 {
+            continue;
+        }
+
+        *term_idf = match weighting {
+            Weighting::TfIdf { .. } => (num_docs / df).ln(),
+            Weighting::Bm25 { .. } => (1.0 + (num_docs - df + 0.5) / (df + 0.5)).ln(),
+        };
+
+        for idx in row_start..row_end {
+            let tf = data.term_counts[idx] as f64;
+            let doc_length = doc_lengths[matrix.col_indices[idx]];
+            values[idx] = match weighting {
+                Weighting::TfIdf { sublinear_tf: true } if tf > 0.0 => (1.0 + tf.ln()) * *term_idf,
+                Weighting::TfIdf { .. } => tf * *term_idf,
+                // The query vector carries the IDF, so documents only hold the saturated tf.
+                Weighting::Bm25 { k1, b } => tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * doc_length / avg_doc_length)),
+            };
+        }
+    }
+
+    if let Weighting::TfIdf { .. } = weighting {
+        let mut col_norms = vec![0.0; matrix.ncols];
+        for (&j, &value) in matrix.col_indices.iter().zip(&values) {
+            col_norms[j] += value * value;
+        }
+        for (&j, value) in matrix.col_indices.iter().zip(values.iter_mut()) {
+            let norm = f64::sqrt(col_norms[j]);
+            if norm > 0.0 {
+                *value /= norm;
+            }
+        }
+    }
+
+    let weighted = SerializableCsrMatrix {
+        nrows: matrix.nrows,
+        ncols: matrix.ncols,
+        row_offsets: matrix.row_offsets.clone(),
+        col_indices: matrix.col_indices.clone(),
+        values,
+    };
+    (weighted, idf)
+}
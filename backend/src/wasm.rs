@@ -0,0 +1,53 @@
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use crate::{Method, SearchEngine};
+
+/// A `SearchEngine` for JavaScript, loaded from a bundle written by `SearchEngine::to_bundle`
+/// (see the `bundle` command) and searched entirely in the browser.
+#[wasm_bindgen(js_name = SearchEngine)]
+pub struct WasmSearchEngine {
+    engine: SearchEngine,
+}
+
+#[derive(Serialize)]
+struct SearchHit<'a> {
+    id: i64,
+    uuid: &'a str,
+    title: &'a str,
+    url: &'a str,
+    score: f64,
+}
+
+#[wasm_bindgen(js_class = SearchEngine)]
+impl WasmSearchEngine {
+    /// Takes the bundle's bytes, e.g. `new Uint8Array(await response.arrayBuffer())`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(bundle: &[u8]) -> Result<WasmSearchEngine, JsError> {
+        let engine = SearchEngine::from_bundle(bundle).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(WasmSearchEngine { engine })
+    }
+
+    /// The best `limit` documents as `{id, uuid, title, url, score}` objects, best first.
+    /// `method` is "tfidf", "svd", "bm25", "qlm" or "hybrid", as in the HTTP API; "svd" and
+    /// "hybrid" need a bundle built with LSI.
+    pub fn search(&self, query: &str, method: &str, limit: usize) -> Result<JsValue, JsError> {
+        let method = match method {
+            "tfidf" => Method::TfIdf,
+            "svd" => Method::Lsi,
+            "bm25" => Method::Bm25,
+            "qlm" => Method::QueryLikelihood,
+            "hybrid" => Method::Hybrid,
+            other => return Err(JsError::new(&format!("Unknown method {:?}; expected tfidf, svd, bm25, qlm or hybrid", other))),
+        };
+        let ranked = self.engine.search(query, method, limit).map_err(|e| JsError::new(&e.to_string()))?;
+        let hits: Vec<SearchHit> = ranked.into_iter()
+            .map(|(doc, score)| SearchHit { id: doc.id, uuid: &doc.uuid, title: &doc.title, url: &doc.url, score })
+            .collect();
+        serde_wasm_bindgen::to_value(&hits).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    #[wasm_bindgen(getter = documentCount)]
+    pub fn document_count(&self) -> usize {
+        self.engine.documents().len()
+    }
+}